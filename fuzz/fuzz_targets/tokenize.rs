@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use simpleshell::parser::tokenize;
+
+fuzz_target!(|line: &str| {
+    let tokens = tokenize(line);
+    if !line.contains(['"', '\'', '\\']) {
+        assert!(tokens.iter().all(|t| !t.is_empty() && !t.contains(' ')));
+        assert_eq!(tokens, line.trim().split(' ').filter(|t| !t.is_empty()).collect::<Vec<_>>());
+    }
+});
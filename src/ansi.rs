@@ -0,0 +1,107 @@
+//! Handling of ANSI escape sequences
+
+/// Removes all ANSI escape sequences (colors, cursor movement, window titles)
+/// from the text
+pub(crate) fn strip(text: &str) -> String {
+    let stripped = Stripper::default().filter(text.as_bytes());
+    String::from_utf8_lossy(&stripped).into_owned()
+}
+
+/// Removes ANSI escape sequences from a stream of output, which may split a
+/// sequence across several writes
+#[derive(Debug, Default)]
+pub(crate) struct Stripper {
+    state: State,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum State {
+    #[default]
+    Text,
+    Escape,
+    /// Escape sequence with intermediate bytes, e.g. `ESC ( B`
+    Intermediate,
+    /// Control sequence: parameters followed by a final byte
+    Control,
+    /// Operating system command, or a device control, privacy message or
+    /// application program command string: terminated by BEL or ESC \
+    Command,
+    CommandEscape,
+}
+
+impl Stripper {
+    /// Returns the bytes that are not part of an escape sequence
+    pub(crate) fn filter(&mut self, bytes: &[u8]) -> Vec<u8> {
+        let mut text = Vec::with_capacity(bytes.len());
+        for &byte in bytes {
+            self.state = match (self.state, byte) {
+                (State::Text, 0x1b) => State::Escape,
+                (State::Text, byte) => {
+                    text.push(byte);
+                    State::Text
+                }
+                (State::Escape, b'[') => State::Control,
+                (State::Escape, b']' | b'P' | b'X' | b'^' | b'_') => State::Command,
+                (State::Escape | State::Intermediate, 0x20..=0x2f) => State::Intermediate,
+                // Any other sequence ends with a single final character
+                (State::Escape | State::Intermediate, _) => State::Text,
+                (State::Control, 0x40..=0x7e) => State::Text,
+                (State::Control, _) => State::Control,
+                (State::Command | State::CommandEscape, 0x07) => State::Text,
+                (State::Command | State::CommandEscape, 0x1b) => State::CommandEscape,
+                (State::CommandEscape, b'\\') => State::Text,
+                (State::Command | State::CommandEscape, _) => State::Command,
+            };
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_control_sequences() {
+        assert_eq!(strip("\x1b[1;31mred\x1b[0m text"), "red text");
+        assert_eq!(strip("\x1b[2K\x1b[10Gdone"), "done");
+    }
+
+    #[test]
+    fn strips_operating_system_commands() {
+        assert_eq!(strip("\x1b]0;title\x07text"), "text");
+        assert_eq!(strip("\x1b]8;;https://x\x1b\\link\x1b]8;;\x1b\\"), "link");
+    }
+
+    #[test]
+    fn strips_sequences_with_intermediate_bytes() {
+        assert_eq!(strip("\x1b(Bplain"), "plain");
+        assert_eq!(strip("\x1b[0m\x1b(B\x1b)0text"), "text");
+        assert_eq!(strip("\x1b#8lines"), "lines");
+    }
+
+    #[test]
+    fn strips_single_character_sequences() {
+        assert_eq!(strip("\x1b7saved\x1b8"), "saved");
+        assert_eq!(strip("\x1bMup"), "up");
+    }
+
+    #[test]
+    fn strips_string_sequences() {
+        assert_eq!(strip("\x1bP1$r0m\x1b\\after"), "after");
+        assert_eq!(strip("a\x1bXprivate\x1b\\b"), "ab");
+        assert_eq!(strip("a\x1b^message\x1b\\b"), "ab");
+        assert_eq!(strip("a\x1b_Gf=100;AAAA\x1b\\b"), "ab");
+    }
+
+    #[test]
+    fn keeps_state_across_writes() {
+        let mut stripper = Stripper::default();
+        let mut text = stripper.filter(b"one\x1b[3");
+        text.extend(stripper.filter(b"1mtwo\x1b"));
+        text.extend(stripper.filter(b"(Bthree\x1bP"));
+        text.extend(stripper.filter(b"data\x1b"));
+        text.extend(stripper.filter(b"\\four"));
+        assert_eq!(text, b"onetwothreefour");
+    }
+}
@@ -0,0 +1,72 @@
+//! Alerts that draw the attention of the user to finished commands, see
+//! [`Shell::with_bell`](crate::Shell::with_bell)
+use std::time::Duration;
+
+/// How the shell alerts the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BellStyle {
+    /// Rings the bell of the terminal
+    #[default]
+    Audible,
+    /// Briefly flashes the screen of the terminal
+    Visual,
+    /// Shows a desktop notification with the OSC 9 escape sequence, which
+    /// e.g. iTerm2, WezTerm and Windows Terminal support
+    Notification,
+}
+
+/// When and how the shell alerts the user after a command
+///
+/// # Example
+/// ```rust
+/// # use std::time::Duration;
+/// # use simpleshell::{BellPolicy, BellStyle, Shell};
+/// let bell = BellPolicy::default()
+///     .with_style(BellStyle::Notification)
+///     .on_error(true)
+///     .after(Duration::from_secs(10));
+/// let shell = Shell::new(None, vec![]).with_bell(bell);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct BellPolicy {
+    /// How the user is alerted
+    pub style: BellStyle,
+    /// Alert when a command fails
+    pub on_error: bool,
+    /// Alert when a command finishes that ran at least this long
+    pub after: Option<Duration>,
+}
+
+impl BellPolicy {
+    /// Sets how the user is alerted
+    pub fn with_style(mut self, style: BellStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Configures whether the user is alerted when a command fails
+    pub fn on_error(mut self, enabled: bool) -> Self {
+        self.on_error = enabled;
+        self
+    }
+
+    /// Alerts the user when a command finishes that ran at least `duration`,
+    /// e.g. to return to a terminal after a long deployment
+    pub fn after(mut self, duration: Duration) -> Self {
+        self.after = Some(duration);
+        self
+    }
+
+    /// Returns `true` if a command that ran for the duration calls for an
+    /// alert
+    pub(crate) fn alerts(&self, failed: bool, duration: Duration) -> bool {
+        (failed && self.on_error) || self.after.is_some_and(|after| duration >= after)
+    }
+}
+
+/// Returns the OSC 9 sequence that shows the text as a desktop notification
+pub(crate) fn notification(text: &str) -> String {
+    // Control characters would end the sequence early
+    let text: String = text.chars().filter(|c| !c.is_control()).collect();
+    format!("\x1b]9;{}\x07", text)
+}
@@ -0,0 +1,574 @@
+//! Commands that are provided by the shell itself and can be enabled with
+//! [`Shell::with_builtin`](crate::Shell::with_builtin)
+use std::io::Write;
+
+use crate::{Command, CommandError, Context};
+
+/// A command that is implemented by the shell itself
+///
+/// # Example
+/// ```rust
+/// # use simpleshell::{testing::TestShell, Builtin, CommandError, Shell};
+/// let shell = Shell::new(None, vec![])
+///     .with_builtin(Builtin::Cd)
+///     .with_builtin(Builtin::Pwd);
+///
+/// let mut shell = TestShell::deterministic(shell);
+/// shell.run("cd /").expect_ok();
+/// shell.run("pwd").expect_ok();
+/// assert_eq!(shell.output(), "/\n");
+///
+/// // Invalid arguments fail with the usage of the builtin
+/// shell
+///     .run("cd /tmp /var")
+///     .expect_error(CommandError::Usage("cd [<dir> | -]".to_string()));
+/// ```
+///
+/// The usages are part of the [`Messages`](crate::Messages) of the shell,
+/// e.g. [`Messages::cd_usage`](crate::Messages::cd_usage), so they can be
+/// translated like all other texts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    /// `help [command | namespace | --short | --search <term>]` - lists all
+    /// commands grouped by their namespace, shows the description and the
+    /// examples of a command, lists the commands of a namespace, lists only
+    /// the names of all commands in columns or lists the commands whose name,
+    /// description or examples contain the term
+    Help,
+    /// `history [--with-time | scrub <pattern>]` - prints the recorded lines
+    /// of the session, optionally with the time they were entered, or
+    /// removes the lines that match the regular expression from the history
+    /// and the history file, see
+    /// [`Shell::with_history_policy`](crate::Shell::with_history_policy).
+    /// `scrub` requires the `regex` feature
+    History,
+    /// `stats` - prints the usage metrics of all executed commands
+    Stats,
+    /// `undo` - reverts the last action recorded with
+    /// [`Context::push_undo`]
+    Undo,
+    /// `profile [list | use <name>]` - shows, lists or switches the active
+    /// [`Profile`](crate::Profile)
+    Profile,
+    /// `cd [dir | -]` - changes the working directory of the session to the
+    /// given directory, the home directory or the previous directory
+    Cd,
+    /// `pwd` - prints the working directory of the session
+    Pwd,
+    /// `tutorial [reset]` - guides the user through the steps of the
+    /// [`Tutorial`](crate::Tutorial) of the shell, continuing where the user
+    /// left off, or starts it over
+    Tutorial,
+    /// `set [<name> <value> | -e | +e | -u | +u]` - lists the variables of
+    /// the session, sets a variable or turns an option of scripts on (`-`) or
+    /// off (`+`)
+    ///
+    /// With `-e` scripts stop at the first failing command that is not the
+    /// condition of an `if`, with `-u` references to undefined variables are
+    /// errors that stop the script. Options set in a script only apply until
+    /// the script ends, e.g. `set -eu` in its first line makes it strict.
+    Set,
+    /// `persist <name>` - keeps the variable across restarts, see
+    /// [`Shell::with_variable_file`](crate::Shell::with_variable_file)
+    Persist,
+    /// `unpersist <name>` - stops keeping the variable across restarts
+    Unpersist,
+    /// `define <name> <body>` - defines a command whose body is compiled by
+    /// the script engine of the shell, see
+    /// [`Shell::with_script_engine`](crate::Shell::with_script_engine). The
+    /// body is usually quoted
+    Define,
+    /// `use [namespace]` - lets the commands of the namespace be called
+    /// without their prefix or clears the namespace, see
+    /// [`Shell::use_namespace`](crate::Shell::use_namespace)
+    Use,
+    /// `capabilities` - prints the commands with their versions and arguments
+    /// as JSON, see [`Shell::capabilities`](crate::Shell::capabilities)
+    Capabilities,
+    /// `sessions [list | attach <id> | detach | close <id> | broadcast
+    /// <message>]` - lists the sessions of the
+    /// [`ShellServer`](crate::ShellServer), attaches the client to a detached
+    /// session, detaches it from the current session, closes a detached
+    /// session or shows a message in all attached sessions
+    #[cfg(unix)]
+    Sessions,
+    /// `schedule [every <interval> <command> | at <HH:MM> <command> | list |
+    /// cancel <id>]` - runs a command periodically (e.g. `every 30s`) or once
+    /// at the next occurrence of a local time of day, lists the scheduled
+    /// jobs or cancels one of them
+    ///
+    /// Scheduled commands run on the thread of the shell before the prompt is
+    /// shown. While a terminal waits for input on Unix, their output replaces
+    /// the prompt, which is shown again below it.
+    Schedule,
+    /// `watch [--diff] <interval> <command>` - runs a command every interval
+    /// (e.g. `2s`) and draws its output over the output of the previous run
+    /// until a key is pressed, with `--diff` marking the lines that changed
+    /// since the previous run, see
+    /// [`Context::diff_with_previous`](crate::Context::diff_with_previous)
+    ///
+    /// The command only runs once if the shell does not write to a terminal.
+    Watch,
+    /// `retry <attempts> [--backoff <interval>] <command>` - runs a command
+    /// again while it fails with an error that may be transient, up to the
+    /// number of attempts, waiting the interval (doubled after every attempt)
+    /// in between, see [`RetryPolicy`](crate::RetryPolicy)
+    Retry,
+    /// `source <file>` - runs the script in the file in the current session,
+    /// see [`Shell::run_script_file`](crate::Shell::run_script_file)
+    Source,
+    /// `exit [status]` - ends the session of [`Shell::run`](crate::Shell::run)
+    /// or the script that is running with the status or the status of the
+    /// previous command
+    Exit,
+    /// `trap <command> exit | trap - exit` - registers a command that runs
+    /// when the script ends, even if it fails, exits or is interrupted with
+    /// Ctrl-C, or removes the registered commands
+    ///
+    /// The commands run in the reverse order of their registration. The
+    /// command is usually quoted, e.g. `trap "disconnect" exit`.
+    Trap,
+    /// `cache [list | clear [<command>]]` - lists the results of cached
+    /// commands or forgets them, see
+    /// [`Command::with_cache`](crate::Command::with_cache)
+    Cache,
+    /// `calc <expression>` - evaluates an arithmetic or string expression
+    /// that can refer to the variables of the shell, see [`crate::calc`]
+    ///
+    /// The arguments are joined with spaces, so string literals have to be
+    /// quoted twice (e.g. `calc '"a" + name'`).
+    #[cfg(feature = "calc")]
+    Calc,
+}
+
+impl Builtin {
+    /// All builtins
+    pub const ALL: &'static [Builtin] = &[
+        Self::Help,
+        Self::History,
+        Self::Stats,
+        Self::Undo,
+        Self::Profile,
+        Self::Cd,
+        Self::Pwd,
+        Self::Tutorial,
+        Self::Set,
+        Self::Persist,
+        Self::Unpersist,
+        Self::Define,
+        Self::Use,
+        Self::Capabilities,
+        #[cfg(unix)]
+        Self::Sessions,
+        Self::Schedule,
+        Self::Watch,
+        Self::Retry,
+        Self::Source,
+        Self::Exit,
+        Self::Trap,
+        Self::Cache,
+        #[cfg(feature = "calc")]
+        Self::Calc,
+    ];
+
+    /// Returns the builtin that is called with the given name
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|b| b.name() == name)
+    }
+
+    /// The name the user has to type to call the builtin
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Help => "help",
+            Self::History => "history",
+            Self::Stats => "stats",
+            Self::Undo => "undo",
+            Self::Profile => "profile",
+            Self::Cd => "cd",
+            Self::Pwd => "pwd",
+            Self::Tutorial => "tutorial",
+            Self::Set => "set",
+            Self::Persist => "persist",
+            Self::Unpersist => "unpersist",
+            Self::Define => "define",
+            Self::Use => "use",
+            Self::Capabilities => "capabilities",
+            #[cfg(unix)]
+            Self::Sessions => "sessions",
+            Self::Schedule => "schedule",
+            Self::Watch => "watch",
+            Self::Retry => "retry",
+            Self::Source => "source",
+            Self::Exit => "exit",
+            Self::Trap => "trap",
+            Self::Cache => "cache",
+            #[cfg(feature = "calc")]
+            Self::Calc => "calc",
+        }
+    }
+
+    pub(crate) fn invoke(&self, arguments: &[String], ctx: &Context) -> Result<(), CommandError> {
+        match self {
+            Self::Help => help(arguments, ctx),
+            Self::History => history(arguments, ctx),
+            Self::Stats => stats(ctx),
+            Self::Undo => undo(ctx),
+            Self::Profile => profile(arguments, ctx),
+            Self::Cd => cd(arguments, ctx),
+            Self::Pwd => {
+                writeln!(ctx.out(), "{}", ctx.working_dir().display())?;
+                Ok(())
+            }
+            Self::Tutorial => tutorial(arguments, ctx),
+            Self::Set => set(arguments, ctx),
+            Self::Persist => match arguments {
+                [name] => ctx.shell.persist_var(name),
+                _ => Err(CommandError::Usage(ctx.messages().persist_usage.clone())),
+            },
+            Self::Unpersist => match arguments {
+                [name] => ctx.shell.unpersist_var(name),
+                _ => Err(CommandError::Usage(ctx.messages().unpersist_usage.clone())),
+            },
+            Self::Define => match arguments {
+                [name, body @ ..] if !body.is_empty() => {
+                    ctx.shell.define_command(name, &body.join(" "))
+                }
+                _ => Err(CommandError::Usage(ctx.messages().define_usage.clone())),
+            },
+            Self::Use => match arguments {
+                [] => ctx.shell.use_namespace(None),
+                [namespace] => ctx.shell.use_namespace(Some(namespace)),
+                _ => Err(CommandError::Usage(ctx.messages().use_usage.clone())),
+            },
+            Self::Capabilities => {
+                writeln!(ctx.out(), "{}", ctx.shell.capabilities())?;
+                Ok(())
+            }
+            #[cfg(unix)]
+            Self::Sessions => crate::server::sessions(arguments, ctx),
+            Self::Schedule => crate::schedule::schedule(arguments, ctx),
+            Self::Watch => crate::watch::watch(arguments, ctx),
+            Self::Retry => crate::retry::retry(arguments, ctx),
+            Self::Source => match arguments {
+                [file] => ctx.shell.run_script_file(file),
+                _ => Err(CommandError::Usage(ctx.messages().source_usage.clone())),
+            },
+            Self::Exit => {
+                let usage = || CommandError::Usage(ctx.messages().exit_usage.clone());
+                let status = match arguments {
+                    [] => ctx.shell.last_status.get(),
+                    [status] => status.parse().map_err(|_| usage())?,
+                    _ => return Err(usage()),
+                };
+                ctx.shell.exit_status.set(Some(status));
+                Ok(())
+            }
+            Self::Trap => crate::script::trap(arguments, ctx),
+            Self::Cache => crate::cache::cache(arguments, ctx),
+            #[cfg(feature = "calc")]
+            Self::Calc => {
+                let value = crate::calc::evaluate(&arguments.join(" "), |name| ctx.var(name))
+                    .map_err(CommandError::Custom)?;
+                writeln!(ctx.out(), "{}", value)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+fn help(arguments: &[String], ctx: &Context) -> Result<(), CommandError> {
+    let mut commands = ctx.commands();
+    let mut out = ctx.out();
+    let locale = ctx.shell.locale();
+    match arguments {
+        [] => list_namespaces(&commands, locale, &mut out)?,
+        [flag] if flag == "--short" => {
+            let names: Vec<&str> = commands.iter().map(|c| c.name.as_str()).collect();
+            ctx.print_columns(&names)?;
+        }
+        [flag, term @ ..] if flag == "--search" && !term.is_empty() => {
+            let term = term.join(" ").to_lowercase();
+            let matches = |text: &str| text.to_lowercase().contains(&term);
+            commands.retain(|c| {
+                matches(&c.name)
+                    || matches(c.description_in(locale))
+                    || c.examples
+                        .iter()
+                        .any(|e| matches(&e.invocation) || matches(&e.explanation))
+            });
+            if commands.is_empty() {
+                writeln!(out, "{}", ctx.messages().no_matches)?;
+            }
+            list_commands(&commands, locale, &mut out)?;
+        }
+        [name] => {
+            let resolved = ctx.shell.resolve_name(name);
+            let Some(cmd) = commands.iter().find(|c| c.name == resolved) else {
+                commands.retain(|c| c.in_namespace(name));
+                if commands.is_empty() {
+                    return Err(CommandError::NotFound);
+                }
+                return list_commands(&commands, locale, &mut out);
+            };
+            writeln!(out, "{} - {}", cmd.name, cmd.description_in(locale))?;
+            if !cmd.examples.is_empty() {
+                writeln!(out, "\n{}:", ctx.messages().examples)?;
+                for example in &cmd.examples {
+                    writeln!(
+                        out,
+                        "  {}\n      {}",
+                        example.invocation, example.explanation
+                    )?;
+                }
+            }
+        }
+        _ => return Err(CommandError::Usage(ctx.messages().help_usage.clone())),
+    }
+    Ok(())
+}
+
+/// Lists the commands without a namespace followed by the commands of every
+/// namespace under a heading
+fn list_namespaces(
+    commands: &[&Command],
+    locale: Option<&str>,
+    out: &mut impl Write,
+) -> Result<(), CommandError> {
+    let mut namespaces: Vec<Option<&str>> = commands.iter().map(|c| c.namespace()).collect();
+    namespaces.sort();
+    namespaces.dedup();
+    for (i, namespace) in namespaces.into_iter().enumerate() {
+        let members: Vec<&Command> = commands
+            .iter()
+            .copied()
+            .filter(|c| c.namespace() == namespace)
+            .collect();
+        if let Some(namespace) = namespace {
+            if i > 0 {
+                writeln!(out)?;
+            }
+            writeln!(out, "{}:", namespace)?;
+        }
+        list_commands(&members, locale, out)?;
+    }
+    Ok(())
+}
+
+fn list_commands(
+    commands: &[&Command],
+    locale: Option<&str>,
+    out: &mut impl Write,
+) -> Result<(), CommandError> {
+    let width = commands
+        .iter()
+        .map(|c| c.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    for cmd in commands {
+        writeln!(out, "{:width$}  {}", cmd.name, cmd.description_in(locale))?;
+    }
+    Ok(())
+}
+
+fn tutorial(arguments: &[String], ctx: &Context) -> Result<(), CommandError> {
+    let shell = ctx.shell;
+    let messages = ctx.messages();
+    let tutorial = shell
+        .tutorial
+        .as_ref()
+        .ok_or_else(|| CommandError::Custom(messages.no_tutorial.clone()))?;
+    match arguments {
+        [] => {}
+        [reset] if reset == "reset" => {
+            *shell.tutorial_step.borrow_mut() = 0;
+            return Ok(());
+        }
+        _ => return Err(CommandError::Usage(messages.tutorial_usage.clone())),
+    }
+
+    writeln!(ctx.out(), "{}", messages.tutorial_pause)?;
+    let total = tutorial.steps.len();
+    loop {
+        let current = *shell.tutorial_step.borrow();
+        let Some(step) = tutorial.steps.get(current) else {
+            break;
+        };
+        writeln!(
+            ctx.out(),
+            "{} {}/{}: {}",
+            messages.tutorial_step,
+            current + 1,
+            total,
+            step.instruction
+        )?;
+
+        let line = ctx.read_line(&shell.prompt())?.unwrap_or_default();
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+        let outcome = shell.process_line(&line);
+        if let Err(e) = &outcome.result {
+            shell.report_error(e);
+        }
+        if (step.validate)(&outcome) {
+            *shell.tutorial_step.borrow_mut() += 1;
+        } else {
+            writeln!(ctx.out(), "{}", messages.tutorial_retry)?;
+        }
+    }
+    writeln!(ctx.out(), "{}", messages.tutorial_done)?;
+    Ok(())
+}
+
+fn history(arguments: &[String], ctx: &Context) -> Result<(), CommandError> {
+    match arguments {
+        [] => print_history(ctx, false)?,
+        [flag] if flag == "--with-time" => print_history(ctx, true)?,
+        #[cfg(feature = "regex")]
+        [scrub, pattern] if scrub == "scrub" => {
+            let pattern =
+                regex::Regex::new(pattern).map_err(|e| CommandError::Custom(e.to_string()))?;
+            let removed = ctx.shell.history.borrow_mut().scrub(&pattern)?;
+            writeln!(ctx.out(), "{}: {}", ctx.messages().scrubbed, removed)?;
+        }
+        _ => return Err(CommandError::Usage(ctx.messages().history_usage.clone())),
+    }
+    Ok(())
+}
+
+fn print_history(ctx: &Context, with_time: bool) -> Result<(), CommandError> {
+    let history = ctx.shell.history_entries();
+    let width = history.len().to_string().len();
+    let mut out = ctx.out();
+    for (i, entry) in history.iter().enumerate() {
+        if with_time {
+            let time = entry.format_time();
+            let time = time.as_deref().unwrap_or("-");
+            writeln!(out, "{:>width$}  {:19}  {}", i + 1, time, entry.line)?;
+        } else {
+            writeln!(out, "{:>width$}  {}", i + 1, entry.line)?;
+        }
+    }
+    Ok(())
+}
+
+fn set(arguments: &[String], ctx: &Context) -> Result<(), CommandError> {
+    match arguments {
+        [] => {
+            let mut out = ctx.out();
+            for (name, value) in ctx.shell.vars() {
+                writeln!(out, "{}={}", name, value)?;
+            }
+        }
+        [name, value @ ..] if !value.is_empty() => ctx.set_var(name, &value.join(" ")),
+        [flags] if flags.len() > 1 && flags.starts_with(['-', '+']) => {
+            let enable = flags.starts_with('-');
+            let mut options = ctx.shell.script_options.get();
+            for flag in flags[1..].chars() {
+                match flag {
+                    'e' => options.errexit = enable,
+                    'u' => options.nounset = enable,
+                    _ => return Err(CommandError::Usage(ctx.messages().set_usage.clone())),
+                }
+            }
+            ctx.shell.script_options.set(options);
+        }
+        _ => return Err(CommandError::Usage(ctx.messages().set_usage.clone())),
+    }
+    Ok(())
+}
+
+fn stats(ctx: &Context) -> Result<(), CommandError> {
+    let metrics = ctx.shell.metrics();
+    let [command, calls, errors, time] = &ctx.shell.messages.stats_header;
+    let width = metrics
+        .iter()
+        .map(|(name, _)| name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(command.chars().count());
+
+    let mut out = ctx.out();
+    writeln!(
+        out,
+        "{:width$}  {:>8}  {:>8}  {:>12}",
+        command, calls, errors, time
+    )?;
+    for (name, m) in metrics.iter() {
+        writeln!(
+            out,
+            "{:width$}  {:>8}  {:>8}  {:>12}",
+            name,
+            m.invocations,
+            m.errors,
+            if ctx.deterministic_output() {
+                "-".to_string()
+            } else {
+                format!("{:.3?}", m.total_duration)
+            },
+        )?;
+    }
+    Ok(())
+}
+
+fn undo(ctx: &Context) -> Result<(), CommandError> {
+    let messages = ctx.messages();
+    let entry = ctx.shell.undo_stack.borrow_mut().pop();
+    match entry {
+        Some(entry) => {
+            (entry.action)(ctx)?;
+            writeln!(ctx.out(), "{}: {}", messages.undone, entry.description)?;
+            Ok(())
+        }
+        None => Err(CommandError::Custom(messages.nothing_to_undo.clone())),
+    }
+}
+
+fn profile(arguments: &[String], ctx: &Context) -> Result<(), CommandError> {
+    let shell = ctx.shell;
+    let mut out = ctx.out();
+    match arguments {
+        [] => match shell.active_profile() {
+            Some(profile) => writeln!(out, "{}", profile.name)?,
+            None => writeln!(out, "{}", ctx.messages().no_profile)?,
+        },
+        [list] if list == "list" => {
+            let active = shell.active_profile().map(|p| &p.name);
+            for profile in &shell.profiles {
+                let marker = if Some(&profile.name) == active {
+                    '*'
+                } else {
+                    ' '
+                };
+                writeln!(out, "{} {}", marker, profile.name)?;
+            }
+        }
+        [command, name] if command == "use" => shell.use_profile(name)?,
+        _ => return Err(CommandError::Usage(ctx.messages().profile_usage.clone())),
+    }
+    Ok(())
+}
+
+fn cd(arguments: &[String], ctx: &Context) -> Result<(), CommandError> {
+    let shell = ctx.shell;
+    match arguments {
+        [] => {
+            let home = std::env::var("HOME")
+                .map_err(|_| CommandError::Custom(ctx.messages().no_home.clone()))?;
+            shell.set_working_dir(home)
+        }
+        [previous] if previous == "-" => {
+            let previous = shell.previous_dir.borrow().clone();
+            match previous {
+                Some(dir) => {
+                    shell.set_working_dir(dir)?;
+                    writeln!(ctx.out(), "{}", shell.working_dir().display())?;
+                    Ok(())
+                }
+                None => Err(CommandError::Custom(ctx.messages().no_previous_dir.clone())),
+            }
+        }
+        [dir] => shell.set_working_dir(dir),
+        _ => Err(CommandError::Usage(ctx.messages().cd_usage.clone())),
+    }
+}
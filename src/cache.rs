@@ -0,0 +1,98 @@
+//! The remembered output of commands, see
+//! [`Command::with_cache`](crate::Command::with_cache) and
+//! [`Builtin::Cache`](crate::Builtin::Cache)
+use std::{
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use crate::{CommandError, Context};
+
+/// The results of cached commands that have been stored in a shell
+#[derive(Default)]
+pub(crate) struct Cache {
+    entries: Vec<Entry>,
+}
+
+struct Entry {
+    command: String,
+    arguments: Vec<String>,
+    output: Vec<u8>,
+    expires: Instant,
+}
+
+impl Cache {
+    /// Returns the output of the command with the arguments if it has been
+    /// stored and has not expired yet
+    pub(crate) fn lookup(&mut self, command: &str, arguments: &[String]) -> Option<Vec<u8>> {
+        self.remove_expired(Instant::now());
+        self.entries
+            .iter()
+            .find(|e| e.command == command && e.arguments == arguments)
+            .map(|e| e.output.clone())
+    }
+
+    /// Stores the output of the command with the arguments for `ttl`
+    pub(crate) fn store(
+        &mut self,
+        command: &str,
+        arguments: &[String],
+        output: Vec<u8>,
+        ttl: Duration,
+    ) {
+        self.entries
+            .retain(|e| !(e.command == command && e.arguments == arguments));
+        self.entries.push(Entry {
+            command: command.to_string(),
+            arguments: arguments.to_vec(),
+            output,
+            expires: Instant::now() + ttl,
+        });
+    }
+
+    fn remove_expired(&mut self, now: Instant) {
+        self.entries.retain(|e| e.expires > now);
+    }
+}
+
+/// Runs the `cache` builtin
+pub(crate) fn cache(arguments: &[String], ctx: &Context) -> Result<(), CommandError> {
+    let mut cache = ctx.shell.cache.borrow_mut();
+    match arguments {
+        [] => list(&mut cache, ctx)?,
+        [argument] if argument == "list" => list(&mut cache, ctx)?,
+        [clear] if clear == "clear" => cache.entries.clear(),
+        [clear, command] if clear == "clear" => cache.entries.retain(|e| e.command != *command),
+        _ => return Err(CommandError::Usage(ctx.messages().cache_usage.clone())),
+    }
+    Ok(())
+}
+
+/// Prints the cached invocations with the seconds until they expire
+fn list(cache: &mut Cache, ctx: &Context) -> Result<(), CommandError> {
+    let now = Instant::now();
+    cache.remove_expired(now);
+    let lines: Vec<String> = cache
+        .entries
+        .iter()
+        .map(|e| {
+            std::iter::once(&e.command)
+                .chain(&e.arguments)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect();
+    let width = lines.iter().map(|l| l.len()).max().unwrap_or(0);
+    for (line, entry) in lines.iter().zip(&cache.entries) {
+        let remaining = entry.expires.saturating_duration_since(now).as_secs_f64();
+        writeln!(
+            ctx.out(),
+            "{:width$}  {} {}s",
+            line,
+            ctx.messages().cache_expires_in,
+            remaining.ceil() as u64
+        )?;
+    }
+    Ok(())
+}
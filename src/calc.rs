@@ -0,0 +1,377 @@
+//! A small, sandboxed evaluator for arithmetic and string expressions
+//!
+//! Expressions support numbers, strings in double or single quotes,
+//! variables (`name` or `$name`), the operators `+ - * / % ^`, comparisons
+//! (`== != < <= > >=`), parentheses and the functions `abs`, `sqrt`, `round`,
+//! `floor`, `ceil`, `min`, `max`, `len`, `upper` and `lower`. `+` concatenates
+//! if one of its operands is a string.
+//!
+//! # Example
+//! ```rust
+//! use simpleshell::calc::{evaluate, Value};
+//!
+//! let vars = |name: &str| (name == "retries").then(|| "3".to_string());
+//! assert_eq!(evaluate("2 * (retries + 1) ^ 2", vars), Ok(Value::Number(32.0)));
+//! assert_eq!(evaluate("upper('db') + '-' + 1", vars), Ok(Value::Str("DB-1".to_string())));
+//! assert_eq!(evaluate("len('abc') >= retries", vars), Ok(Value::Bool(true)));
+//! ```
+use std::fmt::Display;
+
+/// The result of an expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Str(String),
+    Bool(bool),
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number(n) => write!(f, "{}", n),
+            Self::Str(s) => write!(f, "{}", s),
+            Self::Bool(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+impl Value {
+    fn number(&self) -> Result<f64, String> {
+        match self {
+            Self::Number(n) => Ok(*n),
+            Self::Str(s) => s.parse().map_err(|_| format!("'{}' is not a number", s)),
+            Self::Bool(b) => Ok(if *b { 1.0 } else { 0.0 }),
+        }
+    }
+}
+
+/// Evaluates the expression
+///
+/// `vars` looks up the value of variables. Values that look like numbers are
+/// treated as numbers, all others as strings. Returns a description of the
+/// problem if the expression is invalid.
+pub fn evaluate(expr: &str, vars: impl Fn(&str) -> Option<String>) -> Result<Value, String> {
+    let tokens = lex(expr)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        depth: 0,
+        vars: &vars,
+    };
+    let value = parser.comparison()?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(value),
+        Some(token) => Err(format!("unexpected {:?}", token)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+}
+
+const OPERATORS: [&str; 15] = [
+    "==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "%", "^", "(", ")", ",",
+];
+
+fn lex(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = expr.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else if c == '"' || c == '\'' {
+            let end = rest[1..]
+                .find(c)
+                .ok_or_else(|| "unterminated string".to_string())?;
+            tokens.push(Token::Str(rest[1..end + 1].to_string()));
+            rest = &rest[end + 2..];
+        } else if c.is_ascii_digit() || c == '.' {
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len());
+            let number = rest[..end]
+                .parse()
+                .map_err(|_| format!("invalid number '{}'", &rest[..end]))?;
+            tokens.push(Token::Number(number));
+            rest = &rest[end..];
+        } else if c.is_alphabetic() || c == '_' || c == '$' {
+            let name = rest.strip_prefix('$').unwrap_or(rest);
+            let end = name
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(name.len());
+            if end == 0 {
+                return Err("expected a variable name after '$'".to_string());
+            }
+            tokens.push(Token::Ident(name[..end].to_string()));
+            rest = &name[end..];
+        } else {
+            return Err(format!("unexpected character '{}'", c));
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// How deeply parentheses, function calls and unary operators may be nested
+const MAX_DEPTH: usize = 100;
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// The current nesting of the recursion
+    depth: usize,
+    vars: &'a dyn Fn(&str) -> Option<String>,
+}
+
+impl Parser<'_> {
+    fn eat(&mut self, op: &str) -> bool {
+        if matches!(self.tokens.get(self.pos), Some(Token::Op(o)) if *o == op) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Value, String> {
+        let left = self.additive()?;
+        for op in ["==", "!=", "<=", ">=", "<", ">"] {
+            if self.eat(op) {
+                let right = self.additive()?;
+                let ordering = match (&left, &right) {
+                    (Value::Str(l), Value::Str(r)) => l.partial_cmp(r),
+                    _ => left.number()?.partial_cmp(&right.number()?),
+                };
+                let result = match op {
+                    "==" => ordering.is_some_and(|o| o.is_eq()),
+                    "!=" => !ordering.is_some_and(|o| o.is_eq()),
+                    "<=" => ordering.is_some_and(|o| o.is_le()),
+                    ">=" => ordering.is_some_and(|o| o.is_ge()),
+                    "<" => ordering.is_some_and(|o| o.is_lt()),
+                    _ => ordering.is_some_and(|o| o.is_gt()),
+                };
+                return Ok(Value::Bool(result));
+            }
+        }
+        Ok(left)
+    }
+
+    fn additive(&mut self) -> Result<Value, String> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat("+") {
+                let right = self.term()?;
+                value = match (&value, &right) {
+                    (Value::Str(_), _) | (_, Value::Str(_)) => {
+                        Value::Str(format!("{}{}", value, right))
+                    }
+                    _ => Value::Number(value.number()? + right.number()?),
+                };
+            } else if self.eat("-") {
+                value = Value::Number(value.number()? - self.term()?.number()?);
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Value, String> {
+        let mut value = self.unary()?;
+        loop {
+            let op = ["*", "/", "%"].into_iter().find(|op| self.eat(op));
+            let Some(op) = op else {
+                return Ok(value);
+            };
+            let (left, right) = (value.number()?, self.unary()?.number()?);
+            if op != "*" && right == 0.0 {
+                return Err("division by zero".to_string());
+            }
+            value = Value::Number(match op {
+                "*" => left * right,
+                "/" => left / right,
+                _ => left % right,
+            });
+        }
+    }
+
+    fn unary(&mut self) -> Result<Value, String> {
+        // Every recursion of the parser passes through here
+        self.depth += 1;
+        let value = if self.depth > MAX_DEPTH {
+            Err("expression is nested too deeply".to_string())
+        } else {
+            self.power()
+        };
+        self.depth -= 1;
+        value
+    }
+
+    fn power(&mut self) -> Result<Value, String> {
+        if self.eat("-") {
+            return Ok(Value::Number(-self.unary()?.number()?));
+        }
+        let base = self.primary()?;
+        if self.eat("^") {
+            // Exponentiation is right associative
+            return Ok(Value::Number(base.number()?.powf(self.unary()?.number()?)));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<Value, String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Number(n)) => Ok(Value::Number(n)),
+            Some(Token::Str(s)) => Ok(Value::Str(s)),
+            Some(Token::Op("(")) => {
+                let value = self.comparison()?;
+                if !self.eat(")") {
+                    return Err("expected ')'".to_string());
+                }
+                Ok(value)
+            }
+            Some(Token::Ident(name)) if self.eat("(") => {
+                let mut args = Vec::new();
+                if !self.eat(")") {
+                    loop {
+                        args.push(self.comparison()?);
+                        if self.eat(")") {
+                            break;
+                        }
+                        if !self.eat(",") {
+                            return Err("expected ',' or ')'".to_string());
+                        }
+                    }
+                }
+                call(&name, &args)
+            }
+            Some(Token::Ident(name)) => {
+                let value =
+                    (self.vars)(&name).ok_or_else(|| format!("unknown variable '{}'", name))?;
+                Ok(match value.parse() {
+                    Ok(n) => Value::Number(n),
+                    Err(_) => Value::Str(value),
+                })
+            }
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+fn call(name: &str, args: &[Value]) -> Result<Value, String> {
+    let number = |f: fn(f64) -> f64| match args {
+        [value] => Ok(Value::Number(f(value.number()?))),
+        _ => Err(format!("{} expects one argument", name)),
+    };
+    let text = |f: fn(&str) -> Value| match args {
+        [value] => Ok(f(&value.to_string())),
+        _ => Err(format!("{} expects one argument", name)),
+    };
+    let fold = |f: fn(f64, f64) -> f64| {
+        let mut numbers = args.iter().map(Value::number);
+        let first = numbers
+            .next()
+            .ok_or_else(|| format!("{} expects at least one argument", name))??;
+        numbers
+            .try_fold(first, |acc, n| Ok(f(acc, n?)))
+            .map(Value::Number)
+    };
+
+    match name {
+        "abs" => number(f64::abs),
+        "sqrt" => number(f64::sqrt),
+        "round" => number(f64::round),
+        "floor" => number(f64::floor),
+        "ceil" => number(f64::ceil),
+        "min" => fold(f64::min),
+        "max" => fold(f64::max),
+        "len" => text(|s| Value::Number(s.chars().count() as f64)),
+        "upper" => text(|s| Value::Str(s.to_uppercase())),
+        "lower" => text(|s| Value::Str(s.to_lowercase())),
+        _ => Err(format!("unknown function '{}'", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expr: &str) -> Result<Value, String> {
+        evaluate(expr, |name| match name {
+            "n" => Some("4".to_string()),
+            "host" => Some("db1".to_string()),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn respects_precedence_and_associativity() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(Value::Number(7.0)));
+        assert_eq!(eval("10 - 4 - 3"), Ok(Value::Number(3.0)));
+        assert_eq!(eval("2 ^ 3 ^ 2"), Ok(Value::Number(512.0)));
+        assert_eq!(eval("-2 ^ 2"), Ok(Value::Number(-4.0)));
+        assert_eq!(eval("7 % 4 * $n"), Ok(Value::Number(12.0)));
+    }
+
+    #[test]
+    fn handles_strings() {
+        assert_eq!(eval("host + ':' + 5432"), Ok(Value::Str("db1:5432".into())));
+        assert_eq!(eval("\"it's\""), Ok(Value::Str("it's".into())));
+        assert_eq!(eval("'a' < 'b'"), Ok(Value::Bool(true)));
+        assert_eq!(eval("len('') == 0"), Ok(Value::Bool(true)));
+        assert_eq!(eval("upper('ß')"), Ok(Value::Str("SS".into())));
+    }
+
+    #[test]
+    fn rejects_unterminated_strings() {
+        assert_eq!(eval("'abc"), Err("unterminated string".into()));
+        assert_eq!(eval("\"abc' + 1"), Err("unterminated string".into()));
+        assert_eq!(eval("len('äö"), Err("unterminated string".into()));
+        assert_eq!(eval("'"), Err("unterminated string".into()));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        assert_eq!(eval("1 +"), Err("unexpected end of expression".into()));
+        assert_eq!(eval("(1 + 2"), Err("expected ')'".into()));
+        assert_eq!(eval("1 2"), Err("unexpected Number(2.0)".into()));
+        assert_eq!(eval("1 / 0"), Err("division by zero".into()));
+        assert_eq!(eval("missing"), Err("unknown variable 'missing'".into()));
+        assert_eq!(eval("nope(1)"), Err("unknown function 'nope'".into()));
+        assert_eq!(eval("abs(1, 2)"), Err("abs expects one argument".into()));
+        assert_eq!(eval("$"), Err("expected a variable name after '$'".into()));
+        assert_eq!(eval("1.2.3"), Err("invalid number '1.2.3'".into()));
+    }
+
+    #[test]
+    fn evaluates_nested_expressions() {
+        let nested = format!("{}1{}", "(".repeat(50), ")".repeat(50));
+        assert_eq!(eval(&nested), Ok(Value::Number(1.0)));
+        assert_eq!(eval("max(1, min(abs(-3), 2), 0)"), Ok(Value::Number(2.0)));
+    }
+
+    #[test]
+    fn rejects_deep_nesting() {
+        let too_deep = "(".repeat(100_000);
+        assert_eq!(
+            eval(&too_deep),
+            Err("expression is nested too deeply".into())
+        );
+        let negations = format!("{}1", "-".repeat(100_000));
+        assert_eq!(
+            eval(&negations),
+            Err("expression is nested too deeply".into())
+        );
+        let calls = format!("{}1{}", "abs(".repeat(100_000), ")".repeat(100_000));
+        assert_eq!(eval(&calls), Err("expression is nested too deeply".into()));
+        let powers = vec!["2"; 100_000].join("^");
+        assert_eq!(eval(&powers), Err("expression is nested too deeply".into()));
+    }
+}
@@ -0,0 +1,83 @@
+//! Charts drawn with Unicode block characters, see
+//! [`Context::sparkline`](crate::Context::sparkline) and
+//! [`Context::print_bar_chart`](crate::Context::print_bar_chart)
+
+/// The blocks of a sparkline from the lowest to the highest value
+const LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// The blocks that end a bar, from one to seven eighths of a character
+const EIGHTHS: [char; 7] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
+/// The narrowest bars are drawn with, even if the width is exceeded
+const MIN_BAR_WIDTH: usize = 10;
+
+/// Draws every value as a block whose height is relative to the lowest and
+/// the highest value
+///
+/// Values that are not finite are drawn as spaces.
+pub(crate) fn sparkline(values: &[f64]) -> String {
+    let finite = values.iter().copied().filter(|v| v.is_finite());
+    let min = finite.clone().fold(f64::INFINITY, f64::min);
+    let max = finite.fold(f64::NEG_INFINITY, f64::max);
+    values
+        .iter()
+        .map(|&value| {
+            if !value.is_finite() {
+                ' '
+            } else if max > min {
+                let level = (value - min) / (max - min) * (LEVELS.len() - 1) as f64;
+                LEVELS[level.round() as usize]
+            } else {
+                LEVELS[0]
+            }
+        })
+        .collect()
+}
+
+/// Draws a horizontal bar for every labeled value, scaled so that the
+/// longest bar and its value fit into the width
+///
+/// Negative values and values that are not finite get no bar.
+pub(crate) fn bars<S: AsRef<str>>(items: &[(S, f64)], width: usize) -> Vec<String> {
+    let label_width = items
+        .iter()
+        .map(|(label, _)| label.as_ref().chars().count())
+        .max()
+        .unwrap_or(0);
+    let values: Vec<String> = items.iter().map(|(_, value)| value.to_string()).collect();
+    let value_width = values.iter().map(String::len).max().unwrap_or(0);
+    let bar_width = width
+        .saturating_sub(label_width + value_width + 4)
+        .max(MIN_BAR_WIDTH);
+    let max = items
+        .iter()
+        .map(|(_, value)| *value)
+        .filter(|v| v.is_finite())
+        .fold(0.0, f64::max);
+
+    items
+        .iter()
+        .zip(&values)
+        .map(|((label, value), text)| {
+            let eighths = if max > 0.0 && value.is_finite() && *value > 0.0 {
+                (value / max * (bar_width * 8) as f64).round() as usize
+            } else {
+                0
+            };
+            let mut bar = "█".repeat(eighths / 8);
+            if eighths % 8 > 0 {
+                bar.push(EIGHTHS[eighths % 8 - 1]);
+            }
+            let padding = label_width - label.as_ref().chars().count();
+            format!(
+                "{}{}  {} {}",
+                label.as_ref(),
+                " ".repeat(padding),
+                bar,
+                text
+            )
+            .trim_end()
+            .to_string()
+        })
+        .collect()
+}
@@ -0,0 +1,49 @@
+//! Layout of short items in columns, see
+//! [`Context::print_columns`](crate::Context::print_columns)
+
+/// The spaces between two columns
+const GAP: usize = 2;
+
+/// Arranges the items in as many columns as fit into the width
+///
+/// Like `ls`, the items are sorted top to bottom first and then left to
+/// right. Items that are wider than the width end up in a single column.
+pub(crate) fn layout<S: AsRef<str>>(items: &[S], width: usize) -> Vec<String> {
+    let widths: Vec<usize> = items
+        .iter()
+        .map(|item| item.as_ref().chars().count())
+        .collect();
+
+    let mut rows = items.len().max(1);
+    let mut column_widths = vec![widths.iter().copied().max().unwrap_or(0)];
+    for candidate in 1..items.len() {
+        let candidate_widths: Vec<usize> = widths
+            .chunks(candidate)
+            .map(|column| column.iter().copied().max().unwrap_or(0))
+            .collect();
+        let total = candidate_widths.iter().sum::<usize>() + GAP * (candidate_widths.len() - 1);
+        if total <= width {
+            rows = candidate;
+            column_widths = candidate_widths;
+            break;
+        }
+    }
+
+    (0..rows.min(items.len()))
+        .map(|row| {
+            let mut line = String::new();
+            for (column, column_width) in column_widths.iter().enumerate() {
+                let Some(item) = items.get(column * rows + row) else {
+                    break;
+                };
+                if column > 0 {
+                    line.push_str(&" ".repeat(GAP));
+                }
+                let item = item.as_ref();
+                line.push_str(item);
+                line.push_str(&" ".repeat(column_width - item.chars().count()));
+            }
+            line.trim_end().to_string()
+        })
+        .collect()
+}
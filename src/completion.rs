@@ -0,0 +1,596 @@
+//! Completion of the word before the cursor in a
+//! [`LineEditor`](crate::editor::LineEditor)
+// The shell only completes the lines of WebSocket sessions so far
+#![cfg_attr(not(feature = "websocket"), allow(dead_code))]
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
+    sync::{mpsc, Arc, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::HistoryEntry;
+
+/// A value the word before the cursor can be completed to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// The text that replaces the word
+    pub value: String,
+    /// What the value means, e.g. the description of a command
+    pub description: Option<String>,
+}
+
+impl Candidate {
+    /// Creates a candidate without a description
+    pub fn new(value: &str) -> Self {
+        Candidate {
+            value: value.to_owned(),
+            description: None,
+        }
+    }
+
+    /// Sets the description that is shown next to the value
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_owned());
+        self
+    }
+}
+
+/// The candidates a [`Completer`] found for a word
+#[derive(Debug)]
+pub enum Completion {
+    /// The candidates are known right away
+    Ready(Vec<Candidate>),
+    /// The candidates are looked up in the background and sent on the
+    /// channel. The editor shows a loading indicator and waits for them at
+    /// most for the timeout.
+    Pending {
+        candidates: mpsc::Receiver<Vec<Candidate>>,
+        timeout: Duration,
+    },
+}
+
+/// Finds the candidates for the word before the cursor, which starts after
+/// the last whitespace in front of it
+pub trait Completer {
+    /// Returns the candidates for the word that ends at the byte offset
+    /// `cursor` of the line
+    fn complete(&mut self, line: &str, cursor: usize) -> Completion;
+}
+
+/// The function of [`Command::with_completer`](crate::Command::with_completer)
+pub type CompleteFn = dyn Fn(&[String]) -> Vec<Candidate>;
+
+/// The function of
+/// [`Command::with_async_completer`](crate::Command::with_async_completer)
+pub type AsyncCompleteFn = dyn Fn(&[String]) -> Vec<Candidate> + Send + Sync;
+
+/// Finds the values of the arguments of a [`Command`](crate::Command)
+///
+/// The function receives the arguments before the one that is completed and
+/// returns all values it may have. The shell picks the ones that match the
+/// typed word.
+#[derive(Clone)]
+pub enum ArgumentCompleter {
+    /// Runs on the thread of the shell while the editor waits
+    Blocking(Rc<CompleteFn>),
+    /// Runs on a thread of its own, e.g. to query a server, while the editor
+    /// shows a loading indicator for at most the timeout
+    Async {
+        complete: Arc<AsyncCompleteFn>,
+        timeout: Duration,
+    },
+}
+
+impl std::fmt::Debug for ArgumentCompleter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArgumentCompleter::Blocking(_) => f.write_str("Blocking"),
+            ArgumentCompleter::Async { timeout, .. } => {
+                f.debug_struct("Async").field("timeout", timeout).finish()
+            }
+        }
+    }
+}
+
+/// How the typed word selects the candidates
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Matcher {
+    /// Candidates that start with the word
+    #[default]
+    Prefix,
+    /// Candidates that contain the characters of the word in order, e.g.
+    /// `git-checkout` for `gco`, best matches first
+    ///
+    /// Like in fzf, matches at the start and at word boundaries and runs of
+    /// consecutive characters score higher, and gaps lower. The case is
+    /// ignored unless the word contains an uppercase letter.
+    Fuzzy,
+}
+
+impl Matcher {
+    /// Returns the candidates that match the word, in the order of their
+    /// scores if the matching is fuzzy
+    pub(crate) fn matching(self, candidates: Vec<Candidate>, word: &str) -> Vec<Candidate> {
+        match self {
+            Matcher::Prefix => candidates
+                .into_iter()
+                .filter(|candidate| candidate.value.starts_with(word))
+                .collect(),
+            Matcher::Fuzzy => {
+                let mut scored: Vec<_> = candidates
+                    .into_iter()
+                    .filter_map(|c| Some((fuzzy_score(&c.value, word)?, c)))
+                    .collect();
+                // Stable, so equal scores keep the order of the completer
+                scored.sort_by(|(a, _), (b, _)| b.cmp(a));
+                scored.into_iter().map(|(_, candidate)| candidate).collect()
+            }
+        }
+    }
+}
+
+/// The score of every matched character
+const SCORE_MATCH: i64 = 16;
+/// The bonus for matching the first character of the value
+const BONUS_START: i64 = 10;
+/// The bonus for matching the first character of a word in the value, e.g.
+/// after `-` or in camel case
+const BONUS_BOUNDARY: i64 = 8;
+/// The bonus for matching the character after the previous match, which
+/// keeps runs ahead of matches at scattered boundaries
+const BONUS_CONSECUTIVE: i64 = 8;
+/// The penalty for a gap between two matches
+const PENALTY_GAP_START: i64 = 3;
+/// The penalty for every further character of a gap
+const PENALTY_GAP_EXTENSION: i64 = 1;
+
+/// Returns the score of the best fuzzy match of the word in the value, or
+/// `None` if the value does not contain its characters in order
+fn fuzzy_score(value: &str, word: &str) -> Option<i64> {
+    let ignore_case = !word.chars().any(char::is_uppercase);
+    let normalize = |c: char| match ignore_case {
+        true => c.to_lowercase().next().unwrap_or(c),
+        false => c,
+    };
+    let value: Vec<char> = value.chars().collect();
+    let word: Vec<char> = word.chars().map(normalize).collect();
+    let bonus = |j: usize| match j.checked_sub(1).map(|i| value[i]) {
+        None => BONUS_START,
+        Some(previous) if !previous.is_alphanumeric() => BONUS_BOUNDARY,
+        Some(previous) if previous.is_lowercase() && value[j].is_uppercase() => BONUS_BOUNDARY,
+        Some(_) => 0,
+    };
+
+    // The best scores for the characters of the word so far ending at each
+    // index of the value
+    let mut scores: Vec<Option<i64>> = vec![Some(0); value.len() + 1];
+    for (i, &c) in word.iter().enumerate() {
+        let mut next = vec![None; value.len() + 1];
+        for j in 0..value.len() {
+            if normalize(value[j]) != c {
+                continue;
+            }
+            let best = match i {
+                0 => Some(0),
+                _ => (0..j)
+                    .filter_map(|k| {
+                        let score = scores[k]?;
+                        Some(match k + 1 == j {
+                            true => score + BONUS_CONSECUTIVE,
+                            false => {
+                                score
+                                    - PENALTY_GAP_START
+                                    - PENALTY_GAP_EXTENSION * (j - k - 2) as i64
+                            }
+                        })
+                    })
+                    .max(),
+            };
+            next[j] = best.map(|score| score + SCORE_MATCH + bonus(j));
+        }
+        scores = next;
+    }
+    match word.is_empty() {
+        true => Some(0),
+        false => scores.into_iter().flatten().max(),
+    }
+}
+
+/// The time after which a run of a command counts half as much for its rank
+const FRECENCY_HALF_LIFE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Returns the frecency of the commands in the history, which adds up their
+/// runs weighted by how recent they are
+///
+/// The weight of a run halves with every [`FRECENCY_HALF_LIFE`] that has
+/// passed since. Entries without a timestamp count as if they were that old.
+fn frecency(history: &[HistoryEntry], now: SystemTime) -> HashMap<&str, f64> {
+    let mut scores = HashMap::new();
+    for entry in history {
+        let Some(command) = entry.line.split_whitespace().next() else {
+            continue;
+        };
+        let age = match entry.time {
+            Some(time) => now.duration_since(time).unwrap_or_default(),
+            None => FRECENCY_HALF_LIFE,
+        };
+        let weight = 0.5f64.powf(age.as_secs_f64() / FRECENCY_HALF_LIFE.as_secs_f64());
+        *scores.entry(command).or_insert(0.0) += weight;
+    }
+    scores
+}
+
+/// The completion of a shell for the line that is being entered, see
+/// [`Shell::serve_websocket`](crate::Shell::serve_websocket)
+///
+/// The first word is completed to the commands, the following ones to the
+/// values of the arguments of the command.
+#[derive(Debug, Default)]
+pub(crate) struct ShellCompleter {
+    /// The commands that may be called
+    pub(crate) commands: Vec<Candidate>,
+    /// The arguments of the commands by their names
+    pub(crate) arguments: HashMap<String, Arguments>,
+    /// The values of completers that have a time to live
+    pub(crate) cache: Cache,
+    pub(crate) matcher: Matcher,
+}
+
+/// How the arguments of a command are completed
+#[derive(Debug, Default)]
+pub(crate) struct Arguments {
+    /// The values the arguments at each position may have
+    pub(crate) values: Vec<Vec<String>>,
+    pub(crate) completer: Option<ArgumentCompleter>,
+    /// How long the values of the completer are kept in the cache
+    pub(crate) ttl: Option<Duration>,
+}
+
+impl ShellCompleter {
+    /// Orders the commands by how often and how recently they were run, see
+    /// [`frecency`]
+    ///
+    /// Commands with the same score, e.g. ones that were never run, keep
+    /// their order. A fuzzy [`Matcher`] orders by its scores first.
+    pub(crate) fn rank_commands(&mut self, history: &[HistoryEntry], now: SystemTime) {
+        let scores = frecency(history, now);
+        let score = |candidate: &Candidate| scores.get(candidate.value.as_str()).copied();
+        self.commands
+            .sort_by(|a, b| score(b).unwrap_or(0.0).total_cmp(&score(a).unwrap_or(0.0)));
+    }
+}
+
+impl Completer for ShellCompleter {
+    fn complete(&mut self, line: &str, cursor: usize) -> Completion {
+        let before = &line[..cursor];
+        let mut words: Vec<String> = before.split_whitespace().map(str::to_owned).collect();
+        let word = match before.ends_with(char::is_whitespace) {
+            true => String::new(),
+            false => words.pop().unwrap_or_default(),
+        };
+        let Some((command, arguments)) = words.split_first() else {
+            return Completion::Ready(self.matcher.matching(self.commands.clone(), &word));
+        };
+        let Some(completion) = self.arguments.get(command) else {
+            return Completion::Ready(Vec::new());
+        };
+        if completion.ttl.is_some() {
+            if let Some(cached) = self.cache.get(&words) {
+                return Completion::Ready(self.matcher.matching(cached, &word));
+            }
+        }
+
+        match &completion.completer {
+            Some(ArgumentCompleter::Blocking(complete)) => {
+                let candidates = complete(arguments);
+                if let Some(ttl) = completion.ttl {
+                    self.cache.insert(words.clone(), candidates.clone(), ttl);
+                }
+                Completion::Ready(self.matcher.matching(candidates, &word))
+            }
+            Some(ArgumentCompleter::Async { complete, timeout }) => {
+                let (sender, candidates) = mpsc::channel();
+                let (complete, cache, ttl) = (complete.clone(), self.cache.clone(), completion.ttl);
+                let matcher = self.matcher;
+                thread::spawn(move || {
+                    let candidates = complete(&words[1..]);
+                    // Values that arrive after the timeout are still cached
+                    if let Some(ttl) = ttl {
+                        cache.insert(words, candidates.clone(), ttl);
+                    }
+                    let _ = sender.send(matcher.matching(candidates, &word));
+                });
+                Completion::Pending {
+                    candidates,
+                    timeout: *timeout,
+                }
+            }
+            None => {
+                let values = completion.values.get(arguments.len());
+                let candidates = values
+                    .into_iter()
+                    .flatten()
+                    .map(|value| Candidate::new(value))
+                    .collect();
+                Completion::Ready(self.matcher.matching(candidates, &word))
+            }
+        }
+    }
+}
+
+/// The values of completers by the words in front of the completed one, which
+/// are kept until their time to live passes or they are invalidated
+///
+/// The cache belongs to the session and is shared with the threads of async
+/// completers.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Cache(Arc<Mutex<HashMap<Vec<String>, Cached>>>);
+
+#[derive(Debug)]
+struct Cached {
+    candidates: Vec<Candidate>,
+    expires: Instant,
+}
+
+impl Cache {
+    /// Returns the values for the words unless they have expired
+    fn get(&self, words: &[String]) -> Option<Vec<Candidate>> {
+        let mut entries = self.lock();
+        match entries.get(words) {
+            Some(cached) if cached.expires > Instant::now() => Some(cached.candidates.clone()),
+            Some(_) => {
+                entries.remove(words);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&self, words: Vec<String>, candidates: Vec<Candidate>, ttl: Duration) {
+        let cached = Cached {
+            candidates,
+            expires: Instant::now() + ttl,
+        };
+        self.lock().insert(words, cached);
+    }
+
+    /// Removes the values of the arguments of the command
+    pub(crate) fn invalidate(&self, command: &str) {
+        self.lock()
+            .retain(|words, _| words.first().map(String::as_str) != Some(command));
+    }
+
+    /// Removes all values, e.g. when a new session starts
+    pub(crate) fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<Vec<String>, Cached>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The completer of a session, which the shell replaces before every line
+/// and removes while commands read input
+pub(crate) type CompleterSlot = Rc<RefCell<Option<ShellCompleter>>>;
+
+/// Completes with the completer in the slot, if there is one
+pub(crate) struct SessionCompleter(pub(crate) CompleterSlot);
+
+impl Completer for SessionCompleter {
+    fn complete(&mut self, line: &str, cursor: usize) -> Completion {
+        match self.0.borrow_mut().as_mut() {
+            Some(completer) => completer.complete(line, cursor),
+            None => Completion::Ready(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(completion: Completion) -> Vec<String> {
+        let candidates = match completion {
+            Completion::Ready(candidates) => candidates,
+            Completion::Pending { candidates, .. } => candidates.recv().unwrap(),
+        };
+        candidates.into_iter().map(|c| c.value).collect()
+    }
+
+    fn completer(completer: Option<ArgumentCompleter>) -> ShellCompleter {
+        let arguments = Arguments {
+            values: vec![vec!["start".to_owned(), "stop".to_owned()]],
+            completer,
+            ttl: None,
+        };
+        ShellCompleter {
+            commands: vec![Candidate::new("service"), Candidate::new("set")],
+            arguments: HashMap::from([("service".to_owned(), arguments)]),
+            cache: Cache::default(),
+            matcher: Matcher::Prefix,
+        }
+    }
+
+    #[test]
+    fn completes_commands_and_choices() {
+        let mut completer = completer(None);
+        assert_eq!(values(completer.complete("se", 2)), ["service", "set"]);
+        assert_eq!(
+            values(completer.complete("service s", 9)),
+            ["start", "stop"]
+        );
+        assert_eq!(
+            values(completer.complete("service st", 10)),
+            ["start", "stop"]
+        );
+        assert!(values(completer.complete("service start ", 14)).is_empty());
+        assert!(values(completer.complete("set ", 4)).is_empty());
+    }
+
+    #[test]
+    fn completes_the_word_before_the_cursor() {
+        let mut completer = completer(None);
+        assert_eq!(values(completer.complete("sex", 1)), ["service", "set"]);
+    }
+
+    #[test]
+    fn passes_the_preceding_arguments() {
+        let complete = |arguments: &[String]| vec![Candidate::new(&arguments.join("-"))];
+        let mut blocking = completer(Some(ArgumentCompleter::Blocking(Rc::new(complete))));
+        assert_eq!(values(blocking.complete("service a b ", 12)), ["a-b"]);
+
+        let mut pending = completer(Some(ArgumentCompleter::Async {
+            complete: Arc::new(complete),
+            timeout: Duration::from_secs(1),
+        }));
+        let completion = pending.complete("service a b a", 13);
+        assert!(matches!(completion, Completion::Pending { .. }));
+        assert_eq!(values(completion), ["a-b"]);
+    }
+
+    /// Returns a completer that counts how often its function is called
+    fn counting(ttl: Duration) -> (ShellCompleter, Arc<Mutex<usize>>) {
+        let calls = Arc::new(Mutex::new(0));
+        let counter = calls.clone();
+        let mut completer = completer(Some(ArgumentCompleter::Async {
+            complete: Arc::new(move |_| {
+                *counter.lock().unwrap() += 1;
+                vec![Candidate::new("web"), Candidate::new("db")]
+            }),
+            timeout: Duration::from_secs(1),
+        }));
+        completer.arguments.get_mut("service").unwrap().ttl = Some(ttl);
+        (completer, calls)
+    }
+
+    #[test]
+    fn caches_values_for_their_time_to_live() {
+        let (mut completer, calls) = counting(Duration::from_secs(60));
+        assert_eq!(values(completer.complete("service w", 9)), ["web"]);
+        let completion = completer.complete("service d", 9);
+        assert!(matches!(completion, Completion::Ready(_)));
+        assert_eq!(values(completion), ["db"]);
+        assert_eq!(*calls.lock().unwrap(), 1);
+
+        // Other preceding arguments are looked up again
+        values(completer.complete("service web d", 13));
+        assert_eq!(*calls.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn looks_up_expired_and_invalidated_values_again() {
+        let (mut completer, calls) = counting(Duration::ZERO);
+        values(completer.complete("service w", 9));
+        values(completer.complete("service w", 9));
+        assert_eq!(*calls.lock().unwrap(), 2);
+
+        let (mut completer, calls) = counting(Duration::from_secs(60));
+        values(completer.complete("service w", 9));
+        completer.cache.invalidate("set");
+        values(completer.complete("service w", 9));
+        assert_eq!(*calls.lock().unwrap(), 1);
+        completer.cache.invalidate("service");
+        values(completer.complete("service w", 9));
+        assert_eq!(*calls.lock().unwrap(), 2);
+    }
+
+    fn fuzzy(values: &[&str], word: &str) -> Vec<String> {
+        let candidates = values.iter().map(|v| Candidate::new(v)).collect();
+        let matching = Matcher::Fuzzy.matching(candidates, word);
+        matching.into_iter().map(|c| c.value).collect()
+    }
+
+    #[test]
+    fn matches_the_characters_of_the_word_in_order() {
+        let values = ["config-get", "git-commit", "gcloud", "status"];
+        assert_eq!(fuzzy(&values, "gc"), ["gcloud", "git-commit"]);
+        assert_eq!(fuzzy(&values, "gco"), ["git-commit", "gcloud"]);
+        assert_eq!(fuzzy(&values, "cg"), ["config-get"]);
+        assert!(fuzzy(&values, "tsu").is_empty());
+        assert_eq!(fuzzy(&values, "").len(), 4);
+    }
+
+    #[test]
+    fn prefers_matches_at_boundaries() {
+        assert_eq!(
+            fuzzy(&["showlogs", "showLogs"], "sl"),
+            ["showLogs", "showlogs"]
+        );
+        assert_eq!(fuzzy(&["blast", "last"], "la"), ["last", "blast"]);
+        assert_eq!(fuzzy(&["l-a-s-t", "last"], "last"), ["last", "l-a-s-t"]);
+    }
+
+    #[test]
+    fn ignores_the_case_of_lowercase_words() {
+        assert_eq!(fuzzy(&["ListPods", "lp"], "lp"), ["lp", "ListPods"]);
+        assert_eq!(fuzzy(&["ListPods", "lp"], "LP"), ["ListPods"]);
+    }
+
+    fn entry(line: &str, days_ago: u64) -> HistoryEntry {
+        let age = Duration::from_secs(days_ago * 24 * 60 * 60);
+        HistoryEntry {
+            line: line.to_owned(),
+            time: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1 << 30) - age),
+        }
+    }
+
+    #[test]
+    fn weights_runs_by_their_age() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1 << 30);
+        let history = [
+            entry("status", 0),
+            entry("status web", 7),
+            entry("  stop web", 14),
+            entry("", 0),
+        ];
+        let scores = frecency(&history, now);
+        assert_eq!(scores["status"], 1.5);
+        assert_eq!(scores["stop"], 0.25);
+        assert_eq!(scores.len(), 2);
+
+        let untimed = HistoryEntry {
+            line: "ls".to_owned(),
+            time: None,
+        };
+        assert_eq!(frecency(&[untimed], now)["ls"], 0.5);
+    }
+
+    #[test]
+    fn ranks_frequent_and_recent_commands_first() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1 << 30);
+        let mut completer = completer(None);
+        completer.commands.push(Candidate::new("stop"));
+        completer.rank_commands(&[], now);
+        assert_eq!(
+            values(completer.complete("s", 1)),
+            ["service", "set", "stop"]
+        );
+
+        // Three old runs of `set` count less than two recent ones of `stop`
+        let history = [
+            entry("set a", 30),
+            entry("set b", 30),
+            entry("set c", 30),
+            entry("stop", 1),
+            entry("stop", 0),
+        ];
+        completer.rank_commands(&history, now);
+        assert_eq!(
+            values(completer.complete("s", 1)),
+            ["stop", "set", "service"]
+        );
+    }
+
+    #[test]
+    fn completes_nothing_without_a_completer_in_the_slot() {
+        let slot = CompleterSlot::default();
+        let mut session = SessionCompleter(slot.clone());
+        assert!(values(session.complete("s", 1)).is_empty());
+        slot.replace(Some(completer(None)));
+        assert_eq!(values(session.complete("s", 1)), ["service", "set"]);
+    }
+}
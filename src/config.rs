@@ -0,0 +1,99 @@
+//! Configuration of the shell that end users can change without recompiling
+use std::{fs, io, path::PathBuf};
+
+use serde::Deserialize;
+
+use crate::{expand, Builtin, HistoryPolicy, Shell};
+
+/// The content of a configuration file, see [`Shell::with_config_file`]
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    prompt: Option<String>,
+    transient_prompt: Option<String>,
+    title: Option<String>,
+    colors: Option<bool>,
+    accessible: Option<bool>,
+    locale: Option<String>,
+    builtins: Vec<String>,
+    history: Option<HistoryConfig>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct HistoryConfig {
+    file: Option<PathBuf>,
+    shared: bool,
+    ignore_duplicates: bool,
+    ignore_space: bool,
+    ignore_patterns: Vec<String>,
+    max_length: Option<usize>,
+}
+
+fn invalid(message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Applies the configuration in the TOML file to the shell
+pub(crate) fn apply(mut shell: Shell, content: &str) -> io::Result<Shell> {
+    let config: Config = toml::from_str(content).map_err(invalid)?;
+
+    if let Some(prompt) = config.prompt {
+        shell.prefix = Some(prompt);
+    }
+    if let Some(marker) = config.transient_prompt {
+        shell = shell.with_transient_prompt(&marker);
+    }
+    if let Some(title) = config.title {
+        shell = shell.with_title(&title);
+    }
+    if let Some(colors) = config.colors {
+        shell = shell.with_colors(colors);
+    }
+    if let Some(accessible) = config.accessible {
+        shell = shell.with_accessible_output(accessible);
+    }
+    if let Some(locale) = config.locale {
+        shell = shell.with_locale(&locale);
+    }
+    for name in &config.builtins {
+        let builtin = Builtin::from_name(name)
+            .ok_or_else(|| invalid(format!("unknown builtin '{}'", name)))?;
+        shell = shell.with_builtin(builtin);
+    }
+
+    if let Some(history) = config.history {
+        let mut policy = HistoryPolicy::default()
+            .ignore_duplicates(history.ignore_duplicates)
+            .ignore_space(history.ignore_space);
+        #[cfg(feature = "regex")]
+        for pattern in &history.ignore_patterns {
+            policy = policy.with_ignore_pattern(pattern).map_err(invalid)?;
+        }
+        #[cfg(not(feature = "regex"))]
+        if !history.ignore_patterns.is_empty() {
+            return Err(invalid("ignore_patterns require the regex feature"));
+        }
+        if let Some(length) = history.max_length {
+            policy = policy.with_max_length(length);
+        }
+        shell = shell
+            .with_history_policy(policy)
+            .with_shared_history(history.shared);
+        if let Some(file) = history.file {
+            let file = expand::tilde(&file.to_string_lossy());
+            shell = shell.with_history_file(file)?;
+        }
+    }
+    Ok(shell)
+}
+
+/// Reads the configuration file
+///
+/// A missing file is treated like an empty configuration.
+pub(crate) fn read(path: &std::path::Path) -> io::Result<String> {
+    match fs::read_to_string(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+        result => result,
+    }
+}
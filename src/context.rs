@@ -0,0 +1,580 @@
+//! The context that is handed to a command while it is executed
+use std::{
+    any::TypeId,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    chart, columns, diff::Line, pretty, terminal, tree, undo::UndoEntry, Command, CommandError,
+    FormPrompt, InputPrompt, Messages, MultiSelectPrompt, SelectPrompt, Shell, TreeNode,
+};
+#[cfg(feature = "images")]
+use crate::{image, Image, ImageProtocol};
+
+/// Gives a command access to the shell that executes it
+pub struct Context<'a> {
+    pub(crate) shell: &'a Shell,
+}
+
+impl<'a> Context<'a> {
+    pub(crate) fn new(shell: &'a Shell) -> Self {
+        Context { shell }
+    }
+
+    /// Returns all commands that are available in the shell
+    ///
+    /// Commands that are disabled by the active [`Profile`](crate::Profile)
+    /// are left out.
+    pub fn commands(&self) -> Vec<&'a Command> {
+        self.shell
+            .available_commands
+            .iter()
+            .filter(|c| self.shell.is_available(&c.name))
+            .collect()
+    }
+
+    /// Returns the value of a variable of the session or the active profile
+    pub fn var(&self, name: &str) -> Option<String> {
+        self.shell.var(name)
+    }
+
+    /// Sets a variable of the session
+    pub fn set_var(&self, name: &str, value: &str) {
+        self.shell.set_var(name, value)
+    }
+
+    /// Returns the resource of the type that was provided with
+    /// [`Shell::provide`](crate::Shell::provide)
+    pub fn get<T: 'static>(&self) -> Option<&'a T> {
+        self.shell
+            .resources
+            .get(&TypeId::of::<T>())
+            .and_then(|resource| resource.downcast_ref())
+    }
+
+    /// Returns `true` if the shell is configured to produce reproducible output
+    ///
+    /// Commands should not print colors or timing information in this case.
+    /// See [`Shell::with_deterministic_output`].
+    pub fn deterministic_output(&self) -> bool {
+        self.shell.deterministic_output
+    }
+
+    /// Returns `true` if the output should be friendly to screen readers, see
+    /// [`Shell::with_accessible_output`](crate::Shell::with_accessible_output)
+    pub fn accessible_output(&self) -> bool {
+        self.shell.accessible_output
+    }
+
+    /// Returns `true` if output may contain ANSI colors
+    ///
+    /// This is the case if the shell writes to a terminal or colors were
+    /// enabled with [`Shell::with_colors`](crate::Shell::with_colors), the
+    /// deterministic output mode is disabled and the client supports ANSI
+    /// escape sequences.
+    pub fn colors_enabled(&self) -> bool {
+        self.shell.colors.unwrap_or(self.shell.terminal.get())
+            && !self.shell.deterministic_output
+            && !self.shell.accessible_output
+            && self.shell.output.borrow().stripper.is_none()
+    }
+
+    /// Returns the text as a hyperlink to the URL, which terminals that
+    /// support OSC 8 show as a clickable link
+    ///
+    /// If the shell does not write to a terminal, the client does not
+    /// support escape sequences or the output is deterministic or accessible,
+    /// the URL is written in parentheses after the text instead.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::io::Write;
+    /// # use simpleshell::{testing::TestShell, Command, Shell};
+    /// let docs = Command::new("docs", "Links the documentation", |_, ctx| {
+    ///     let link = ctx.link("the manual", "https://example.com/manual");
+    ///     writeln!(ctx.out(), "See {}", link)?;
+    ///     Ok(())
+    /// });
+    ///
+    /// let mut shell = TestShell::deterministic(Shell::new(None, vec![docs]));
+    /// shell.run("docs").expect_ok();
+    /// assert_eq!(shell.output(), "See the manual (https://example.com/manual)\n");
+    /// ```
+    pub fn link(&self, text: &str, url: &str) -> String {
+        let hyperlinks = self.shell.terminal.get()
+            && !self.shell.deterministic_output
+            && !self.shell.accessible_output
+            && self.shell.output.borrow().stripper.is_none();
+        if hyperlinks {
+            // Control characters would end the sequence early
+            let url: String = url.chars().filter(|c| !c.is_control()).collect();
+            format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+        } else if text == url {
+            text.to_string()
+        } else {
+            format!("{} ({})", text, url)
+        }
+    }
+
+    /// Returns the texts the shell shows to the user
+    pub fn messages(&self) -> &Messages {
+        &self.shell.messages
+    }
+
+    /// Returns the working directory of the session
+    pub fn working_dir(&self) -> PathBuf {
+        self.shell.working_dir()
+    }
+
+    /// Resolves a path relative to the working directory of the session
+    ///
+    /// Commands should resolve path arguments with this function, because the
+    /// working directory of the session may differ from the working directory
+    /// of the process.
+    pub fn resolve_path(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.shell.working_dir.borrow().join(path)
+    }
+
+    /// Shows the prompt and reads a line of input from the user
+    ///
+    /// Returns `None` if the input has been closed.
+    pub fn read_line(&self, prompt: &str) -> io::Result<Option<String>> {
+        self.shell.read_line(prompt)
+    }
+
+    /// Asks the user a question that is answered with a line of text
+    ///
+    /// See [`InputPrompt`] for defaults and validation.
+    pub fn input(&self, prompt: &str) -> InputPrompt<'a> {
+        InputPrompt::new(self.shell, prompt)
+    }
+
+    /// Asks the user to pick one of the items
+    ///
+    /// See [`SelectPrompt`] for an example.
+    pub fn select<'i, S: AsRef<str>>(&self, prompt: &str, items: &'i [S]) -> SelectPrompt<'i, S>
+    where
+        'a: 'i,
+    {
+        SelectPrompt::new(self.shell, prompt, items)
+    }
+
+    /// Asks the user to pick any number of the items
+    ///
+    /// See [`MultiSelectPrompt`] for an example.
+    pub fn multi_select<'i, S: AsRef<str>>(
+        &self,
+        prompt: &str,
+        items: &'i [S],
+    ) -> MultiSelectPrompt<'i, S>
+    where
+        'a: 'i,
+    {
+        MultiSelectPrompt::new(self.shell, prompt, items)
+    }
+
+    /// Asks the user for the fields of a form one after another
+    ///
+    /// See [`FormPrompt`] for an example.
+    pub fn form(&self) -> FormPrompt<'a> {
+        FormPrompt::new(self.shell)
+    }
+
+    /// Invokes another command of the shell with the given arguments
+    ///
+    /// The command goes through the same dispatch path as user input, so
+    /// profiles, confirmations, hooks and events apply and its output ends up
+    /// in the output of the shell.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::io::Write;
+    /// # use simpleshell::{testing::TestShell, Command, Shell};
+    /// let commands = vec![
+    ///     Command::new("greet", "Greets someone", |args, ctx| {
+    ///         writeln!(ctx.out(), "Hello {}", args.join(" "))?;
+    ///         Ok(())
+    ///     }),
+    ///     Command::new("welcome", "Greets everyone", |_, ctx| {
+    ///         ctx.invoke("greet", &["alice"])?;
+    ///         ctx.invoke("greet", &["bob"])
+    ///     }),
+    /// ];
+    ///
+    /// let mut shell = TestShell::deterministic(Shell::new(None, commands));
+    /// shell.run("welcome").expect_ok();
+    /// assert_eq!(shell.output(), "Hello alice\nHello bob\n");
+    /// ```
+    pub fn invoke(&self, command: &str, arguments: &[&str]) -> Result<(), CommandError> {
+        let mut line = vec![command.to_string()];
+        line.extend(arguments.iter().map(|a| a.to_string()));
+        self.shell.dispatch(line).result
+    }
+
+    /// Records an action that reverts the effect of the running command
+    ///
+    /// The most recently recorded action is executed when the user calls the
+    /// [`Builtin::Undo`](crate::Builtin::Undo) command.
+    pub fn push_undo(
+        &self,
+        description: &str,
+        action: impl FnOnce(&Context) -> Result<(), CommandError> + 'static,
+    ) {
+        self.shell.undo_stack.borrow_mut().push(UndoEntry {
+            description: description.to_string(),
+            action: Box::new(action),
+        });
+    }
+
+    /// Returns a writer to the output of the shell
+    ///
+    /// Commands should write their output here instead of using `println!` so
+    /// that it ends up wherever the shell is connected to.
+    pub fn out(&self) -> Output<'a> {
+        Output { shell: self.shell }
+    }
+
+    /// Prints the differences between two texts as a unified diff
+    ///
+    /// Every hunk shows up to three unchanged lines around its changes.
+    /// Removed lines are printed in red and added lines in green if
+    /// [colors are enabled](Self::colors_enabled). Nothing is printed if the
+    /// texts have the same lines.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use simpleshell::{testing::TestShell, Command, Shell};
+    /// let commands = vec![Command::new("config", "Shows the changes", |_, ctx| {
+    ///     ctx.print_diff("port = 80\nhost = a\n", "port = 8080\nhost = a\n")?;
+    ///     Ok(())
+    /// })];
+    ///
+    /// let mut shell = TestShell::deterministic(Shell::new(None, commands));
+    /// shell.run("config").expect_ok();
+    /// assert_eq!(
+    ///     shell.output(),
+    ///     "@@ -1,2 +1,2 @@\n-port = 80\n+port = 8080\n host = a\n"
+    /// );
+    /// ```
+    pub fn print_diff(&self, old: &str, new: &str) -> io::Result<()> {
+        let colors = self.colors_enabled();
+        let mut out = self.out();
+        for hunk in crate::diff::hunks(old, new) {
+            if colors {
+                writeln!(out, "\x1b[36m{}\x1b[0m", hunk.header())?;
+            } else {
+                writeln!(out, "{}", hunk.header())?;
+            }
+            for line in hunk.lines {
+                match (line, colors) {
+                    (Line::Same(line), _) => writeln!(out, " {}", line)?,
+                    (Line::Removed(line), true) => writeln!(out, "\x1b[31m-{}\x1b[0m", line)?,
+                    (Line::Removed(line), false) => writeln!(out, "-{}", line)?,
+                    (Line::Added(line), true) => writeln!(out, "\x1b[32m+{}\x1b[0m", line)?,
+                    (Line::Added(line), false) => writeln!(out, "+{}", line)?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints the text and marks the lines that changed since the previous
+    /// text with the same key, e.g. the last run of a monitoring command
+    ///
+    /// The first text of a key is printed as is. Afterwards every line is
+    /// prefixed like in [`Context::print_diff`]: unchanged lines with a space,
+    /// removed lines with `-` and added lines with `+`, which are printed in
+    /// red and green if [colors are enabled](Self::colors_enabled). The texts
+    /// are kept for the rest of the session.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use simpleshell::{testing::TestShell, Command, Shell};
+    /// let status = Command::new("status", "Shows the state of the services", |args, ctx| {
+    ///     ctx.diff_with_previous("status", &format!("api up\ndb {}\n", args[0]))?;
+    ///     Ok(())
+    /// });
+    ///
+    /// let mut shell = TestShell::deterministic(Shell::new(None, vec![status]));
+    /// shell.run("status up").expect_ok();
+    /// assert_eq!(shell.output(), "api up\ndb up\n");
+    /// shell.run("status down").expect_ok();
+    /// assert_eq!(shell.output(), " api up\n-db up\n+db down\n");
+    /// ```
+    pub fn diff_with_previous(&self, key: &str, text: &str) -> io::Result<()> {
+        let changes = self.changes_since_previous(key, text);
+        write!(self.out(), "{}", changes)
+    }
+
+    /// Returns the text with the changes since the previous text of the key
+    /// marked, see [`Context::diff_with_previous`]
+    pub(crate) fn changes_since_previous(&self, key: &str, text: &str) -> String {
+        let previous = self
+            .shell
+            .previous_outputs
+            .borrow_mut()
+            .insert(key.to_string(), text.to_string());
+        let Some(previous) = previous else {
+            let mut text = text.to_string();
+            if !text.is_empty() && !text.ends_with('\n') {
+                text.push('\n');
+            }
+            return text;
+        };
+
+        let colors = self.colors_enabled();
+        let mut marked = String::new();
+        for line in crate::diff::lines(&previous, text) {
+            let line = match (line, colors) {
+                (Line::Same(line), _) => format!(" {}", line),
+                (Line::Removed(line), true) => format!("\x1b[31m-{}\x1b[0m", line),
+                (Line::Removed(line), false) => format!("-{}", line),
+                (Line::Added(line), true) => format!("\x1b[32m+{}\x1b[0m", line),
+                (Line::Added(line), false) => format!("+{}", line),
+            };
+            marked.push_str(&line);
+            marked.push('\n');
+        }
+        marked
+    }
+
+    /// Prints short items in as many columns as fit on the screen, like `ls`
+    ///
+    /// The width of the terminal is used if the shell writes to one and the
+    /// deterministic output mode is disabled, otherwise 80 characters.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use simpleshell::{testing::TestShell, Command, Shell};
+    /// let commands = vec![Command::new("hosts", "Lists the hosts", |_, ctx| {
+    ///     ctx.print_columns(&["alpha", "beta", "gamma", "delta"])?;
+    ///     Ok(())
+    /// })];
+    ///
+    /// let mut shell = TestShell::deterministic(Shell::new(None, commands));
+    /// shell.run("hosts").expect_ok();
+    /// assert_eq!(shell.output(), "alpha  beta  gamma  delta\n");
+    /// ```
+    pub fn print_columns<S: AsRef<str>>(&self, items: &[S]) -> io::Result<()> {
+        let mut out = self.out();
+        for line in columns::layout(items, self.output_width()) {
+            writeln!(out, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the values as a sparkline like `▁▃▅█▂`, e.g. to show the
+    /// recent history of a metric in a single line
+    ///
+    /// The blocks are scaled between the lowest and the highest value.
+    /// Values that are not finite (e.g. missing samples as `f64::NAN`) are
+    /// shown as spaces.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::io::Write;
+    /// # use simpleshell::{testing::TestShell, Command, Shell};
+    /// let load = Command::new("load", "Shows the recent load", |_, ctx| {
+    ///     writeln!(ctx.out(), "load {}", ctx.sparkline(&[1.0, 2.0, 4.0, 8.0, 3.0]))?;
+    ///     Ok(())
+    /// });
+    ///
+    /// let mut shell = TestShell::deterministic(Shell::new(None, vec![load]));
+    /// shell.run("load").expect_ok();
+    /// assert_eq!(shell.output(), "load ▁▂▄█▃\n");
+    /// ```
+    pub fn sparkline(&self, values: &[f64]) -> String {
+        chart::sparkline(values)
+    }
+
+    /// Prints a horizontal bar with its value for every label
+    ///
+    /// The bars are scaled to the largest value so that the chart fits
+    /// into the same width as [`Context::print_columns`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use simpleshell::{testing::TestShell, Command, Shell};
+    /// let requests = Command::new("requests", "Shows the requests per host", |_, ctx| {
+    ///     ctx.print_bar_chart(&[("alpha", 60.0), ("beta", 20.0)])?;
+    ///     Ok(())
+    /// });
+    ///
+    /// let mut shell = TestShell::deterministic(Shell::new(None, vec![requests]));
+    /// shell.run("requests").expect_ok();
+    /// assert_eq!(
+    ///     shell.output(),
+    ///     format!("alpha  {} 60\nbeta   {} 20\n", "█".repeat(69), "█".repeat(23))
+    /// );
+    /// ```
+    pub fn print_bar_chart<S: AsRef<str>>(&self, bars: &[(S, f64)]) -> io::Result<()> {
+        let mut out = self.out();
+        for line in chart::bars(bars, self.output_width()) {
+            writeln!(out, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the width of the terminal if the shell writes to one and the
+    /// deterministic output mode is disabled, otherwise 80 characters
+    fn output_width(&self) -> usize {
+        if self.shell.terminal.get() && !self.shell.deterministic_output {
+            terminal::width().unwrap_or(80)
+        } else {
+            80
+        }
+    }
+
+    /// Shows the image in the output if the terminal supports images
+    ///
+    /// The protocol is detected when the first image is printed unless it
+    /// was set with
+    /// [`Shell::with_image_protocol`](crate::Shell::with_image_protocol).
+    /// Returns `false` without printing anything if the shell does not write
+    /// to a terminal that shows images, so the command can fall back to text.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::io::Write;
+    /// # use simpleshell::{testing::TestShell, Command, Image, Shell};
+    /// let logo = Command::new("logo", "Shows the logo", |_, ctx| {
+    ///     // A red square
+    ///     let image = Image::from_rgb(8, 8, [255, 0, 0].repeat(64))?;
+    ///     if !ctx.print_image(&image)? {
+    ///         writeln!(ctx.out(), "[logo]")?;
+    ///     }
+    ///     Ok(())
+    /// });
+    ///
+    /// let mut shell = TestShell::deterministic(Shell::new(None, vec![logo]));
+    /// shell.run("logo").expect_ok();
+    /// assert_eq!(shell.output(), "[logo]\n");
+    /// ```
+    #[cfg(feature = "images")]
+    pub fn print_image(&self, image: &Image) -> io::Result<bool> {
+        let shell = self.shell;
+        if !shell.terminal.get()
+            || shell.deterministic_output
+            || shell.accessible_output
+            || shell.output.borrow().stripper.is_some()
+        {
+            return Ok(false);
+        }
+        let encoded = match shell.image_protocol.get_or_init(image::detect) {
+            Some(ImageProtocol::Sixel) => image::sixel(image),
+            Some(ImageProtocol::Iterm2) => image::iterm2(image),
+            None => return Ok(false),
+        };
+        writeln!(self.out(), "{}", encoded)?;
+        Ok(true)
+    }
+
+    /// Prints the node and all nodes below it with branches between them
+    ///
+    /// The branches are drawn in the style configured with
+    /// [`Shell::with_tree_style`](crate::Shell::with_tree_style). See
+    /// [`TreeNode`] for an example.
+    pub fn print_tree(&self, root: &TreeNode) -> io::Result<()> {
+        let mut out = self.out();
+        for line in tree::render(root, self.shell.tree_style) {
+            writeln!(out, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    /// Pretty prints a JSON document with one value per line
+    ///
+    /// Keys, strings, numbers and keywords are highlighted if
+    /// [colors are enabled](Self::colors_enabled) and large arrays are
+    /// collapsed if the shell is configured with
+    /// [`Shell::with_collapsed_arrays`](crate::Shell::with_collapsed_arrays).
+    /// Returns an error of kind [`io::ErrorKind::InvalidData`] if the text is
+    /// not valid JSON.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use simpleshell::{testing::TestShell, Command, Shell};
+    /// let commands = vec![Command::new("status", "Shows the status", |_, ctx| {
+    ///     ctx.print_json(r#"{"name": "web", "ports": [80, 443, 8080]}"#)?;
+    ///     Ok(())
+    /// })];
+    ///
+    /// let shell = Shell::new(None, commands).with_collapsed_arrays(2);
+    /// let mut shell = TestShell::deterministic(shell);
+    /// shell.run("status").expect_ok();
+    /// assert_eq!(
+    ///     shell.output(),
+    ///     "{\n  \"name\": \"web\",\n  \"ports\": [\n    80,\n    443,\n    ... 1 more\n  ]\n}\n"
+    /// );
+    /// ```
+    pub fn print_json(&self, json: &str) -> io::Result<()> {
+        let printed = pretty::json(json, &self.pretty_style())?;
+        self.out().write_all(printed.as_bytes())
+    }
+
+    /// Prints a JSON document as YAML
+    ///
+    /// Highlighting and collapsing work like for [`Context::print_json`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use simpleshell::{testing::TestShell, Command, Shell};
+    /// let commands = vec![Command::new("status", "Shows the status", |_, ctx| {
+    ///     ctx.print_yaml(r#"{"name": "web", "ports": [80, 443], "tls": {"enabled": true}}"#)?;
+    ///     Ok(())
+    /// })];
+    ///
+    /// let mut shell = TestShell::deterministic(Shell::new(None, commands));
+    /// shell.run("status").expect_ok();
+    /// assert_eq!(
+    ///     shell.output(),
+    ///     "name: web\nports:\n  - 80\n  - 443\ntls:\n  enabled: true\n"
+    /// );
+    /// ```
+    pub fn print_yaml(&self, json: &str) -> io::Result<()> {
+        let printed = pretty::yaml(json, &self.pretty_style())?;
+        self.out().write_all(printed.as_bytes())
+    }
+
+    /// Discards the cached completions of the arguments of a command, see
+    /// [`Command::with_completion_ttl`](crate::Command::with_completion_ttl)
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use simpleshell::{completion::Candidate, Command};
+    /// let delete = Command::new("delete", "Deletes a pod", |_, ctx| {
+    ///     // The pods `logs` completes have changed
+    ///     ctx.invalidate_completions("logs");
+    ///     Ok(())
+    /// });
+    /// ```
+    #[cfg(feature = "editor")]
+    pub fn invalidate_completions(&self, command: &str) {
+        self.shell.completion_cache.invalidate(command)
+    }
+
+    fn pretty_style(&self) -> pretty::Style<'_> {
+        pretty::Style {
+            colors: self.colors_enabled(),
+            max_items: self.shell.max_array_items,
+            collapsed: &self.shell.messages.collapsed_items,
+        }
+    }
+}
+
+/// A writer to the output of a [`Shell`]
+pub struct Output<'a> {
+    shell: &'a Shell,
+}
+
+impl Write for Output<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.shell.output.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.shell.output.borrow_mut().flush()
+    }
+}
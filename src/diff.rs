@@ -0,0 +1,235 @@
+//! Line based unified diffs, see [`Context::print_diff`](crate::Context::print_diff)
+
+/// The number of unchanged lines shown around a change
+const CONTEXT: usize = 3;
+
+/// Inputs with more line pairs than this are not aligned, but the differing
+/// middle parts are shown as removed and added as a whole
+const MAX_CELLS: usize = 4_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// A group of changes with the unchanged lines around them
+pub(crate) struct Hunk<'a> {
+    /// The first line of the hunk in the old text, starting at 0
+    pub(crate) old_start: usize,
+    pub(crate) old_len: usize,
+    /// The first line of the hunk in the new text, starting at 0
+    pub(crate) new_start: usize,
+    pub(crate) new_len: usize,
+    pub(crate) lines: Vec<Line<'a>>,
+}
+
+impl Hunk<'_> {
+    /// Returns the header of the hunk, e.g. `@@ -1,4 +1,5 @@`
+    pub(crate) fn header(&self) -> String {
+        let range = |start: usize, len: usize| match len {
+            0 => format!("{},0", start),
+            1 => format!("{}", start + 1),
+            _ => format!("{},{}", start + 1, len),
+        };
+        format!(
+            "@@ -{} +{} @@",
+            range(self.old_start, self.old_len),
+            range(self.new_start, self.new_len)
+        )
+    }
+}
+
+/// Compares the lines of the texts and returns all of them, unchanged or not
+pub(crate) fn lines<'a>(old: &'a str, new: &'a str) -> Vec<Line<'a>> {
+    diff(
+        &old.lines().collect::<Vec<_>>(),
+        &new.lines().collect::<Vec<_>>(),
+    )
+}
+
+/// Compares the lines of the texts and groups the changes into hunks
+pub(crate) fn hunks<'a>(old: &'a str, new: &'a str) -> Vec<Hunk<'a>> {
+    let lines = diff(
+        &old.lines().collect::<Vec<_>>(),
+        &new.lines().collect::<Vec<_>>(),
+    );
+    let changes: Vec<usize> = (0..lines.len())
+        .filter(|&i| !matches!(lines[i], Line::Same(_)))
+        .collect();
+
+    // Changes that are close enough to share their context end up in the same
+    // hunk
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for &i in &changes {
+        match ranges.last_mut() {
+            Some((_, last)) if i - *last <= 2 * CONTEXT => *last = i,
+            _ => ranges.push((i, i)),
+        }
+    }
+
+    ranges
+        .into_iter()
+        .map(|(first, last)| {
+            let start = first.saturating_sub(CONTEXT);
+            let end = (last + 1 + CONTEXT).min(lines.len());
+            let before = &lines[..start];
+            let lines = lines[start..end].to_vec();
+            Hunk {
+                old_start: before
+                    .iter()
+                    .filter(|l| !matches!(l, Line::Added(_)))
+                    .count(),
+                old_len: lines
+                    .iter()
+                    .filter(|l| !matches!(l, Line::Added(_)))
+                    .count(),
+                new_start: before
+                    .iter()
+                    .filter(|l| !matches!(l, Line::Removed(_)))
+                    .count(),
+                new_len: lines
+                    .iter()
+                    .filter(|l| !matches!(l, Line::Removed(_)))
+                    .count(),
+                lines,
+            }
+        })
+        .collect()
+}
+
+/// Aligns the lines with their longest common subsequence
+fn diff<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut lines: Vec<Line> = old[..prefix].iter().map(|l| Line::Same(l)).collect();
+    if old_middle.len() * new_middle.len() > MAX_CELLS {
+        lines.extend(old_middle.iter().map(|l| Line::Removed(l)));
+        lines.extend(new_middle.iter().map(|l| Line::Added(l)));
+    } else {
+        lines.extend(align(old_middle, new_middle));
+    }
+    lines.extend(old[old.len() - suffix..].iter().map(|l| Line::Same(l)));
+    lines
+}
+
+fn align<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    // common[i][j] is the length of the common subsequence of old[i..] and
+    // new[j..]
+    let width = new.len() + 1;
+    let mut common = vec![0usize; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i * width + j] = if old[i] == new[j] {
+                common[(i + 1) * width + j + 1] + 1
+            } else {
+                common[(i + 1) * width + j].max(common[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::with_capacity(old.len() + new.len());
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            lines.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if common[(i + 1) * width + j] >= common[i * width + j + 1] {
+            lines.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+    lines.extend(old[i..].iter().map(|l| Line::Removed(l)));
+    lines.extend(new[j..].iter().map(|l| Line::Added(l)));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(old: &str, new: &str) -> Vec<String> {
+        hunks(old, new).iter().map(Hunk::header).collect()
+    }
+
+    #[test]
+    fn aligns_changed_lines() {
+        assert_eq!(
+            lines("a\nb\nc", "a\nx\nc\nd"),
+            [
+                Line::Same("a"),
+                Line::Removed("b"),
+                Line::Added("x"),
+                Line::Same("c"),
+                Line::Added("d"),
+            ]
+        );
+    }
+
+    #[test]
+    fn identical_texts_have_no_hunks() {
+        assert!(hunks("a\nb", "a\nb").is_empty());
+        assert!(hunks("", "").is_empty());
+    }
+
+    #[test]
+    fn added_to_an_empty_text() {
+        let hunks = hunks("", "a\nb");
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(hunks[0].header(), "@@ -0,0 +1,2 @@");
+        assert_eq!(hunks[0].lines, [Line::Added("a"), Line::Added("b")]);
+        assert_eq!(headers("", "a"), ["@@ -0,0 +1 @@"]);
+    }
+
+    #[test]
+    fn removed_all_lines() {
+        let hunks = hunks("a\nb", "");
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(hunks[0].header(), "@@ -1,2 +0,0 @@");
+        assert_eq!(hunks[0].lines, [Line::Removed("a"), Line::Removed("b")]);
+        assert_eq!(headers("a", ""), ["@@ -1 +0,0 @@"]);
+    }
+
+    #[test]
+    fn shows_context_around_changes() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9";
+        assert_eq!(headers(old, &old.replace('5', "five")), ["@@ -2,7 +2,7 @@"]);
+        assert_eq!(headers(old, &old.replace("1\n", "")), ["@@ -1,4 +1,3 @@"]);
+        assert_eq!(headers(old, &format!("{}\n10", old)), ["@@ -7,3 +7,4 @@"]);
+    }
+
+    #[test]
+    fn separates_distant_changes() {
+        let numbers = |skip: usize, rename: usize| -> String {
+            (1..=20usize)
+                .filter(|&n| n != skip)
+                .map(|n| {
+                    if n == rename {
+                        "changed\n".to_string()
+                    } else {
+                        format!("{}\n", n)
+                    }
+                })
+                .collect()
+        };
+        let old = numbers(0, 0);
+        assert_eq!(
+            headers(&old, &numbers(18, 2)),
+            ["@@ -1,5 +1,5 @@", "@@ -15,6 +15,5 @@"]
+        );
+        assert_eq!(headers(&old, &numbers(8, 2)), ["@@ -1,11 +1,10 @@"]);
+    }
+}
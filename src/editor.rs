@@ -0,0 +1,1988 @@
+//! A line editor for transports that forward the raw keys of a terminal
+//! emulator, e.g. a terminal in a browser
+//!
+//! The shell reads its own input in the line mode of the terminal, which
+//! edits the line itself. Transports that deliver every key as it is pressed
+//! feed the keys to a [`LineEditor`], which turns them into lines and returns
+//! what has to be echoed to the terminal.
+//!
+//! # Example
+//! ```rust
+//! use simpleshell::editor::{Edit, LineEditor};
+//!
+//! let mut editor = LineEditor::new();
+//! let mut echo = String::new();
+//! assert_eq!(editor.feed("versoin", &mut echo), None);
+//! assert_eq!(echo, "versoin");
+//!
+//! // Three backspaces, then enter
+//! let edit = editor.feed("\x7f\x7f\x7fion\r", &mut echo);
+//! assert_eq!(edit, Some(Edit::Line("version".to_string())));
+//! ```
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    ops::Range,
+    path::Path,
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    time::Instant,
+};
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::completion::{Candidate, Completer, Completion};
+
+/// What happened to the line after keys were fed to a [`LineEditor`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Edit {
+    /// Enter was pressed. Contains the line without the line break
+    Line(String),
+    /// `Ctrl-D` was pressed on an empty line
+    Closed,
+}
+
+/// Edits a line with the keys a terminal emulator sends
+///
+/// Typed characters are inserted at the cursor, which the arrow keys, `Home`
+/// and `End` as well as `Ctrl-B`, `Ctrl-F`, `Ctrl-A` and `Ctrl-E` move.
+/// Backspace removes the character before the cursor, `Delete` and `Ctrl-D`
+/// the one under it. Enter completes the line and `Ctrl-D` on an empty line
+/// closes the input.
+///
+/// Like in readline, `Ctrl-K` cuts the text after the cursor, `Ctrl-U` the
+/// text before it and `Ctrl-W` the word before it into a kill ring. Cuts
+/// right after each other are joined. `Ctrl-Y` pastes the last cut and
+/// `Alt-Y` right after it replaces it with the one before. The kill ring is
+/// kept for the following lines.
+///
+/// `Ctrl-_` and `Ctrl-X Ctrl-U` undo the last change of the line, typed
+/// characters right after each other at once, and `Ctrl-X Ctrl-R` redoes
+/// it. Changes can be undone until the line is completed.
+///
+/// `Shift` with the arrow keys, `Home` or `End` selects text, which is shown
+/// in reverse video. Typing replaces the selection, backspace and `Delete`
+/// remove it and `Ctrl-W` cuts it.
+///
+/// `Alt-Enter` starts a new row within the line, which is shown in several
+/// rows and taken as one line with spaces for the row breaks once it is
+/// completed. The up and down arrow keys move the cursor between the rows,
+/// and in the first and last row, like `Ctrl-P` and `Ctrl-N`, go through
+/// the lines that were entered before. With [`LineEditor::with_mouse`], a
+/// click places the cursor and the scroll wheel goes through the entered
+/// lines, too.
+///
+/// `Ctrl-X Ctrl-E` opens the line in an external editor, see
+/// [`LineEditor::with_external_editor`].
+///
+/// With the `clipboard` feature, `Ctrl-Shift-C` or `Alt-W` copies the
+/// selection or the line to the clipboard of the system and `Ctrl-Shift-V`
+/// or `Alt-V` pastes from
+/// it. The clipboard is accessed with OSC 52 sequences, so it works over SSH
+/// and in browser terminals, but only if the terminal allows it. Most
+/// terminals only report `Ctrl-Shift-C` and `Ctrl-Shift-V` when their
+/// keyboard protocol is enabled and otherwise handle them themselves.
+///
+/// Characters are edited as grapheme clusters and take up their display
+/// width, so that wide characters like CJK and emoji, combining marks and
+/// multi-byte UTF-8 keep the line and the cursor intact.
+///
+/// Lines longer than the width of the terminal continue in the next rows.
+/// The width is 80 columns until [`LineEditor::resize`] sets it or the
+/// terminal reports it with `CSI 8 ; rows ; columns t`, and the line is
+/// wrapped again when it changes.
+///
+/// With a [`Completer`], `Tab` completes the word before the cursor. A single
+/// candidate replaces it, several ones extend it as far as they agree, and
+/// pressing `Tab` again opens a menu below the line with the candidates and
+/// their descriptions. `Tab` and `Down` select the next candidate, `Shift-Tab`
+/// and `Up` the previous one, `Enter` accepts it, `Ctrl-G` restores the typed
+/// word, and other keys keep the selected candidate and apply as usual.
+#[derive(Debug, Default)]
+pub struct LineEditor {
+    line: String,
+    /// The byte offset of the cursor in the line
+    cursor: usize,
+    /// The byte offset where the selection starts, it reaches to the cursor
+    anchor: Option<usize>,
+    /// The row, counted from the one the line starts in, and the column of
+    /// the cursor of the terminal
+    shown: (usize, usize),
+    /// The width of the terminal, if it is known
+    columns: Option<usize>,
+    /// The escape sequence of a key that has been received partially
+    escape: Option<String>,
+    /// Keys that were received after the end of a line
+    queued: String,
+    /// Set after a carriage return, so that the line feed of a `\r\n` line
+    /// ending is not taken as a second enter
+    after_return: bool,
+    /// The cut texts, the last one is the newest
+    kill_ring: Vec<String>,
+    /// What the previous key did
+    last: Action,
+    /// Set after `Ctrl-X`, which starts a key binding of two keys
+    after_ctrl_x: bool,
+    /// The states of the line before its changes, the last one is the newest
+    undo: Vec<State>,
+    /// The states of the line that were undone, the last one is the newest
+    redo: Vec<State>,
+    /// The entered lines, the last one is the newest
+    history: Vec<String>,
+    /// The index of the entered line that is shown and the line that was
+    /// being edited before
+    browsing: Option<(usize, String)>,
+    /// The column of the terminal the line starts at
+    column: usize,
+    /// The row of the terminal the line starts at, once the terminal reported
+    /// it
+    row: Option<usize>,
+    /// Set while the terminal is asked for the position of the cursor
+    awaiting_position: bool,
+    mouse: bool,
+    external_editor: Option<ExternalEditor>,
+    completer: Option<CompleterBox>,
+    /// The completion that waits for its candidates
+    completing: Option<PendingCompletion>,
+    /// Set while rows are shown below the line
+    below: bool,
+    menu: Option<Menu>,
+}
+
+/// Completes the word before the cursor, see [`LineEditor::with_completer`]
+struct CompleterBox(Box<dyn Completer>);
+
+impl fmt::Debug for CompleterBox {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Completer")
+    }
+}
+
+/// A completion whose candidates are looked up in the background
+#[derive(Debug)]
+struct PendingCompletion {
+    candidates: mpsc::Receiver<Vec<Candidate>>,
+    deadline: Instant,
+    /// The byte offset where the completed word starts
+    start: usize,
+    /// What the key before `Tab` did
+    last: Action,
+}
+
+/// The candidates of a completion that are shown below the line to select
+/// one of them
+#[derive(Debug)]
+struct Menu {
+    /// The byte offset where the completed word starts
+    start: usize,
+    /// The typed word, which is restored when the menu is cancelled
+    word: String,
+    candidates: Vec<Candidate>,
+    selected: usize,
+    /// The index of the first candidate that is shown
+    top: usize,
+}
+
+/// The number of candidates the menu shows at once
+const MENU_ROWS: usize = 10;
+
+/// Edits the text of a line outside of the editor, see
+/// [`LineEditor::with_external_editor`]
+struct ExternalEditor(Box<EditFn>);
+
+/// The function that edits a line in [`LineEditor::with_external_editor`]
+type EditFn = dyn FnMut(&str) -> io::Result<String>;
+
+impl fmt::Debug for ExternalEditor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ExternalEditor")
+    }
+}
+
+/// The number of entered lines the editor keeps
+const HISTORY_SIZE: usize = 1000;
+
+/// The width of the terminal until it is known
+const DEFAULT_COLUMNS: usize = 80;
+
+/// The text and the cursor of a line at some point
+#[derive(Debug)]
+struct State {
+    line: String,
+    cursor: usize,
+}
+
+/// The number of cut texts the kill ring keeps
+const KILL_RING_SIZE: usize = 16;
+
+/// What a key did, for the keys that continue what the previous one did
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    #[default]
+    Other,
+    Insert,
+    Kill,
+    /// The word was completed without selecting one of several candidates
+    Complete,
+    /// The text from the byte offset to the cursor was pasted from the entry
+    /// of the kill ring at the index, counted from the newest one
+    Yank {
+        start: usize,
+        index: usize,
+    },
+}
+
+/// A key that was decoded from the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    Up,
+    Down,
+    /// `Shift-Tab`
+    BackTab,
+    ShiftLeft,
+    ShiftRight,
+    ShiftHome,
+    ShiftEnd,
+    /// A letter pressed together with `Ctrl`
+    Ctrl(char),
+    /// A letter pressed together with `Ctrl` after `Ctrl-X`
+    CtrlX(char),
+    /// A letter pressed together with `Ctrl` and `Shift`, which terminals
+    /// only report in their extended keyboard protocols
+    CtrlShift(char),
+    /// A character pressed together with `Alt`, sent as escape and the
+    /// character
+    Alt(char),
+    /// A mouse event with the button and the 1-based column and row of the
+    /// terminal
+    Mouse {
+        button: u32,
+        column: usize,
+        row: usize,
+        pressed: bool,
+    },
+    /// The report of the 1-based row and column of the cursor
+    Position {
+        row: usize,
+        column: usize,
+    },
+    /// The report of the width of the terminal
+    Resize {
+        columns: usize,
+    },
+    /// An operating system command the terminal sent, e.g. as the answer to
+    /// a query, without the introducer and the terminator
+    Osc(String),
+    /// An escape sequence of a key the editor does not handle
+    Unknown,
+}
+
+/// The length up to which an operating system command is received, longer
+/// ones are dropped
+const MAX_OSC_LENGTH: usize = 1 << 20;
+
+impl LineEditor {
+    /// Creates an editor with an empty line
+    pub fn new() -> Self {
+        LineEditor::default()
+    }
+
+    /// Turns on mouse reporting while a line is edited, so that a click
+    /// places the cursor and the scroll wheel goes through the entered lines
+    ///
+    /// The terminal cannot select text with the mouse while it reports the
+    /// mouse, so this is off by default. Reporting is turned on by
+    /// [`LineEditor::start`] and off when the line is completed.
+    pub fn with_mouse(mut self, enabled: bool) -> Self {
+        self.mouse = enabled;
+        self
+    }
+
+    /// Lets `Ctrl-X Ctrl-E` replace the line with what `edit` returns for it,
+    /// e.g. [`run_external_editor`] to edit it in the editor of the user
+    ///
+    /// Row breaks in the edited text are kept, so that it is shown as it was
+    /// written. If `edit` fails, the line is left as it is and the terminal
+    /// rings its bell.
+    ///
+    /// # Example
+    /// ```rust
+    /// use simpleshell::editor::{Edit, LineEditor};
+    ///
+    /// let mut editor = LineEditor::new().with_external_editor(|line| Ok(line.replace("ls", "ls -la")));
+    /// let mut echo = String::new();
+    /// let edit = editor.feed("ls /tmp\x18\x05\r", &mut echo);
+    /// assert_eq!(edit, Some(Edit::Line("ls -la /tmp".to_string())));
+    /// ```
+    pub fn with_external_editor(
+        mut self,
+        edit: impl FnMut(&str) -> io::Result<String> + 'static,
+    ) -> Self {
+        self.external_editor = Some(ExternalEditor(Box::new(edit)));
+        self
+    }
+
+    /// Lets `Tab` complete the word before the cursor with the candidates of
+    /// the completer
+    ///
+    /// # Example
+    /// ```rust
+    /// use simpleshell::completion::{Candidate, Completer, Completion};
+    /// use simpleshell::editor::{Edit, LineEditor};
+    ///
+    /// struct Commands;
+    ///
+    /// impl Completer for Commands {
+    ///     fn complete(&mut self, line: &str, cursor: usize) -> Completion {
+    ///         let candidates = ["status", "stop"]
+    ///             .into_iter()
+    ///             .filter(|c| c.starts_with(&line[..cursor]))
+    ///             .map(Candidate::new)
+    ///             .collect();
+    ///         Completion::Ready(candidates)
+    ///     }
+    /// }
+    ///
+    /// let mut editor = LineEditor::new().with_completer(Commands);
+    /// let mut echo = String::new();
+    /// let edit = editor.feed("sta\t\r", &mut echo);
+    /// assert_eq!(edit, Some(Edit::Line("status ".to_string())));
+    /// ```
+    pub fn with_completer(mut self, completer: impl Completer + 'static) -> Self {
+        self.completer = Some(CompleterBox(Box::new(completer)));
+        self
+    }
+
+    /// Starts a new line at the column the prompt left the cursor in
+    ///
+    /// With mouse reporting, the terminal is asked for the position of the
+    /// cursor to find the row of the line.
+    pub fn start(&mut self, column: usize, echo: &mut String) {
+        self.column = column;
+        self.shown = (0, column);
+        self.row = None;
+        if self.mouse {
+            echo.push_str("\x1b[?1000h\x1b[?1006h\x1b[6n");
+            self.awaiting_position = true;
+        }
+    }
+
+    /// Sets the width of the terminal and wraps the line again, e.g. after the
+    /// terminal was resized
+    ///
+    /// The rows the line was shown in before are replaced, which assumes that
+    /// the terminal keeps ended rows of the old width as they are.
+    pub fn resize(&mut self, columns: usize, echo: &mut String) {
+        self.columns = Some(columns.max(1));
+        if !self.line.is_empty() {
+            self.refresh(echo);
+        }
+    }
+
+    /// Returns the line that is being edited
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+
+    /// Returns the byte offset of the cursor in the line
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Returns `true` while a completion waits for the candidates of a
+    /// [`Completion::Pending`], see [`LineEditor::wait_for_completion`]
+    pub fn is_completing(&self) -> bool {
+        self.completing.is_some()
+    }
+
+    /// Waits until the candidates of a pending completion arrive or its
+    /// timeout passes and completes the word with them
+    ///
+    /// Keys that are fed while the completion is pending are kept until it
+    /// is done and applied by the next call of [`LineEditor::feed`].
+    pub fn wait_for_completion(&mut self, echo: &mut String) {
+        let Some(completing) = self.completing.take() else {
+            return;
+        };
+        let timeout = completing
+            .deadline
+            .saturating_duration_since(Instant::now());
+        let candidates = completing.candidates.recv_timeout(timeout);
+        // Remove the loading indicator
+        self.refresh(echo);
+        let before = State {
+            line: self.line.clone(),
+            cursor: self.cursor,
+        };
+        match candidates {
+            Ok(candidates) => {
+                self.apply_completion(completing.start, candidates, completing.last, echo)
+            }
+            Err(_) => echo.push('\x07'),
+        }
+        if self.line != before.line {
+            self.undo.push(before);
+            self.redo.clear();
+        }
+    }
+
+    /// Applies the keys and appends what the terminal has to show to `echo`
+    ///
+    /// Returns `None` while the line is being edited. Once a line is
+    /// completed, the keys after it are kept and applied to the next line by
+    /// the following call, so a pasted block or fast typing that arrives at
+    /// once is not lost. Call `feed` with no keys to apply them.
+    pub fn feed(&mut self, keys: &str, echo: &mut String) -> Option<Edit> {
+        let mut pending = std::mem::take(&mut self.queued);
+        pending.push_str(keys);
+        for (i, c) in pending.char_indices() {
+            let Some(key) = self.decode(c) else {
+                continue;
+            };
+            if let Some(edit) = self.apply(key, echo) {
+                self.queued = pending[i + c.len_utf8()..].to_string();
+                return Some(edit);
+            }
+            if self.completing.is_some() {
+                // The keys after Tab apply to the completed word
+                self.queued = pending[i + c.len_utf8()..].to_string();
+                return None;
+            }
+        }
+        None
+    }
+
+    /// Decodes the next character of the input and returns the key once it is
+    /// complete
+    fn decode(&mut self, c: char) -> Option<Key> {
+        let after_return = std::mem::replace(&mut self.after_return, c == '\r');
+        if let Some(sequence) = &mut self.escape {
+            sequence.push(c);
+            let complete = match sequence.as_bytes()[0] {
+                // CSI sequences end with a character in `@`..=`~`
+                b'[' => sequence.len() > 1 && ('@'..='~').contains(&c),
+                b'O' => sequence.len() > 1,
+                // Operating system commands end with BEL or ST
+                b']' => {
+                    c == '\x07' || sequence.ends_with("\x1b\\") || sequence.len() > MAX_OSC_LENGTH
+                }
+                _ => true,
+            };
+            if !complete {
+                return None;
+            }
+            let key = self.escape.take().map(|sequence| escape_key(&sequence));
+            return match key {
+                // Only an awaited report is a position, others are keys like
+                // Shift-F3
+                Some(Key::Position { .. }) if !std::mem::take(&mut self.awaiting_position) => {
+                    Some(Key::Unknown)
+                }
+                key => key,
+            };
+        }
+        Some(match c {
+            '\r' => Key::Enter,
+            '\n' if after_return => return None,
+            '\n' => Key::Enter,
+            '\x7f' | '\x08' => Key::Backspace,
+            '\x1b' => {
+                self.escape = Some(String::new());
+                return None;
+            }
+            // Ctrl-A is 0x01, Ctrl-B 0x02 and so on up to Ctrl-_ as 0x1f
+            c if (c as u32) < 0x20 => Key::Ctrl((c as u8 | 0x40).to_ascii_lowercase() as char),
+            c if c.is_control() => return None,
+            c => Key::Char(c),
+        })
+    }
+
+    /// Applies the key to the line and returns the edit if it ends the line
+    fn apply(&mut self, key: Key, echo: &mut String) -> Option<Edit> {
+        let key = match (std::mem::take(&mut self.after_ctrl_x), key) {
+            (false, Key::Ctrl('x')) => {
+                self.after_ctrl_x = true;
+                return None;
+            }
+            (true, Key::Ctrl(c)) => Key::CtrlX(c),
+            // Other keys after Ctrl-X do not form a binding
+            (true, _) => return None,
+            (false, key) => key,
+        };
+        if self.menu.is_some() && self.select(&key, echo) {
+            return None;
+        }
+        self.clear_below(echo);
+        let undoing = matches!(key, Key::Ctrl('_') | Key::CtrlX('u' | 'r'));
+        let last = std::mem::take(&mut self.last);
+        let before = State {
+            line: self.line.clone(),
+            cursor: self.cursor,
+        };
+        let selection = self.selection();
+        let anchor = self.anchor.take();
+        let edited_selection = match selection {
+            Some(range) => self.edit_selection(&key, range, last, echo),
+            None => false,
+        };
+        match key {
+            _ if edited_selection => {}
+            Key::ShiftLeft | Key::ShiftRight | Key::ShiftHome | Key::ShiftEnd => {
+                self.anchor = Some(anchor.unwrap_or(self.cursor));
+                self.cursor = match key {
+                    Key::ShiftLeft => self.cursor - self.previous_grapheme().unwrap_or(0),
+                    Key::ShiftRight => self.cursor + self.next_grapheme().unwrap_or(0),
+                    Key::ShiftHome => 0,
+                    _ => self.line.len(),
+                };
+                self.refresh(echo);
+            }
+            Key::Char(c) => {
+                self.insert(c.encode_utf8(&mut [0; 4]), echo);
+                self.last = Action::Insert;
+            }
+            Key::Enter => return Some(Edit::Line(self.finish(echo))),
+            Key::Ctrl('d') if self.line.is_empty() => {
+                self.stop_mouse(echo);
+                return Some(Edit::Closed);
+            }
+            Key::Alt('\r') => self.insert("\n", echo),
+            Key::Up | Key::Down => match self.row_change(key == Key::Up) {
+                Some(cursor) => self.move_to(cursor, echo),
+                None => self.browse(key == Key::Up, echo),
+            },
+            Key::Ctrl('p') | Key::Mouse { button: 64, .. } => self.browse(true, echo),
+            Key::Ctrl('n') | Key::Mouse { button: 65, .. } => self.browse(false, echo),
+            Key::Mouse {
+                button: 0,
+                column,
+                row,
+                pressed: true,
+            } if self.row.is_some_and(|start| row > start) => {
+                let start = self.row.unwrap_or_default();
+                let cursor = self.offset_at((row - 1 - start, column - 1));
+                self.move_to(cursor, echo);
+            }
+            Key::Position { row, .. } => self.row = Some(row - 1),
+            Key::Resize { columns } => self.resize(columns, echo),
+            Key::Backspace => {
+                if let Some(previous) = self.previous_grapheme() {
+                    self.cursor -= previous;
+                    self.line
+                        .replace_range(self.cursor..self.cursor + previous, "");
+                    self.refresh(echo);
+                }
+            }
+            Key::Delete | Key::Ctrl('d') => {
+                if let Some(next) = self.next_grapheme() {
+                    self.line.replace_range(self.cursor..self.cursor + next, "");
+                    self.refresh(echo);
+                }
+            }
+            Key::Left | Key::Ctrl('b') => {
+                if let Some(previous) = self.previous_grapheme() {
+                    self.move_to(self.cursor - previous, echo);
+                }
+            }
+            Key::Right | Key::Ctrl('f') => {
+                if let Some(next) = self.next_grapheme() {
+                    self.move_to(self.cursor + next, echo);
+                }
+            }
+            Key::Home | Key::Ctrl('a') => self.move_to(0, echo),
+            Key::End | Key::Ctrl('e') => self.move_to(self.line.len(), echo),
+            Key::Ctrl('k') => self.kill(self.cursor..self.line.len(), false, last, echo),
+            Key::Ctrl('u') => self.kill(0..self.cursor, true, last, echo),
+            Key::Ctrl('w') => {
+                let start = word_start(&self.line[..self.cursor]);
+                self.kill(start..self.cursor, true, last, echo);
+            }
+            Key::Ctrl('y') => {
+                if let Some(text) = self.kill_ring.last().cloned() {
+                    let start = self.cursor;
+                    self.insert(&text, echo);
+                    self.last = Action::Yank { start, index: 0 };
+                }
+            }
+            Key::Alt('y') => {
+                if let Action::Yank { start, index } = last {
+                    let index = (index + 1) % self.kill_ring.len();
+                    let text = self.kill_ring[self.kill_ring.len() - 1 - index].clone();
+                    self.replace(start..self.cursor, &text, echo);
+                    self.last = Action::Yank { start, index };
+                }
+            }
+            // Tab is sent as Ctrl-I
+            Key::Ctrl('i') => self.complete(last, echo),
+            Key::Ctrl('_') | Key::CtrlX('u') => self.undo(false, echo),
+            Key::CtrlX('r') => self.undo(true, echo),
+            Key::CtrlX('e') => {
+                let edited = match &mut self.external_editor {
+                    Some(ExternalEditor(edit)) => edit(&self.line),
+                    None => return None,
+                };
+                match edited {
+                    Ok(text) => {
+                        let text: String = text
+                            .trim_end_matches(['\r', '\n'])
+                            .replace("\r\n", "\n")
+                            .chars()
+                            .map(|c| if c.is_control() && c != '\n' { ' ' } else { c })
+                            .collect();
+                        self.replace(0..self.line.len(), &text, echo);
+                    }
+                    Err(_) => echo.push('\x07'),
+                }
+            }
+            #[cfg(feature = "clipboard")]
+            Key::CtrlShift('c') | Key::Alt('w') => copy(&self.line, echo),
+            // Ask the terminal for the content of the clipboard
+            #[cfg(feature = "clipboard")]
+            Key::CtrlShift('v') | Key::Alt('v') => echo.push_str("\x1b]52;c;?\x07"),
+            #[cfg(feature = "clipboard")]
+            Key::Osc(report) => {
+                if let Some(text) = pasted(&report) {
+                    self.insert(&text, echo);
+                }
+            }
+            _ => {}
+        }
+        if anchor.is_some() && self.anchor.is_none() && self.line == before.line {
+            // Remove the highlighting of the selection
+            self.refresh(echo);
+        }
+
+        if self.line != before.line && !undoing {
+            // Characters typed right after each other are undone at once
+            if !(last == Action::Insert && self.last == Action::Insert) {
+                self.undo.push(before);
+            }
+            self.redo.clear();
+        }
+        None
+    }
+
+    /// Completes the word before the cursor with the candidates of the
+    /// completer
+    fn complete(&mut self, last: Action, echo: &mut String) {
+        let Some(CompleterBox(completer)) = &mut self.completer else {
+            return;
+        };
+        let start = completed_word_start(&self.line[..self.cursor]);
+        match completer.complete(&self.line, self.cursor) {
+            Completion::Ready(candidates) => self.apply_completion(start, candidates, last, echo),
+            Completion::Pending {
+                candidates,
+                timeout,
+            } => {
+                self.completing = Some(PendingCompletion {
+                    candidates,
+                    deadline: Instant::now() + timeout,
+                    start,
+                    last,
+                });
+                // A dimmed ellipsis behind the cursor, which stays in place
+                echo.push_str("\x1b7 \x1b[2m…\x1b[22m\x1b8");
+            }
+        }
+    }
+
+    /// Completes the word from the byte offset `start` to the cursor with
+    /// the candidates
+    ///
+    /// If they do not agree on more than the word, they are listed when the
+    /// previous key tried to complete it as well.
+    fn apply_completion(
+        &mut self,
+        start: usize,
+        candidates: Vec<Candidate>,
+        last: Action,
+        echo: &mut String,
+    ) {
+        let word = &self.line[start..self.cursor];
+        let common = match candidates.as_slice() {
+            [] => return echo.push('\x07'),
+            [candidate] => {
+                let completed = format!("{} ", candidate.value);
+                return self.replace(start..self.cursor, &completed, echo);
+            }
+            [first, rest @ ..] => rest.iter().fold(first.value.as_str(), |common, candidate| {
+                let length = common
+                    .char_indices()
+                    .zip(candidate.value.chars())
+                    .find(|((_, a), b)| a != b)
+                    .map_or(common.len().min(candidate.value.len()), |((i, _), _)| i);
+                &common[..length]
+            }),
+        };
+        if common.len() > word.len() && common.starts_with(word) {
+            let common = common.to_string();
+            self.replace(start..self.cursor, &common, echo);
+            self.last = Action::Complete;
+        } else if last == Action::Complete {
+            let word = word.to_string();
+            let value = candidates[0].value.clone();
+            self.menu = Some(Menu {
+                start,
+                word,
+                candidates,
+                selected: 0,
+                top: 0,
+            });
+            self.replace(start..self.cursor, &value, echo);
+            self.show_menu(echo);
+        } else {
+            self.last = Action::Complete;
+        }
+    }
+
+    /// Applies a key to the open menu and returns `false` if it closed the
+    /// menu without handling the key
+    fn select(&mut self, key: &Key, echo: &mut String) -> bool {
+        let Some(menu) = &mut self.menu else {
+            return false;
+        };
+        let count = menu.candidates.len();
+        menu.selected = match key {
+            Key::Ctrl('i') | Key::Down => (menu.selected + 1) % count,
+            Key::BackTab | Key::Up => (menu.selected + count - 1) % count,
+            Key::Enter | Key::Ctrl('g') => {
+                let menu = self.menu.take().expect("the menu is open");
+                self.clear_below(echo);
+                match key {
+                    Key::Enter => self.insert(" ", echo),
+                    _ => self.replace(menu.start..self.cursor, &menu.word, echo),
+                }
+                return true;
+            }
+            _ => {
+                self.menu = None;
+                return false;
+            }
+        };
+        menu.top = menu
+            .top
+            .min(menu.selected)
+            .max((menu.selected + 1).saturating_sub(MENU_ROWS));
+        let (start, value) = (menu.start, menu.candidates[menu.selected].value.clone());
+        self.replace(start..self.cursor, &value, echo);
+        self.show_menu(echo);
+        true
+    }
+
+    /// Shows the candidates of the menu with their descriptions below the line
+    fn show_menu(&mut self, echo: &mut String) {
+        let Some(menu) = &self.menu else {
+            return;
+        };
+        let shown = &menu.candidates[menu.top..(menu.top + MENU_ROWS).min(menu.candidates.len())];
+        let width = shown.iter().map(|c| c.value.width()).max().unwrap_or(0);
+        let rows: Vec<String> = shown
+            .iter()
+            .map(|candidate| match &candidate.description {
+                Some(description) => {
+                    let padding = " ".repeat(width - candidate.value.width());
+                    format!("{}{padding}  {description}", candidate.value)
+                }
+                None => candidate.value.clone(),
+            })
+            .collect();
+        let selected = menu.selected - menu.top;
+        self.show_below(&rows, Some(selected), echo);
+    }
+
+    /// Shows the rows below the line, the selected one in reverse video, and
+    /// puts the cursor back
+    ///
+    /// Rows wider than the terminal are cut off.
+    fn show_below(&mut self, rows: &[String], selected: Option<usize>, echo: &mut String) {
+        let end = self.lay_out(&self.line, (0, self.column), None);
+        self.move_cursor(end, echo);
+        // A full last row has been ended already
+        let mut row = end.0;
+        if end.1 > 0 || end.0 == 0 {
+            echo.push_str("\r\n");
+            row += 1;
+        }
+        echo.push_str("\x1b[J");
+        let columns = self.columns();
+        let mut column = 0;
+        for (i, text) in rows.iter().enumerate() {
+            if i > 0 {
+                echo.push_str("\r\n");
+                row += 1;
+            }
+            column = 0;
+            if selected == Some(i) {
+                echo.push_str("\x1b[7m");
+            }
+            for grapheme in text.graphemes(true) {
+                if column + grapheme.width() >= columns {
+                    break;
+                }
+                echo.push_str(grapheme);
+                column += grapheme.width();
+            }
+            if selected == Some(i) {
+                echo.push_str("\x1b[27m");
+            }
+        }
+        self.shown = (row, column);
+        self.below = true;
+        self.move_to(self.cursor, echo);
+    }
+
+    /// Removes the rows that are shown below the line
+    fn clear_below(&mut self, echo: &mut String) {
+        if std::mem::take(&mut self.below) {
+            let end = self.lay_out(&self.line, (0, self.column), None);
+            self.move_cursor(end, echo);
+            echo.push_str("\x1b[J");
+            self.move_to(self.cursor, echo);
+        }
+    }
+
+    /// Shows the previous entered line, or the next one if not `back`
+    ///
+    /// Going forward from the newest line shows the line that was being
+    /// edited before.
+    fn browse(&mut self, back: bool, echo: &mut String) {
+        let index = match (&self.browsing, back) {
+            (None, true) => self.history.len().checked_sub(1),
+            (None, false) => None,
+            (Some((index, _)), true) => index.checked_sub(1),
+            (Some((index, _)), false) => Some(index + 1),
+        };
+        let line = match index {
+            Some(index) if index < self.history.len() => {
+                let draft = match self.browsing.take() {
+                    Some((_, draft)) => draft,
+                    None => self.line.clone(),
+                };
+                self.browsing = Some((index, draft));
+                self.history[index].clone()
+            }
+            // The end of the history
+            Some(_) if !back => match self.browsing.take() {
+                Some((_, draft)) => draft,
+                None => return,
+            },
+            _ => return,
+        };
+        self.replace(0..self.line.len(), &line, echo);
+    }
+
+    /// Returns the byte offset in the row above the cursor, or below it if not
+    /// `up`, that is closest to the column of the cursor
+    fn row_change(&self, up: bool) -> Option<usize> {
+        let (row, column) = self.lay_out(&self.line[..self.cursor], (0, self.column), None);
+        let last_row = self.lay_out(&self.line, (0, self.column), None).0;
+        match up {
+            true => Some(self.offset_at((row.checked_sub(1)?, column))),
+            false if row < last_row => Some(self.offset_at((row + 1, column))),
+            false => None,
+        }
+    }
+
+    /// Returns the byte offset of the grapheme cluster shown at the row,
+    /// counted from the one the line starts in, and the column
+    ///
+    /// Behind the end of a row, this is the offset behind its last grapheme
+    /// cluster.
+    fn offset_at(&self, target: (usize, usize)) -> usize {
+        let mut position = (0, self.column);
+        for (offset, grapheme) in self.line.grapheme_indices(true) {
+            let width = grapheme.width();
+            let (row, column) = match position.1 + width > self.columns() && position.1 > 0 {
+                true => (position.0 + 1, 0),
+                false => position,
+            };
+            // A row break takes up the rest of the row
+            let end = match grapheme {
+                "\n" => usize::MAX,
+                _ => column + width,
+            };
+            if target < (row, end) {
+                return offset;
+            }
+            position = self.lay_out(grapheme, position, None);
+        }
+        self.line.len()
+    }
+
+    /// Returns the width of the terminal
+    fn columns(&self) -> usize {
+        self.columns.unwrap_or(DEFAULT_COLUMNS)
+    }
+
+    /// Turns off mouse reporting
+    fn stop_mouse(&mut self, echo: &mut String) {
+        if self.mouse {
+            echo.push_str("\x1b[?1006l\x1b[?1000l");
+        }
+    }
+
+    /// Returns the selected byte range, if text is selected
+    fn selection(&self) -> Option<Range<usize>> {
+        let anchor = self.anchor?;
+        let range = anchor.min(self.cursor)..anchor.max(self.cursor);
+        (!range.is_empty()).then_some(range)
+    }
+
+    /// Applies a key that acts on the selected text and returns whether it
+    /// did
+    fn edit_selection(
+        &mut self,
+        key: &Key,
+        range: Range<usize>,
+        last: Action,
+        echo: &mut String,
+    ) -> bool {
+        match key {
+            Key::Char(c) => self.replace(range, c.encode_utf8(&mut [0; 4]), echo),
+            Key::Backspace | Key::Delete | Key::Ctrl('d') => self.replace(range, "", echo),
+            Key::Ctrl('w') => self.kill(range, true, last, echo),
+            #[cfg(feature = "clipboard")]
+            Key::CtrlShift('c') | Key::Alt('w') => copy(&self.line[range], echo),
+            #[cfg(feature = "clipboard")]
+            Key::Osc(report) => match pasted(report) {
+                Some(text) => self.replace(range, &text, echo),
+                None => return false,
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    /// Restores the state of the line before the last change, or after the
+    /// last undone one when `redoing`
+    fn undo(&mut self, redoing: bool, echo: &mut String) {
+        let (from, to) = match redoing {
+            false => (&mut self.undo, &mut self.redo),
+            true => (&mut self.redo, &mut self.undo),
+        };
+        let Some(state) = from.pop() else {
+            return;
+        };
+        to.push(State {
+            line: std::mem::replace(&mut self.line, state.line),
+            cursor: self.cursor,
+        });
+        self.cursor = state.cursor;
+        self.refresh(echo);
+    }
+
+    /// Cuts the text in the range into the kill ring
+    ///
+    /// Right after another cut, the text is joined with it, in front of it
+    /// when cutting `backward`.
+    fn kill(&mut self, range: Range<usize>, backward: bool, last: Action, echo: &mut String) {
+        let text: String = self.line.drain(range.clone()).collect();
+        self.cursor = range.start;
+        self.last = Action::Kill;
+        match self.kill_ring.last_mut() {
+            Some(newest) if last == Action::Kill => match backward {
+                true => newest.insert_str(0, &text),
+                false => newest.push_str(&text),
+            },
+            _ if text.is_empty() => return,
+            _ => {
+                if self.kill_ring.len() == KILL_RING_SIZE {
+                    self.kill_ring.remove(0);
+                }
+                self.kill_ring.push(text);
+            }
+        }
+        self.refresh(echo);
+    }
+
+    /// Returns the length of the grapheme cluster before the cursor
+    fn previous_grapheme(&self) -> Option<usize> {
+        self.line[..self.cursor]
+            .graphemes(true)
+            .next_back()
+            .map(str::len)
+    }
+
+    /// Returns the length of the grapheme cluster after the cursor
+    fn next_grapheme(&self) -> Option<usize> {
+        self.line[self.cursor..]
+            .graphemes(true)
+            .next()
+            .map(str::len)
+    }
+
+    /// Inserts the text at the cursor
+    fn insert(&mut self, text: &str, echo: &mut String) {
+        self.line.insert_str(self.cursor, text);
+        self.cursor += text.len();
+        if self.cursor == self.line.len() && self.previous_grapheme() == Some(text.len()) {
+            // Appending a character that does not join the one before only
+            // needs it to be echoed
+            self.shown = self.lay_out(text, self.shown, Some(echo));
+        } else {
+            self.refresh(echo);
+        }
+    }
+
+    /// Replaces the text in the range and places the cursor behind the new
+    /// text
+    fn replace(&mut self, range: Range<usize>, text: &str, echo: &mut String) {
+        self.cursor = range.start + text.len();
+        self.line.replace_range(range, text);
+        self.refresh(echo);
+    }
+
+    /// Moves the cursor to the byte offset
+    fn move_to(&mut self, cursor: usize, echo: &mut String) {
+        self.cursor = cursor;
+        let position = self.lay_out(&self.line[..cursor], (0, self.column), None);
+        self.move_cursor(position, echo);
+    }
+
+    /// Moves the cursor of the terminal to the row, counted from the one the
+    /// line starts in, and the column
+    fn move_cursor(&mut self, (row, column): (usize, usize), echo: &mut String) {
+        let (shown_row, shown_column) = self.shown;
+        if row < shown_row {
+            echo.push_str(&format!("\x1b[{}A", shown_row - row));
+        } else if row > shown_row {
+            echo.push_str(&format!("\x1b[{}B", row - shown_row));
+        }
+        if column != shown_column {
+            echo.push_str(&format!("\x1b[{}G", column + 1));
+        }
+        self.shown = (row, column);
+    }
+
+    /// Returns the position behind the text shown at the position and
+    /// writes it to `echo`, if given
+    ///
+    /// A character that does not fit into the row is moved to the next one.
+    /// Full rows are ended right away, so that the position does not depend
+    /// on how the terminal wraps and the rows stay as they are when it is
+    /// resized.
+    fn lay_out(
+        &self,
+        text: &str,
+        (mut row, mut column): (usize, usize),
+        mut echo: Option<&mut String>,
+    ) -> (usize, usize) {
+        let columns = self.columns();
+        for grapheme in text.graphemes(true) {
+            if grapheme == "\n" {
+                if let Some(echo) = echo.as_mut() {
+                    echo.push_str("\r\n");
+                }
+                (row, column) = (row + 1, 0);
+                continue;
+            }
+            let width = grapheme.width();
+            if column + width > columns && column > 0 {
+                if let Some(echo) = echo.as_mut() {
+                    echo.push_str("\r\n");
+                }
+                (row, column) = (row + 1, 0);
+            }
+            if let Some(echo) = echo.as_mut() {
+                echo.push_str(grapheme);
+            }
+            column += width;
+            if column >= columns {
+                if let Some(echo) = echo.as_mut() {
+                    echo.push_str("\r\n");
+                }
+                (row, column) = (row + 1, 0);
+            }
+        }
+        (row, column)
+    }
+
+    /// Redraws the line from its start and places the cursor
+    fn refresh(&mut self, echo: &mut String) {
+        self.move_cursor((0, self.column), echo);
+        echo.push_str("\x1b[J");
+        let start = (0, self.column);
+        self.shown = match self.selection() {
+            Some(range) => {
+                let position = self.lay_out(&self.line[..range.start], start, Some(echo));
+                echo.push_str("\x1b[7m");
+                let position = self.lay_out(&self.line[range.clone()], position, Some(echo));
+                echo.push_str("\x1b[27m");
+                self.lay_out(&self.line[range.end..], position, Some(echo))
+            }
+            None => self.lay_out(&self.line, start, Some(echo)),
+        };
+        self.move_to(self.cursor, echo);
+    }
+
+    /// Moves the cursor behind the line, ends it and starts an empty line
+    fn finish(&mut self, echo: &mut String) -> String {
+        self.move_to(self.line.len(), echo);
+        // A full last row has been ended already
+        if self.shown.1 > 0 || self.shown.0 == 0 {
+            echo.push_str("\r\n");
+        }
+        self.stop_mouse(echo);
+        self.cursor = 0;
+        self.column = 0;
+        self.shown = (0, 0);
+        self.undo.clear();
+        self.redo.clear();
+        self.browsing = None;
+        let line = std::mem::take(&mut self.line).replace('\n', " ");
+        if !line.trim().is_empty() && self.history.last() != Some(&line) {
+            if self.history.len() == HISTORY_SIZE {
+                self.history.remove(0);
+            }
+            self.history.push(line.clone());
+        }
+        line
+    }
+}
+
+/// Edits the text in the editor of the user, which is the command in the
+/// `VISUAL` or `EDITOR` environment variable or `vi`
+///
+/// The text is written to a temporary file that only the user can read, and
+/// the editor runs on the terminal of the process until it exits. This is
+/// meant for [`LineEditor::with_external_editor`] when the editor runs on
+/// the local terminal.
+pub fn run_external_editor(text: &str) -> io::Result<String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    edit_with(&editor, text)
+}
+
+/// Edits the text in a temporary file with the editor command
+fn edit_with(editor: &str, text: &str) -> io::Result<String> {
+    static FILES: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "simpleshell-line-{}-{}.txt",
+        process::id(),
+        FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(&path)?.write_all(text.as_bytes())?;
+
+    let status = editor_command(editor, &path).status();
+    let edited = fs::read_to_string(&path);
+    let _ = fs::remove_file(&path);
+    if !status?.success() {
+        return Err(io::Error::other(format!("{} failed", editor)));
+    }
+    edited
+}
+
+/// Returns the command that runs the editor, which may have arguments, on
+/// the file
+#[cfg(unix)]
+fn editor_command(editor: &str, path: &Path) -> process::Command {
+    let mut command = process::Command::new("sh");
+    command
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(path);
+    command
+}
+
+/// Returns the command that runs the editor, which may have arguments, on
+/// the file
+#[cfg(not(unix))]
+fn editor_command(editor: &str, path: &Path) -> process::Command {
+    let mut words = editor.split_whitespace();
+    let mut command = process::Command::new(words.next().unwrap_or("notepad"));
+    command.args(words).arg(path);
+    command
+}
+
+/// Returns the byte offset of the start of the last word, which is
+/// delimited by whitespace
+fn word_start(text: &str) -> usize {
+    let end = text.trim_end().len();
+    text[..end]
+        .char_indices()
+        .rev()
+        .find(|(_, c)| c.is_whitespace())
+        .map_or(0, |(i, c)| i + c.len_utf8())
+}
+
+/// Returns the byte offset where the word that is completed starts, which is
+/// behind the last whitespace of the text
+fn completed_word_start(text: &str) -> usize {
+    text.char_indices()
+        .rev()
+        .find(|(_, c)| c.is_whitespace())
+        .map_or(0, |(i, c)| i + c.len_utf8())
+}
+
+/// Returns the key of a complete escape sequence without the escape
+fn escape_key(sequence: &str) -> Key {
+    match sequence {
+        "[A" | "OA" => Key::Up,
+        "[B" | "OB" => Key::Down,
+        "[C" | "OC" => Key::Right,
+        "[D" | "OD" => Key::Left,
+        "[H" | "OH" | "[1~" | "[7~" => Key::Home,
+        "[F" | "OF" | "[4~" | "[8~" => Key::End,
+        "[3~" => Key::Delete,
+        "[Z" => Key::BackTab,
+        "[1;2D" => Key::ShiftLeft,
+        "[1;2C" => Key::ShiftRight,
+        "[1;2H" => Key::ShiftHome,
+        "[1;2F" => Key::ShiftEnd,
+        _ if sequence.starts_with(']') => {
+            let command = sequence[1..].trim_end_matches('\x07');
+            Key::Osc(command.trim_end_matches("\x1b\\").to_string())
+        }
+        _ => {
+            let mut chars = sequence.chars();
+            if let (Some(c), None) = (chars.next(), chars.next()) {
+                if c != '[' && c != 'O' {
+                    return Key::Alt(c);
+                }
+            }
+            mouse_event(sequence)
+                .or_else(|| position(sequence))
+                .or_else(|| size(sequence))
+                .or_else(|| extended_key(sequence))
+                .unwrap_or(Key::Unknown)
+        }
+    }
+}
+
+/// Returns the mouse event of an SGR mouse report, `CSI < button ; column ;
+/// row M` for presses and with `m` for releases
+fn mouse_event(sequence: &str) -> Option<Key> {
+    let report = sequence.strip_prefix("[<")?;
+    let pressed = report.ends_with('M');
+    let mut numbers = report[..report.len() - 1].split(';').map(str::parse);
+    let (Some(Ok(button)), Some(Ok(column)), Some(Ok(row)), None) = (
+        numbers.next(),
+        numbers.next(),
+        numbers.next(),
+        numbers.next(),
+    ) else {
+        return None;
+    };
+    (column > 0 && row > 0).then_some(Key::Mouse {
+        button: button as u32,
+        column,
+        row,
+        pressed,
+    })
+}
+
+/// Returns the position of a cursor position report, `CSI row ; column R`
+fn position(sequence: &str) -> Option<Key> {
+    let (row, column) = sequence
+        .strip_prefix('[')?
+        .strip_suffix('R')?
+        .split_once(';')?;
+    let (row, column) = (row.parse().ok()?, column.parse().ok()?);
+    (row > 0 && column > 0).then_some(Key::Position { row, column })
+}
+
+/// Returns the width of a report of the size of the terminal, `CSI 8 ; rows ;
+/// columns t`
+fn size(sequence: &str) -> Option<Key> {
+    let (_, columns) = sequence
+        .strip_prefix("[8;")?
+        .strip_suffix('t')?
+        .split_once(';')?;
+    Some(Key::Resize {
+        columns: columns.parse().ok()?,
+    })
+}
+
+/// Returns the key of a sequence of the extended keyboard protocols, which
+/// report the code point and the modifiers of keys with `CSI code ;
+/// modifiers u` or `CSI 27 ; modifiers ; code ~`
+fn extended_key(sequence: &str) -> Option<Key> {
+    let parameters = sequence.strip_prefix('[')?;
+    let (code, modifiers) = match parameters.strip_suffix('u') {
+        Some(parameters) => {
+            let (code, modifiers) = parameters.split_once(';')?;
+            (code, modifiers)
+        }
+        None => {
+            let parameters = parameters.strip_prefix("27;")?.strip_suffix('~')?;
+            let (modifiers, code) = parameters.split_once(';')?;
+            (code, modifiers)
+        }
+    };
+    let c = char::from_u32(code.parse().ok()?)?.to_ascii_lowercase();
+    // The modifiers are sent as a bit mask plus one, with shift as 1, alt
+    // as 2 and ctrl as 4
+    Some(match modifiers.parse::<u32>().ok()?.checked_sub(1)? {
+        0 | 1 => Key::Char(c),
+        2 => Key::Alt(c),
+        4 => Key::Ctrl(c),
+        5 => Key::CtrlShift(c),
+        _ => Key::Unknown,
+    })
+}
+
+/// Copies the text to the clipboard of the terminal
+#[cfg(feature = "clipboard")]
+fn copy(text: &str, echo: &mut String) {
+    echo.push_str(&format!("\x1b]52;c;{}\x07", encode_base64(text.as_bytes())));
+}
+
+/// Returns the text of the answer to a query of the clipboard with control
+/// characters replaced by spaces
+#[cfg(feature = "clipboard")]
+fn pasted(report: &str) -> Option<String> {
+    let (_, data) = report.strip_prefix("52;")?.split_once(';')?;
+    let data = decode_base64(data)?;
+    let text = String::from_utf8_lossy(&data)
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    Some(text)
+}
+
+/// Encodes the bytes in standard Base64 with padding
+#[cfg(feature = "clipboard")]
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let block = chunk.iter().enumerate().fold(0u32, |block, (i, &byte)| {
+            block | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => encoded.push(BASE64[(block >> (18 - 6 * i) & 0x3f) as usize] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+    encoded
+}
+
+/// Decodes standard Base64 with or without padding
+#[cfg(feature = "clipboard")]
+fn decode_base64(encoded: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(encoded.len() / 4 * 3);
+    let (mut block, mut bits) = (0u32, 0);
+    for byte in encoded.trim_end_matches('=').bytes() {
+        let value = BASE64.iter().position(|&b| b == byte)? as u32;
+        block = block << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((block >> bits) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+#[cfg(feature = "clipboard")]
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds the keys and returns the completed line
+    fn line(keys: &str) -> Option<Edit> {
+        LineEditor::new().feed(keys, &mut String::new())
+    }
+
+    impl LineEditor {
+        fn decode_all(&mut self, keys: &str) -> Vec<Key> {
+            keys.chars().filter_map(|c| self.decode(c)).collect()
+        }
+    }
+
+    #[test]
+    fn edits_grapheme_clusters() {
+        // The family emoji is one cluster of several code points
+        assert_eq!(line("a👨‍👩‍👧b\x1b[D\x7f\r"), Some(Edit::Line("ab".to_string())));
+        assert_eq!(
+            line("e\u{301}x\x1b[D\x1b[D\x1b[3~\r"),
+            Some(Edit::Line("x".to_string()))
+        );
+    }
+
+    #[test]
+    fn moves_by_display_width() {
+        let mut editor = LineEditor::new();
+        let mut echo = String::new();
+        editor.feed("日本", &mut echo);
+        echo.clear();
+        editor.feed("\x1b[D", &mut echo);
+        assert_eq!(echo, "\x1b[3G");
+        echo.clear();
+        editor.feed("x", &mut echo);
+        assert_eq!(echo, "\x1b[1G\x1b[J日x本\x1b[4G");
+        assert_eq!(editor.line(), "日x本");
+    }
+
+    #[test]
+    fn keeps_the_keys_after_a_line() {
+        let mut editor = LineEditor::new();
+        let mut echo = String::new();
+        assert_eq!(
+            editor.feed("one\rtwo\r\nthr", &mut echo),
+            Some(Edit::Line("one".to_string()))
+        );
+        assert_eq!(
+            editor.feed("", &mut echo),
+            Some(Edit::Line("two".to_string()))
+        );
+        assert_eq!(
+            editor.feed("ee\r", &mut echo),
+            Some(Edit::Line("three".to_string()))
+        );
+        assert_eq!(editor.feed("", &mut echo), None);
+    }
+
+    #[test]
+    fn decodes_keys_split_across_calls() {
+        let mut editor = LineEditor::new();
+        let mut echo = String::new();
+        editor.feed("ab\x1b", &mut echo);
+        editor.feed("[", &mut echo);
+        editor.feed("Dx", &mut echo);
+        assert_eq!(editor.line(), "axb");
+        assert_eq!(editor.cursor(), 2);
+    }
+
+    #[test]
+    fn closes_on_ctrl_d_only_when_empty() {
+        assert_eq!(line("\x04"), Some(Edit::Closed));
+        assert_eq!(line("ab\x01\x04\r"), Some(Edit::Line("b".to_string())));
+    }
+
+    #[test]
+    fn joins_cuts_right_after_each_other() {
+        // Ctrl-W twice cuts both words into one entry, Ctrl-Y pastes them back
+        assert_eq!(
+            line("echo one two\x17\x17\x05 \x19\r"),
+            Some(Edit::Line("echo  one two".to_string()))
+        );
+        // Ctrl-K and Ctrl-U around the cursor cut the whole line
+        assert_eq!(
+            line("abcd\x1b[D\x1b[D\x0b\x15x\x19\r"),
+            Some(Edit::Line("xabcd".to_string()))
+        );
+    }
+
+    #[test]
+    fn rotates_the_kill_ring_after_a_paste() {
+        let mut editor = LineEditor::new();
+        let mut echo = String::new();
+        editor.feed("one\x15two\x15three\x15", &mut echo);
+        // Alt-Y goes back from the last cut and wraps around
+        editor.feed("\x19\x1by", &mut echo);
+        assert!(echo.ends_with("\x1b[1G\x1b[Jtwo"));
+        editor.feed("\x1by", &mut echo);
+        assert_eq!(editor.line(), "one");
+        editor.feed("\x1by", &mut echo);
+        assert_eq!(editor.line(), "three");
+        // Alt-Y does nothing when the previous key did not paste
+        editor.feed("\x02\x1by", &mut echo);
+        assert_eq!(editor.line(), "three");
+        assert_eq!(
+            editor.feed("\r", &mut echo),
+            Some(Edit::Line("three".to_string()))
+        );
+        // The kill ring is kept for the next line
+        assert_eq!(
+            editor.feed("\x19\r", &mut echo),
+            Some(Edit::Line("three".to_string()))
+        );
+    }
+
+    #[test]
+    fn undoes_and_redoes_changes() {
+        let mut editor = LineEditor::new();
+        let mut echo = String::new();
+        editor.feed("ls -la\x7f\x7f\x01\x0b", &mut echo);
+        assert_eq!(editor.line(), "");
+        // Undo the cut, both backspaces, then the typing at once
+        editor.feed("\x1f", &mut echo);
+        assert_eq!((editor.line(), editor.cursor()), ("ls -", 0));
+        editor.feed("\x18\x15\x1f", &mut echo);
+        assert_eq!(editor.line(), "ls -la");
+        editor.feed("\x1f\x1f", &mut echo);
+        assert_eq!(editor.line(), "");
+        editor.feed("\x18\x12\x18\x12", &mut echo);
+        assert_eq!((editor.line(), editor.cursor()), ("ls -l", 5));
+        // A change drops what was undone
+        editor.feed("x\x18\x12", &mut echo);
+        assert_eq!(editor.line(), "ls -lx");
+    }
+
+    #[test]
+    fn forgets_the_changes_of_completed_lines() {
+        let mut editor = LineEditor::new();
+        let mut echo = String::new();
+        editor.feed("one\r", &mut echo);
+        editor.feed("\x1f", &mut echo);
+        assert_eq!(editor.line(), "");
+        // Keys after Ctrl-X other than a letter with Ctrl are ignored
+        editor.feed("a\x18b", &mut echo);
+        assert_eq!(editor.line(), "a");
+    }
+
+    #[test]
+    fn decodes_keys_of_the_extended_keyboard_protocols() {
+        assert_eq!(escape_key("[99;6u"), Key::CtrlShift('c'));
+        assert_eq!(escape_key("[27;6;86~"), Key::CtrlShift('v'));
+        assert_eq!(escape_key("[97;5u"), Key::Ctrl('a'));
+        assert_eq!(escape_key("[97;u"), Key::Unknown);
+        assert_eq!(escape_key("]52;c;?\x1b\\"), Key::Osc("52;c;?".to_string()));
+    }
+
+    #[cfg(feature = "clipboard")]
+    #[test]
+    fn copies_and_pastes_with_osc_52() {
+        let mut editor = LineEditor::new();
+        let mut echo = String::new();
+        editor.feed("ls 日本\x1bw", &mut echo);
+        assert!(echo.ends_with("\x1b]52;c;bHMg5pel5pys\x07"));
+        echo.clear();
+        editor.feed("\x1b[118;6u", &mut echo);
+        assert_eq!(echo, "\x1b]52;c;?\x07");
+        // The answer of the terminal is pasted at the cursor
+        editor.feed("\x01\x1b]52;c;Y2Qg\x07", &mut echo);
+        assert_eq!(editor.line(), "cd ls 日本");
+    }
+
+    #[cfg(feature = "clipboard")]
+    #[test]
+    fn round_trips_base64() {
+        for text in ["", "a", "ab", "abc", "abcd", "日本語"] {
+            let encoded = encode_base64(text.as_bytes());
+            assert_eq!(encoded.len() % 4, 0);
+            assert_eq!(decode_base64(&encoded).as_deref(), Some(text.as_bytes()));
+        }
+        assert_eq!(encode_base64(b"ab"), "YWI=");
+        assert_eq!(decode_base64("YWI"), Some(b"ab".to_vec()));
+        assert_eq!(decode_base64("Y!"), None);
+    }
+
+    #[test]
+    fn edits_the_selection() {
+        // Select "two" with Shift-Left and replace it
+        assert_eq!(
+            line("one two\x1b[1;2D\x1b[1;2D\x1b[1;2Dsix\r"),
+            Some(Edit::Line("one six".to_string()))
+        );
+        // Select the whole line backward from the end and delete it
+        assert_eq!(
+            line("one\x1b[1;2H\x7fx\r"),
+            Some(Edit::Line("x".to_string()))
+        );
+        // Cut the selection and paste it at the end
+        assert_eq!(
+            line("ab cd\x01\x1b[1;2C\x1b[1;2C\x17\x05\x19\r"),
+            Some(Edit::Line(" cdab".to_string()))
+        );
+    }
+
+    #[test]
+    fn highlights_the_selection() {
+        let mut editor = LineEditor::new();
+        let mut echo = String::new();
+        editor.feed("abc\x1b[D", &mut echo);
+        echo.clear();
+        editor.feed("\x1b[1;2H", &mut echo);
+        assert_eq!(echo, "\x1b[1G\x1b[J\x1b[7mab\x1b[27mc\x1b[1G");
+        // Moving the cursor ends the selection
+        echo.clear();
+        editor.feed("\x1b[C", &mut echo);
+        assert_eq!(echo, "\x1b[2G\x1b[1G\x1b[Jabc\x1b[2G");
+        editor.feed("x", &mut echo);
+        assert_eq!(editor.line(), "axbc");
+    }
+
+    #[cfg(feature = "clipboard")]
+    #[test]
+    fn copies_the_selection() {
+        let mut editor = LineEditor::new();
+        let mut echo = String::new();
+        editor.feed("ls ab\x1b[1;2D\x1b[1;2D", &mut echo);
+        echo.clear();
+        editor.feed("\x1b[99;6u", &mut echo);
+        assert!(echo.starts_with("\x1b]52;c;YWI=\x07"));
+        assert_eq!(editor.selection(), None);
+    }
+
+    #[test]
+    fn goes_through_the_entered_lines() {
+        let mut editor = LineEditor::new();
+        let mut echo = String::new();
+        editor.feed("one\rtwo\r", &mut echo);
+        editor.feed("", &mut echo);
+        editor.feed("dra\x1b[A", &mut echo);
+        assert_eq!(editor.line(), "two");
+        editor.feed("\x10\x10", &mut echo);
+        assert_eq!(editor.line(), "one");
+        // Going forward past the newest line restores the draft
+        editor.feed("\x1b[B\x0e", &mut echo);
+        assert_eq!(editor.line(), "dra");
+        editor.feed("\x1b[B", &mut echo);
+        assert_eq!(editor.line(), "dra");
+        // Lines entered twice in a row are kept once
+        editor.feed("\x15two\r", &mut echo);
+        editor.feed("\x1b[A\x1b[A", &mut echo);
+        assert_eq!(editor.line(), "one");
+    }
+
+    #[test]
+    fn places_the_cursor_where_the_mouse_clicked() {
+        let mut editor = LineEditor::new().with_mouse(true);
+        let mut echo = String::new();
+        editor.start(2, &mut echo);
+        assert_eq!(echo, "\x1b[?1000h\x1b[?1006h\x1b[6n");
+        // The terminal reports the cursor in row 5 behind the prompt `$ `
+        editor.feed("\x1b[5;3R日本語", &mut echo);
+        editor.feed("\x1b[<0;5;5M\x1b[<0;5;5mx", &mut echo);
+        assert_eq!(editor.line(), "日x本語");
+        // Clicks in other rows are ignored
+        editor.feed("\x1b[<0;3;4M", &mut echo);
+        assert_eq!(editor.cursor(), "日x".len());
+        // Scrolling up shows the entered lines
+        echo.clear();
+        assert_eq!(
+            editor.feed("\r", &mut echo),
+            Some(Edit::Line("日x本語".to_string()))
+        );
+        assert!(echo.ends_with("\r\n\x1b[?1006l\x1b[?1000l"));
+        editor.feed("\x1b[<64;1;1M", &mut echo);
+        assert_eq!(editor.line(), "日x本語");
+    }
+
+    #[test]
+    fn takes_only_awaited_reports_as_positions() {
+        let mut editor = LineEditor::new();
+        assert_eq!(editor.decode_all("\x1b[1;2R"), [Key::Unknown]);
+        editor.awaiting_position = true;
+        assert_eq!(
+            editor.decode_all("\x1b[1;2R"),
+            [Key::Position { row: 1, column: 2 }]
+        );
+    }
+
+    #[test]
+    fn wraps_long_lines() {
+        let mut editor = LineEditor::new();
+        let mut echo = String::new();
+        editor.resize(6, &mut echo);
+        editor.start(2, &mut echo);
+        editor.feed("abcd", &mut echo);
+        assert_eq!(echo, "abcd\r\n");
+        // A wide character that does not fit moves to the next row
+        editor.feed("efghi日", &mut echo);
+        assert_eq!(editor.shown, (2, 2));
+        assert!(echo.ends_with("efghi\r\n日"));
+        // The cursor goes up to the row of the character before it
+        echo.clear();
+        editor.feed("\x1b[D", &mut echo);
+        assert_eq!(echo, "\x1b[1A\x1b[6G");
+        // Changes redraw all rows
+        echo.clear();
+        editor.feed("\x01\x04", &mut echo);
+        assert!(echo.starts_with("\x1b[1A\x1b[3G\x1b[Jbcde\r\nfghi日"));
+    }
+
+    #[test]
+    fn wraps_again_when_resized() {
+        let mut editor = LineEditor::new();
+        let mut echo = String::new();
+        editor.start(2, &mut echo);
+        editor.feed("abcdefghij\x1b[D", &mut echo);
+        echo.clear();
+        editor.feed("\x1b[8;24;6t", &mut echo);
+        assert_eq!(echo, "\x1b[3G\x1b[Jabcd\r\nefghij\r\n\x1b[1A\x1b[6G");
+        // Ending the line while the cursor is behind a full row only adds
+        // the row
+        echo.clear();
+        assert_eq!(
+            editor.feed("\x05\r", &mut echo),
+            Some(Edit::Line("abcdefghij".to_string()))
+        );
+        assert_eq!(echo, "\x1b[1B\x1b[1G");
+    }
+
+    #[test]
+    fn places_the_cursor_in_wrapped_rows() {
+        let mut editor = LineEditor::new().with_mouse(true);
+        let mut echo = String::new();
+        editor.resize(4, &mut echo);
+        editor.start(1, &mut echo);
+        // Rows 3 to 5 show "abc", "defg" and "hi"
+        editor.feed("\x1b[3;2Rabcdefghi", &mut echo);
+        editor.feed("\x1b[<0;2;4M", &mut echo);
+        assert_eq!(editor.cursor(), 4);
+        // Behind the end of a row
+        editor.feed("\x1b[<0;9;4M", &mut echo);
+        assert_eq!(editor.cursor(), 7);
+        editor.feed("\x1b[<0;1;9M", &mut echo);
+        assert_eq!(editor.cursor(), 9);
+        // On the prompt
+        editor.feed("\x1b[<0;1;3M", &mut echo);
+        assert_eq!(editor.cursor(), 0);
+    }
+
+    #[test]
+    fn edits_lines_with_several_rows() {
+        let mut editor = LineEditor::new();
+        let mut echo = String::new();
+        editor.start(2, &mut echo);
+        editor.feed("for x in a b\x1b\r  echo $x\x1b\rend", &mut echo);
+        assert_eq!(echo, "for x in a b\r\n  echo $x\r\nend");
+        assert_eq!(editor.shown, (2, 3));
+        // Up keeps the column, or goes to the end of a shorter row
+        echo.clear();
+        editor.feed("\x1b[A", &mut echo);
+        assert_eq!(echo, "\x1b[1A");
+        editor.feed("\x1b[A\x1b[A", &mut echo);
+        assert_eq!(editor.cursor(), 1);
+        editor.feed(&"\x1b[C".repeat(11), &mut echo);
+        editor.feed("\x1b[B", &mut echo);
+        assert_eq!(editor.cursor(), "for x in a b\n  echo $x".len());
+        // The completed line has spaces instead of the row breaks
+        assert_eq!(
+            editor.feed("\r", &mut echo),
+            Some(Edit::Line("for x in a b   echo $x end".to_string()))
+        );
+    }
+
+    #[test]
+    fn goes_through_the_entered_lines_from_the_first_and_last_row() {
+        let mut editor = LineEditor::new();
+        let mut echo = String::new();
+        editor.feed("one\r", &mut echo);
+        editor.feed("a\x1b\rb\x1b[A", &mut echo);
+        assert_eq!(editor.line(), "a\nb");
+        assert_eq!(editor.cursor(), 1);
+        editor.feed("\x1b[A", &mut echo);
+        assert_eq!(editor.line(), "one");
+        editor.feed("\x1b[B", &mut echo);
+        assert_eq!(editor.line(), "a\nb");
+    }
+
+    #[test]
+    fn replaces_the_line_with_the_external_edit() {
+        let mut editor = LineEditor::new().with_external_editor(|line| match line {
+            "fail" => Err(io::Error::other("failed")),
+            _ => Ok(format!("{}\r\n\tdone\n", line)),
+        });
+        let mut echo = String::new();
+        editor.feed("echo\x18\x05", &mut echo);
+        assert_eq!(editor.line(), "echo\n done");
+        assert_eq!(editor.cursor(), editor.line().len());
+        // The edit can be undone
+        editor.feed("\x1f", &mut echo);
+        assert_eq!(editor.line(), "echo");
+        editor.feed("\x15fail\x18\x05", &mut echo);
+        assert_eq!(editor.line(), "fail");
+        assert!(echo.ends_with('\x07'));
+    }
+
+    #[test]
+    fn ignores_ctrl_x_ctrl_e_without_an_external_editor() {
+        assert_eq!(line("ls\x18\x05\r"), Some(Edit::Line("ls".to_string())));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn edits_in_a_temporary_file() {
+        assert_eq!(edit_with("printf 'ls -la' >", "ls").unwrap(), "ls -la");
+        assert_eq!(edit_with("cat", "ls").unwrap(), "ls");
+        assert!(edit_with("false", "ls").is_err());
+    }
+
+    #[test]
+    fn cuts_words_delimited_by_whitespace() {
+        assert_eq!(word_start("ls -la  "), 3);
+        assert_eq!(word_start("日本 語"), "日本 ".len());
+        assert_eq!(word_start("word"), 0);
+    }
+
+    #[test]
+    fn ignores_unknown_sequences() {
+        assert_eq!(
+            line("a\x1b[15~\x1bOPb\r"),
+            Some(Edit::Line("ab".to_string()))
+        );
+    }
+
+    /// Completes the words to fixed candidates
+    struct Words(&'static [&'static str]);
+
+    impl Completer for Words {
+        fn complete(&mut self, line: &str, cursor: usize) -> Completion {
+            let word = &line[completed_word_start(&line[..cursor])..cursor];
+            let candidates = self.0.iter().filter(|w| w.starts_with(word));
+            Completion::Ready(candidates.map(|w| Candidate::new(w)).collect())
+        }
+    }
+
+    /// Completes after a delay, or never if there is no delay
+    struct Slow(Option<std::time::Duration>);
+
+    impl Completer for Slow {
+        fn complete(&mut self, _: &str, _: usize) -> Completion {
+            let (sender, candidates) = mpsc::channel();
+            if let Some(delay) = self.0 {
+                std::thread::spawn(move || {
+                    std::thread::sleep(delay);
+                    let _ = sender.send(vec![Candidate::new("status")]);
+                });
+            } else {
+                std::mem::forget(sender);
+            }
+            Completion::Pending {
+                candidates,
+                timeout: std::time::Duration::from_millis(50),
+            }
+        }
+    }
+
+    #[test]
+    fn completes_a_single_candidate() {
+        let mut editor = LineEditor::new().with_completer(Words(&["status", "stop"]));
+        let edit = editor.feed("ls sta\t-a\r", &mut String::new());
+        assert_eq!(edit, Some(Edit::Line("ls status -a".to_string())));
+    }
+
+    #[test]
+    fn completes_the_common_prefix_and_opens_a_menu() {
+        let mut editor = LineEditor::new().with_completer(Words(&["status", "stop", "echo"]));
+        let mut echo = String::new();
+        editor.feed("s\t", &mut echo);
+        assert_eq!(editor.line(), "st");
+        echo.clear();
+        editor.feed("\t", &mut echo);
+        assert_eq!(editor.line(), "status");
+        assert!(echo.contains("\x1b[7mstatus\x1b[27m\r\nstop"));
+        editor.feed("\t", &mut echo);
+        assert_eq!(editor.line(), "stop");
+        echo.clear();
+        editor.feed("\r", &mut echo);
+        assert!(echo.contains("\x1b[J"));
+        assert_eq!(editor.line(), "stop ");
+        let edit = editor.feed("-a\r", &mut echo);
+        assert_eq!(edit, Some(Edit::Line("stop -a".to_string())));
+    }
+
+    /// Completes to the menu of three commands with descriptions
+    struct Commands;
+
+    impl Completer for Commands {
+        fn complete(&mut self, _: &str, _: usize) -> Completion {
+            Completion::Ready(vec![
+                Candidate::new("status").with_description("Shows the status"),
+                Candidate::new("stop").with_description("Stops the service"),
+                Candidate::new("su"),
+            ])
+        }
+    }
+
+    #[test]
+    fn shows_the_descriptions_in_the_menu() {
+        let mut editor = LineEditor::new().with_completer(Commands);
+        let mut echo = String::new();
+        editor.feed("s\t\t", &mut echo);
+        assert!(echo.contains("status  Shows the status"));
+        assert!(echo.contains("stop    Stops the service"));
+        assert!(echo.contains("\r\nsu"));
+    }
+
+    #[test]
+    fn moves_through_the_menu() {
+        let mut editor = LineEditor::new().with_completer(Commands);
+        let mut echo = String::new();
+        editor.feed("ls s\t\t", &mut echo);
+        assert_eq!(editor.line(), "ls status");
+        editor.feed("\x1b[Z", &mut echo);
+        assert_eq!(editor.line(), "ls su");
+        editor.feed("\x1b[A\x1b[B\x1b[B", &mut echo);
+        assert_eq!(editor.line(), "ls status");
+        editor.feed("\x07", &mut echo);
+        assert_eq!(editor.line(), "ls s");
+        let edit = editor.feed("\t\t\t-\r", &mut echo);
+        assert_eq!(edit, Some(Edit::Line("ls stop-".to_string())));
+    }
+
+    #[test]
+    fn scrolls_the_menu() {
+        const WORDS: [&str; 12] = [
+            "a0", "a1", "a2", "a3", "a4", "a5", "a6", "a7", "a8", "a9", "a10", "a11",
+        ];
+        let mut editor = LineEditor::new().with_completer(Words(&WORDS));
+        let mut echo = String::new();
+        editor.feed("a\t\t", &mut echo);
+        assert!(!echo.contains("a10"));
+        echo.clear();
+        editor.feed("\x1b[Z", &mut echo);
+        assert_eq!(editor.line(), "a11");
+        assert!(echo.contains("a2\r\n"));
+        assert!(!echo.contains("a1\r\n"));
+    }
+
+    #[test]
+    fn rings_the_bell_without_candidates() {
+        let mut editor = LineEditor::new().with_completer(Words(&["status"]));
+        let mut echo = String::new();
+        editor.feed("x\t", &mut echo);
+        assert!(echo.ends_with('\x07'));
+        assert_eq!(editor.line(), "x");
+    }
+
+    #[test]
+    fn waits_for_pending_completions() {
+        let delay = std::time::Duration::from_millis(1);
+        let mut editor = LineEditor::new().with_completer(Slow(Some(delay)));
+        let mut echo = String::new();
+        assert_eq!(editor.feed("s\t -a\r", &mut echo), None);
+        assert!(editor.is_completing());
+        editor.wait_for_completion(&mut echo);
+        assert!(!editor.is_completing());
+        let edit = editor.feed("", &mut echo);
+        assert_eq!(edit, Some(Edit::Line("status  -a".to_string())));
+    }
+
+    #[test]
+    fn gives_up_pending_completions_after_the_timeout() {
+        let mut editor = LineEditor::new().with_completer(Slow(None));
+        let mut echo = String::new();
+        editor.feed("s\t\r", &mut echo);
+        echo.clear();
+        editor.wait_for_completion(&mut echo);
+        assert!(echo.ends_with('\x07'));
+        assert_eq!(
+            editor.feed("", &mut echo),
+            Some(Edit::Line("s".to_string()))
+        );
+    }
+
+    #[test]
+    fn undoes_completions() {
+        let mut editor = LineEditor::new().with_completer(Words(&["status"]));
+        let edit = editor.feed("s\t\x1f\r", &mut String::new());
+        assert_eq!(edit, Some(Edit::Line("s".to_string())));
+    }
+}
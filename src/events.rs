@@ -0,0 +1,43 @@
+//! Events that are emitted by the [`Shell`](crate::Shell) while it processes
+//! user input
+use std::{sync::mpsc::Sender, time::Duration};
+
+use crate::CommandError;
+
+/// An event that occured while the shell processed a line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShellEvent {
+    /// A line was read from the user
+    LineRead(String),
+    /// A command is about to be executed
+    CommandStarted {
+        name: String,
+        arguments: Vec<String>,
+    },
+    /// A command finished its execution
+    CommandFinished {
+        name: String,
+        duration: Duration,
+        result: Result<(), CommandError>,
+    },
+    /// Processing the line resulted in an error
+    ///
+    /// Blank lines are not reported.
+    Error(CommandError),
+}
+
+/// Keeps track of all receivers that subscribed to the events of a shell
+#[derive(Default)]
+pub(crate) struct Subscribers(Vec<Sender<ShellEvent>>);
+
+impl Subscribers {
+    pub(crate) fn add(&mut self, sender: Sender<ShellEvent>) {
+        self.0.push(sender);
+    }
+
+    /// Sends the event to every subscriber and forgets about subscribers whose
+    /// receiver has been dropped
+    pub(crate) fn emit(&mut self, event: ShellEvent) {
+        self.0.retain(|s| s.send(event.clone()).is_ok());
+    }
+}
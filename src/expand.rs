@@ -0,0 +1,93 @@
+//! Expansions that are applied to arguments before a command is executed
+use std::env;
+
+/// Replaces a leading `~` or `~user` with the home directory of the current
+/// or the given user
+///
+/// The argument is returned unchanged if the home directory is unknown.
+pub(crate) fn tilde(argument: &str) -> String {
+    let Some(rest) = argument.strip_prefix('~') else {
+        return argument.to_string();
+    };
+    let (user, path) = match rest.find('/') {
+        Some(pos) => rest.split_at(pos),
+        None => (rest, ""),
+    };
+
+    let home = if user.is_empty() {
+        env::var("HOME").ok()
+    } else {
+        home_of(user)
+    };
+    match home {
+        Some(home) if home.trim_end_matches('/').is_empty() && path.is_empty() => "/".to_string(),
+        Some(home) => format!("{}{}", home.trim_end_matches('/'), path),
+        None => argument.to_string(),
+    }
+}
+
+/// Looks up the home directory of the user in the user database
+#[cfg(unix)]
+fn home_of(user: &str) -> Option<String> {
+    use std::ffi::{CStr, CString};
+
+    let name = CString::new(user).ok()?;
+    let mut buf = vec![0 as libc::c_char; 4096];
+    // SAFETY: passwd only contains plain data and is filled by getpwnam_r
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut();
+
+    // SAFETY: all pointers are valid for the duration of the call and `buf`
+    // outlives the use of the strings in `passwd`
+    let ret = unsafe {
+        libc::getpwnam_r(
+            name.as_ptr(),
+            &mut passwd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+    if ret != 0 || result.is_null() || passwd.pw_dir.is_null() {
+        return None;
+    }
+    // SAFETY: getpwnam_r succeeded, so pw_dir points to a string inside `buf`
+    let dir = unsafe { CStr::from_ptr(passwd.pw_dir) };
+    Some(dir.to_string_lossy().into_owned())
+}
+
+#[cfg(not(unix))]
+fn home_of(_user: &str) -> Option<String> {
+    None
+}
+
+/// Replaces `$name` and `${name}` with the values of the variables
+///
+/// References to unknown variables are left unchanged.
+pub(crate) fn variables(text: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find('$') {
+        expanded.push_str(&rest[..pos]);
+        let reference = &rest[pos + 1..];
+        let (name, len) = match reference.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = reference
+                    .find(|c: char| !c.is_alphanumeric() && c != '_')
+                    .unwrap_or(reference.len());
+                (&reference[..end], end)
+            }
+        };
+        match lookup(name).filter(|_| !name.is_empty()) {
+            Some(value) => expanded.push_str(&value),
+            None => expanded.push_str(&rest[pos..pos + 1 + len]),
+        }
+        rest = &reference[len..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
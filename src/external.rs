@@ -0,0 +1,100 @@
+//! Execution of native OS commands from within the shell
+use std::{
+    io::{self, Read, Write},
+    path::Path,
+    process::{Command, ExitStatus, Stdio},
+    sync::mpsc,
+    thread,
+};
+
+use crate::{CommandError, Context};
+
+/// Runs the program with the arguments in the working directory of the
+/// session and streams its output and errors to the output of the shell
+///
+/// Returns the exit status of the process, which may have failed.
+pub(crate) fn run(
+    program: &str,
+    arguments: &[String],
+    ctx: &Context,
+) -> Result<ExitStatus, CommandError> {
+    let mut child = Command::new(program)
+        .args(arguments)
+        .current_dir(ctx.working_dir())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| CommandError::Custom(format!("{}: {}", program, e)))?;
+
+    // Both streams are read on their own threads, so that neither of them
+    // blocks the process while the other is being forwarded
+    let (sender, receiver) = mpsc::channel();
+    let readers = [
+        child
+            .stdout
+            .take()
+            .map(|s| Box::new(s) as Box<dyn Read + Send>),
+        child
+            .stderr
+            .take()
+            .map(|s| Box::new(s) as Box<dyn Read + Send>),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|stream| {
+        let sender = sender.clone();
+        thread::spawn(move || forward(stream, sender))
+    })
+    .collect::<Vec<_>>();
+    drop(sender);
+
+    let mut out = ctx.out();
+    for chunk in receiver {
+        out.write_all(&chunk)?;
+        out.flush()?;
+    }
+    for reader in readers {
+        let _ = reader.join();
+    }
+
+    Ok(child.wait()?)
+}
+
+/// Returns the exit code of a process, or 128 plus the number of the signal
+/// that terminated it like POSIX shells do
+pub(crate) fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return 128 + signal;
+    }
+    status.code().unwrap_or(1)
+}
+
+/// Runs the program like [`run`], but collects its output and errors
+/// instead of streaming them, so that it can run on another thread than the
+/// shell
+pub(crate) fn output(
+    program: &str,
+    arguments: &[String],
+    working_dir: &Path,
+) -> io::Result<(Vec<u8>, ExitStatus)> {
+    let output = Command::new(program)
+        .args(arguments)
+        .current_dir(working_dir)
+        .stdin(Stdio::null())
+        .output()?;
+    let mut collected = output.stdout;
+    collected.extend_from_slice(&output.stderr);
+    Ok((collected, output.status))
+}
+
+fn forward(mut stream: Box<dyn Read + Send>, sender: mpsc::Sender<Vec<u8>>) -> io::Result<()> {
+    let mut buf = [0; 4096];
+    loop {
+        let read = stream.read(&mut buf)?;
+        if read == 0 || sender.send(buf[..read].to_vec()).is_err() {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,246 @@
+//! Expansion of `*.log`-style patterns in arguments
+use std::{fs, path::Path};
+
+/// Resolves glob patterns to the values they match
+///
+/// The default resolver is [`FsGlobResolver`], which matches against the
+/// filesystem. Other resolvers can match e.g. against remote resources.
+pub trait GlobResolver {
+    /// Returns all values the pattern matches. An empty result leaves the
+    /// pattern unchanged
+    fn expand(&self, pattern: &str) -> Vec<String>;
+}
+
+/// Matches glob patterns against the paths in the filesystem
+///
+/// Supports `*`, `?` and character classes like `[a-z]` or `[!0-9]` in every
+/// path component. Hidden files are only matched by components that start
+/// with a `.`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsGlobResolver;
+
+impl GlobResolver for FsGlobResolver {
+    fn expand(&self, pattern: &str) -> Vec<String> {
+        let (mut candidates, rest) = match pattern.strip_prefix('/') {
+            Some(rest) => (vec!["/".to_string()], rest),
+            None => (vec![String::new()], pattern),
+        };
+
+        for component in rest.split('/').filter(|c| !c.is_empty()) {
+            candidates = candidates
+                .iter()
+                .flat_map(|base| expand_component(base, component))
+                .collect();
+        }
+
+        candidates.retain(|c| !c.is_empty());
+        candidates.sort();
+        candidates
+    }
+}
+
+/// Returns `true` if the argument contains characters with a special meaning
+/// in glob patterns
+pub(crate) fn is_pattern(argument: &str) -> bool {
+    argument.contains(['*', '?', '['])
+}
+
+/// Appends all entries of `base` that match the component of a pattern
+fn expand_component(base: &str, component: &str) -> Vec<String> {
+    let join = |name: &str| {
+        if base.is_empty() || base.ends_with('/') {
+            format!("{}{}", base, name)
+        } else {
+            format!("{}/{}", base, name)
+        }
+    };
+
+    if !is_pattern(component) {
+        let path = join(component);
+        return if Path::new(&path).exists() {
+            vec![path]
+        } else {
+            vec![]
+        };
+    }
+
+    let dir = if base.is_empty() { "." } else { base };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| !name.starts_with('.') || component.starts_with('.'))
+        .filter(|name| matches(component, name))
+        .map(|name| join(&name))
+        .collect()
+}
+
+/// Matches the name against a single component of a glob pattern
+///
+/// # Example
+/// ```rust
+/// use simpleshell::glob::matches;
+///
+/// assert!(matches("*.log", "server.log"));
+/// assert!(matches("db-[0-9]?", "db-1a"));
+/// assert!(!matches("[!a]*", "abc"));
+/// ```
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches_at(&pattern, &name)
+}
+
+/// Matches with two positions and goes back to the last `*` on a mismatch,
+/// so that the time grows with the product of the lengths instead of
+/// exponentially with the number of stars
+fn matches_at(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // The position after the last `*` and the position in the name from which
+    // the rest of the pattern is tried
+    let mut star = None;
+    while n < name.len() {
+        if pattern.get(p) == Some(&'*') {
+            p += 1;
+            star = Some((p, n));
+        } else if let Some(next) = match_one(pattern, p, name[n]) {
+            p = next;
+            n += 1;
+        } else if let Some((after_star, from)) = star {
+            // The `*` takes one more character
+            p = after_star;
+            n = from + 1;
+            star = Some((after_star, n));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Returns the position after the element of the pattern at `p` if it
+/// matches the character
+fn match_one(pattern: &[char], p: usize, c: char) -> Option<usize> {
+    match *pattern.get(p)? {
+        '?' => Some(p + 1),
+        '[' => match class_end(&pattern[p..]) {
+            Some(end) => in_class(&pattern[p + 1..p + end], c).then_some(p + end + 1),
+            // An unterminated class is matched literally
+            None => (c == '[').then_some(p + 1),
+        },
+        literal => (literal == c).then_some(p + 1),
+    }
+}
+
+/// Returns the index of the `]` that closes the class at the start of the
+/// pattern
+fn class_end(pattern: &[char]) -> Option<usize> {
+    let first = match pattern.get(1) {
+        Some('!') | Some('^') => 3,
+        _ => 2,
+    };
+    // A `]` directly after the opening bracket belongs to the class
+    (first..pattern.len()).find(|&i| pattern[i] == ']')
+}
+
+fn in_class(class: &[char], c: char) -> bool {
+    let (negated, class) = match class.first() {
+        Some('!') | Some('^') => (true, &class[1..]),
+        _ => (false, class),
+    };
+
+    let mut found = false;
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            found |= (class[i]..=class[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            found |= class[i] == c;
+            i += 1;
+        }
+    }
+    found != negated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(matches("*", ""));
+        assert!(matches("a*b*c", "aXbYc"));
+        assert!(matches("a*b*c", "abbc"));
+        assert!(!matches("a*b*c", "abcd"));
+        assert!(matches("??", "äö"));
+        assert!(!matches("?", ""));
+        assert!(matches("**.log", "x.log"));
+    }
+
+    #[test]
+    fn backtracks_to_the_last_star() {
+        assert!(matches("*ab", "aab"));
+        assert!(matches("*aab", "aaab"));
+        assert!(matches("a*ab*b", "aababab"));
+        assert!(!matches("*a*b", "ba"));
+        assert!(matches("*[0-9]x", "a1y2x"));
+    }
+
+    #[test]
+    fn backtracking_stays_fast() {
+        let name = "a".repeat(100);
+        assert!(!matches(&format!("{}b", "*a".repeat(30)), &name));
+        assert!(matches(&"a*".repeat(50), &name));
+    }
+
+    #[test]
+    fn matches_classes() {
+        assert!(matches("[abc]", "b"));
+        assert!(matches("[a-c][0-9]", "c7"));
+        assert!(!matches("[a-c]", "d"));
+        assert!(matches("[!a-c]", "d"));
+        assert!(matches("[^a-c]", "d"));
+        assert!(matches("[a-]", "-"));
+    }
+
+    #[test]
+    fn closing_bracket_first_belongs_to_the_class() {
+        assert!(matches("[]a]", "]"));
+        assert!(matches("[]a]", "a"));
+        assert!(!matches("[]a]", "b"));
+        assert!(matches("[!]a]", "b"));
+        assert!(!matches("[!]a]", "]"));
+    }
+
+    #[test]
+    fn unterminated_classes_are_literal() {
+        assert!(matches("[!]", "[!]"));
+        assert!(!matches("[!]", "a"));
+        assert!(matches("[]", "[]"));
+        assert!(matches("x[a", "x[a"));
+        assert!(matches("[*", "[abc"));
+    }
+
+    #[test]
+    fn expands_paths() {
+        let dir = std::env::temp_dir().join(format!("simpleshell-glob-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        for file in ["a.log", "b.log", "c.txt", ".hidden.log", "sub/d.log"] {
+            fs::write(dir.join(file), "").unwrap();
+        }
+        let base = dir.to_str().unwrap();
+
+        let expand = |pattern: &str| FsGlobResolver.expand(&format!("{}/{}", base, pattern));
+        assert_eq!(
+            expand("*.log"),
+            [format!("{}/a.log", base), format!("{}/b.log", base)]
+        );
+        assert_eq!(expand(".*.log"), [format!("{}/.hidden.log", base)]);
+        assert_eq!(expand("*/d.log"), [format!("{}/sub/d.log", base)]);
+        assert!(expand("*.none").is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
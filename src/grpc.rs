@@ -0,0 +1,301 @@
+//! A gRPC admin interface that drives the commands of a shell, see
+//! [`Shell::serve_grpc`]
+//!
+//! The service is described in `proto/admin.proto` of this crate, which
+//! clients can generate their stubs from. The messages are provided here for
+//! clients written in Rust.
+use std::{
+    io::{self, Write},
+    net::SocketAddr,
+    pin::Pin,
+    sync::mpsc,
+    thread,
+};
+
+use tokio::sync::{mpsc as channel, oneshot};
+use tokio_stream::{wrappers::UnboundedReceiverStream, Stream, StreamExt};
+use tonic::{
+    codegen::{http, Body, BoxFuture, Service, StdError},
+    server::{Grpc, NamedService, ServerStreamingService, UnaryService},
+    Code, Request, Response, Status,
+};
+use tonic_prost::ProstCodec;
+
+use crate::{CommandInfo, Shell};
+
+/// The request to execute a line like user input
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CommandRequest {
+    /// The line to execute
+    #[prost(string, tag = "1")]
+    pub line: String,
+}
+
+/// The result of an executed line
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CommandResponse {
+    /// Whether the command succeeded
+    #[prost(bool, tag = "1")]
+    pub success: bool,
+    /// Everything the command has written
+    #[prost(string, tag = "2")]
+    pub output: String,
+    /// The error message if the command failed
+    #[prost(string, tag = "3")]
+    pub error: String,
+}
+
+/// The request to list the available commands
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListCommandsRequest {}
+
+/// The commands that are available in the active profile
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListCommandsResponse {
+    /// The available commands
+    #[prost(message, repeated, tag = "1")]
+    pub commands: Vec<CommandDescriptor>,
+}
+
+/// The metadata of a command
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CommandDescriptor {
+    /// The name of the command
+    #[prost(string, tag = "1")]
+    pub name: String,
+    /// A short description what the command does
+    #[prost(string, tag = "2")]
+    pub description: String,
+    /// How the command is called
+    #[prost(string, tag = "3")]
+    pub usage: String,
+    /// The version of the command or an empty string
+    #[prost(string, tag = "4")]
+    pub version: String,
+}
+
+/// A piece of the output of a running command
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct OutputChunk {
+    /// The written text
+    #[prost(string, tag = "1")]
+    pub data: String,
+}
+
+/// The work that has to be done by the thread that owns the shell
+enum Job {
+    Execute {
+        line: String,
+        output: channel::UnboundedSender<String>,
+        done: oneshot::Sender<Result<(), String>>,
+    },
+    List(oneshot::Sender<Vec<CommandInfo>>),
+}
+
+/// Serves the admin interface on `addr` and executes the requests one after
+/// another with the shell
+pub(crate) fn serve(shell: &Shell, addr: SocketAddr) -> io::Result<()> {
+    let (jobs, queue) = mpsc::channel();
+    let server = thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new()?;
+        runtime.block_on(async {
+            tonic::transport::Server::builder()
+                .add_service(AdminServer { jobs })
+                .serve(addr)
+                .await
+                .map_err(io::Error::other)
+        })
+    });
+
+    // The queue is closed once the server has stopped
+    for job in queue {
+        match job {
+            Job::Execute { line, output, done } => {
+                let writer = Box::new(ChunkWriter(output));
+                let outcome = shell.redirected(Box::new(io::empty()), writer, false, || {
+                    shell.process_line(&line)
+                });
+                let result = outcome.result.map_err(|e| shell.messages.error(&e));
+                let _ = done.send(result);
+            }
+            Job::List(done) => {
+                let _ = done.send(shell.catalog());
+            }
+        }
+    }
+    server
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::other("the server panicked")))
+}
+
+/// Sends everything that is written to a client
+struct ChunkWriter(channel::UnboundedSender<String>);
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A disconnected client must not fail the command
+        let _ = self.0.send(String::from_utf8_lossy(buf).into_owned());
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The output and the result of a queued line
+type Execution = (
+    channel::UnboundedReceiver<String>,
+    oneshot::Receiver<Result<(), String>>,
+);
+
+#[derive(Clone)]
+struct AdminServer {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl AdminServer {
+    /// Queues the line for execution and returns its output and result
+    fn execute(&self, line: String) -> Result<Execution, Status> {
+        let (output, chunks) = channel::unbounded_channel();
+        let (done, result) = oneshot::channel();
+        self.jobs
+            .send(Job::Execute { line, output, done })
+            .map_err(|_| Status::unavailable("the shell has stopped"))?;
+        Ok((chunks, result))
+    }
+
+    async fn execute_command(&self, request: CommandRequest) -> Result<CommandResponse, Status> {
+        let (mut chunks, result) = self.execute(request.line)?;
+        let result = result
+            .await
+            .map_err(|_| Status::aborted("the command was interrupted"))?;
+        let mut output = String::new();
+        while let Ok(chunk) = chunks.try_recv() {
+            output.push_str(&chunk);
+        }
+        Ok(CommandResponse {
+            success: result.is_ok(),
+            output,
+            error: result.err().unwrap_or_default(),
+        })
+    }
+
+    async fn list_commands(&self) -> Result<ListCommandsResponse, Status> {
+        let (done, result) = oneshot::channel();
+        self.jobs
+            .send(Job::List(done))
+            .map_err(|_| Status::unavailable("the shell has stopped"))?;
+        let commands = result
+            .await
+            .map_err(|_| Status::aborted("the shell has stopped"))?
+            .into_iter()
+            .map(|info| CommandDescriptor {
+                name: info.name,
+                description: info.description,
+                usage: info.usage,
+                version: info.version.unwrap_or_default(),
+            })
+            .collect();
+        Ok(ListCommandsResponse { commands })
+    }
+
+    fn stream_output(&self, request: CommandRequest) -> Result<OutputStream, Status> {
+        let (chunks, result) = self.execute(request.line)?;
+        let output = UnboundedReceiverStream::new(chunks).map(|data| Ok(OutputChunk { data }));
+        // The stream ends with an error if the command failed
+        let mut result = Some(result);
+        let end = tokio_stream::once(())
+            .then(move |_| result.take().expect("the stream has a single item"))
+            .filter_map(|result| match result {
+                Ok(Ok(())) => None,
+                Ok(Err(error)) => Some(Err(Status::unknown(error))),
+                Err(_) => Some(Err(Status::aborted("the command was interrupted"))),
+            });
+        Ok(Box::pin(output.chain(end)))
+    }
+}
+
+type OutputStream = Pin<Box<dyn Stream<Item = Result<OutputChunk, Status>> + Send>>;
+
+struct ExecuteCommand(AdminServer);
+
+impl UnaryService<CommandRequest> for ExecuteCommand {
+    type Response = CommandResponse;
+    type Future = BoxFuture<Response<CommandResponse>, Status>;
+
+    fn call(&mut self, request: Request<CommandRequest>) -> Self::Future {
+        let server = self.0.clone();
+        Box::pin(async move {
+            server
+                .execute_command(request.into_inner())
+                .await
+                .map(Response::new)
+        })
+    }
+}
+
+struct ListCommands(AdminServer);
+
+impl UnaryService<ListCommandsRequest> for ListCommands {
+    type Response = ListCommandsResponse;
+    type Future = BoxFuture<Response<ListCommandsResponse>, Status>;
+
+    fn call(&mut self, _: Request<ListCommandsRequest>) -> Self::Future {
+        let server = self.0.clone();
+        Box::pin(async move { server.list_commands().await.map(Response::new) })
+    }
+}
+
+struct StreamOutput(AdminServer);
+
+impl ServerStreamingService<CommandRequest> for StreamOutput {
+    type Response = OutputChunk;
+    type ResponseStream = OutputStream;
+    type Future = BoxFuture<Response<OutputStream>, Status>;
+
+    fn call(&mut self, request: Request<CommandRequest>) -> Self::Future {
+        let stream = self.0.stream_output(request.into_inner());
+        Box::pin(async move { stream.map(Response::new) })
+    }
+}
+
+impl<B> Service<http::Request<B>> for AdminServer
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::Body>;
+    type Error = std::convert::Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(
+        &mut self,
+        _: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let server = self.clone();
+        match request.uri().path() {
+            "/simpleshell.Admin/ExecuteCommand" => Box::pin(async move {
+                let mut grpc = Grpc::new(ProstCodec::default());
+                Ok(grpc.unary(ExecuteCommand(server), request).await)
+            }),
+            "/simpleshell.Admin/ListCommands" => Box::pin(async move {
+                let mut grpc = Grpc::new(ProstCodec::default());
+                Ok(grpc.unary(ListCommands(server), request).await)
+            }),
+            "/simpleshell.Admin/StreamOutput" => Box::pin(async move {
+                let mut grpc = Grpc::new(ProstCodec::default());
+                Ok(grpc.server_streaming(StreamOutput(server), request).await)
+            }),
+            _ => Box::pin(async move { Ok(Status::new(Code::Unimplemented, "").into_http()) }),
+        }
+    }
+}
+
+impl NamedService for AdminServer {
+    const NAME: &'static str = "simpleshell.Admin";
+}
@@ -0,0 +1,549 @@
+//! The lines the user entered during the session
+use std::{
+    fs,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(feature = "encrypted-history")]
+use chacha20poly1305::{
+    aead::{Aead, Generate, KeyInit},
+    Key, XChaCha20Poly1305, XNonce,
+};
+#[cfg(feature = "regex")]
+use regex::Regex;
+
+/// Rules that decide which lines are recorded in the history
+///
+/// By default every line that is not blank is recorded.
+///
+/// # Example
+/// ```rust
+/// # use simpleshell::{testing::TestShell, HistoryPolicy, Shell};
+/// let policy = HistoryPolicy::default()
+///     .ignore_duplicates(true)
+///     .ignore_space(true);
+/// let mut shell = TestShell::new(Shell::new(None, vec![]).with_history_policy(policy));
+///
+/// shell.run("status");
+/// shell.run("status");
+/// shell.run(" secret-command");
+/// assert_eq!(shell.shell().history(), ["status"]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct HistoryPolicy {
+    /// Skip lines that are equal to the previous entry
+    pub ignore_duplicates: bool,
+    /// Skip lines that start with a space
+    pub ignore_space: bool,
+    /// Skip lines that match any of the patterns, e.g. lines with secrets
+    #[cfg(feature = "regex")]
+    pub ignore_patterns: Vec<Regex>,
+    /// Skip lines that are longer than the given number of characters
+    pub max_length: Option<usize>,
+}
+
+impl HistoryPolicy {
+    /// Configures whether lines equal to the previous entry are skipped
+    pub fn ignore_duplicates(mut self, enabled: bool) -> Self {
+        self.ignore_duplicates = enabled;
+        self
+    }
+
+    /// Configures whether lines starting with a space are skipped
+    pub fn ignore_space(mut self, enabled: bool) -> Self {
+        self.ignore_space = enabled;
+        self
+    }
+
+    /// Skips lines that match the regular expression
+    ///
+    /// # Example
+    /// ```rust
+    /// # use simpleshell::{testing::TestShell, HistoryPolicy, Shell};
+    /// let policy = HistoryPolicy::default()
+    ///     .with_ignore_pattern(r"(?i)password")
+    ///     .unwrap();
+    /// let mut shell = TestShell::new(Shell::new(None, vec![]).with_history_policy(policy));
+    ///
+    /// shell.run("status");
+    /// shell.run("login --password hunter2");
+    /// assert_eq!(shell.shell().history(), ["status"]);
+    /// ```
+    #[cfg(feature = "regex")]
+    pub fn with_ignore_pattern(mut self, pattern: &str) -> Result<Self, regex::Error> {
+        self.ignore_patterns.push(Regex::new(pattern)?);
+        Ok(self)
+    }
+
+    /// Skips lines that are longer than `length` characters
+    pub fn with_max_length(mut self, length: usize) -> Self {
+        self.max_length = Some(length);
+        self
+    }
+
+    /// Returns `true` if the line may be recorded after the `previous` entry
+    pub fn allows(&self, line: &str, previous: Option<&str>) -> bool {
+        #[cfg(feature = "regex")]
+        if self.ignore_patterns.iter().any(|p| p.is_match(line)) {
+            return false;
+        }
+        let ignored = line.trim().is_empty()
+            || (self.ignore_duplicates && previous == Some(line))
+            || (self.ignore_space && line.starts_with(' '))
+            || self
+                .max_length
+                .is_some_and(|max| line.chars().count() > max);
+        !ignored
+    }
+}
+
+/// A line in the history
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// The line the user entered
+    pub line: String,
+    /// When the line was entered. `None` for entries loaded from history
+    /// files without timestamps
+    pub time: Option<SystemTime>,
+}
+
+impl HistoryEntry {
+    /// Formats the time of the entry as `YYYY-MM-DD HH:MM:SS` in UTC
+    pub fn format_time(&self) -> Option<String> {
+        let secs = self.time?.duration_since(UNIX_EPOCH).ok()?.as_secs();
+        let (days, secs) = ((secs / 86400) as i64, secs % 86400);
+
+        // Converts the days since the epoch to a civil date, see
+        // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+
+        Some(format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        ))
+    }
+}
+
+/// The recorded lines of a session
+#[derive(Default)]
+pub(crate) struct History {
+    pub(crate) entries: Vec<HistoryEntry>,
+    pub(crate) policy: HistoryPolicy,
+    pub(crate) file: Option<HistoryFile>,
+    /// Whether other sessions write to the history file at the same time
+    pub(crate) shared: bool,
+}
+
+impl History {
+    /// Records the line if the policy allows it
+    ///
+    /// Persisting the entry is best effort, the entry is kept in the session
+    /// even if it cannot be written to the history file.
+    pub(crate) fn add(&mut self, line: &str) {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let _ = self.sync();
+        if !self
+            .policy
+            .allows(line, self.entries.last().map(|e| e.line.as_str()))
+        {
+            return;
+        }
+        let entry = HistoryEntry {
+            line: line.to_string(),
+            time: Some(SystemTime::now()),
+        };
+        if let Some(file) = &mut self.file {
+            let appended = file.append(&entry);
+            if appended.is_ok() && self.shared {
+                // The entry is read back together with the entries of the
+                // other sessions to keep the order of the file
+                if self.sync().is_ok() {
+                    return;
+                }
+            }
+        }
+        self.entries.push(entry);
+    }
+
+    /// Reads the entries other sessions appended to a shared history file
+    pub(crate) fn sync(&mut self) -> io::Result<()> {
+        let Some(file) = self.file.as_mut().filter(|_| self.shared) else {
+            return Ok(());
+        };
+        match file.read_new()? {
+            NewEntries::Appended(mut entries) => self.entries.append(&mut entries),
+            NewEntries::Replaced(entries) => self.entries = entries,
+        }
+        Ok(())
+    }
+
+    /// Loads the entries of the file and persists new entries in it
+    pub(crate) fn attach(&mut self, mut file: HistoryFile) -> io::Result<()> {
+        let mut entries = file.load()?;
+        entries.append(&mut self.entries);
+        self.entries = entries;
+        self.file = Some(file);
+        Ok(())
+    }
+
+    /// Removes all entries that match the pattern and returns their number
+    #[cfg(feature = "regex")]
+    pub(crate) fn scrub(&mut self, pattern: &Regex) -> io::Result<usize> {
+        let before = self.entries.len();
+        self.entries.retain(|entry| !pattern.is_match(&entry.line));
+        if let Some(file) = &mut self.file {
+            file.rewrite(&self.entries)?;
+        }
+        Ok(before - self.entries.len())
+    }
+}
+
+/// The entries that were read from a shared history file
+pub(crate) enum NewEntries {
+    /// Entries were appended since the file was read the last time
+    Appended(Vec<HistoryEntry>),
+    /// The file was rewritten and contains the given entries
+    Replaced(Vec<HistoryEntry>),
+}
+
+/// The file the history is persisted in
+pub(crate) struct HistoryFile {
+    path: PathBuf,
+    /// The length of the file when it was read the last time
+    offset: u64,
+    #[cfg(feature = "encrypted-history")]
+    cipher: Option<XChaCha20Poly1305>,
+}
+
+impl HistoryFile {
+    pub(crate) fn new(path: &Path) -> Self {
+        HistoryFile {
+            path: path.to_path_buf(),
+            offset: 0,
+            #[cfg(feature = "encrypted-history")]
+            cipher: None,
+        }
+    }
+
+    /// Encrypts every entry with XChaCha20-Poly1305 and the given key
+    #[cfg(feature = "encrypted-history")]
+    pub(crate) fn encrypted(path: &Path, key: &[u8; 32]) -> Self {
+        HistoryFile {
+            path: path.to_path_buf(),
+            offset: 0,
+            cipher: Some(XChaCha20Poly1305::new(&Key::from(*key))),
+        }
+    }
+
+    /// Reads the entries of the file
+    ///
+    /// Every entry is stored in its own line, optionally preceded by a line
+    /// with its timestamp in the form `#<seconds since the epoch>`. Entries
+    /// that start with `#` are stored with another `#` in front, so they are
+    /// not mistaken for timestamps. A missing file contains no entries.
+    /// Entries that cannot be decrypted are skipped. The permissions of the
+    /// file are restricted to its owner.
+    pub(crate) fn load(&mut self) -> io::Result<Vec<HistoryEntry>> {
+        if let Ok(file) = fs::File::open(&self.path) {
+            restrict(&file)?;
+        }
+        self.offset = 0;
+        match self.read_new()? {
+            NewEntries::Appended(entries) | NewEntries::Replaced(entries) => Ok(entries),
+        }
+    }
+
+    /// Reads the entries that were written since the file was read the last
+    /// time or all entries if the file was rewritten in the meantime
+    pub(crate) fn read_new(&mut self) -> io::Result<NewEntries> {
+        let mut file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(NewEntries::Appended(Vec::new()))
+            }
+            Err(e) => return Err(e),
+        };
+        let replaced = file.metadata()?.len() < self.offset;
+        if replaced {
+            self.offset = 0;
+        }
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+        // Only complete lines are read, the rest may still be written
+        let complete = content
+            .iter()
+            .rposition(|b| *b == b'\n')
+            .map_or(0, |i| i + 1);
+        self.offset += complete as u64;
+
+        let content = String::from_utf8_lossy(&content[..complete]);
+        let mut entries = Vec::new();
+        let mut time = None;
+        for line in content.lines().filter_map(|l| self.decode(l)) {
+            if let Some(escaped) = line.strip_prefix("##") {
+                entries.push(HistoryEntry {
+                    line: format!("#{}", escaped),
+                    time: time.take(),
+                });
+                continue;
+            }
+            let timestamp = line
+                .strip_prefix('#')
+                .and_then(|secs| secs.parse().ok())
+                .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+            match timestamp {
+                Some(timestamp) => time = Some(timestamp),
+                None => entries.push(HistoryEntry {
+                    line,
+                    time: time.take(),
+                }),
+            }
+        }
+        Ok(match replaced {
+            true => NewEntries::Replaced(entries),
+            false => NewEntries::Appended(entries),
+        })
+    }
+
+    /// Appends an entry to the file
+    pub(crate) fn append(&self, entry: &HistoryEntry) -> io::Result<()> {
+        let mut file = open_private(fs::OpenOptions::new().append(true), &self.path)?;
+        // A single write keeps the timestamp and the entry together
+        file.write_all(self.encode_entry(entry).as_bytes())
+    }
+
+    /// Replaces the content of the file with the entries
+    #[cfg(feature = "regex")]
+    pub(crate) fn rewrite(&mut self, entries: &[HistoryEntry]) -> io::Result<()> {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(".tmp");
+        let tmp = self.path.with_file_name(name);
+
+        let mut file = open_private(fs::OpenOptions::new().write(true).truncate(true), &tmp)?;
+        for entry in entries {
+            file.write_all(self.encode_entry(entry).as_bytes())?;
+        }
+        file.sync_all()?;
+        self.offset = file.metadata()?.len();
+        fs::rename(tmp, &self.path)
+    }
+
+    fn encode_entry(&self, entry: &HistoryEntry) -> String {
+        let secs = entry
+            .time
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|time| time.as_secs());
+        let line = match entry.line.starts_with('#') {
+            true => self.encode(&format!("#{}", entry.line)),
+            false => self.encode(&entry.line),
+        };
+        match secs {
+            Some(secs) => format!("{}\n{}\n", self.encode(&format!("#{}", secs)), line),
+            None => format!("{}\n", line),
+        }
+    }
+
+    fn encode(&self, line: &str) -> String {
+        #[cfg(feature = "encrypted-history")]
+        if let Some(cipher) = &self.cipher {
+            let nonce = XNonce::generate();
+            let ciphertext = cipher
+                .encrypt(&nonce, line.as_bytes())
+                .expect("the entry is small enough to be encrypted");
+            return nonce
+                .iter()
+                .chain(&ciphertext)
+                .map(|b| format!("{:02x}", b))
+                .collect();
+        }
+        line.to_string()
+    }
+
+    fn decode(&self, line: &str) -> Option<String> {
+        #[cfg(feature = "encrypted-history")]
+        if let Some(cipher) = &self.cipher {
+            let bytes = (0..line.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(line.get(i..i + 2)?, 16).ok())
+                .collect::<Option<Vec<u8>>>()?;
+            if bytes.len() < 24 {
+                return None;
+            }
+            let (nonce, ciphertext) = bytes.split_at(24);
+            let nonce = XNonce::try_from(nonce).ok()?;
+            let plaintext = cipher.decrypt(&nonce, ciphertext).ok()?;
+            return String::from_utf8(plaintext).ok();
+        }
+        Some(line.to_string())
+    }
+}
+
+/// Opens the file and creates it, if it does not exist, so that only the
+/// owner can read it
+///
+/// The permissions of an existing file are restricted as well.
+fn open_private(options: &mut fs::OpenOptions, path: &Path) -> io::Result<fs::File> {
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(options, 0o600);
+    let file = options.create(true).open(path)?;
+    restrict(&file)?;
+    Ok(file)
+}
+
+/// Makes the file only readable by its owner
+fn restrict(file: &fs::File) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    #[cfg(not(unix))]
+    let _ = file;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(line: &str, secs: Option<u64>) -> HistoryEntry {
+        HistoryEntry {
+            line: line.to_string(),
+            time: secs.map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+        }
+    }
+
+    /// A history file in the temporary directory that is removed on drop
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "simpleshell-history-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_file(&path);
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn reads_timestamps_in_front_of_entries() {
+        let path = TempFile::new("timestamps");
+        fs::write(&path.0, "#1700000000\nstatus\nplain\n#12\n#34\nlast\n").unwrap();
+        let entries = HistoryFile::new(&path.0).load().unwrap();
+        assert_eq!(
+            entries,
+            [
+                entry("status", Some(1_700_000_000)),
+                entry("plain", None),
+                entry("last", Some(34)),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_comments_that_are_not_timestamps() {
+        let path = TempFile::new("comments");
+        fs::write(&path.0, "#todo\n#12a\n#\n").unwrap();
+        let entries = HistoryFile::new(&path.0).load().unwrap();
+        assert_eq!(
+            entries,
+            [entry("#todo", None), entry("#12a", None), entry("#", None)]
+        );
+    }
+
+    #[test]
+    fn entries_that_look_like_timestamps_survive_a_reload() {
+        let path = TempFile::new("escaped");
+        let written = [
+            entry("#1700000000", Some(1_700_000_000)),
+            entry("#42", None),
+            entry("##", Some(5)),
+            entry("echo #1", Some(6)),
+        ];
+        let file = HistoryFile::new(&path.0);
+        for entry in &written {
+            file.append(entry).unwrap();
+        }
+        assert_eq!(
+            fs::read_to_string(&path.0).unwrap(),
+            "#1700000000\n##1700000000\n##42\n#5\n###\n#6\necho #1\n"
+        );
+        assert_eq!(HistoryFile::new(&path.0).load().unwrap(), written);
+    }
+
+    #[test]
+    fn reads_only_complete_new_lines() {
+        let path = TempFile::new("incremental");
+        let mut file = HistoryFile::new(&path.0);
+        file.append(&entry("first", Some(1))).unwrap();
+        assert_eq!(file.load().unwrap(), [entry("first", Some(1))]);
+
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path.0)
+            .unwrap()
+            .write_all(b"second\npart")
+            .unwrap();
+        assert!(matches!(
+            file.read_new().unwrap(),
+            NewEntries::Appended(entries) if entries == [entry("second", None)]
+        ));
+
+        fs::write(&path.0, "new\n").unwrap();
+        assert!(matches!(
+            file.read_new().unwrap(),
+            NewEntries::Replaced(entries) if entries == [entry("new", None)]
+        ));
+    }
+
+    #[test]
+    fn formats_times_in_utc() {
+        assert_eq!(
+            entry("", Some(0)).format_time().as_deref(),
+            Some("1970-01-01 00:00:00")
+        );
+        assert_eq!(
+            entry("", Some(951_782_400 + 3661)).format_time().as_deref(),
+            Some("2000-02-29 01:01:01")
+        );
+        assert_eq!(entry("", None).format_time(), None);
+    }
+
+    #[test]
+    fn policy_skips_ignored_lines() {
+        let policy = HistoryPolicy::default()
+            .ignore_duplicates(true)
+            .ignore_space(true)
+            .with_max_length(5);
+        assert!(policy.allows("ls", Some("pwd")));
+        assert!(!policy.allows("ls", Some("ls")));
+        assert!(!policy.allows(" ls", None));
+        assert!(!policy.allows("   ", None));
+        assert!(!policy.allows("status", None));
+    }
+}
@@ -0,0 +1,270 @@
+//! Inline images in the output of commands, see
+//! [`Context::print_image`](crate::Context::print_image)
+use std::io;
+
+/// An image with 8-bit RGB pixels, e.g. a rendered graph or QR code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    /// The red, green and blue values of the pixels row by row
+    pixels: Vec<u8>,
+}
+
+impl Image {
+    /// Creates an image from the red, green and blue values of its pixels,
+    /// row by row from the top left corner
+    ///
+    /// Returns an error with [`io::ErrorKind::InvalidData`] if the number of
+    /// values does not match the size of the image.
+    pub fn from_rgb(width: usize, height: usize, pixels: Vec<u8>) -> io::Result<Self> {
+        if width == 0 || height == 0 || pixels.len() != width * height * 3 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected {} RGB values", width * height * 3),
+            ));
+        }
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        let i = (y * self.width + x) * 3;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]]
+    }
+}
+
+/// The escape sequences a terminal understands for showing images
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageProtocol {
+    /// DEC Sixel graphics, e.g. in xterm, foot, mlterm and WezTerm
+    Sixel,
+    /// The inline images of iTerm2, which WezTerm supports as well
+    Iterm2,
+}
+
+/// Returns the protocol the terminal the shell writes to supports
+///
+/// Terminals that announce themselves in the environment are recognized
+/// without asking. On Unix, other terminals are asked for their device
+/// attributes, which list Sixel support.
+pub(crate) fn detect() -> Option<ImageProtocol> {
+    let var = |name| std::env::var(name).unwrap_or_default();
+    if matches!(var("TERM_PROGRAM").as_str(), "iTerm.app" | "WezTerm")
+        || var("LC_TERMINAL") == "iTerm2"
+    {
+        return Some(ImageProtocol::Iterm2);
+    }
+    let term = var("TERM");
+    if ["foot", "mlterm", "sixel"].iter().any(|t| term.contains(t)) {
+        return Some(ImageProtocol::Sixel);
+    }
+
+    #[cfg(unix)]
+    if query_sixel() {
+        return Some(ImageProtocol::Sixel);
+    }
+    None
+}
+
+/// Sends the primary device attributes request and returns `true` if the
+/// answer of the terminal includes Sixel graphics (attribute 4)
+#[cfg(unix)]
+fn query_sixel() -> bool {
+    use std::{io::Write, time::Duration};
+
+    use crate::terminal;
+
+    let _mode = terminal::InputMode::keystrokes();
+    let mut stdout = io::stdout();
+    if stdout
+        .write_all(b"\x1b[c")
+        .and_then(|_| stdout.flush())
+        .is_err()
+    {
+        return false;
+    }
+
+    // The answer looks like `ESC [ ? 62 ; 4 ; 22 c`
+    let mut answer = Vec::new();
+    while !answer.ends_with(b"c") && answer.len() < 64 {
+        if !terminal::wait_for_input(Duration::from_millis(200)) {
+            return false;
+        }
+        let mut byte = 0u8;
+        // SAFETY: read writes at most one byte into the provided byte
+        if unsafe { libc::read(libc::STDIN_FILENO, (&mut byte as *mut u8).cast(), 1) } != 1 {
+            return false;
+        }
+        answer.push(byte);
+    }
+    let answer = String::from_utf8_lossy(&answer);
+    answer
+        .trim_start_matches("\x1b[?")
+        .trim_end_matches('c')
+        .split(';')
+        .any(|attribute| attribute == "4")
+}
+
+/// Encodes the image as Sixel graphics with a palette of 216 colors
+pub(crate) fn sixel(image: &Image) -> String {
+    // Every channel is reduced to 6 levels
+    let level = |value: u8| value as usize * 6 / 256;
+    let color = |[r, g, b]: [u8; 3]| level(r) * 36 + level(g) * 6 + level(b);
+
+    let mut encoded = format!("\x1bPq\"1;1;{};{}", image.width, image.height);
+    for i in 0..216 {
+        let percent = |level: usize| level * 100 / 5;
+        encoded.push_str(&format!(
+            "#{};2;{};{};{}",
+            i,
+            percent(i / 36),
+            percent(i / 6 % 6),
+            percent(i % 6)
+        ));
+    }
+
+    // Every band covers 6 rows of pixels, which are drawn color by color
+    for top in (0..image.height).step_by(6) {
+        let rows = top..(top + 6).min(image.height);
+        let mut colors: Vec<usize> = rows
+            .clone()
+            .flat_map(|y| (0..image.width).map(move |x| (x, y)))
+            .map(|(x, y)| color(image.pixel(x, y)))
+            .collect();
+        colors.sort_unstable();
+        colors.dedup();
+
+        for (i, &c) in colors.iter().enumerate() {
+            if i > 0 {
+                encoded.push('$');
+            }
+            encoded.push_str(&format!("#{}", c));
+            let columns: Vec<u8> = (0..image.width)
+                .map(|x| {
+                    let bits = rows
+                        .clone()
+                        .filter(|&y| color(image.pixel(x, y)) == c)
+                        .fold(0, |bits, y| bits | 1 << (y - top));
+                    63 + bits
+                })
+                .collect();
+            push_runs(&mut encoded, &columns);
+        }
+        encoded.push('-');
+    }
+    encoded.push_str("\x1b\\");
+    encoded
+}
+
+/// Appends the Sixel characters, shortening repetitions to `!<count><char>`
+fn push_runs(encoded: &mut String, columns: &[u8]) {
+    let mut rest = columns;
+    while let Some(&first) = rest.first() {
+        let run = rest.iter().take_while(|&&c| c == first).count();
+        if run > 3 {
+            encoded.push_str(&format!("!{}", run));
+        } else {
+            encoded.extend(std::iter::repeat_n(first as char, run - 1));
+        }
+        encoded.push(first as char);
+        rest = &rest[run..];
+    }
+}
+
+/// Encodes the image as an inline image of iTerm2
+pub(crate) fn iterm2(image: &Image) -> String {
+    let png = png(image);
+    format!(
+        "\x1b]1337;File=inline=1;size={};width={}px;height={}px:{}\x07",
+        png.len(),
+        image.width,
+        image.height,
+        base64(&png)
+    )
+}
+
+/// Encodes the image as an uncompressed PNG file
+fn png(image: &Image) -> Vec<u8> {
+    let mut data = Vec::with_capacity(image.height * (image.width * 3 + 1));
+    for row in image.pixels.chunks(image.width * 3) {
+        // Filter type `None`
+        data.push(0);
+        data.extend_from_slice(row);
+    }
+
+    // A zlib stream of stored deflate blocks
+    let mut zlib = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = data.chunks(u16::MAX as usize).collect();
+    for (i, block) in blocks.iter().enumerate() {
+        zlib.push(u8::from(i + 1 == blocks.len()));
+        let length = block.len() as u16;
+        zlib.extend_from_slice(&length.to_le_bytes());
+        zlib.extend_from_slice(&(!length).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&data).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(image.width as u32).to_be_bytes());
+    header.extend_from_slice(&(image.height as u32).to_be_bytes());
+    // 8 bits per channel, RGB, default compression, filtering and no
+    // interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    for (kind, content) in [(b"IHDR", &header), (b"IDAT", &zlib), (b"IEND", &Vec::new())] {
+        png.extend_from_slice(&(content.len() as u32).to_be_bytes());
+        let start = png.len();
+        png.extend_from_slice(kind);
+        png.extend_from_slice(content);
+        let crc = crc32(&png[start..]);
+        png.extend_from_slice(&crc.to_be_bytes());
+    }
+    png
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let value = chunk.iter().enumerate().fold(0u32, |value, (i, &byte)| {
+            value | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(value >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
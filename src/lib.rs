@@ -1,35 +1,31 @@
 //! A minimalistic shell for commands
 //!
 //! # Example
-//! ```rust
-//! fn version(_: &[String], _: &[Command]) -> Result<(), CommandError> {
-//!     println!("v0.1.0");
+//! ```rust,no_run
+//! # use std::io::Write;
+//! # use simpleshell::{Command, CommandError, Context, Shell};
+//! fn version(_: &[String], ctx: &Context) -> Result<(), CommandError> {
+//!     writeln!(ctx.out(), "v0.1.0")?;
 //!     Ok(())
 //! }
 //!
-//! fn help(_: &[String], commands: &[Command]) -> Result<(), CommandError> {
-//!     println!("{}", Color::Blue.paint("HELP"));
-//!     commands.iter().for_each(|c| println!("{}: {}", Style::new().bold().paint(&c.name), c.description));
+//! fn help(_: &[String], ctx: &Context) -> Result<(), CommandError> {
+//!     writeln!(ctx.out(), "HELP")?;
+//!     for c in ctx.commands() {
+//!         writeln!(ctx.out(), "{}: {}", c.name, c.description)?;
+//!     }
 //!     Ok(())
 //! }
 //!
 //! let commands = vec![
-//!     Command {
-//!         name: "version".to_owned(),
-//!         description: "Returns the version of the software".to_owned(),
-//!         exec: Box::new(version),
-//!     },
-//!     Command {
-//!         name: "help".to_owned(),
-//!         description: "Prints out this help".to_owned(),
-//!         exec: Box::new(help),
-//!     },
+//!     Command::new("version", "Returns the version of the software", version),
+//!     Command::new("help", "Prints out this help", help),
 //! ];
 //!
 //! let shell = Shell::new(None, commands);
-//! loop {
-//!     if let Err(e) = shell.process(){
-//!         eprintln!("{}", e);
+//! while let Ok(outcome) = shell.process() {
+//!     if let Err(e) = outcome.result {
+//!         shell.report_error(&e);
 //!     }
 //! }
 //!
@@ -39,32 +35,145 @@
 //! // v0.1.0
 //! ```
 use std::{
+    any::{Any, TypeId},
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap, HashSet},
     fmt::Debug,
     fmt::Display,
-    io::{self, Write},
+    io::{self, BufRead, IsTerminal, Write},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{
+        mpsc::{self, Receiver},
+        Once,
+    },
+    time::{Duration, Instant},
 };
 
+mod ansi;
+mod bell;
+mod builtins;
+mod cache;
+#[cfg(feature = "calc")]
+pub mod calc;
+mod chart;
+mod columns;
+#[cfg(feature = "editor")]
+pub mod completion;
+#[cfg(feature = "config")]
+mod config;
+mod context;
+mod diff;
+#[cfg(feature = "editor")]
+pub mod editor;
+mod events;
+mod expand;
+mod external;
+pub mod glob;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+mod history;
+#[cfg(feature = "images")]
+mod image;
+mod messages;
+mod metrics;
+mod outcome;
+mod output;
+mod pager;
+pub mod parser;
+pub mod plugins;
+mod pretty;
+mod profiles;
+#[cfg(feature = "prometheus")]
+mod prometheus_metrics;
+mod prompts;
+mod retry;
+mod schedule;
+mod script;
+pub mod scripting;
+#[cfg(unix)]
+mod server;
+#[cfg(feature = "spec")]
+pub mod spec;
+mod suggest;
+mod terminal;
+pub mod testing;
+mod transcript;
+mod tree;
+mod tutorial;
+mod undo;
+mod variables;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+mod watch;
+#[cfg(feature = "websocket")]
+mod websocket;
+
+pub use bell::{BellPolicy, BellStyle};
+pub use builtins::Builtin;
+pub use context::{Context, Output};
+pub use events::ShellEvent;
+use events::Subscribers;
+pub use history::{HistoryEntry, HistoryPolicy};
+#[cfg(feature = "images")]
+pub use image::{Image, ImageProtocol};
+pub use messages::Messages;
+pub use metrics::{CommandMetrics, Metrics};
+pub use outcome::ProcessOutcome;
+pub use output::OutputLimit;
+pub use profiles::Profile;
+pub use prompts::{Field, FormPrompt, InputPrompt, MultiSelectPrompt, SelectPrompt, Validator};
+pub use retry::RetryPolicy;
+#[cfg(unix)]
+pub use server::ShellServer;
+pub use tree::{TreeNode, TreeStyle};
+pub use tutorial::{StepValidator, Tutorial};
+pub use undo::UndoFn;
+
 /// Errors that may occur while processing a command. An error occurs if it was
 /// not found or an error occured while executing the command
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum CommandError {
     Empty,
     NotFound,
     ExecutionError,
+    /// The command panicked while it was executed. Contains the panic message
+    Panicked(String),
+    /// The command failed with a message that should be shown to the user
+    Custom(String),
+    /// The command was invoked again before its cooldown elapsed. Contains the
+    /// remaining time
+    Cooldown(Duration),
+    /// The command does not allow to be invoked while it is already running
+    AlreadyRunning,
+    /// The user did not confirm the execution of a destructive command
+    Aborted,
+    /// The command was called with invalid arguments. Contains its usage,
+    /// e.g. `exit [<status>]`
+    Usage(String),
 }
 
 impl std::error::Error for CommandError {}
 
+impl From<io::Error> for CommandError {
+    fn from(_: io::Error) -> Self {
+        Self::ExecutionError
+    }
+}
+
 impl Display for CommandError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Empty => write!(f, "No command given"),
-            Self::NotFound => write!(f, "Command not found"),
-            Self::ExecutionError => write!(f, "Error while executing command"),
-        }
+        write!(f, "{}", Messages::default().error(self))
     }
 }
 
+/// The signature of a function that formats a [`CommandError`] for display
+pub type ErrorRenderer = dyn Fn(&Context, &CommandError) -> String;
+
+/// The signature of the function that is executed when a [`Command`] is called
+pub type CommandFn = dyn Fn(&[String], &Context) -> Result<(), CommandError>;
+
 /// Represents an executable command
 pub struct Command {
     /// This field represents the name of the command that the user will call
@@ -72,13 +181,464 @@ pub struct Command {
     /// A short description what this command does
     pub description: String,
     /// The function that will be executed if the user called the command
-    pub exec: Box<dyn Fn(&[String], &[Command]) -> Result<(), CommandError>>,
+    pub exec: Box<CommandFn>,
+    /// The minimum time between the start of two invocations of the command
+    pub cooldown: Option<Duration>,
+    /// Whether the command may be invoked while it is already running
+    pub reentrant: bool,
+    /// Whether the user has to confirm the execution of the command
+    pub destructive: bool,
+    /// Whether glob patterns in the arguments are expanded before the command
+    /// is executed
+    pub expand_globs: bool,
+    /// Example invocations that are shown by `help <command>`
+    pub examples: Vec<Example>,
+    /// The arguments the command cannot be executed without
+    pub required_args: Vec<Arg>,
+    /// The version of the command, e.g. `1.2.0`
+    pub version: Option<String>,
+    /// How often the command is attempted if it fails
+    pub retry: Option<RetryPolicy>,
+    /// Finds the values of the arguments in line editors
+    #[cfg(feature = "editor")]
+    pub completer: Option<completion::ArgumentCompleter>,
+    /// How long the values of the completer are cached
+    #[cfg(feature = "editor")]
+    pub completion_ttl: Option<Duration>,
+    /// Descriptions in other languages, keyed by language tags like `de` or
+    /// `pt-BR`
+    pub translations: HashMap<String, String>,
+    /// How long the output of an invocation is reused for invocations with
+    /// the same arguments
+    pub cache_ttl: Option<Duration>,
 }
 
 impl Command {
+    /// Creates a new command
+    ///
+    /// # Arguments
+    /// * `name` - The name the user has to type to call the command
+    /// * `description` - A short description what this command does
+    /// * `exec` - The function that is executed if the user called the command
+    pub fn new(
+        name: &str,
+        description: &str,
+        exec: impl Fn(&[String], &Context) -> Result<(), CommandError> + 'static,
+    ) -> Self {
+        Command {
+            name: name.to_owned(),
+            description: description.to_owned(),
+            exec: Box::new(exec),
+            cooldown: None,
+            reentrant: true,
+            destructive: false,
+            expand_globs: false,
+            examples: Vec::new(),
+            required_args: Vec::new(),
+            version: None,
+            retry: None,
+            #[cfg(feature = "editor")]
+            completer: None,
+            #[cfg(feature = "editor")]
+            completion_ttl: None,
+            translations: HashMap::new(),
+            cache_ttl: None,
+        }
+    }
+
+    /// Rejects invocations that start less than `cooldown` after the previous
+    /// one with [`CommandError::Cooldown`]
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use simpleshell::{testing::TestShell, Command, CommandError, Shell};
+    /// let reindex = Command::new("reindex", "Rebuilds the index", |_, _| Ok(()))
+    ///     .with_cooldown(Duration::from_secs(60));
+    ///
+    /// let mut shell = TestShell::new(Shell::new(None, vec![reindex]));
+    /// shell.run("reindex").expect_ok();
+    /// let result = shell.run("reindex").result();
+    /// assert!(matches!(result, Some(Err(CommandError::Cooldown(_)))));
+    /// ```
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = Some(cooldown);
+        self
+    }
+
+    /// Marks the command as destructive
+    ///
+    /// The shell asks the user for confirmation before the command is
+    /// executed, unless `--yes` is passed as an argument. The `--yes` flag is
+    /// not handed to the command. Commands can record a way to revert their
+    /// effect with [`Context::push_undo`], which is executed by the
+    /// [`Builtin::Undo`] command.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use simpleshell::{testing::TestShell, Builtin, Command, CommandError, Shell};
+    /// let drop = Command::new("drop", "Drops the table", |_, ctx| {
+    ///     ctx.push_undo("restore the table", |_| Ok(()));
+    ///     Ok(())
+    /// })
+    /// .destructive();
+    ///
+    /// let mut shell = TestShell::new(Shell::new(None, vec![drop]).with_builtin(Builtin::Undo));
+    /// shell.feed("no").run("drop").expect_error(CommandError::Aborted);
+    /// shell.feed("y").run("drop").expect_ok();
+    /// shell.run("undo").expect_ok().expect_output_contains("restore the table");
+    /// ```
+    pub fn destructive(mut self) -> Self {
+        self.destructive = true;
+        self
+    }
+
+    /// Expands glob patterns like `*.log` in the arguments before the command
+    /// is executed
+    ///
+    /// Patterns are resolved with the [`GlobResolver`](glob::GlobResolver) of
+    /// the shell. Patterns that match nothing are passed unchanged.
+    pub fn with_glob_expansion(mut self) -> Self {
+        self.expand_globs = true;
+        self
+    }
+
+    /// Rejects invocations while the command is already running with
+    /// [`CommandError::AlreadyRunning`]
+    pub fn non_reentrant(mut self) -> Self {
+        self.reentrant = false;
+        self
+    }
+
+    /// Declares the version of the command, preferably in the form
+    /// `major.minor.patch`, so that remote clients can tell which behavior
+    /// to expect, see [`Builtin::Capabilities`]
+    pub fn with_version(mut self, version: &str) -> Self {
+        self.version = Some(version.to_string());
+        self
+    }
+
+    /// Adds a description in the language with the tag (e.g. `de`), which
+    /// `help` shows instead of the default description if the shell is
+    /// configured for the language with [`Shell::with_locale`]
+    ///
+    /// # Example
+    /// ```rust
+    /// # use simpleshell::{testing::TestShell, Builtin, Command, Shell};
+    /// let deploy = Command::new("deploy", "Deploys a service", |_, _| Ok(()))
+    ///     .with_translation("de", "Installiert einen Dienst");
+    ///
+    /// let shell = Shell::new(None, vec![deploy])
+    ///     .with_builtin(Builtin::Help)
+    ///     .with_locale("de-AT");
+    /// let mut shell = TestShell::deterministic(shell);
+    /// shell.run("help deploy").expect_ok();
+    /// assert_eq!(shell.output(), "deploy - Installiert einen Dienst\n");
+    /// ```
+    pub fn with_translation(mut self, locale: &str, description: &str) -> Self {
+        self.translations
+            .insert(locale.to_owned(), description.to_owned());
+        self
+    }
+
+    /// Returns the description in the language with the tag
+    ///
+    /// Falls back to the language without its region (`de` for `de-AT`) and
+    /// then to the default description.
+    pub fn description_in(&self, locale: Option<&str>) -> &str {
+        let Some(locale) = locale else {
+            return &self.description;
+        };
+        let language = locale.split(['-', '_']).next().unwrap_or(locale);
+        [locale, language]
+            .into_iter()
+            .find_map(|tag| {
+                self.translations
+                    .iter()
+                    .find(|(t, _)| t.eq_ignore_ascii_case(tag))
+            })
+            .map_or(&self.description, |(_, description)| description)
+    }
+
+    /// Attempts the command again if it fails with an error that may be
+    /// transient, see [`RetryPolicy`]
+    pub fn with_retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Reuses the output of a successful invocation for `ttl`
+    ///
+    /// Invocations with the same arguments within `ttl` print the stored
+    /// output instead of executing the command again. A `--no-cache`
+    /// argument executes the command anyway and is not handed to the
+    /// command. The stored results are shown and cleared by the
+    /// [`Builtin::Cache`] command.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::{cell::Cell, io::Write, rc::Rc, time::Duration};
+    /// # use simpleshell::{testing::TestShell, Command, Shell};
+    /// let runs = Rc::new(Cell::new(0));
+    /// let counter = runs.clone();
+    /// let report = Command::new("report", "Builds a big report", move |_, ctx| {
+    ///     counter.set(counter.get() + 1);
+    ///     writeln!(ctx.out(), "run {}", counter.get())?;
+    ///     Ok(())
+    /// })
+    /// .with_cache(Duration::from_secs(60));
+    ///
+    /// let mut shell = TestShell::deterministic(Shell::new(None, vec![report]));
+    /// shell.run("report").expect_ok();
+    /// shell.run("report").expect_ok();
+    /// assert_eq!(shell.output(), "run 1\n");
+    /// shell.run("report --no-cache").expect_ok();
+    /// assert_eq!(shell.output(), "run 2\n");
+    /// assert_eq!(runs.get(), 2);
+    /// ```
+    ///
+    /// A cached command that panics does not keep later commands from
+    /// printing:
+    /// ```rust
+    /// # use std::{io::Write, time::Duration};
+    /// # use simpleshell::{testing::TestShell, Command, CommandError, Shell};
+    /// let boom = Command::new("boom", "Panics", |_, _| panic!("boom"))
+    ///     .with_cache(Duration::from_secs(60));
+    /// let hello = Command::new("hello", "Greets", |_, ctx| {
+    ///     writeln!(ctx.out(), "hello")?;
+    ///     Ok(())
+    /// });
+    ///
+    /// let mut shell = TestShell::deterministic(Shell::new(None, vec![boom, hello]));
+    /// let result = shell.run("boom").result();
+    /// assert!(matches!(result, Some(Err(CommandError::Panicked(_)))));
+    /// shell.run("hello").expect_ok();
+    /// assert_eq!(shell.output(), "hello\n");
+    /// ```
+    pub fn with_cache(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = Some(ttl);
+        self
+    }
+
+    /// Adds an example invocation with an explanation what it does
+    ///
+    /// Examples are shown by the [`Builtin::Help`] command.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use simpleshell::{testing::TestShell, Builtin, Command, Shell};
+    /// let deploy = Command::new("deploy", "Deploys a service", |_, _| Ok(()))
+    ///     .with_example("deploy api", "Deploys the api to staging")
+    ///     .with_example("deploy api prod", "Deploys the api to production");
+    ///
+    /// let mut shell = TestShell::new(Shell::new(None, vec![deploy]).with_builtin(Builtin::Help));
+    /// shell
+    ///     .run("help deploy")
+    ///     .expect_ok()
+    ///     .expect_output_contains("deploy api prod\n      Deploys the api to production");
+    /// ```
+    pub fn with_example(mut self, invocation: &str, explanation: &str) -> Self {
+        self.examples.push(Example {
+            invocation: invocation.to_owned(),
+            explanation: explanation.to_owned(),
+        });
+        self
+    }
+
+    /// Declares the next positional argument as required
+    ///
+    /// The command fails if it is invoked without the argument, unless the
+    /// argument wizard of the shell is enabled with
+    /// [`Shell::with_argument_wizard`].
+    pub fn with_required_arg(self, name: &str, description: &str) -> Self {
+        self.with_validated_arg(name, description, |_| Ok(()))
+    }
+
+    /// Declares the next positional argument as required and checks its
+    /// value with `validate`, which returns the reason for invalid values
+    ///
+    /// # Example
+    /// ```rust
+    /// # use simpleshell::{testing::TestShell, Command, Shell};
+    /// let scale = Command::new("scale", "Scales a service", |_, _| Ok(()))
+    ///     .with_required_arg("service", "the name of the service")
+    ///     .with_validated_arg("replicas", "the number of instances", |value| {
+    ///         value.parse::<u32>().map(|_| ()).map_err(|_| "not a number".to_string())
+    ///     });
+    ///
+    /// let shell = Shell::new(None, vec![scale]).with_argument_wizard(true);
+    /// let mut shell = TestShell::deterministic(shell);
+    /// shell.feed("many").feed("3").run("scale api").expect_ok();
+    /// assert_eq!(shell.outcome().unwrap().arguments, ["api", "3"]);
+    /// ```
+    pub fn with_validated_arg(
+        mut self,
+        name: &str,
+        description: &str,
+        validate: impl Fn(&str) -> Result<(), String> + 'static,
+    ) -> Self {
+        self.required_args.push(Arg {
+            name: name.to_owned(),
+            description: description.to_owned(),
+            validate: Box::new(validate),
+            values: Vec::new(),
+        });
+        self
+    }
+
+    /// Declares the next positional argument as required and only accepts
+    /// one of the given values
+    ///
+    /// A misspelled value is rejected with a suggestion of the closest
+    /// value.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use simpleshell::{testing::TestShell, Command, CommandError, Shell};
+    /// let deploy = Command::new("deploy", "Deploys to an environment", |_, _| Ok(()))
+    ///     .with_choice_arg("environment", "where to deploy", &["staging", "production"]);
+    ///
+    /// let mut shell = TestShell::deterministic(Shell::new(None, vec![deploy]));
+    /// shell.run("deploy porduction").expect_error(CommandError::Custom(
+    ///     "environment: unknown value 'porduction', did you mean 'production'?".to_string(),
+    /// ));
+    /// shell.run("deploy production").expect_ok();
+    /// ```
+    pub fn with_choice_arg(mut self, name: &str, description: &str, values: &[&str]) -> Self {
+        self.required_args.push(Arg {
+            name: name.to_owned(),
+            description: description.to_owned(),
+            validate: Box::new(|_| Ok(())),
+            values: values.iter().map(|v| v.to_string()).collect(),
+        });
+        self
+    }
+
+    /// Completes the arguments of the command in line editors with the values
+    /// the function returns
+    ///
+    /// The function receives the arguments before the one that is completed
+    /// and returns all values it may have, of which the ones that start with
+    /// the typed word are offered. Without a completer, the values of
+    /// [`Command::with_choice_arg`] are offered.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use simpleshell::{completion::Candidate, Command};
+    /// let checkout = Command::new("checkout", "Switches to a branch", |_, _| Ok(()))
+    ///     .with_completer(|_| vec![Candidate::new("main"), Candidate::new("develop")]);
+    /// ```
+    #[cfg(feature = "editor")]
+    pub fn with_completer(
+        mut self,
+        complete: impl Fn(&[String]) -> Vec<completion::Candidate> + 'static,
+    ) -> Self {
+        self.completer = Some(completion::ArgumentCompleter::Blocking(Rc::new(complete)));
+        self
+    }
+
+    /// Completes the arguments of the command like
+    /// [`Command::with_completer`], but runs the function on a thread of its
+    /// own
+    ///
+    /// This keeps slow lookups, e.g. of the pods on a server, from freezing
+    /// the editor. It shows a loading indicator until the values arrive and
+    /// gives up after the timeout.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use simpleshell::{completion::Candidate, Command};
+    /// let logs = Command::new("logs", "Shows the logs of a pod", |_, _| Ok(()))
+    ///     .with_async_completer(Duration::from_secs(2), |_| {
+    ///         // e.g. ask the cluster for its pods
+    ///         vec![Candidate::new("web-7d4b9"), Candidate::new("db-0")]
+    ///     });
+    /// ```
+    #[cfg(feature = "editor")]
+    pub fn with_async_completer(
+        mut self,
+        timeout: Duration,
+        complete: impl Fn(&[String]) -> Vec<completion::Candidate> + Send + Sync + 'static,
+    ) -> Self {
+        self.completer = Some(completion::ArgumentCompleter::Async {
+            complete: std::sync::Arc::new(complete),
+            timeout,
+        });
+        self
+    }
+
+    /// Keeps the values of the completer of the command for the time to live
+    ///
+    /// Expensive lookups, e.g. scans of directories or queries of servers,
+    /// then run once per session and arguments in front of the completed
+    /// one. [`Context::invalidate_completions`] discards them earlier, e.g.
+    /// after a command changed them.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use simpleshell::{completion::Candidate, Command};
+    /// let logs = Command::new("logs", "Shows the logs of a pod", |_, _| Ok(()))
+    ///     .with_async_completer(Duration::from_secs(2), |_| vec![Candidate::new("db-0")])
+    ///     .with_completion_ttl(Duration::from_secs(30));
+    /// ```
+    #[cfg(feature = "editor")]
+    pub fn with_completion_ttl(mut self, ttl: Duration) -> Self {
+        self.completion_ttl = Some(ttl);
+        self
+    }
+
+    /// Returns the namespace of the command, which is the part of the name
+    /// in front of the last `.` (e.g. `net` for `net.ping`)
+    pub fn namespace(&self) -> Option<&str> {
+        self.name.rsplit_once('.').map(|(namespace, _)| namespace)
+    }
+
+    /// Returns `true` if the command is part of the namespace or one of its
+    /// nested namespaces
+    pub(crate) fn in_namespace(&self, namespace: &str) -> bool {
+        self.namespace().is_some_and(|n| {
+            n.strip_prefix(namespace)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+        })
+    }
+
+    /// Returns the metadata of the command
+    ///
+    /// # Example
+    /// ```rust
+    /// # use simpleshell::Command;
+    /// let scale = Command::new("scale", "Scales a service", |_, _| Ok(()))
+    ///     .with_required_arg("service", "the name of the service")
+    ///     .with_required_arg("replicas", "the number of instances");
+    /// assert_eq!(scale.info().usage, "scale <service> <replicas>");
+    /// ```
+    pub fn info(&self) -> CommandInfo {
+        let usage = std::iter::once(self.name.clone())
+            .chain(self.required_args.iter().map(|a| format!("<{}>", a.name)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        CommandInfo {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            usage,
+            args: self
+                .required_args
+                .iter()
+                .map(|a| ArgInfo {
+                    name: a.name.clone(),
+                    description: a.description.clone(),
+                })
+                .collect(),
+            examples: self.examples.clone(),
+            version: self.version.clone(),
+        }
+    }
+
     /// Invokes the command
-    fn invoke(&self, arguments: &[String], commands: &[Command]) -> Result<(), CommandError> {
-        (self.exec)(arguments, commands)
+    fn invoke(&self, arguments: &[String], ctx: &Context) -> Result<(), CommandError> {
+        (self.exec)(arguments, ctx)
     }
 }
 
@@ -90,10 +650,206 @@ impl Debug for Command {
     }
 }
 
+/// An example invocation of a [`Command`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Example {
+    /// The line the user types
+    pub invocation: String,
+    /// What the invocation does
+    pub explanation: String,
+}
+
+/// The signature of a function that checks the value of an [`Arg`]
+pub type ArgValidator = dyn Fn(&str) -> Result<(), String>;
+
+/// A positional argument a [`Command`] requires
+pub struct Arg {
+    /// The name of the argument
+    pub name: String,
+    /// What the argument means
+    pub description: String,
+    /// Checks the value and returns the reason if it is invalid
+    pub validate: Box<ArgValidator>,
+    /// The values the argument may have, empty if it accepts any value
+    pub values: Vec<String>,
+}
+
+/// The metadata of a [`Command`] without its behavior, e.g. to export a
+/// catalog of commands or to generate documentation
+///
+/// With the `serde` feature the metadata can be serialized and deserialized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CommandInfo {
+    /// The name the user has to type to call the command
+    pub name: String,
+    /// A short description what this command does
+    pub description: String,
+    /// How the command is called, e.g. `scale <service> <replicas>`
+    pub usage: String,
+    /// The required arguments of the command
+    pub args: Vec<ArgInfo>,
+    /// Example invocations of the command
+    pub examples: Vec<Example>,
+    /// The version of the command
+    pub version: Option<String>,
+}
+
+/// The metadata of an [`Arg`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ArgInfo {
+    /// The name of the argument
+    pub name: String,
+    /// What the argument means
+    pub description: String,
+}
+
+impl Debug for Arg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Arg").field("name", &self.name).finish()
+    }
+}
+
+/// How a [`Shell`] handles a command whose name is already taken by a
+/// registered command, see [`Shell::with_duplicate_policy`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// The registration fails with an error
+    #[default]
+    Reject,
+    /// The new command replaces the registered command
+    Replace,
+    /// The new command is ignored
+    KeepExisting,
+}
+
 /// Represents the `Shell` that parses the user input into a command and executes it
 pub struct Shell {
     prefix: Option<String>,
     available_commands: Vec<Command>,
+    before_prompt: Option<Box<dyn Fn() -> Option<String>>>,
+    right_prompt: Option<Box<dyn Fn() -> String>>,
+    transient_prompt: Option<String>,
+    subscribers: RefCell<Subscribers>,
+    metrics: RefCell<Metrics>,
+    builtins: Vec<Builtin>,
+    #[cfg(feature = "prometheus")]
+    prometheus: Option<prometheus_metrics::PrometheusMetrics>,
+    input: RefCell<Box<dyn BufRead>>,
+    output: RefCell<output::ShellOutput>,
+    terminal: Cell<bool>,
+    ansi_clients: bool,
+    pager: bool,
+    max_array_items: Option<usize>,
+    tree_style: TreeStyle,
+    input_closed: Cell<bool>,
+    locale: Option<String>,
+    /// Changed by `set -e` and `set -u`
+    script_options: Cell<script::Options>,
+    /// The cleanup commands of the running scripts, innermost last
+    traps: RefCell<Vec<script::Traps>>,
+    /// Set by the `exit` builtin
+    exit_status: Cell<Option<i32>>,
+    /// The status of the last command, `0` if it succeeded
+    last_status: Cell<i32>,
+    colors: Option<bool>,
+    deterministic_output: bool,
+    accessible_output: bool,
+    bell: BellPolicy,
+    title: Option<String>,
+    /// The outputs that were passed to `Context::diff_with_previous` by key
+    previous_outputs: RefCell<HashMap<String, String>>,
+    /// Detected when the first image is printed
+    #[cfg(feature = "images")]
+    image_protocol: std::cell::OnceCell<Option<ImageProtocol>>,
+    catch_panics: bool,
+    messages: Messages,
+    error_renderer: Option<Box<ErrorRenderer>>,
+    last_invocations: RefCell<HashMap<String, Instant>>,
+    running: RefCell<HashSet<String>>,
+    undo_stack: RefCell<Vec<undo::UndoEntry>>,
+    variables: RefCell<BTreeMap<String, String>>,
+    profiles: Vec<Profile>,
+    active_profile: RefCell<Option<usize>>,
+    profile_file: Option<PathBuf>,
+    tokenizer: parser::Tokenizer,
+    glob_resolver: Box<dyn glob::GlobResolver>,
+    expand_tilde: bool,
+    working_dir: RefCell<PathBuf>,
+    previous_dir: RefCell<Option<PathBuf>>,
+    sync_working_dir: bool,
+    external_prefix: Option<String>,
+    external_allowlist: Option<Vec<String>>,
+    script_dirs: RefCell<Vec<PathBuf>>,
+    tutorial: Option<Tutorial>,
+    tutorial_step: RefCell<usize>,
+    argument_wizard: bool,
+    history: RefCell<history::History>,
+    variable_file: Option<PathBuf>,
+    persisted: RefCell<BTreeMap<String, String>>,
+    script_engine: Option<Box<dyn scripting::ScriptEngine>>,
+    defined_commands: RefCell<Vec<Rc<Command>>>,
+    namespace: RefCell<Option<String>>,
+    duplicate_policy: DuplicatePolicy,
+    #[cfg(unix)]
+    session: RefCell<Option<server::Current>>,
+    scheduler: RefCell<schedule::Scheduler>,
+    #[cfg(feature = "websocket")]
+    keystroke_recorder: RefCell<Option<Rc<RefCell<websocket::Recorder>>>>,
+    /// The completer of the line editor of the session that is served
+    #[cfg(feature = "websocket")]
+    completions: RefCell<Option<completion::CompleterSlot>>,
+    #[cfg(feature = "editor")]
+    completion_cache: completion::Cache,
+    #[cfg(feature = "editor")]
+    completion_matcher: completion::Matcher,
+    cache: RefCell<cache::Cache>,
+    resources: HashMap<TypeId, Box<dyn Any>>,
+    /// Loaded plugins, which have to outlive the commands they provide
+    #[cfg(feature = "plugins")]
+    plugins: Vec<libloading::Library>,
+}
+
+fn default_error_renderer(ctx: &Context, error: &CommandError) -> String {
+    let messages = ctx.messages();
+    if ctx.colors_enabled() {
+        format!(
+            "\x1b[1;31m{}:\x1b[0m {}",
+            messages.error,
+            messages.error(error)
+        )
+    } else {
+        format!("{}: {}", messages.error, messages.error(error))
+    }
+}
+
+thread_local! {
+    /// The number of commands on this thread whose panics are caught
+    static CATCHING_PANICS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Runs the function and catches its panic without printing it
+///
+/// The panic is reported as [`CommandError::Panicked`] instead, so the panic
+/// hook is only silenced while commands of this thread run. Panics of other
+/// threads are still passed to the previous hook.
+fn catch_panic<T>(f: impl FnOnce() -> T) -> std::thread::Result<T> {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if CATCHING_PANICS.with(Cell::get) == 0 {
+                previous(info);
+            }
+        }));
+    });
+
+    CATCHING_PANICS.with(|count| count.set(count.get() + 1));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING_PANICS.with(|count| count.set(count.get() - 1));
+    result
 }
 
 impl Shell {
@@ -101,64 +857,2545 @@ impl Shell {
     ///
     /// # Arguments
     /// * `prefix` - The prefix that should be printed before the user inputs a
-    ///              command
+    ///   command
     /// * `available_commands` - A list of commands that are executable
+    ///
+    /// # Panics
+    /// In debug builds if two commands have the same name. Use
+    /// [`Shell::with_commands`] to handle duplicates according to a
+    /// [`DuplicatePolicy`] instead.
     pub fn new(prefix: Option<&str>, available_commands: Vec<Command>) -> Self {
+        if cfg!(debug_assertions) {
+            let mut names = HashSet::new();
+            for cmd in &available_commands {
+                assert!(names.insert(&cmd.name), "duplicate command: {}", cmd.name);
+            }
+        }
+        let ansi = std::env::var_os("TERM").is_none_or(|term| term != "dumb");
         Shell {
             prefix: prefix.map(|s| s.to_string()),
             available_commands,
+            before_prompt: None,
+            right_prompt: None,
+            transient_prompt: None,
+            subscribers: RefCell::default(),
+            metrics: RefCell::default(),
+            builtins: Vec::new(),
+            #[cfg(feature = "prometheus")]
+            prometheus: None,
+            input: RefCell::new(Box::new(io::BufReader::new(io::stdin()))),
+            output: RefCell::new(output::ShellOutput::new(Box::new(io::stdout()))),
+            terminal: Cell::new(io::stdout().is_terminal()),
+            ansi_clients: true,
+            pager: false,
+            max_array_items: None,
+            tree_style: TreeStyle::Unicode,
+            input_closed: Cell::new(false),
+            locale: None,
+            script_options: Cell::default(),
+            traps: RefCell::default(),
+            exit_status: Cell::new(None),
+            last_status: Cell::new(0),
+            colors: None,
+            deterministic_output: false,
+            accessible_output: false,
+            bell: BellPolicy::default(),
+            title: None,
+            previous_outputs: RefCell::default(),
+            #[cfg(feature = "images")]
+            image_protocol: std::cell::OnceCell::new(),
+            catch_panics: true,
+            messages: Messages::default(),
+            error_renderer: None,
+            last_invocations: RefCell::default(),
+            running: RefCell::default(),
+            undo_stack: RefCell::default(),
+            variables: RefCell::default(),
+            profiles: Vec::new(),
+            active_profile: RefCell::new(None),
+            profile_file: None,
+            tokenizer: parser::Tokenizer::default(),
+            glob_resolver: Box::new(glob::FsGlobResolver),
+            expand_tilde: false,
+            working_dir: RefCell::new(
+                std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/")),
+            ),
+            previous_dir: RefCell::new(None),
+            sync_working_dir: false,
+            external_prefix: None,
+            external_allowlist: None,
+            script_dirs: RefCell::default(),
+            tutorial: None,
+            tutorial_step: RefCell::new(0),
+            argument_wizard: false,
+            history: RefCell::default(),
+            variable_file: None,
+            persisted: RefCell::default(),
+            script_engine: None,
+            defined_commands: RefCell::default(),
+            namespace: RefCell::default(),
+            duplicate_policy: DuplicatePolicy::default(),
+            #[cfg(unix)]
+            session: RefCell::default(),
+            scheduler: RefCell::default(),
+            #[cfg(feature = "websocket")]
+            keystroke_recorder: RefCell::default(),
+            #[cfg(feature = "websocket")]
+            completions: RefCell::default(),
+            #[cfg(feature = "editor")]
+            completion_cache: completion::Cache::default(),
+            #[cfg(feature = "editor")]
+            completion_matcher: completion::Matcher::default(),
+            cache: RefCell::default(),
+            resources: HashMap::new(),
+            #[cfg(feature = "plugins")]
+            plugins: Vec::new(),
         }
+        .with_ansi(ansi)
     }
 
-    /// Processes a whole command
+    /// Limits how much output every command may produce, see [`OutputLimit`]
     ///
-    /// This includes:
-    /// * Reading the command with arguments from `STDIN`
-    /// * Execute the command
-    /// * Return the result
+    /// The limit applies to every transport the shell is served on. The
+    /// notices use the [`Messages`] the shell has when this is called.
+    pub fn with_output_limit(self, limit: OutputLimit) -> Self {
+        let limiter = output::Limiter::new(limit, &self.messages);
+        self.output.borrow_mut().limiter = Some(limiter);
+        self
+    }
+
+    /// Replaces `STDIN` and `STDOUT` with the given reader and writer
     ///
-    /// # Returns
-    /// This function returns `Ok(())` if everything went fine. Otherwise it
-    /// will return a [`CommandError`] which represents the error hat occured
-    pub fn process(&self) -> Result<(), CommandError> {
-        let mut user_input = self.get_user_input();
-        match user_input.pop() {
-            Some(requested_cmd) => {
-                let selected_command = self
-                    .available_commands
-                    .iter()
-                    .filter(|c| c.name == requested_cmd)
-                    .collect::<Vec<&Command>>()
-                    .pop();
+    /// The shell reads the user input from `input` and writes the prompt as
+    /// well as the output of the commands to `output`.
+    pub fn with_io(mut self, input: impl BufRead + 'static, output: impl Write + 'static) -> Self {
+        self.input = RefCell::new(Box::new(input));
+        let _ = self.output.get_mut().redirect(Box::new(output));
+        self.terminal.set(false);
+        self
+    }
+
+    /// Makes a shared resource, e.g. a database pool, available to the
+    /// commands by its type
+    ///
+    /// Commands get the resource with [`Context::get`]. A resource of the
+    /// same type that was provided before is replaced.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::io::Write;
+    /// # use simpleshell::{testing::TestShell, Command, Shell};
+    /// struct Inventory {
+    ///     hosts: Vec<String>,
+    /// }
+    ///
+    /// let commands = vec![Command::new("hosts", "Lists the hosts", |_, ctx| {
+    ///     let inventory = ctx.get::<Inventory>().expect("an inventory is provided");
+    ///     writeln!(ctx.out(), "{}", inventory.hosts.join(" "))?;
+    ///     Ok(())
+    /// })];
+    ///
+    /// let shell = Shell::new(None, commands).provide(Inventory {
+    ///     hosts: vec!["web".to_string(), "db".to_string()],
+    /// });
+    /// let mut shell = TestShell::deterministic(shell);
+    /// shell.run("hosts").expect_ok();
+    /// assert_eq!(shell.output(), "web db\n");
+    /// ```
+    pub fn provide<T: 'static>(mut self, resource: T) -> Self {
+        self.resources.insert(TypeId::of::<T>(), Box::new(resource));
+        self
+    }
+
+    /// Registers a hook that is called before each prompt is rendered
+    ///
+    /// The hook may be used to refresh application state (e.g. poll a
+    /// connection) or to print notifications above the prompt. If it returns
+    /// `Some(prompt)`, that prompt is shown instead of the configured prefix
+    /// for this render.
+    pub fn on_before_prompt(mut self, hook: impl Fn() -> Option<String> + 'static) -> Self {
+        self.before_prompt = Some(Box::new(hook));
+        self
+    }
+
+    /// Shows the text returned by `segment` right-aligned on the prompt line
+    ///
+    /// The segment is evaluated on every prompt render, so it may contain a
+    /// clock or other status information. It is only shown when the shell
+    /// writes to a terminal and is overwritten by the input once the typed
+    /// line reaches it. The segment should be plain text, because its width is
+    /// measured in characters.
+    pub fn with_right_prompt(mut self, segment: impl Fn() -> String + 'static) -> Self {
+        self.right_prompt = Some(Box::new(segment));
+        self
+    }
+
+    /// Replaces the full prompt with `marker` once a line has been accepted
+    ///
+    /// This keeps the scrollback of long sessions clean when a large prompt
+    /// is used. It only has an effect when the shell writes to a terminal.
+    pub fn with_transient_prompt(mut self, marker: &str) -> Self {
+        self.transient_prompt = Some(marker.to_string());
+        self
+    }
+
+    /// Records everything that is shown on the screen to the file at `path`
+    ///
+    /// The prompts, the input of the user and the output of the commands are
+    /// written with their timing in the asciicast v2 format, which can be
+    /// played back with [`Shell::replay`] or `asciinema play`. If
+    /// `strip_ansi` is set, colors and other escape sequences are removed
+    /// from the recording. A running recording is replaced.
+    pub fn record_transcript(&self, path: impl AsRef<Path>, strip_ansi: bool) -> io::Result<()> {
+        let transcript = transcript::Transcript::create(path.as_ref(), strip_ansi)?;
+        self.output.borrow_mut().transcript = Some(transcript);
+        Ok(())
+    }
+
+    /// Records the raw keys that clients of [`Shell::serve_websocket`] send to
+    /// the file at `path`, e.g. to reproduce a problem of the line editor
+    ///
+    /// Every occurrence of one of the `secrets` in the typed keys is replaced
+    /// with `*`. The keys are written as input events in the asciicast v2 format
+    /// and can be replayed against the line editor with
+    /// [`testing::replay_keystrokes`]. A running recording is replaced.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use simpleshell::Shell;
+    /// let shell = Shell::new(Some("admin"), vec![]);
+    /// shell.record_keystrokes("keys.cast", &["hunter2"])?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[cfg(feature = "websocket")]
+    pub fn record_keystrokes(&self, path: impl AsRef<Path>, secrets: &[&str]) -> io::Result<()> {
+        let recorder = websocket::Recorder::create(path.as_ref(), secrets)?;
+        self.keystroke_recorder
+            .replace(Some(Rc::new(RefCell::new(recorder))));
+        Ok(())
+    }
+
+    /// Replaces how the typed word selects the completions of line editors,
+    /// which is [`Matcher::Prefix`](completion::Matcher::Prefix) by default
+    ///
+    /// # Example
+    /// ```rust
+    /// # use simpleshell::{completion::Matcher, Shell};
+    /// // `gco` completes to `git-checkout`
+    /// let shell = Shell::new(Some("admin"), vec![]).with_completion_matcher(Matcher::Fuzzy);
+    /// ```
+    #[cfg(feature = "editor")]
+    pub fn with_completion_matcher(mut self, matcher: completion::Matcher) -> Self {
+        self.completion_matcher = matcher;
+        self
+    }
+
+    /// Runs `f` with the input and output of the shell replaced and restores
+    /// them afterwards
+    ///
+    /// The replacements are not treated as a terminal. If the client of the
+    /// output does not support `ansi` escape sequences, they are removed.
+    pub(crate) fn redirected<R>(
+        &self,
+        input: Box<dyn BufRead>,
+        output: Box<dyn Write>,
+        ansi: bool,
+        f: impl FnOnce() -> R,
+    ) -> R {
+        let input = self.input.replace(input);
+        let mut shell_output = self.output.borrow_mut();
+        let output = shell_output.redirect(output);
+        let stripper = match ansi {
+            true => shell_output.stripper.take(),
+            false => shell_output.stripper.replace(ansi::Stripper::default()),
+        };
+        drop(shell_output);
+        let terminal = self.terminal.replace(false);
+
+        let result = f();
+
+        self.terminal.set(terminal);
+        self.input.replace(input);
+        let mut shell_output = self.output.borrow_mut();
+        shell_output.stripper = stripper;
+        let _ = shell_output.redirect(output).flush();
+        result
+    }
+
+    /// Drives the shell with a line protocol instead of an interactive
+    /// session until the input is closed
+    ///
+    /// Every line of the input is executed like user input, and for every
+    /// line exactly one line with a JSON object is written to the output:
+    /// `{"ok":true,"output":"..."}` if the command succeeded or
+    /// `{"ok":false,"output":"...","error":"..."}` if it failed. No prompts
+    /// are shown and commands cannot read additional input, which makes the
+    /// shell easy to drive from another process without a terminal.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::{cell::RefCell, io::{self, Write}, rc::Rc};
+    /// # use simpleshell::{Command, Shell};
+    /// # #[derive(Clone, Default)]
+    /// # struct Buffer(Rc<RefCell<Vec<u8>>>);
+    /// # impl Write for Buffer {
+    /// #     fn write(&mut self, buf: &[u8]) -> io::Result<usize> { self.0.borrow_mut().write(buf) }
+    /// #     fn flush(&mut self) -> io::Result<()> { Ok(()) }
+    /// # }
+    /// let commands = vec![Command::new("version", "Shows the version", |_, ctx| {
+    ///     writeln!(ctx.out(), "v0.1.0")?;
+    ///     Ok(())
+    /// })];
+    ///
+    /// let output = Buffer::default();
+    /// let shell = Shell::new(None, commands).with_io(&b"version\nunknown\n"[..], output.clone());
+    /// shell.serve_line_protocol()?;
+    /// assert_eq!(
+    ///     String::from_utf8_lossy(&output.0.borrow()),
+    ///     "{\"ok\":true,\"output\":\"v0.1.0\\n\"}\n\
+    ///      {\"ok\":false,\"output\":\"\",\"error\":\"Command not found\"}\n"
+    /// );
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn serve_line_protocol(&self) -> io::Result<()> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.read_input(&mut line)? == 0 {
+                return Ok(());
+            }
+
+            let capture = output::Capture::default();
+            let outcome = self.redirected(
+                Box::new(io::empty()),
+                Box::new(capture.clone()),
+                false,
+                || self.process_line(line.trim_end_matches(['\r', '\n'])),
+            );
+            let output = transcript::escape_json(&String::from_utf8_lossy(&capture.0.borrow()));
+            let response = match outcome.result {
+                Ok(()) => format!(r#"{{"ok":true,"output":"{}"}}"#, output),
+                Err(e) => format!(
+                    r#"{{"ok":false,"output":"{}","error":"{}"}}"#,
+                    output,
+                    transcript::escape_json(&self.messages.error(&e))
+                ),
+            };
+            let mut out = self.output.borrow_mut();
+            writeln!(out, "{}", response)?;
+            out.flush()?;
+        }
+    }
+
+    /// Serves an interactive session on a WebSocket until the client closes
+    /// it, e.g. to attach a browser-based terminal like xterm.js
+    ///
+    /// The embedder accepts the connection, including the HTTP upgrade and
+    /// the authentication of the client, and hands the established socket
+    /// over. The keystrokes the client sends are edited into lines with a
+    /// [`LineEditor`](editor::LineEditor), and the output is sent as text
+    /// messages with `\r\n` line endings. `Tab` completes the names of the
+    /// commands and their arguments, see [`Command::with_completer`]. The
+    /// commands that were run most often and most recently according to the
+    /// history, including a [history file](Shell::with_history_file), are
+    /// offered first.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use std::net::TcpListener;
+    /// # use simpleshell::Shell;
+    /// let shell = Shell::new(Some("admin"), vec![]);
+    /// let listener = TcpListener::bind("127.0.0.1:8080")?;
+    /// for stream in listener.incoming() {
+    ///     let socket = tungstenite::accept(stream?).map_err(std::io::Error::other)?;
+    ///     shell.serve_websocket(socket);
+    /// }
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    ///
+    /// A client that disconnects in the middle of its session ends only that
+    /// session:
+    /// ```rust
+    /// # use std::net::{TcpListener, TcpStream};
+    /// # use simpleshell::Shell;
+    /// # use tungstenite::Message;
+    /// let listener = TcpListener::bind("127.0.0.1:0")?;
+    /// let address = listener.local_addr()?;
+    /// std::thread::spawn(move || {
+    ///     let shell = Shell::new(Some("admin"), vec![]);
+    ///     for stream in listener.incoming() {
+    ///         shell.serve_websocket(tungstenite::accept(stream.unwrap()).unwrap());
+    ///     }
+    /// });
+    ///
+    /// let url = format!("ws://{}", address);
+    /// let (mut socket, _) = tungstenite::client(&url, TcpStream::connect(address)?)?;
+    /// socket.read()?;
+    /// socket.send(Message::text("help\r"))?;
+    /// drop(socket);
+    ///
+    /// let (mut socket, _) = tungstenite::client(&url, TcpStream::connect(address)?)?;
+    /// assert!(socket.read()?.to_text()?.contains("admin"));
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[cfg(feature = "websocket")]
+    pub fn serve_websocket<S: io::Read + Write + 'static>(
+        &self,
+        socket: tungstenite::WebSocket<S>,
+    ) {
+        let recorder = self.keystroke_recorder.borrow().clone();
+        self.completion_cache.clear();
+        let completions = completion::CompleterSlot::default();
+        self.completions.replace(Some(completions.clone()));
+        let (input, output) = websocket::split(socket, recorder, completions);
+        self.serve_session(Box::new(input), Box::new(output), true);
+        self.completions.take();
+    }
 
-                match selected_command {
-                    Some(cmd) => cmd.invoke(&user_input, &self.available_commands),
-                    None => Err(CommandError::NotFound),
+    /// Runs an interactive session on the given input and output until the
+    /// input is closed or the connection fails
+    fn serve_session(&self, input: Box<dyn BufRead>, output: Box<dyn Write>, ansi: bool) {
+        self.input_closed.set(false);
+        self.redirected(input, output, ansi, || {
+            while !self.input_closed.get() && self.exit_status.take().is_none() {
+                // A client that disconnected ends only its own session
+                let Ok(outcome) = self.process() else {
+                    break;
+                };
+                // Empty lines and the end of the input are not worth reporting
+                match outcome.result {
+                    Ok(()) | Err(CommandError::Empty) => {}
+                    // The session may be left in an inconsistent state
+                    Err(e @ CommandError::Panicked(_)) => {
+                        self.report_error(&e);
+                        break;
+                    }
+                    Err(e) => self.report_error(&e),
                 }
             }
-            None => Err(CommandError::Empty),
+        });
+    }
+
+    /// Listens on a Unix domain socket and serves an interactive session to
+    /// every client that connects, e.g. with `socat - /run/app.sock`
+    ///
+    /// The clients are served one after another, each until it closes the
+    /// connection, and share the state of the shell such as its variables and
+    /// history. A stale socket file at `path` is replaced. The socket is
+    /// accessible according to the umask of the process, so it should be
+    /// placed in a directory that only the intended users can access.
+    ///
+    /// A client that disconnects, calls [`Builtin::Exit`] or runs a command
+    /// that panics ends only its own session. Returns only if accepting a
+    /// connection fails.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::{io::{Read, Write}, os::unix::net::UnixStream};
+    /// # use simpleshell::{Command, Shell};
+    /// let path = std::env::temp_dir().join(format!("simpleshell-{}.sock", std::process::id()));
+    /// let socket = path.clone();
+    /// std::thread::spawn(move || {
+    ///     let boom = Command::new("boom", "Panics", |_, _| panic!("boom"));
+    ///     Shell::new(Some("admin"), vec![boom]).serve_unix(socket)
+    /// });
+    /// let connect = || loop {
+    ///     match UnixStream::connect(&path) {
+    ///         Ok(stream) => break stream,
+    ///         Err(_) => std::thread::sleep(std::time::Duration::from_millis(10)),
+    ///     }
+    /// };
+    ///
+    /// let mut client = connect();
+    /// client.write_all(b"boom\n")?;
+    /// let mut output = String::new();
+    /// client.read_to_string(&mut output)?;
+    /// assert!(output.contains("boom"));
+    ///
+    /// let mut client = connect();
+    /// client.shutdown(std::net::Shutdown::Write)?;
+    /// let mut output = String::new();
+    /// client.read_to_string(&mut output)?;
+    /// assert!(output.contains("admin"));
+    /// # std::fs::remove_file(&path)?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[cfg(unix)]
+    pub fn serve_unix(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        use std::os::unix::{fs::FileTypeExt, net::UnixListener};
+
+        let path = path.as_ref();
+        if std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+            std::fs::remove_file(path)?;
         }
+        let listener = UnixListener::bind(path)?;
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let input = io::BufReader::new(stream.try_clone()?);
+            self.serve_session(Box::new(input), Box::new(stream), self.ansi_clients);
+        }
+        Ok(())
+    }
+
+    /// Stops the running transcript recording
+    pub fn stop_transcript(&self) {
+        self.output.borrow_mut().transcript = None;
     }
 
-    /// Reads the user input from `STDIN` and splits it at the whitespaces
-    fn get_user_input(&self) -> Vec<String> {
-        match self.prefix.as_ref() {
-            Some(p) => print!("{}", p),
-            None => print!("cmdshell> "),
+    /// Plays a transcript recorded with [`Shell::record_transcript`] back to
+    /// the output of the shell
+    ///
+    /// The pauses between the recorded events are kept, scaled by `speed`: a
+    /// speed of `2.0` replays twice as fast, `f64::INFINITY` without any
+    /// pauses. The input of the user is shown as it was echoed by the
+    /// terminal during the recording.
+    pub fn replay(&self, path: impl AsRef<Path>, speed: f64) -> io::Result<()> {
+        let file = io::BufReader::new(std::fs::File::open(path)?);
+        let mut previous = 0.0;
+        // The first line is the header of the recording
+        for line in file.lines().skip(1) {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event = transcript::parse_event(&line).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid event: {}", line),
+                )
+            })?;
+            if event.kind.is_none() {
+                continue;
+            }
+
+            let pause = ((event.time - previous) / speed).max(0.0);
+            if pause > 0.0 && pause.is_finite() {
+                std::thread::sleep(Duration::from_secs_f64(pause));
+            }
+            previous = event.time;
+
+            let mut output = self.output.borrow_mut();
+            output.write_all(event.data.as_bytes())?;
+            output.flush()?;
         }
-        io::stdout()
-            .flush()
-            .expect("Could not flush prefix of input");
+        Ok(())
+    }
 
-        let mut user_input = String::new();
-        io::stdin()
-            .read_line(&mut user_input)
-            .expect("Failed to read user input");
-
-        user_input
-            .trim()
-            .split(' ')
-            .map(|s| s.to_string())
-            .collect()
+    /// Makes the output of the shell reproducible
+    ///
+    /// In this mode the shell does not print prompts, colors or timing
+    /// information, so captured output is stable across runs and platforms
+    /// (e.g. for snapshot tests). Commands can query the mode with
+    /// [`Context::deterministic_output`].
+    pub fn with_deterministic_output(mut self, enabled: bool) -> Self {
+        self.deterministic_output = enabled;
+        self
+    }
+
+    /// Makes the output of the shell friendly to screen readers and braille
+    /// terminals
+    ///
+    /// In this mode the shell only appends plain text to its output: it uses
+    /// no colors, right prompt, transient prompt or pager and does not
+    /// redraw the prompt or the output of `watch`, which only prints the runs
+    /// whose output changed. Commands can query the mode with
+    /// [`Context::accessible_output`] and should not rely on colors alone to
+    /// convey information.
+    pub fn with_accessible_output(mut self, enabled: bool) -> Self {
+        self.accessible_output = enabled;
+        self
+    }
+
+    /// Shows the template as the title of the terminal window while a
+    /// command runs and restores the previous title afterwards
+    ///
+    /// `{command}` in the template is replaced with the entered line, e.g.
+    /// `myapp: {command}`. The title is only changed when the shell writes to
+    /// a terminal, and restoring it requires a terminal with a title stack
+    /// like xterm.
+    pub fn with_title(mut self, template: &str) -> Self {
+        self.title = Some(template.to_owned());
+        self
+    }
+
+    /// Sets the protocol images are shown with instead of detecting the one
+    /// the terminal supports, or disables images with `None`, see
+    /// [`Context::print_image`]
+    #[cfg(feature = "images")]
+    pub fn with_image_protocol(mut self, protocol: Option<ImageProtocol>) -> Self {
+        self.image_protocol = std::cell::OnceCell::from(protocol);
+        self
+    }
+
+    /// Alerts the user when commands fail or finish after running for a
+    /// while, see [`BellPolicy`]
+    ///
+    /// Alerts are only shown when the shell writes to a terminal.
+    pub fn with_bell(mut self, policy: BellPolicy) -> Self {
+        self.bell = policy;
+        self
+    }
+
+    /// Enables or disables colored output
+    ///
+    /// By default colors are used if the shell writes to a terminal. The
+    /// deterministic and the accessible output modes never use colors.
+    pub fn with_colors(mut self, enabled: bool) -> Self {
+        self.colors = Some(enabled);
+        self
+    }
+
+    /// Shows the output of commands that does not fit on the screen in the
+    /// pager of the user
+    ///
+    /// The pager is taken from the `PAGER` environment variable and defaults
+    /// to `less -R`. Paging only happens when the shell writes to a terminal,
+    /// and the output of a command is held back until it finishes or asks the
+    /// user for input.
+    pub fn with_pager(mut self, enabled: bool) -> Self {
+        self.pager = enabled;
+        self
+    }
+
+    /// Collapses arrays printed with [`Context::print_json`] and
+    /// [`Context::print_yaml`] to their first `max_items` items
+    ///
+    /// The remaining items are replaced by a line with their number. By
+    /// default arrays are printed completely.
+    pub fn with_collapsed_arrays(mut self, max_items: usize) -> Self {
+        self.max_array_items = Some(max_items);
+        self
+    }
+
+    /// Sets the characters [`Context::print_tree`] draws branches with
+    ///
+    /// Trees are drawn with unicode box drawing characters by default.
+    pub fn with_tree_style(mut self, style: TreeStyle) -> Self {
+        self.tree_style = style;
+        self
+    }
+
+    /// Declares whether the output and the clients of [`Shell::serve_unix`]
+    /// support ANSI escape sequences
+    ///
+    /// If they do not, colors, cursor movements and other escape sequences
+    /// are removed from everything the shell writes. By default escape
+    /// sequences are removed if the `TERM` environment variable is `dumb`.
+    /// The line protocol and the gRPC interface never send escape sequences.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::io::Write;
+    /// # use simpleshell::{testing::TestShell, Command, Shell};
+    /// let commands = vec![Command::new("status", "Shows the status", |_, ctx| {
+    ///     writeln!(ctx.out(), "\x1b[32mhealthy\x1b[0m")?;
+    ///     Ok(())
+    /// })];
+    ///
+    /// let shell = Shell::new(None, commands).with_ansi(false);
+    /// let mut shell = TestShell::deterministic(shell);
+    /// shell.run("status").expect_ok();
+    /// assert_eq!(shell.output(), "healthy\n");
+    /// ```
+    pub fn with_ansi(mut self, supported: bool) -> Self {
+        self.ansi_clients = supported;
+        self.output.get_mut().stripper = (!supported).then(ansi::Stripper::default);
+        self
+    }
+
+    /// Applies the settings of a TOML configuration file
+    ///
+    /// Settings in the file override the settings that were made before this
+    /// call and are overridden by the ones that are made afterwards. A
+    /// missing file leaves the shell unchanged. Errors in the file are
+    /// reported as [`io::ErrorKind::InvalidData`].
+    ///
+    /// # Example
+    /// ```toml
+    /// prompt = "db> "
+    /// transient_prompt = "> "
+    /// title = "db: {command}"
+    /// colors = false
+    /// accessible = true
+    /// locale = "de"
+    /// builtins = ["help", "history"]
+    ///
+    /// [history]
+    /// file = "~/.db_history"
+    /// shared = true
+    /// ignore_duplicates = true
+    /// ignore_space = true
+    /// ignore_patterns = ["(?i)password"]
+    /// max_length = 500
+    /// ```
+    ///
+    /// `ignore_patterns` requires the `regex` feature.
+    #[cfg(feature = "config")]
+    pub fn with_config_file(self, path: impl AsRef<Path>) -> io::Result<Self> {
+        let content = config::read(path.as_ref())?;
+        config::apply(self, &content)
+    }
+
+    /// Replaces the tokenizer that splits the user input into the command and
+    /// its arguments
+    pub fn with_tokenizer(mut self, tokenizer: parser::Tokenizer) -> Self {
+        self.tokenizer = tokenizer;
+        self
+    }
+
+    /// Replaces the resolver for glob patterns in the arguments of commands
+    /// that enabled [`Command::with_glob_expansion`]
+    pub fn with_glob_resolver(mut self, resolver: impl glob::GlobResolver + 'static) -> Self {
+        self.glob_resolver = Box::new(resolver);
+        self
+    }
+
+    /// Configures whether a leading `~` or `~user` in arguments is replaced
+    /// with the home directory of the current or the given user
+    pub fn with_tilde_expansion(mut self, enabled: bool) -> Self {
+        self.expand_tilde = enabled;
+        self
+    }
+
+    /// Configures whether changing the working directory of the session with
+    /// [`Builtin::Cd`] also changes the working directory of the process
+    pub fn with_synced_working_dir(mut self, enabled: bool) -> Self {
+        self.sync_working_dir = enabled;
+        self
+    }
+
+    /// Returns the namespace that was selected with [`Shell::use_namespace`]
+    pub fn namespace(&self) -> Option<String> {
+        self.namespace.borrow().clone()
+    }
+
+    /// Selects the namespace whose commands can be called without their
+    /// prefix for the rest of the session or clears it
+    ///
+    /// Commands of the namespace take precedence over commands with the same
+    /// name outside of it. Returns an error if no command is part of the
+    /// namespace.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::io::Write;
+    /// # use simpleshell::{testing::TestShell, Builtin, Command, Shell};
+    /// let commands = vec![Command::new("net.ping", "Pings a host", |args, ctx| {
+    ///     writeln!(ctx.out(), "pong from {}", args[0])?;
+    ///     Ok(())
+    /// })];
+    ///
+    /// let shell = Shell::new(None, commands).with_builtin(Builtin::Use);
+    /// let mut shell = TestShell::deterministic(shell);
+    /// shell.run("use net").expect_ok();
+    /// shell.run("ping example.com").expect_ok();
+    /// assert_eq!(shell.output(), "pong from example.com\n");
+    /// ```
+    pub fn use_namespace(&self, namespace: Option<&str>) -> Result<(), CommandError> {
+        if let Some(namespace) = namespace {
+            let known = self
+                .available_commands
+                .iter()
+                .any(|c| c.in_namespace(namespace));
+            if !known {
+                return Err(CommandError::Custom(format!(
+                    "{}: {}",
+                    self.messages.unknown_namespace, namespace
+                )));
+            }
+        }
+        *self.namespace.borrow_mut() = namespace.map(str::to_string);
+        Ok(())
+    }
+
+    /// Returns `true` if a command or builtin with the name can be executed
+    pub(crate) fn knows_command(&self, name: &str) -> bool {
+        let name = self.resolve_name(name);
+        self.is_available(&name)
+            && (self.available_commands.iter().any(|c| c.name == name)
+                || self
+                    .defined_commands
+                    .borrow()
+                    .iter()
+                    .any(|c| c.name == name)
+                || self.builtins.iter().any(|b| b.name() == name))
+    }
+
+    /// Returns the full name of a command whose name may lack the prefix of
+    /// the selected namespace
+    pub(crate) fn resolve_name(&self, name: &str) -> String {
+        let Some(namespace) = self.namespace.borrow().clone() else {
+            return name.to_string();
+        };
+        let qualified = format!("{}.{}", namespace, name);
+        let exists = self.available_commands.iter().any(|c| c.name == qualified)
+            || self
+                .defined_commands
+                .borrow()
+                .iter()
+                .any(|c| c.name == qualified);
+        if exists {
+            qualified
+        } else {
+            name.to_string()
+        }
+    }
+
+    /// Returns the working directory of the session
+    pub fn working_dir(&self) -> PathBuf {
+        self.working_dir.borrow().clone()
+    }
+
+    /// Changes the working directory of the session
+    ///
+    /// Relative paths are resolved against the current working directory of
+    /// the session. Returns an error if the path is not a directory.
+    pub fn set_working_dir(&self, path: impl AsRef<Path>) -> Result<(), CommandError> {
+        let path = self.working_dir.borrow().join(path);
+        let not_found = || {
+            CommandError::Custom(format!(
+                "{}: {}",
+                self.messages.no_such_directory,
+                path.display()
+            ))
+        };
+        let path = std::fs::canonicalize(&path).map_err(|_| not_found())?;
+        if !path.is_dir() {
+            return Err(not_found());
+        }
+
+        if self.sync_working_dir {
+            std::env::set_current_dir(&path)?;
+        }
+        let previous = self.working_dir.replace(path);
+        *self.previous_dir.borrow_mut() = Some(previous);
+        Ok(())
+    }
+
+    /// Runs lines that start with `prefix` as native OS commands
+    ///
+    /// With the prefix `!`, the line `!ls -la` runs `ls -la` in the working
+    /// directory of the session. Its output and errors are streamed to the
+    /// output of the shell. A failing exit status is reported as
+    /// [`CommandError::Custom`], and its exit code becomes the status of the
+    /// shell, see [`Shell::run`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use simpleshell::{Builtin, Shell};
+    /// let shell = Shell::new(None, vec![])
+    ///     .with_external_commands("!")
+    ///     .with_builtin(Builtin::Exit)
+    ///     .with_io(&b"!false\nexit\n"[..], Vec::new());
+    /// # #[cfg(unix)]
+    /// assert_eq!(shell.run(), 1);
+    /// ```
+    ///
+    /// # Panics
+    /// If the prefix is empty, which would run every line, including
+    /// mistyped commands, as a native OS command.
+    pub fn with_external_commands(mut self, prefix: &str) -> Self {
+        assert!(
+            !prefix.is_empty(),
+            "the prefix of external commands is empty"
+        );
+        self.external_prefix = Some(prefix.to_string());
+        self
+    }
+
+    /// Restricts the native OS commands that may be run with the prefix of
+    /// [`Shell::with_external_commands`] to the given programs
+    pub fn with_external_allowlist(mut self, programs: &[&str]) -> Self {
+        self.external_allowlist = Some(programs.iter().map(|p| p.to_string()).collect());
+        self
+    }
+
+    /// Replaces the built-in texts of the shell, e.g. to translate them
+    pub fn with_messages(mut self, messages: Messages) -> Self {
+        self.messages = messages;
+        self
+    }
+
+    /// Sets the language of the session as a tag like `de` or `pt-BR`, which
+    /// selects the translated descriptions of commands, see
+    /// [`Command::with_translation`]
+    pub fn with_locale(mut self, locale: &str) -> Self {
+        self.locale = Some(locale.to_owned());
+        self
+    }
+
+    /// Returns the language of the session, see [`Shell::with_locale`]
+    pub fn locale(&self) -> Option<&str> {
+        self.locale.as_deref()
+    }
+
+    /// Returns the texts the shell shows to the user
+    ///
+    /// Use [`Messages::error`] to display a [`CommandError`] returned by
+    /// [`Shell::process`] in the configured language.
+    pub fn messages(&self) -> &Messages {
+        &self.messages
+    }
+
+    /// Replaces the function that formats errors in [`Shell::report_error`]
+    ///
+    /// By default errors are shown as `error: <message>` using the configured
+    /// [`Messages`], with a colored label when writing to a terminal.
+    pub fn with_error_renderer(
+        mut self,
+        renderer: impl Fn(&Context, &CommandError) -> String + 'static,
+    ) -> Self {
+        self.error_renderer = Some(Box::new(renderer));
+        self
+    }
+
+    /// Formats the error for display
+    pub fn render_error(&self, error: &CommandError) -> String {
+        let ctx = Context::new(self);
+        match &self.error_renderer {
+            Some(renderer) => renderer(&ctx, error),
+            None => default_error_renderer(&ctx, error),
+        }
+    }
+
+    /// Writes the formatted error to the output of the shell
+    pub fn report_error(&self, error: &CommandError) {
+        let rendered = self.render_error(error);
+        let mut output = self.output.borrow_mut();
+        // There is nowhere left to report a failing output to
+        let _ = writeln!(output, "{}", rendered).and_then(|_| output.flush());
+    }
+
+    /// Configures whether panics of commands are caught
+    ///
+    /// By default a panicking command is reported as
+    /// [`CommandError::Panicked`] and the shell keeps running. The panic hook
+    /// does not print the panic in this case. If disabled, the panic is
+    /// propagated to the caller of [`Shell::process`].
+    pub fn with_catch_panics(mut self, enabled: bool) -> Self {
+        self.catch_panics = enabled;
+        self
+    }
+
+    /// Configures whether the shell asks for missing required arguments
+    ///
+    /// If enabled, the user is prompted for every required argument (see
+    /// [`Command::with_required_arg`]) that is missing in the invocation
+    /// until a valid value is entered. Otherwise the command fails.
+    pub fn with_argument_wizard(mut self, enabled: bool) -> Self {
+        self.argument_wizard = enabled;
+        self
+    }
+
+    /// Sets the rules which entered lines are recorded in the history
+    pub fn with_history_policy(self, policy: HistoryPolicy) -> Self {
+        self.history.borrow_mut().policy = policy;
+        self
+    }
+
+    /// Persists the history in the given file
+    ///
+    /// The entries of the file are loaded into the history and every new
+    /// entry is appended to it. The file is only readable by its owner.
+    pub fn with_history_file(self, path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = history::HistoryFile::new(path.as_ref());
+        self.history.borrow_mut().attach(file)?;
+        Ok(self)
+    }
+
+    /// Persists the history in the given file like
+    /// [`Shell::with_history_file`] and encrypts every entry with
+    /// XChaCha20-Poly1305 and the given key
+    ///
+    /// Entries that cannot be decrypted with the key are skipped when the
+    /// file is loaded.
+    #[cfg(feature = "encrypted-history")]
+    pub fn with_encrypted_history_file(
+        self,
+        path: impl AsRef<Path>,
+        key: &[u8; 32],
+    ) -> io::Result<Self> {
+        let file = history::HistoryFile::encrypted(path.as_ref(), key);
+        self.history.borrow_mut().attach(file)?;
+        Ok(self)
+    }
+
+    /// Configures whether the history file is shared with other sessions
+    ///
+    /// If enabled, entries that other sessions append to the history file
+    /// (see [`Shell::with_history_file`]) become visible in this session as
+    /// soon as the history is accessed or a new line is recorded. Entries are
+    /// appended with a single write, so sessions can share the file safely.
+    pub fn with_shared_history(self, enabled: bool) -> Self {
+        self.history.borrow_mut().shared = enabled;
+        self
+    }
+
+    /// Returns the recorded lines of the session, the oldest first
+    pub fn history(&self) -> Vec<String> {
+        let _ = self.history.borrow_mut().sync();
+        let history = self.history.borrow();
+        history.entries.iter().map(|e| e.line.clone()).collect()
+    }
+
+    /// Returns the recorded entries of the session with their timestamps,
+    /// the oldest first
+    pub fn history_entries(&self) -> Vec<HistoryEntry> {
+        let _ = self.history.borrow_mut().sync();
+        self.history.borrow().entries.clone()
+    }
+
+    /// Enables a command that is provided by the shell itself
+    ///
+    /// Commands that were passed to [`Shell::new`] take precedence over
+    /// builtins with the same name.
+    pub fn with_builtin(mut self, builtin: Builtin) -> Self {
+        if !self.builtins.contains(&builtin) {
+            self.builtins.push(builtin);
+        }
+        self
+    }
+
+    /// Adds a profile the user can switch to with the [`Builtin::Profile`]
+    /// command
+    pub fn with_profile(mut self, profile: Profile) -> Self {
+        self.profiles.retain(|p| p.name != profile.name);
+        self.profiles.push(profile);
+        self
+    }
+
+    /// Remembers the active profile in the file at `path`
+    ///
+    /// The profile that is stored in the file is activated right away, so
+    /// this should be called after all profiles have been added.
+    pub fn with_profile_file(mut self, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_path_buf();
+        if let Ok(name) = profiles::load_active(&path) {
+            *self.active_profile.get_mut() = self.profiles.iter().position(|p| p.name == name);
+        }
+        self.profile_file = Some(path);
+        self
+    }
+
+    /// Returns the active profile
+    pub fn active_profile(&self) -> Option<&Profile> {
+        self.active_profile.borrow().map(|i| &self.profiles[i])
+    }
+
+    /// Switches to the profile with the given name
+    ///
+    /// The choice is written to the profile file if one was configured.
+    /// Returns an error if no profile with that name exists.
+    pub fn use_profile(&self, name: &str) -> Result<(), CommandError> {
+        let index = self
+            .profiles
+            .iter()
+            .position(|p| p.name == name)
+            .ok_or_else(|| {
+                CommandError::Custom(format!("{}: {}", self.messages.unknown_profile, name))
+            })?;
+        *self.active_profile.borrow_mut() = Some(index);
+
+        if let Some(path) = &self.profile_file {
+            profiles::store_active(path, name)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the tutorial the [`Builtin::Tutorial`] command guides the user
+    /// through
+    pub fn with_tutorial(mut self, tutorial: Tutorial) -> Self {
+        self.tutorial = Some(tutorial);
+        self
+    }
+
+    /// Returns the number of completed steps and the total number of steps of
+    /// the tutorial or `None` if the shell has no tutorial
+    pub fn tutorial_progress(&self) -> Option<(usize, usize)> {
+        let tutorial = self.tutorial.as_ref()?;
+        Some((*self.tutorial_step.borrow(), tutorial.len()))
+    }
+
+    /// Sets a variable of the session
+    ///
+    /// If the variable is persisted, the new value is written to the variable
+    /// file as well.
+    pub fn set_var(&self, name: &str, value: &str) {
+        self.variables
+            .borrow_mut()
+            .insert(name.to_string(), value.to_string());
+        if self.persisted.borrow().contains_key(name) {
+            let _ = self.persist_var(name);
+        }
+    }
+
+    /// Returns all variables of the session
+    pub fn vars(&self) -> BTreeMap<String, String> {
+        self.variables.borrow().clone()
+    }
+
+    /// Loads the variables that were persisted in the given file
+    ///
+    /// The file contains a JSON object with the names and values of all
+    /// variables that were persisted with [`Shell::persist_var`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use simpleshell::{testing::TestShell, Builtin, Shell};
+    /// let path = std::env::temp_dir().join("simpleshell-doc-variables.json");
+    /// # let _ = std::fs::remove_file(&path);
+    /// let shell = Shell::new(None, vec![])
+    ///     .with_builtin(Builtin::Set)
+    ///     .with_builtin(Builtin::Persist)
+    ///     .with_variable_file(&path)?;
+    /// let mut shell = TestShell::new(shell);
+    /// shell.run("set region eu-west-1").expect_ok();
+    /// shell.run("persist region").expect_ok();
+    ///
+    /// let restarted = Shell::new(None, vec![]).with_variable_file(&path)?;
+    /// assert_eq!(restarted.var("region").as_deref(), Some("eu-west-1"));
+    /// # std::fs::remove_file(&path)?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn with_variable_file(mut self, path: impl AsRef<Path>) -> io::Result<Self> {
+        let persisted = variables::load(path.as_ref())?;
+        self.variables
+            .borrow_mut()
+            .extend(persisted.iter().map(|(k, v)| (k.clone(), v.clone())));
+        self.persisted = RefCell::new(persisted);
+        self.variable_file = Some(path.as_ref().to_path_buf());
+        Ok(self)
+    }
+
+    /// Writes the current value of the variable to the variable file, so that
+    /// it survives restarts of the shell
+    pub fn persist_var(&self, name: &str) -> Result<(), CommandError> {
+        let path = self
+            .variable_file
+            .as_ref()
+            .ok_or_else(|| CommandError::Custom(self.messages.no_variable_file.clone()))?;
+        let value = self.variables.borrow().get(name).cloned().ok_or_else(|| {
+            CommandError::Custom(format!("{}: {}", self.messages.unknown_variable, name))
+        })?;
+
+        let mut persisted = self.persisted.borrow_mut();
+        persisted.insert(name.to_string(), value);
+        variables::store(path, &persisted)?;
+        Ok(())
+    }
+
+    /// Removes the variable from the variable file. It keeps its value until
+    /// the shell exits
+    pub fn unpersist_var(&self, name: &str) -> Result<(), CommandError> {
+        let path = self
+            .variable_file
+            .as_ref()
+            .ok_or_else(|| CommandError::Custom(self.messages.no_variable_file.clone()))?;
+        let mut persisted = self.persisted.borrow_mut();
+        if persisted.remove(name).is_none() {
+            return Err(CommandError::Custom(format!(
+                "{}: {}",
+                self.messages.unknown_variable, name
+            )));
+        }
+        variables::store(path, &persisted)?;
+        Ok(())
+    }
+
+    /// Returns the value of a variable
+    ///
+    /// Variables of the session take precedence over the variables of the
+    /// active profile.
+    pub fn var(&self, name: &str) -> Option<String> {
+        self.variables.borrow().get(name).cloned().or_else(|| {
+            self.active_profile()
+                .and_then(|p| p.variables.get(name).cloned())
+        })
+    }
+
+    /// Returns `true` if the command may be called in the active profile
+    fn is_available(&self, name: &str) -> bool {
+        self.active_profile().is_none_or(|p| p.allows(name))
+    }
+
+    /// Adds the commands of all plugins in the directory
+    ///
+    /// Every shared library in the directory is loaded and has to export its
+    /// commands with [`export_plugin!`]. The libraries stay loaded as long as
+    /// the shell exists.
+    ///
+    /// # Safety
+    /// Loading a library runs arbitrary code. Only directories with trusted
+    /// plugins that were built with the same compiler and the same version and
+    /// features of this crate may be loaded.
+    #[cfg(feature = "plugins")]
+    pub unsafe fn with_plugins_dir(mut self, dir: impl AsRef<Path>) -> io::Result<Self> {
+        // SAFETY: the caller trusts the plugins
+        let (libraries, commands) = unsafe { plugins::load_dir(dir.as_ref())? };
+        self.plugins.extend(libraries);
+        self.register(commands)?;
+        Ok(self)
+    }
+
+    /// Adds the commands of a WebAssembly plugin
+    ///
+    /// The module is loaded from a binary `.wasm` or a text `.wat` file and
+    /// runs sandboxed from the host, see [`wasm`] for the interface it has to
+    /// implement.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use simpleshell::{testing::TestShell, Shell};
+    /// let path = std::env::temp_dir().join("simpleshell-doc-plugin.wat");
+    /// std::fs::write(&path, r#"(module
+    ///     (import "env" "write" (func $write (param i32 i32)))
+    ///     (memory (export "memory") 1)
+    ///     (data (i32.const 0) "hello\tGreets the world")
+    ///     (data (i32.const 32) "Hello world\n")
+    ///     (func (export "alloc") (param i32) (result i32) (i32.const 1024))
+    ///     (func (export "manifest") (result i64) (i64.const 22))
+    ///     (func (export "execute") (param i32 i32 i32 i32) (result i32)
+    ///         (call $write (i32.const 32) (i32.const 12))
+    ///         (i32.const 0)))"#)?;
+    ///
+    /// let shell = Shell::new(None, vec![]).with_wasm_plugin(&path)?;
+    /// let mut shell = TestShell::deterministic(shell);
+    /// shell.run("hello").expect_ok();
+    /// assert_eq!(shell.output(), "Hello world\n");
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[cfg(feature = "wasm")]
+    pub fn with_wasm_plugin(mut self, path: impl AsRef<Path>) -> io::Result<Self> {
+        self.register(wasm::load(path.as_ref())?)?;
+        Ok(self)
+    }
+
+    /// Sets the engine that compiles the commands users define at runtime
+    /// with the [`Builtin::Define`] command
+    pub fn with_script_engine(mut self, engine: impl scripting::ScriptEngine + 'static) -> Self {
+        self.script_engine = Some(Box::new(engine));
+        self
+    }
+
+    /// Defines a command whose body is compiled by the script engine of the
+    /// shell, replacing a previously defined command with the same name
+    ///
+    /// Returns an error if a registered command has the same name.
+    pub fn define_command(&self, name: &str, body: &str) -> Result<(), CommandError> {
+        if self.available_commands.iter().any(|c| c.name == name) {
+            return Err(CommandError::Custom(format!(
+                "{}: {}",
+                self.messages.duplicate_command, name
+            )));
+        }
+        let engine = self
+            .script_engine
+            .as_ref()
+            .ok_or_else(|| CommandError::Custom(self.messages.no_script_engine.clone()))?;
+        let exec = engine.compile(name, body).map_err(CommandError::Custom)?;
+        let mut cmd = Command::new(name, body, |_, _| Ok(()));
+        cmd.exec = exec;
+
+        let mut defined = self.defined_commands.borrow_mut();
+        defined.retain(|c| c.name != name);
+        defined.push(Rc::new(cmd));
+        Ok(())
+    }
+
+    /// Configures how commands that are added with [`Shell::with_commands`]
+    /// or loaded from plugins are handled if their name is already taken
+    pub fn with_duplicate_policy(mut self, policy: DuplicatePolicy) -> Self {
+        self.duplicate_policy = policy;
+        self
+    }
+
+    /// Adds commands to the shell
+    ///
+    /// Returns an error of the kind [`io::ErrorKind::AlreadyExists`] if a
+    /// name is already taken and the [`DuplicatePolicy`] rejects duplicates.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use simpleshell::{Command, DuplicatePolicy, Shell};
+    /// let status = || Command::new("status", "Shows the status", |_, _| Ok(()));
+    ///
+    /// let shell = Shell::new(None, vec![status()]);
+    /// let error = shell.with_commands(vec![status()]).err().unwrap();
+    /// assert_eq!(error.to_string(), "duplicate command: status");
+    ///
+    /// let shell = Shell::new(None, vec![status()])
+    ///     .with_duplicate_policy(DuplicatePolicy::Replace)
+    ///     .with_commands(vec![status()]);
+    /// assert_eq!(shell.unwrap().catalog().len(), 1);
+    /// ```
+    pub fn with_commands(mut self, commands: Vec<Command>) -> io::Result<Self> {
+        self.register(commands)?;
+        Ok(self)
+    }
+
+    /// Adds commands according to the [`DuplicatePolicy`] of the shell
+    fn register(&mut self, commands: Vec<Command>) -> io::Result<()> {
+        for cmd in commands {
+            let existing = self
+                .available_commands
+                .iter()
+                .position(|c| c.name == cmd.name);
+            match (existing, self.duplicate_policy) {
+                (None, _) => self.available_commands.push(cmd),
+                (Some(_), DuplicatePolicy::Reject) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("duplicate command: {}", cmd.name),
+                    ))
+                }
+                (Some(i), DuplicatePolicy::Replace) => self.available_commands[i] = cmd,
+                (Some(_), DuplicatePolicy::KeepExisting) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the metadata of all commands of the shell
+    pub fn catalog(&self) -> Vec<CommandInfo> {
+        self.available_commands.iter().map(Command::info).collect()
+    }
+
+    /// Serves the commands of the shell to remote clients over gRPC until the
+    /// server fails
+    ///
+    /// The service is described in `proto/admin.proto` and [`grpc`]. Requests
+    /// are executed one after another on the calling thread like user input,
+    /// without access to the input of the shell.
+    #[cfg(feature = "grpc")]
+    pub fn serve_grpc(&self, addr: std::net::SocketAddr) -> io::Result<()> {
+        grpc::serve(self, addr)
+    }
+
+    /// Returns the names, versions, usages and arguments of all commands
+    /// available in the active profile as JSON, so that remote clients can
+    /// find out what the shell offers
+    ///
+    /// # Example
+    /// ```rust
+    /// # use simpleshell::{Command, Shell};
+    /// let scale = Command::new("scale", "Scales a service", |_, _| Ok(()))
+    ///     .with_version("1.2.0")
+    ///     .with_required_arg("replicas", "the number of instances");
+    ///
+    /// let shell = Shell::new(None, vec![scale]);
+    /// assert_eq!(
+    ///     shell.capabilities(),
+    ///     r#"{"commands":[{"name":"scale","version":"1.2.0","description":"Scales a service","usage":"scale <replicas>","args":[{"name":"replicas","description":"the number of instances"}]}]}"#
+    /// );
+    /// ```
+    pub fn capabilities(&self) -> String {
+        let string = |text: &str| format!("\"{}\"", transcript::escape_json(text));
+        let commands = self
+            .available_commands
+            .iter()
+            .filter(|c| self.is_available(&c.name))
+            .map(|c| {
+                let info = c.info();
+                let args = info
+                    .args
+                    .iter()
+                    .map(|a| {
+                        format!(
+                            r#"{{"name":{},"description":{}}}"#,
+                            string(&a.name),
+                            string(&a.description)
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",");
+                format!(
+                    r#"{{"name":{},"version":{},"description":{},"usage":{},"args":[{}]}}"#,
+                    string(&info.name),
+                    info.version.as_deref().map_or("null".to_string(), string),
+                    string(&info.description),
+                    string(&info.usage),
+                    args
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(r#"{{"commands":[{}]}}"#, commands)
+    }
+
+    /// Returns the usage metrics of all commands executed so far
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.borrow().clone()
+    }
+
+    /// Registers the per-command metrics with the given prometheus registry
+    ///
+    /// The registered counters are updated on every command execution, so the
+    /// registry can be scraped while the shell is running.
+    #[cfg(feature = "prometheus")]
+    pub fn with_prometheus(mut self, registry: &prometheus::Registry) -> prometheus::Result<Self> {
+        self.prometheus = Some(prometheus_metrics::PrometheusMetrics::register(registry)?);
+        Ok(self)
+    }
+
+    /// Subscribes to the events of this shell
+    ///
+    /// Every [`ShellEvent`] that occurs while processing user input is sent to
+    /// the returned receiver. Dropping the receiver ends the subscription.
+    ///
+    /// # Example
+    /// ```rust
+    /// use simpleshell::{Shell, ShellEvent};
+    ///
+    /// let shell = Shell::new(None, vec![]).with_io(std::io::empty(), std::io::sink());
+    /// let events = shell.subscribe();
+    /// shell.process_line("   ");
+    /// shell.process_line("unknown");
+    /// let errors = events.try_iter().filter(|e| matches!(e, ShellEvent::Error(_)));
+    /// assert_eq!(errors.count(), 1);
+    /// ```
+    pub fn subscribe(&self) -> Receiver<ShellEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.borrow_mut().add(sender);
+        receiver
+    }
+
+    /// Processes commands until the input is closed or fails or the
+    /// [`Builtin::Exit`] builtin is called and returns the exit status for
+    /// the process
+    ///
+    /// The status is the one passed to `exit` or, without one, the status of
+    /// the last command: `0` if it succeeded, the exit code of a failed
+    /// native OS command (see [`Shell::with_external_commands`]) and `1` for
+    /// other failures. Errors of failing commands are reported.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use simpleshell::{Builtin, Shell};
+    /// let shell = Shell::new(None, vec![])
+    ///     .with_builtin(Builtin::Exit)
+    ///     .with_io(&b"exit 3\n"[..], Vec::new());
+    /// assert_eq!(shell.run(), 3);
+    ///
+    /// let shell = Shell::new(None, vec![])
+    ///     .with_external_commands("!")
+    ///     .with_io(&b"!sh -c 'exit 3'\n"[..], Vec::new());
+    /// # #[cfg(unix)]
+    /// assert_eq!(shell.run(), 3);
+    /// ```
+    ///
+    /// An application can pass the status on to its caller:
+    /// ```rust,no_run
+    /// # use simpleshell::Shell;
+    /// let shell = Shell::new(None, vec![]);
+    /// std::process::exit(shell.run());
+    /// ```
+    pub fn run(&self) -> i32 {
+        self.input_closed.set(false);
+        self.exit_status.set(None);
+        while !self.input_closed.get() && self.exit_status.get().is_none() {
+            let Ok(outcome) = self.process() else {
+                break;
+            };
+            // Empty lines and the end of the input are not worth reporting
+            match outcome.result {
+                Ok(()) | Err(CommandError::Empty) => {}
+                Err(e) => self.report_error(&e),
+            }
+        }
+        let _ = self.output.borrow_mut().flush();
+        self.exit_status.take().unwrap_or(self.last_status.get())
+    }
+
+    /// Processes a whole command
+    ///
+    /// This includes:
+    /// * Reading the command with arguments from the input (`STDIN` by default)
+    /// * Execute the command
+    /// * Return the result
+    ///
+    /// # Returns
+    /// This function returns a [`ProcessOutcome`] that describes which
+    /// command ran with which arguments and how long it took. Its `result` is
+    /// `Ok(())` if everything went fine. Otherwise it contains a
+    /// [`CommandError`] which represents the error hat occured
+    ///
+    /// # Errors
+    /// Returns an error if the input cannot be read or the prompt cannot be
+    /// written, e.g. because the client of a session disconnected.
+    pub fn process(&self) -> io::Result<ProcessOutcome> {
+        let user_input = match self.get_user_input()? {
+            Ok(user_input) => user_input,
+            Err(e) => return Ok(self.reject(e)),
+        };
+        self.reset_output_limit();
+        let titled = self.show_title(&user_input);
+        let outcome = self.dispatch_paged(user_input);
+        if titled {
+            self.write_terminal("\x1b[23;0t");
+        }
+        self.alert(&outcome);
+        Ok(outcome)
+    }
+
+    /// Executes the command and shows its output in the pager if it is
+    /// enabled and the output does not fit on the screen
+    fn dispatch_paged(&self, user_input: Vec<String>) -> ProcessOutcome {
+        if !self.pager || !self.terminal.get() || self.accessible_output {
+            return self.dispatch(user_input);
+        }
+
+        self.output.borrow_mut().start_buffering();
+        let outcome = self.dispatch(user_input);
+        let buffer = self.output.borrow_mut().take_buffer();
+        if let Some(buffer) = buffer {
+            let paged = pager::is_long(&buffer) && pager::show(&buffer).is_ok();
+            if !paged {
+                let mut output = self.output.borrow_mut();
+                // There is nowhere left to report a failing output to
+                let _ = output.write_all(&buffer).and_then(|_| output.flush());
+            }
+        }
+        outcome
+    }
+
+    /// Saves the title of the terminal and replaces it with the title
+    /// template of [`Shell::with_title`] for the entered line
+    ///
+    /// Returns `true` if the title was replaced and has to be restored.
+    fn show_title(&self, user_input: &[String]) -> bool {
+        let Some(template) = &self.title else {
+            return false;
+        };
+        if user_input.is_empty() || !self.terminal.get() || self.deterministic_output {
+            return false;
+        }
+        let title: String = template
+            .replace("{command}", &user_input.join(" "))
+            .chars()
+            // Control characters would end the sequence early
+            .filter(|c| !c.is_control())
+            .collect();
+        // Pushes the current title onto the title stack of the terminal
+        self.write_terminal(&format!("\x1b[22;0t\x1b]2;{}\x07", title));
+        true
+    }
+
+    /// Writes an escape sequence for the terminal to the output
+    fn write_terminal(&self, sequence: &str) {
+        let mut output = self.output.borrow_mut();
+        // There is nowhere left to report a failing output to
+        let _ = write!(output, "{}", sequence).and_then(|_| output.flush());
+    }
+
+    /// Alerts the user about the finished command according to the
+    /// [`BellPolicy`]
+    fn alert(&self, outcome: &ProcessOutcome) {
+        let Some(command) = &outcome.command else {
+            return;
+        };
+        let failed = outcome.result.is_err();
+        if !self.terminal.get()
+            || self.deterministic_output
+            || !self.bell.alerts(failed, outcome.duration)
+        {
+            return;
+        }
+
+        let mut output = self.output.borrow_mut();
+        // There is nowhere left to report a failing output to
+        let _ = match self.bell.style {
+            BellStyle::Audible => write!(output, "\x07"),
+            BellStyle::Visual => write!(output, "\x1b[?5h")
+                .and_then(|_| output.flush())
+                .map(|_| std::thread::sleep(Duration::from_millis(100)))
+                .and_then(|_| write!(output, "\x1b[?5l")),
+            BellStyle::Notification => {
+                let status = if failed {
+                    &self.messages.command_failed
+                } else {
+                    &self.messages.command_finished
+                };
+                let text = format!(
+                    "{} {} ({:.1}s)",
+                    command,
+                    status,
+                    outcome.duration.as_secs_f64()
+                );
+                write!(output, "{}", bell::notification(&text))
+            }
+        }
+        .and_then(|_| output.flush());
+    }
+
+    /// Processes the given line as if the user had entered it
+    ///
+    /// Unlike [`Shell::process`] this neither shows a prompt nor reads from
+    /// the input of the shell.
+    ///
+    /// Like in POSIX shells, a command may be preceded by assignments like
+    /// `VERBOSE=1 deploy`, which set the variables only while that command
+    /// runs. A line that ends inside of quotes fails without running
+    /// anything.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::io::Write;
+    /// # use simpleshell::{Command, CommandError, Shell};
+    /// # use simpleshell::testing::TestShell;
+    /// let deploy = Command::new("deploy", "Deploys the application", |_, ctx| {
+    ///     if ctx.var("VERBOSE").is_some() {
+    ///         writeln!(ctx.out(), "deploying verbosely")?;
+    ///     }
+    ///     Ok(())
+    /// });
+    /// let mut shell = TestShell::deterministic(Shell::new(None, vec![deploy]));
+    ///
+    /// shell.run("VERBOSE=1 deploy");
+    /// assert_eq!(shell.output(), "deploying verbosely\n");
+    /// assert_eq!(shell.shell().var("VERBOSE"), None);
+    ///
+    /// shell
+    ///     .run("deploy \"now")
+    ///     .expect_error(CommandError::Custom("Unterminated quote".into()));
+    /// ```
+    pub fn process_line(&self, line: &str) -> ProcessOutcome {
+        self.reset_output_limit();
+        match self.tokenize(line) {
+            Ok(user_input) => self.dispatch(user_input),
+            Err(e) => self.reject(e),
+        }
+    }
+
+    /// Splits the line into tokens with the tokenizer of the shell
+    fn tokenize(&self, line: &str) -> Result<Vec<String>, CommandError> {
+        self.tokenizer.tokenize(line).map_err(|e| match e {
+            parser::ParseError::UnterminatedQuote(_) => {
+                CommandError::Custom(self.messages.unterminated_quote.clone())
+            }
+        })
+    }
+
+    /// Fails a line that could not be parsed like a failed command
+    fn reject(&self, error: CommandError) -> ProcessOutcome {
+        self.last_status.set(1);
+        self.emit(ShellEvent::Error(error.clone()));
+        ProcessOutcome::failed(None, vec![], error)
+    }
+
+    /// Starts counting the output of the next command against the
+    /// [`OutputLimit`]
+    fn reset_output_limit(&self) {
+        if let Some(limiter) = &mut self.output.borrow_mut().limiter {
+            limiter.reset();
+        }
+    }
+
+    /// Runs a script line by line
+    ///
+    /// Besides commands, scripts may contain comments starting with `#` and
+    /// the following constructs, which can be nested:
+    /// * `if <command>` ... [`else` ...] `end` - runs the first block if the
+    ///   command succeeds and the `else` block otherwise
+    /// * `for <name> in <values>` ... `end` - runs the block once for every
+    ///   value, which is available as the variable `name`
+    /// * `repeat <count>` ... `end` - runs the block `count` times
+    /// * `parallel [<limit>]` ... `end` - runs the native OS commands of the
+    ///   block concurrently, at most `limit` at a time, see
+    ///   [`Shell::with_external_commands`]. The other commands of the block
+    ///   run one after another in the meantime, as they share the session.
+    ///   The output of the native commands is shown once all of them have
+    ///   exited and the block fails if any of its commands failed
+    ///
+    /// References to variables (`$name` or `${name}`) are replaced with their
+    /// values before a line is executed. Errors of failing commands are
+    /// reported and do not stop the script, but the [`Builtin::Exit`]
+    /// builtin does.
+    ///
+    /// # Returns
+    /// The result of the last command that was executed or an error if the
+    /// script is invalid
+    ///
+    /// # Example
+    /// ```rust
+    /// # use simpleshell::{Command, CommandError, Shell};
+    /// let commands = vec![Command::new("add", "Adds a number to the total", |args, ctx| {
+    ///     let total: i64 = ctx.var("total").and_then(|t| t.parse().ok()).unwrap_or(0);
+    ///     let number: i64 = args[0].parse().map_err(|_| CommandError::ExecutionError)?;
+    ///     ctx.set_var("total", &(total + number).to_string());
+    ///     Ok(())
+    /// })];
+    ///
+    /// let shell = Shell::new(None, commands);
+    /// let script = "
+    ///     for n in 1 2 3
+    ///         repeat 2
+    ///             add $n
+    ///         end
+    ///     end
+    ///     if add one
+    ///         add 100
+    ///     else
+    ///         add 1000
+    ///     end
+    /// ";
+    /// assert_eq!(shell.run_script(script), Ok(()));
+    /// assert_eq!(shell.var("total").as_deref(), Some("1012"));
+    /// ```
+    ///
+    /// With the [`Builtin::Set`] builtin a script can make itself strict, so
+    /// that it stops at the first failing command (`-e`) and at references
+    /// to undefined variables (`-u`):
+    /// ```rust
+    /// # use simpleshell::{Builtin, Shell};
+    /// let shell = Shell::new(None, vec![])
+    ///     .with_builtin(Builtin::Set)
+    ///     .with_io(std::io::empty(), Vec::new());
+    /// let script = "
+    ///     set -eu
+    ///     set greeting $undefined
+    ///     set reached yes
+    /// ";
+    /// assert!(shell.run_script(script).is_err());
+    /// assert_eq!(shell.var("reached"), None);
+    /// ```
+    ///
+    /// The native OS commands of a `parallel` block run at the same time:
+    /// ```rust
+    /// # use simpleshell::Shell;
+    /// let shell = Shell::new(None, vec![])
+    ///     .with_external_commands("!")
+    ///     .with_io(std::io::empty(), Vec::new());
+    /// let script = "
+    ///     parallel 2
+    ///         !true
+    ///         !false
+    ///         !true
+    ///     end
+    /// ";
+    /// assert!(shell.run_script(script).is_err());
+    /// ```
+    pub fn run_script(&self, script: &str) -> Result<(), CommandError> {
+        let statements = self.parse_script(script)?;
+        self.exit_status.set(None);
+        script::run_script(self, &statements)
+    }
+
+    fn parse_script(&self, script: &str) -> Result<Vec<script::Statement>, CommandError> {
+        script::parse(self, script).map_err(|line| {
+            CommandError::Custom(format!("{} {}", self.messages.script_syntax, line))
+        })
+    }
+
+    /// Runs the script in the given file with [`Shell::run_script`]
+    ///
+    /// Relative paths are resolved against the directory of the script that
+    /// is currently running or, outside of scripts, the working directory of
+    /// the session. Scripts may run other scripts, e.g. with the
+    /// [`Builtin::Source`] builtin, up to a fixed depth.
+    pub fn run_script_file(&self, path: impl AsRef<Path>) -> Result<(), CommandError> {
+        let (dir, statements) = self.load_script(path.as_ref())?;
+        self.exit_status.set(None);
+        self.run_script_in(dir, &statements)
+    }
+
+    /// Reads and parses a script file and returns the directory it is in
+    fn load_script(&self, path: &Path) -> Result<(PathBuf, Vec<script::Statement>), CommandError> {
+        let path = match self.script_dirs.borrow().last() {
+            Some(dir) => dir.join(path),
+            None => self.working_dir.borrow().join(path),
+        };
+        if self.script_dirs.borrow().len() >= script::MAX_DEPTH {
+            return Err(CommandError::Custom(self.messages.script_depth.clone()));
+        }
+        let script = std::fs::read_to_string(&path).map_err(|_| {
+            CommandError::Custom(format!(
+                "{}: {}",
+                self.messages.no_such_file,
+                path.display()
+            ))
+        })?;
+
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok((dir, self.parse_script(&script)?))
+    }
+
+    fn run_script_in(
+        &self,
+        dir: PathBuf,
+        statements: &[script::Statement],
+    ) -> Result<(), CommandError> {
+        self.script_dirs.borrow_mut().push(dir);
+        let result = script::run_script(self, statements);
+        self.script_dirs.borrow_mut().pop();
+        result
+    }
+
+    /// Runs the script whose path is passed as the first command line argument
+    /// and exits the process
+    ///
+    /// This allows script files starting with a shebang line like
+    /// `#!/usr/bin/env myapp` to be executed directly, while the application
+    /// starts the interactive shell if it was called without arguments. The
+    /// remaining arguments are available to the script as the variables `1`,
+    /// `2`, and so on. The process exits with the status passed to the
+    /// [`Builtin::Exit`] builtin or, if the script does not call it, with
+    /// status `0` if the last command of the script succeeded and with `1`
+    /// otherwise.
+    ///
+    /// The first argument is taken as the script if it is an existing file
+    /// or, explicitly, if it follows a `--script` flag. Otherwise, e.g. if
+    /// the first argument is a flag of the application like `--verbose` or
+    /// there are no arguments, the function returns without doing anything.
+    /// A `--script` flag without a path prints the usage and exits with
+    /// status `1`.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use simpleshell::Shell;
+    /// let shell = Shell::new(None, vec![]);
+    /// shell.main_for_scripts();
+    /// while let Ok(outcome) = shell.process() {
+    ///     if let Err(e) = outcome.result {
+    ///         shell.report_error(&e);
+    ///     }
+    /// }
+    /// ```
+    pub fn main_for_scripts(&self) {
+        let mut args = std::env::args_os().skip(1);
+        let path = match args.next() {
+            Some(flag) if flag == "--script" => match args.next() {
+                Some(path) => path,
+                None => {
+                    let usage = CommandError::Custom(self.messages.script_usage.clone());
+                    self.report_error(&usage);
+                    std::process::exit(1);
+                }
+            },
+            Some(path) if Path::new(&path).is_file() => path,
+            _ => return,
+        };
+        for (i, arg) in args.enumerate() {
+            self.set_var(&(i + 1).to_string(), &arg.to_string_lossy());
+        }
+
+        // Errors of failing commands have already been reported by the script
+        self.exit_status.set(None);
+        let status = match self.load_script(Path::new(&path)) {
+            Ok((dir, statements)) => match self.run_script_in(dir, &statements) {
+                Ok(()) => 0,
+                Err(_) => 1,
+            },
+            Err(e) => {
+                self.report_error(&e);
+                1
+            }
+        };
+        let _ = self.output.borrow_mut().flush();
+        std::process::exit(self.exit_status.get().unwrap_or(status));
+    }
+
+    /// Executes the command that is named by the first token
+    fn dispatch(&self, mut user_input: Vec<String>) -> ProcessOutcome {
+        let assignments = user_input
+            .iter()
+            .take_while(|token| variables::assignment(token).is_some())
+            .count();
+        // Lines that only consist of assignments are not commands
+        if assignments > 0 && assignments < user_input.len() {
+            let command = user_input.split_off(assignments);
+            return self.with_scoped_vars(&user_input, || self.dispatch(command));
+        }
+
+        let requested_cmd = (!user_input.is_empty()).then(|| user_input.remove(0));
+        let external = self
+            .external_prefix
+            .as_ref()
+            .zip(requested_cmd.as_ref())
+            .and_then(|(prefix, cmd)| cmd.strip_prefix(prefix.as_str()));
+        // The exit code of a failed native OS command
+        let mut exit_code = None;
+
+        let outcome = if let Some(program) = external {
+            self.run_external(program.to_string(), user_input, &mut exit_code)
+        } else {
+            match requested_cmd.map(|c| self.resolve_name(&c)) {
+                Some(requested_cmd) => {
+                    let selected_command = self
+                        .available_commands
+                        .iter()
+                        .filter(|c| c.name == requested_cmd && self.is_available(&c.name))
+                        .collect::<Vec<&Command>>()
+                        .pop();
+
+                    let defined = self
+                        .defined_commands
+                        .borrow()
+                        .iter()
+                        .find(|c| c.name == requested_cmd && self.is_available(&c.name))
+                        .cloned();
+                    let builtin = self.builtins.iter().find(|b| b.name() == requested_cmd);
+
+                    match (selected_command, builtin) {
+                        (Some(cmd), _) => self.run_command(cmd, user_input),
+                        (None, _) if defined.is_some() => {
+                            let cmd = defined.expect("the command is defined");
+                            self.run_command(&cmd, user_input)
+                        }
+                        (None, Some(builtin)) => self.execute(builtin.name(), &user_input, || {
+                            builtin.invoke(&user_input, &Context::new(self))
+                        }),
+                        (None, None) => ProcessOutcome::failed(
+                            Some(requested_cmd),
+                            user_input,
+                            CommandError::NotFound,
+                        ),
+                    }
+                }
+                None => ProcessOutcome::failed(None, user_input, CommandError::Empty),
+            }
+        };
+
+        match &outcome.result {
+            Ok(()) => self.last_status.set(0),
+            Err(CommandError::Empty) => {}
+            Err(_) => self.last_status.set(exit_code.unwrap_or(1)),
+        }
+        if let Some(e) = outcome.err().filter(|e| !matches!(e, CommandError::Empty)) {
+            self.emit(ShellEvent::Error(e.clone()));
+        }
+        outcome
+    }
+
+    /// Returns `true` once the [`Builtin::Exit`] builtin has been called
+    pub(crate) fn exit_requested(&self) -> bool {
+        self.exit_status.get().is_some()
+    }
+
+    /// Sets the variables of the `NAME=value` assignments while the function
+    /// runs and restores their previous values afterwards
+    fn with_scoped_vars<T>(&self, assignments: &[String], f: impl FnOnce() -> T) -> T {
+        let previous: Vec<(String, Option<String>)> = assignments
+            .iter()
+            .filter_map(|token| variables::assignment(token))
+            .map(|(name, value)| {
+                let old = self
+                    .variables
+                    .borrow_mut()
+                    .insert(name.to_string(), value.to_string());
+                (name.to_string(), old)
+            })
+            .collect();
+        let result = f();
+
+        let mut variables = self.variables.borrow_mut();
+        for (name, old) in previous.into_iter().rev() {
+            match old {
+                Some(value) => variables.insert(name, value),
+                None => variables.remove(&name),
+            };
+        }
+        result
+    }
+
+    /// Runs a native OS command that was requested with the external prefix
+    ///
+    /// Sets `exit_code` to the exit code of the process if it failed.
+    fn run_external(
+        &self,
+        program: String,
+        mut arguments: Vec<String>,
+        exit_code: &mut Option<i32>,
+    ) -> ProcessOutcome {
+        // The program may also be separated from the prefix, e.g. `! ls`
+        let program = if program.is_empty() && !arguments.is_empty() {
+            arguments.remove(0)
+        } else {
+            program
+        };
+        let name = format!(
+            "{}{}",
+            self.external_prefix.as_deref().unwrap_or_default(),
+            program
+        );
+
+        if program.is_empty() {
+            ProcessOutcome::failed(None, arguments, CommandError::Empty)
+        } else if self
+            .external_allowlist
+            .as_ref()
+            .is_some_and(|allowed| !allowed.contains(&program))
+        {
+            let error = CommandError::Custom(format!("{}: {}", self.messages.not_allowed, program));
+            ProcessOutcome::failed(Some(name), arguments, error)
+        } else {
+            self.execute(&name, &arguments, || {
+                let status = external::run(&program, &arguments, &Context::new(self))?;
+                if status.success() {
+                    return Ok(());
+                }
+                *exit_code = Some(external::exit_code(status));
+                Err(CommandError::Custom(format!(
+                    "{} {}",
+                    self.messages.process_failed, status
+                )))
+            })
+        }
+    }
+
+    /// Returns the program and the arguments if the line runs a native OS
+    /// command that is allowed, see [`Shell::with_external_commands`]
+    pub(crate) fn external_command(&self, line: &str) -> Option<(String, Vec<String>)> {
+        let prefix = self.external_prefix.as_deref()?;
+        let mut arguments = self.tokenizer.tokenize(line).ok()?;
+        if arguments.is_empty() {
+            return None;
+        }
+        let mut program = arguments.remove(0).strip_prefix(prefix)?.to_string();
+        if program.is_empty() && !arguments.is_empty() {
+            program = arguments.remove(0);
+        }
+        let allowed = self
+            .external_allowlist
+            .as_ref()
+            .is_none_or(|allowed| allowed.contains(&program));
+        (!program.is_empty() && allowed).then_some((program, arguments))
+    }
+
+    /// Executes a user defined command after checking its restrictions
+    fn run_command(&self, cmd: &Command, mut arguments: Vec<String>) -> ProcessOutcome {
+        if self.expand_tilde {
+            arguments = arguments.iter().map(|a| expand::tilde(a)).collect();
+        }
+        if cmd.expand_globs {
+            arguments = self.expand_globs(arguments);
+        }
+        // `--no-cache` is no argument of cached commands, see `cached`
+        let mut refresh = false;
+        if cmd.cache_ttl.is_some() {
+            if let Some(pos) = arguments.iter().position(|a| a == "--no-cache") {
+                arguments.remove(pos);
+                refresh = true;
+            }
+        }
+        let checked = self
+            .complete_arguments(cmd, &mut arguments)
+            .and_then(|_| self.confirm(cmd, &mut arguments))
+            .and_then(|_| self.guard(cmd));
+
+        match checked {
+            Ok(_guard) => self.execute(&cmd.name, &arguments, || {
+                self.cached(cmd, &arguments, refresh, || match &cmd.retry {
+                    Some(policy) => {
+                        policy.run(self, || cmd.invoke(&arguments, &Context::new(self)))
+                    }
+                    None => cmd.invoke(&arguments, &Context::new(self)),
+                })
+            }),
+            Err(e) => ProcessOutcome::failed(Some(cmd.name.clone()), arguments, e),
+        }
+    }
+
+    /// Prints the stored output of a cached command or invokes the command
+    /// and stores its output if it succeeds
+    ///
+    /// With `refresh` the command is invoked even if its output is stored.
+    fn cached(
+        &self,
+        cmd: &Command,
+        arguments: &[String],
+        refresh: bool,
+        invoke: impl FnOnce() -> Result<(), CommandError>,
+    ) -> Result<(), CommandError> {
+        let Some(ttl) = cmd.cache_ttl else {
+            return invoke();
+        };
+        if !refresh {
+            if let Some(output) = self.cache.borrow_mut().lookup(&cmd.name, arguments) {
+                self.output.borrow_mut().write_all(&output)?;
+                return Ok(());
+            }
+        }
+
+        // The output is captured in the buffer of the pager if it is active
+        let start = self.output.borrow().buffered().map(<[u8]>::len);
+        let _buffering = start.is_none().then(|| {
+            self.output.borrow_mut().start_buffering();
+            BufferingGuard(&self.output)
+        });
+        let result = invoke();
+        let captured = {
+            let mut output = self.output.borrow_mut();
+            match start {
+                Some(start) => output
+                    .buffered()
+                    .and_then(|buffer| buffer.get(start..))
+                    .map(<[u8]>::to_vec),
+                None => {
+                    let captured = output.buffered().map(<[u8]>::to_vec);
+                    output.release()?;
+                    captured
+                }
+            }
+        };
+
+        // Output that was released early, e.g. because the command asked
+        // for input, is incomplete and not stored
+        if let (Ok(()), Some(captured)) = (&result, captured) {
+            self.cache
+                .borrow_mut()
+                .store(&cmd.name, arguments, captured, ttl);
+        }
+        result
+    }
+
+    /// Replaces every glob pattern in the arguments with the values it matches
+    fn expand_globs(&self, arguments: Vec<String>) -> Vec<String> {
+        arguments
+            .into_iter()
+            .flat_map(|argument| {
+                let expanded = if glob::is_pattern(&argument) {
+                    self.glob_resolver.expand(&argument)
+                } else {
+                    Vec::new()
+                };
+                if expanded.is_empty() {
+                    vec![argument]
+                } else {
+                    expanded
+                }
+            })
+            .collect()
+    }
+
+    /// Validates the required arguments of the command and asks for the
+    /// missing ones if the argument wizard is enabled
+    fn complete_arguments(
+        &self,
+        cmd: &Command,
+        arguments: &mut Vec<String>,
+    ) -> Result<(), CommandError> {
+        // `--yes` is no argument of destructive commands, see `confirm`
+        let given = arguments
+            .iter()
+            .filter(|a| !(cmd.destructive && *a == "--yes"))
+            .collect::<Vec<_>>();
+        for (arg, value) in cmd.required_args.iter().zip(&given) {
+            self.check_argument(arg, value)
+                .map_err(|e| CommandError::Custom(format!("{}: {}", arg.name, e)))?;
+        }
+
+        for arg in cmd.required_args.iter().skip(given.len()) {
+            if !self.argument_wizard {
+                return Err(CommandError::Custom(format!(
+                    "{}: {}",
+                    self.messages.missing_argument, arg.name
+                )));
+            }
+            let prompt = format!("{} ({}): ", arg.name, arg.description);
+            loop {
+                let value = self.read_line(&prompt)?.ok_or(CommandError::Aborted)?;
+                let value = value.trim();
+                if value.is_empty() {
+                    continue;
+                }
+                match self.check_argument(arg, value) {
+                    Ok(()) => {
+                        arguments.push(value.to_string());
+                        break;
+                    }
+                    Err(e) => self.report_error(&CommandError::Custom(e)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the value of a required argument and returns the reason if it
+    /// is invalid
+    fn check_argument(&self, arg: &Arg, value: &str) -> Result<(), String> {
+        if !arg.values.is_empty() && !arg.values.iter().any(|v| v == value) {
+            let mut reason = format!("{} '{}'", self.messages.unknown_value, value);
+            if let Some(suggestion) = suggest::closest(value, &arg.values) {
+                reason.push_str(&format!(
+                    ", {} '{}'?",
+                    self.messages.did_you_mean, suggestion
+                ));
+            }
+            return Err(reason);
+        }
+        (arg.validate)(value)
+    }
+
+    /// Asks the user to confirm the execution of a destructive command
+    ///
+    /// A `--yes` argument confirms the execution and is removed from the
+    /// arguments.
+    fn confirm(&self, cmd: &Command, arguments: &mut Vec<String>) -> Result<(), CommandError> {
+        if !cmd.destructive {
+            return Ok(());
+        }
+        if let Some(pos) = arguments.iter().position(|a| a == "--yes") {
+            arguments.remove(pos);
+            return Ok(());
+        }
+
+        let question = self.messages.confirm.replace("{command}", &cmd.name);
+        match self.read_line(&question)? {
+            Some(answer)
+                if self
+                    .messages
+                    .yes_answers
+                    .iter()
+                    .any(|yes| yes.eq_ignore_ascii_case(answer.trim())) =>
+            {
+                Ok(())
+            }
+            _ => Err(CommandError::Aborted),
+        }
+    }
+
+    /// Shows the prompt and reads a line from the input of the shell
+    ///
+    /// Returns `None` if the input has been closed.
+    fn read_line(&self, prompt: &str) -> io::Result<Option<String>> {
+        let mut output = self.output.borrow_mut();
+        output.release()?;
+        write!(output, "{}", prompt)?;
+        output.flush()?;
+        drop(output);
+
+        let mut line = String::new();
+        if self.read_input(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+    }
+
+    /// Shows the prompt and reads a line without showing it to the user
+    ///
+    /// The echo of the terminal is turned off while the line is typed and
+    /// the line is not recorded in the transcript.
+    fn read_secret(&self, prompt: &str) -> io::Result<Option<String>> {
+        let mut output = self.output.borrow_mut();
+        output.release()?;
+        write!(output, "{}", prompt)?;
+        output.flush()?;
+        drop(output);
+
+        let hidden = self.terminal.get().then(terminal::InputMode::hidden);
+        let mut line = String::new();
+        let read = self.input.borrow_mut().read_line(&mut line)?;
+        if hidden.is_some() {
+            // The newline of the user has not been echoed either
+            writeln!(self.output.borrow_mut())?;
+        }
+        if read == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+    }
+
+    /// Reads a line from the input and records it in the transcript
+    fn read_input(&self, line: &mut String) -> io::Result<usize> {
+        let read = self.input.borrow_mut().read_line(line)?;
+        self.output.borrow_mut().record_input(line);
+        Ok(read)
+    }
+
+    /// Checks the cooldown and reentrancy restrictions of the command
+    ///
+    /// The returned guard marks the command as running until it is dropped.
+    fn guard(&self, cmd: &Command) -> Result<RunningGuard<'_>, CommandError> {
+        if !cmd.reentrant && self.running.borrow().contains(&cmd.name) {
+            return Err(CommandError::AlreadyRunning);
+        }
+
+        if let Some(cooldown) = cmd.cooldown {
+            let mut last_invocations = self.last_invocations.borrow_mut();
+            if let Some(last) = last_invocations.get(&cmd.name) {
+                let elapsed = last.elapsed();
+                if elapsed < cooldown {
+                    return Err(CommandError::Cooldown(cooldown - elapsed));
+                }
+            }
+            last_invocations.insert(cmd.name.clone(), Instant::now());
+        }
+
+        if cmd.reentrant {
+            return Ok(RunningGuard(None));
+        }
+        self.running.borrow_mut().insert(cmd.name.clone());
+        Ok(RunningGuard(Some((&self.running, cmd.name.clone()))))
+    }
+
+    /// Invokes the command, records its metrics and emits the events around
+    /// its execution
+    fn execute(
+        &self,
+        name: &str,
+        arguments: &[String],
+        invoke: impl FnOnce() -> Result<(), CommandError>,
+    ) -> ProcessOutcome {
+        self.emit(ShellEvent::CommandStarted {
+            name: name.to_string(),
+            arguments: arguments.to_vec(),
+        });
+
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!(
+            "command",
+            command = name,
+            args = arguments.len(),
+            outcome = tracing::field::Empty,
+            duration_ms = tracing::field::Empty,
+        );
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+
+        let started = Instant::now();
+        let result = if self.catch_panics {
+            catch_panic(invoke).unwrap_or_else(|payload| {
+                let msg = payload
+                    .downcast_ref::<&str>()
+                    .map(|s| s.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown cause".to_string());
+                Err(CommandError::Panicked(msg))
+            })
+        } else {
+            invoke()
+        };
+        let duration = started.elapsed();
+
+        #[cfg(feature = "tracing")]
+        {
+            span.record("duration_ms", duration.as_secs_f64() * 1000.0);
+            match &result {
+                Ok(()) => span.record("outcome", "ok"),
+                Err(e) => span.record("outcome", tracing::field::display(e)),
+            };
+        }
+
+        self.metrics
+            .borrow_mut()
+            .record(name, duration, result.is_err());
+        #[cfg(feature = "prometheus")]
+        if let Some(prometheus) = &self.prometheus {
+            prometheus.record(name, duration, result.is_err());
+        }
+        self.emit(ShellEvent::CommandFinished {
+            name: name.to_string(),
+            duration,
+            result: result.clone(),
+        });
+        ProcessOutcome {
+            command: Some(name.to_string()),
+            arguments: arguments.to_vec(),
+            duration,
+            result,
+        }
+    }
+
+    fn emit(&self, event: ShellEvent) {
+        self.subscribers.borrow_mut().emit(event);
+    }
+
+    /// Returns the configured prefix or the default prompt
+    fn prompt(&self) -> String {
+        self.active_profile()
+            .and_then(|p| p.prompt.as_ref())
+            .or(self.prefix.as_ref())
+            .unwrap_or(&self.messages.prompt)
+            .clone()
+    }
+
+    /// Returns the prompt of the hook registered with
+    /// [`Shell::on_before_prompt`] or the prompt of the shell
+    pub(crate) fn current_prompt(&self) -> String {
+        let dynamic_prefix = self.before_prompt.as_ref().and_then(|hook| hook());
+        dynamic_prefix.unwrap_or_else(|| self.prompt())
+    }
+
+    /// Writes the prompt and, on terminals, the right prompt segment
+    fn render_prompt(&self, prompt: &str) -> io::Result<()> {
+        let mut output = self.output.borrow_mut();
+        write!(output, "{}", prompt)?;
+
+        let right_prompt = self
+            .right_prompt
+            .as_ref()
+            .filter(|_| self.terminal.get() && !self.accessible_output);
+        if let (Some(segment), Some(width)) = (right_prompt, terminal::width()) {
+            let segment = segment();
+            let segment_width = segment.chars().count();
+            if prompt.chars().count() + segment_width < width {
+                // Save the cursor, print the segment at the right edge and
+                // move back to the end of the prompt
+                let column = width - segment_width + 1;
+                write!(output, "\x1b7\x1b[{}G{}\x1b8", column, segment)?;
+            }
+        }
+        output.flush()
+    }
+
+    /// Rewrites the accepted line with the transient prompt marker
+    fn collapse_prompt(&self, prompt: &str, line: &str) -> io::Result<()> {
+        let marker = match self
+            .transient_prompt
+            .as_ref()
+            .filter(|_| self.terminal.get() && !self.accessible_output)
+        {
+            Some(marker) => marker,
+            None => return Ok(()),
+        };
+        let width = terminal::width().unwrap_or(80).max(1);
+        let rows = (prompt.chars().count() + line.chars().count())
+            .max(1)
+            .div_ceil(width);
+
+        let mut output = self.output.borrow_mut();
+        // Move to the first row of the accepted line and clear everything below
+        write!(output, "\x1b[{}A\r\x1b[J{}{}\n", rows, marker, line)?;
+        output.flush()
+    }
+
+    /// Runs the scheduled commands that are due
+    fn run_scheduled(&self) {
+        let due = self.scheduler.borrow_mut().take_due(Instant::now());
+        for line in due {
+            if let Some(e) = self.process_line(&line).err() {
+                self.report_error(e);
+            }
+        }
+    }
+
+    /// Waits until the user enters a line or the next scheduled command is
+    /// due and returns `true` in the first case
+    #[cfg(unix)]
+    fn wait_for_line(&self) -> bool {
+        let Some(due) = self.scheduler.borrow().next_due() else {
+            return true;
+        };
+        terminal::wait_for_input(due.saturating_duration_since(Instant::now()))
+    }
+
+    /// Reads the user input from the input of the shell and splits it into
+    /// tokens
+    ///
+    /// The inner result fails if the line cannot be split into tokens.
+    fn get_user_input(&self) -> io::Result<Result<Vec<String>, CommandError>> {
+        self.run_scheduled();
+        let prompt = self.current_prompt();
+        if !self.deterministic_output {
+            self.render_prompt(&prompt)?;
+        }
+        #[cfg(unix)]
+        while self.terminal.get() && !self.wait_for_line() {
+            // Replace the prompt with the output of the jobs and show it again
+            // below them
+            let clear = if self.accessible_output {
+                "\n"
+            } else {
+                "\r\x1b[2K"
+            };
+            write!(self.output.borrow_mut(), "{}", clear)?;
+            self.run_scheduled();
+            self.render_prompt(&prompt)?;
+        }
+
+        let mut user_input = String::new();
+        #[cfg(feature = "websocket")]
+        self.offer_completions(true);
+        let read = self.read_input(&mut user_input);
+        #[cfg(feature = "websocket")]
+        self.offer_completions(false);
+        let read = read?;
+
+        if read == 0 {
+            self.input_closed.set(true);
+        }
+        if user_input.ends_with('\n') && !self.deterministic_output {
+            self.collapse_prompt(&prompt, user_input.trim_end_matches(['\r', '\n']))?;
+        }
+        self.emit(ShellEvent::LineRead(
+            user_input.trim_end_matches(['\r', '\n']).to_string(),
+        ));
+        self.history.borrow_mut().add(&user_input);
+
+        Ok(self.tokenize(&user_input))
+    }
+
+    /// Lets the line editor of the session complete the line that is read
+    /// next, or stops it from completing the input of commands
+    #[cfg(feature = "websocket")]
+    fn offer_completions(&self, enabled: bool) {
+        if let Some(slot) = &*self.completions.borrow() {
+            slot.replace(enabled.then(|| self.completer()));
+        }
+    }
+
+    /// Returns the completer for the commands that are available and their
+    /// arguments
+    #[cfg(feature = "websocket")]
+    fn completer(&self) -> completion::ShellCompleter {
+        let defined = self.defined_commands.borrow();
+        let commands = self
+            .available_commands
+            .iter()
+            .chain(defined.iter().map(|c| c.as_ref()))
+            .filter(|c| self.is_available(&c.name));
+
+        let mut completer = completion::ShellCompleter {
+            cache: self.completion_cache.clone(),
+            matcher: self.completion_matcher,
+            ..Default::default()
+        };
+        for command in commands {
+            completer.commands.push(
+                completion::Candidate::new(&command.name)
+                    .with_description(command.description_in(self.locale())),
+            );
+            let arguments = completion::Arguments {
+                values: command
+                    .required_args
+                    .iter()
+                    .map(|a| a.values.clone())
+                    .collect(),
+                completer: command.completer.clone(),
+                ttl: command.completion_ttl,
+            };
+            completer.arguments.insert(command.name.clone(), arguments);
+        }
+        for builtin in &self.builtins {
+            completer
+                .commands
+                .push(completion::Candidate::new(builtin.name()));
+        }
+        completer.rank_commands(&self.history.borrow().entries, std::time::SystemTime::now());
+        completer
+    }
+}
+
+/// Writes the output that is held back and stops buffering when dropped, so
+/// that the output is not swallowed after a command panicked
+struct BufferingGuard<'a>(&'a RefCell<output::ShellOutput>);
+
+impl Drop for BufferingGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut output) = self.0.try_borrow_mut() {
+            // There is nowhere left to report a failing output to
+            let _ = output.release();
+        }
+    }
+}
+
+/// Removes a non-reentrant command from the running commands when dropped
+struct RunningGuard<'a>(Option<(&'a RefCell<HashSet<String>>, String)>);
+
+impl Drop for RunningGuard<'_> {
+    fn drop(&mut self) {
+        if let Some((running, name)) = self.0.take() {
+            running.borrow_mut().remove(&name);
+        }
     }
 }
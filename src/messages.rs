@@ -0,0 +1,320 @@
+//! The texts the shell shows to the user
+use crate::CommandError;
+
+/// All built-in texts of the shell, which may be replaced to ship consoles in
+/// other languages
+///
+/// # Example
+/// ```rust
+/// use simpleshell::{CommandError, Messages, Shell};
+///
+/// let messages = Messages {
+///     prompt: "befehl> ".to_owned(),
+///     not_found: "Befehl nicht gefunden".to_owned(),
+///     ..Messages::default()
+/// };
+/// assert_eq!(messages.error(&CommandError::NotFound), "Befehl nicht gefunden");
+///
+/// let shell = Shell::new(None, vec![]).with_messages(messages);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Messages {
+    /// The prompt that is shown if no prefix was given to the shell
+    pub prompt: String,
+    /// Shown for [`CommandError::Empty`]
+    pub empty: String,
+    /// Shown for [`CommandError::NotFound`]
+    pub not_found: String,
+    /// Shown for [`CommandError::ExecutionError`]
+    pub execution_error: String,
+    /// The label in front of errors reported by the shell
+    pub error: String,
+    /// Shown in front of the message of [`CommandError::Panicked`]
+    pub panicked: String,
+    /// Shown in front of the remaining time of [`CommandError::Cooldown`]
+    pub cooldown: String,
+    /// Shown for [`CommandError::AlreadyRunning`]
+    pub already_running: String,
+    /// Shown for [`CommandError::Aborted`]
+    pub aborted: String,
+    /// Shown in front of the usage of [`CommandError::Usage`]
+    pub usage: String,
+    /// The question before a destructive command is executed. `{command}` is
+    /// replaced with the name of the command
+    pub confirm: String,
+    /// The answers that confirm the execution of a destructive command
+    pub yes_answers: Vec<String>,
+    /// Shown by the `undo` builtin in front of the description of the
+    /// reverted action
+    pub undone: String,
+    /// Shown by the `undo` builtin if there is nothing to undo
+    pub nothing_to_undo: String,
+    /// Shown in front of the name of a profile that does not exist
+    pub unknown_profile: String,
+    /// Shown by the `profile` builtin if no profile is active
+    pub no_profile: String,
+    /// Shown in front of a path that is not a directory
+    pub no_such_directory: String,
+    /// Shown in front of the exit status of a failed native OS command
+    pub process_failed: String,
+    /// Shown in front of a native OS command that is not on the allowlist
+    pub not_allowed: String,
+    /// The error of a line that ends inside of quotes
+    pub unterminated_quote: String,
+    /// The error of a script with invalid syntax, followed by the line number
+    pub script_syntax: String,
+    /// The error of a script file that cannot be read, followed by its path
+    pub no_such_file: String,
+    /// The usage of the `--script` flag, see
+    /// [`Shell::main_for_scripts`](crate::Shell::main_for_scripts)
+    pub script_usage: String,
+    /// The error of script files that run each other too deeply nested
+    pub script_depth: String,
+    /// The error of a reference to an undefined variable in `set -u` mode,
+    /// followed by its name
+    pub unbound_variable: String,
+    /// The error of the `trap` builtin outside of scripts
+    pub trap_outside_script: String,
+    /// The error of a script that was interrupted with Ctrl-C
+    pub interrupted: String,
+    /// The error of a `parallel` block with failed commands, followed by
+    /// their number and the number of commands in the block
+    pub parallel_failed: String,
+    /// The heading above the examples of a command in `help <command>`
+    pub examples: String,
+    /// Shown by `help --search` if no command matches the term
+    pub no_matches: String,
+    /// Shown when the `tutorial` builtin starts
+    pub tutorial_pause: String,
+    /// Shown in front of the number of a tutorial step
+    pub tutorial_step: String,
+    /// Shown if the user did not complete a tutorial step
+    pub tutorial_retry: String,
+    /// Shown after the last step of the tutorial has been completed
+    pub tutorial_done: String,
+    /// The error of a command that is invoked without a required argument,
+    /// followed by the name of the argument
+    pub missing_argument: String,
+    /// The error of a value that is not one of the values of the argument,
+    /// followed by the value
+    pub unknown_value: String,
+    /// Shown in front of the closest value to a misspelled one
+    pub did_you_mean: String,
+    /// Shown in front of the number of entries removed by `history scrub`
+    pub scrubbed: String,
+    /// The error of persisting a variable without a variable file
+    pub no_variable_file: String,
+    /// Shown in front of the name of a variable that does not exist
+    pub unknown_variable: String,
+    /// The error of defining a command without a script engine
+    pub no_script_engine: String,
+    /// Shown in front of a namespace that contains no commands
+    pub unknown_namespace: String,
+    /// Shown in front of the name of a command that already exists
+    pub duplicate_command: String,
+    /// The error of managing sessions outside of a session server
+    pub no_session_server: String,
+    /// Shown in front of the id of a session that does not exist
+    pub unknown_session: String,
+    /// Shown in front of the id of a session another client is attached to
+    pub session_in_use: String,
+    /// The state of a session a client is attached to
+    pub session_attached: String,
+    /// The state of a session no client is attached to
+    pub session_detached: String,
+    /// Shown in place of the output beyond the size limit of a command
+    pub output_truncated: String,
+    /// Shown in place of the output beyond the line rate limit of a command
+    pub output_throttled: String,
+    /// Shown after the number of hidden items of a collapsed array
+    pub collapsed_items: String,
+    /// Shown if the answer to a selection does not match any item
+    pub invalid_selection: String,
+    /// Explains how the items of a multi selection are picked
+    pub multi_select_hint: String,
+    /// Explains how an item is picked with the keys of a terminal
+    pub select_keys_hint: String,
+    /// Explains how the items of a multi selection are picked with the keys
+    /// of a terminal
+    pub multi_select_keys_hint: String,
+    /// Shown in front of the id of a new scheduled job
+    pub job_scheduled: String,
+    /// Shown in front of the id of a scheduled job that does not exist
+    pub unknown_job: String,
+    /// Shown in front of the interval and the command of the `watch` builtin
+    pub watch_header: String,
+    /// Shown in front of the number of the next attempt of a failed command
+    pub retrying: String,
+    /// Shown in the desktop notification of a command that finished, see
+    /// [`BellStyle::Notification`](crate::BellStyle::Notification)
+    pub command_finished: String,
+    /// Shown in the desktop notification of a command that failed
+    pub command_failed: String,
+    /// Shown in front of the seconds until a result of the `cache` builtin
+    /// expires
+    pub cache_expires_in: String,
+    /// The usage of the `help` builtin
+    pub help_usage: String,
+    /// The usage of the `history` builtin
+    pub history_usage: String,
+    /// The usage of the `profile` builtin
+    pub profile_usage: String,
+    /// The usage of the `cd` builtin
+    pub cd_usage: String,
+    /// The usage of the `tutorial` builtin
+    pub tutorial_usage: String,
+    /// The usage of the `set` builtin
+    pub set_usage: String,
+    /// The usage of the `persist` builtin
+    pub persist_usage: String,
+    /// The usage of the `unpersist` builtin
+    pub unpersist_usage: String,
+    /// The usage of the `define` builtin
+    pub define_usage: String,
+    /// The usage of the `use` builtin
+    pub use_usage: String,
+    /// The usage of the `sessions` builtin
+    pub sessions_usage: String,
+    /// The usage of the `schedule` builtin
+    pub schedule_usage: String,
+    /// The usage of the `watch` builtin
+    pub watch_usage: String,
+    /// The usage of the `retry` builtin
+    pub retry_usage: String,
+    /// The usage of the `source` builtin
+    pub source_usage: String,
+    /// The usage of the `exit` builtin
+    pub exit_usage: String,
+    /// The usage of the `trap` builtin
+    pub trap_usage: String,
+    /// The usage of the `cache` builtin
+    pub cache_usage: String,
+    /// The error of the `tutorial` builtin if the shell has no tutorial
+    pub no_tutorial: String,
+    /// The error of the `cd` builtin without a directory if `HOME` is not set
+    pub no_home: String,
+    /// The error of `cd -` before the directory was changed
+    pub no_previous_dir: String,
+    /// The column headers of the `stats` builtin
+    pub stats_header: [String; 4],
+}
+
+impl Messages {
+    /// Returns the text that describes the error
+    pub fn error(&self, error: &CommandError) -> String {
+        match error {
+            CommandError::Empty => self.empty.clone(),
+            CommandError::NotFound => self.not_found.clone(),
+            CommandError::ExecutionError => self.execution_error.clone(),
+            CommandError::Panicked(msg) => format!("{}: {}", self.panicked, msg),
+            CommandError::Custom(msg) => msg.clone(),
+            CommandError::Cooldown(remaining) => {
+                format!("{} {:.1}s", self.cooldown, remaining.as_secs_f64())
+            }
+            CommandError::AlreadyRunning => self.already_running.clone(),
+            CommandError::Aborted => self.aborted.clone(),
+            CommandError::Usage(usage) => format!("{}: {}", self.usage, usage),
+        }
+    }
+}
+
+impl Default for Messages {
+    fn default() -> Self {
+        Messages {
+            prompt: "cmdshell> ".to_owned(),
+            empty: "No command given".to_owned(),
+            not_found: "Command not found".to_owned(),
+            execution_error: "Error while executing command".to_owned(),
+            error: "error".to_owned(),
+            panicked: "Command panicked".to_owned(),
+            cooldown: "Command is cooling down, try again in".to_owned(),
+            already_running: "Command is already running".to_owned(),
+            aborted: "Command aborted".to_owned(),
+            usage: "Usage".to_owned(),
+            confirm: "Do you really want to execute '{command}'? [y/N] ".to_owned(),
+            yes_answers: vec!["y".to_owned(), "yes".to_owned()],
+            undone: "Undone".to_owned(),
+            nothing_to_undo: "Nothing to undo".to_owned(),
+            unknown_profile: "Unknown profile".to_owned(),
+            no_profile: "No profile is active".to_owned(),
+            no_such_directory: "No such directory".to_owned(),
+            process_failed: "Process failed with".to_owned(),
+            not_allowed: "Not allowed".to_owned(),
+            unterminated_quote: "Unterminated quote".to_owned(),
+            script_syntax: "Syntax error in line".to_owned(),
+            no_such_file: "No such file".to_owned(),
+            script_usage: "Usage: --script <file> [arguments...]".to_owned(),
+            script_depth: "Scripts are nested too deeply".to_owned(),
+            unbound_variable: "Undefined variable".to_owned(),
+            trap_outside_script: "trap can only be used in scripts".to_owned(),
+            interrupted: "Interrupted".to_owned(),
+            parallel_failed: "Commands of the parallel block failed".to_owned(),
+            examples: "Examples".to_owned(),
+            no_matches: "No matching commands".to_owned(),
+            tutorial_pause: "Enter an empty line to pause the tutorial".to_owned(),
+            tutorial_step: "Step".to_owned(),
+            tutorial_retry: "Not quite, try again".to_owned(),
+            tutorial_done: "Tutorial completed".to_owned(),
+            missing_argument: "Missing argument".to_owned(),
+            unknown_value: "unknown value".to_owned(),
+            did_you_mean: "did you mean".to_owned(),
+            scrubbed: "Removed history entries".to_owned(),
+            no_variable_file: "No variable file configured".to_owned(),
+            unknown_variable: "Unknown variable".to_owned(),
+            no_script_engine: "No script engine configured".to_owned(),
+            unknown_namespace: "Unknown namespace".to_owned(),
+            duplicate_command: "Command already exists".to_owned(),
+            no_session_server: "Not running in a session server".to_owned(),
+            unknown_session: "Unknown session".to_owned(),
+            session_in_use: "Session is attached to another client".to_owned(),
+            session_attached: "attached".to_owned(),
+            session_detached: "detached".to_owned(),
+            output_truncated: "Output truncated".to_owned(),
+            output_throttled: "Output rate limited, lines dropped".to_owned(),
+            collapsed_items: "more".to_owned(),
+            invalid_selection: "Invalid selection".to_owned(),
+            multi_select_hint: "numbers toggle, /text filters, an empty line confirms".to_owned(),
+            select_keys_hint: "arrow keys move, Enter picks".to_owned(),
+            multi_select_keys_hint:
+                "arrow keys move, Space toggles, typing filters, Enter confirms".to_owned(),
+            job_scheduled: "Scheduled job".to_owned(),
+            unknown_job: "Unknown job".to_owned(),
+            watch_header: "Every".to_owned(),
+            retrying: "Retrying".to_owned(),
+            command_finished: "finished".to_owned(),
+            command_failed: "failed".to_owned(),
+            cache_expires_in: "expires in".to_owned(),
+            help_usage: "help [<command> | <namespace> | --short | --search <term>]".to_owned(),
+            history_usage: "history [--with-time | scrub <pattern>]".to_owned(),
+            profile_usage: "profile [list | use <name>]".to_owned(),
+            cd_usage: "cd [<dir> | -]".to_owned(),
+            tutorial_usage: "tutorial [reset]".to_owned(),
+            set_usage: "set [<name> <value> | -e | +e | -u | +u]".to_owned(),
+            persist_usage: "persist <name>".to_owned(),
+            unpersist_usage: "unpersist <name>".to_owned(),
+            define_usage: "define <name> <body>".to_owned(),
+            use_usage: "use [<namespace>]".to_owned(),
+            sessions_usage:
+                "sessions [list | attach <id> | detach | close <id> | broadcast <message>]"
+                    .to_owned(),
+            schedule_usage:
+                "schedule [every <interval> <command> | at <HH:MM> <command> | list | cancel <id>]"
+                    .to_owned(),
+            watch_usage: "watch [--diff] <interval> <command>".to_owned(),
+            retry_usage: "retry <attempts> [--backoff <interval>] <command>".to_owned(),
+            source_usage: "source <file>".to_owned(),
+            exit_usage: "exit [<status>]".to_owned(),
+            trap_usage: "trap <command> exit | trap - exit".to_owned(),
+            cache_usage: "cache [list | clear [<command>]]".to_owned(),
+            no_tutorial: "No tutorial is available".to_owned(),
+            no_home: "HOME is not set".to_owned(),
+            no_previous_dir: "No previous directory".to_owned(),
+            stats_header: [
+                "COMMAND".to_owned(),
+                "CALLS".to_owned(),
+                "ERRORS".to_owned(),
+                "TIME".to_owned(),
+            ],
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//! Usage metrics of the commands that have been executed by a
+//! [`Shell`](crate::Shell)
+use std::{collections::BTreeMap, time::Duration};
+
+/// Usage counters of a single command
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandMetrics {
+    /// How often the command has been invoked
+    pub invocations: u64,
+    /// How many of the invocations returned an error
+    pub errors: u64,
+    /// The time spent executing the command over all invocations
+    pub total_duration: Duration,
+}
+
+/// Usage counters of all commands that have been executed at least once
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metrics {
+    commands: BTreeMap<String, CommandMetrics>,
+}
+
+impl Metrics {
+    /// Returns the counters of the command with the given name
+    pub fn get(&self, name: &str) -> Option<&CommandMetrics> {
+        self.commands.get(name)
+    }
+
+    /// Iterates over the counters of all commands ordered by their name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &CommandMetrics)> {
+        self.commands.iter().map(|(name, m)| (name.as_str(), m))
+    }
+
+    pub(crate) fn record(&mut self, name: &str, duration: Duration, failed: bool) {
+        let metrics = self.commands.entry(name.to_string()).or_default();
+        metrics.invocations += 1;
+        metrics.total_duration += duration;
+        if failed {
+            metrics.errors += 1;
+        }
+    }
+}
@@ -0,0 +1,47 @@
+//! The outcome of processing a line of user input
+use std::time::Duration;
+
+use crate::CommandError;
+
+/// Describes what happened while the shell processed a line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessOutcome {
+    /// The name of the requested command or `None` if the line was empty
+    pub command: Option<String>,
+    /// The arguments that were passed to the command
+    pub arguments: Vec<String>,
+    /// How long the command was running. Zero if it was not executed
+    pub duration: Duration,
+    /// The result of the command
+    pub result: Result<(), CommandError>,
+}
+
+impl ProcessOutcome {
+    pub(crate) fn failed(
+        command: Option<String>,
+        arguments: Vec<String>,
+        error: CommandError,
+    ) -> Self {
+        ProcessOutcome {
+            command,
+            arguments,
+            duration: Duration::ZERO,
+            result: Err(error),
+        }
+    }
+
+    /// Returns `true` if the command was executed successfully
+    pub fn is_ok(&self) -> bool {
+        self.result.is_ok()
+    }
+
+    /// Returns the error if processing the line failed
+    pub fn err(&self) -> Option<&CommandError> {
+        self.result.as_ref().err()
+    }
+
+    /// Converts the outcome into the bare result of the command
+    pub fn into_result(self) -> Result<(), CommandError> {
+        self.result
+    }
+}
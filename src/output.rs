@@ -0,0 +1,302 @@
+//! The output of a shell, which may be mirrored into a transcript
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    ansi,
+    transcript::{EventKind, Transcript},
+    Messages,
+};
+
+/// Limits how much output a single command may produce, so that a command
+/// that floods the output cannot make the shell unresponsive
+///
+/// Output beyond the limits is dropped and replaced with a notice. See
+/// [`Shell::with_output_limit`](crate::Shell::with_output_limit) and
+/// [`ShellServer::with_output_limit`](crate::ShellServer::with_output_limit).
+///
+/// # Example
+/// ```rust
+/// # use std::io::Write;
+/// # use simpleshell::{testing::TestShell, Command, OutputLimit, Shell};
+/// let commands = vec![Command::new("dump", "Dumps everything", |_, ctx| {
+///     write!(ctx.out(), "{}", "x".repeat(1000))?;
+///     Ok(())
+/// })];
+///
+/// let shell = Shell::new(None, commands).with_output_limit(OutputLimit::new().with_max_bytes(8));
+/// let mut shell = TestShell::deterministic(shell);
+/// shell.run("dump").expect_ok();
+/// assert_eq!(shell.output(), "xxxxxxxx\n[Output truncated]\n");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputLimit {
+    max_bytes: Option<usize>,
+    max_lines_per_second: Option<usize>,
+}
+
+impl OutputLimit {
+    /// Creates a limit that lets all output through
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Truncates the output of a command after `bytes`
+    pub fn with_max_bytes(mut self, bytes: usize) -> Self {
+        self.max_bytes = Some(bytes);
+        self
+    }
+
+    /// Drops the output of a command that writes more than `lines` lines
+    /// within a second until the second is over
+    ///
+    /// # Example
+    /// ```rust
+    /// # use std::io::Write;
+    /// # use simpleshell::{testing::TestShell, Command, OutputLimit, Shell};
+    /// let commands = vec![Command::new("flood", "Floods the output", |_, ctx| {
+    ///     write!(ctx.out(), "{}", "x\n".repeat(1000))?;
+    ///     Ok(())
+    /// })];
+    ///
+    /// let limit = OutputLimit::new().with_max_lines_per_second(5);
+    /// let mut shell = TestShell::deterministic(Shell::new(None, commands).with_output_limit(limit));
+    /// shell.run("flood").expect_ok();
+    /// assert_eq!(shell.output(), "x\nx\nx\nx\nx\n[Output rate limited, lines dropped]\n");
+    /// ```
+    pub fn with_max_lines_per_second(mut self, lines: usize) -> Self {
+        self.max_lines_per_second = Some(lines);
+        self
+    }
+}
+
+/// Applies an [`OutputLimit`] to the output of one command after another
+pub(crate) struct Limiter {
+    limit: OutputLimit,
+    written: usize,
+    truncated: bool,
+    window: Instant,
+    lines: usize,
+    throttled: bool,
+    at_line_start: bool,
+    truncated_notice: String,
+    throttled_notice: String,
+}
+
+impl Limiter {
+    pub(crate) fn new(limit: OutputLimit, messages: &Messages) -> Self {
+        Limiter {
+            limit,
+            written: 0,
+            truncated: false,
+            window: Instant::now(),
+            lines: 0,
+            throttled: false,
+            at_line_start: true,
+            truncated_notice: messages.output_truncated.clone(),
+            throttled_notice: messages.output_throttled.clone(),
+        }
+    }
+
+    /// Starts counting the output of the next command
+    pub(crate) fn reset(&mut self) {
+        self.written = 0;
+        self.truncated = false;
+        self.window = Instant::now();
+        self.lines = 0;
+        self.throttled = false;
+    }
+
+    /// Returns how many bytes at the start of `buf` may be written and the
+    /// notice that has to be written after them
+    pub(crate) fn admit(&mut self, buf: &[u8]) -> (usize, Option<String>) {
+        if self.truncated {
+            return (0, None);
+        }
+        if self.window.elapsed() >= Duration::from_secs(1) {
+            self.window = Instant::now();
+            self.lines = 0;
+            self.throttled = false;
+        }
+
+        let mut admitted = buf.len();
+        let mut throttled = false;
+        if let Some(max) = self.limit.max_lines_per_second {
+            if self.lines >= max {
+                let notice = (!self.throttled).then(|| self.notice(&self.throttled_notice));
+                self.throttled = true;
+                self.at_line_start = true;
+                return (0, notice);
+            }
+            // Only the lines up to the limit are admitted, even if they are
+            // written at once
+            let mut newlines = buf
+                .iter()
+                .enumerate()
+                .filter(|(_, b)| **b == b'\n')
+                .map(|(i, _)| i);
+            match newlines.nth(max - self.lines - 1) {
+                Some(last) => {
+                    admitted = last + 1;
+                    self.lines = max;
+                    throttled = admitted < buf.len();
+                    self.throttled = throttled;
+                }
+                None => self.lines += buf.iter().filter(|b| **b == b'\n').count(),
+            }
+        }
+
+        let mut notice = None;
+        if let Some(max) = self.limit.max_bytes {
+            let remaining = max.saturating_sub(self.written);
+            if admitted > remaining {
+                admitted = remaining;
+                self.truncated = true;
+            }
+        }
+        self.written += admitted;
+        if admitted > 0 {
+            self.at_line_start = buf[admitted - 1] == b'\n';
+        }
+        if self.truncated {
+            notice = Some(self.notice(&self.truncated_notice));
+        } else if throttled {
+            notice = Some(self.notice(&self.throttled_notice));
+        }
+        (admitted, notice)
+    }
+
+    /// Formats the notice on a line of its own
+    fn notice(&self, text: &str) -> String {
+        let separator = if self.at_line_start { "" } else { "\n" };
+        format!("{}[{}]\n", separator, text)
+    }
+}
+
+/// Writes to the output of the shell and records everything in the active
+/// transcript
+pub(crate) struct ShellOutput {
+    inner: Box<dyn Write>,
+    pub(crate) transcript: Option<Transcript>,
+    pub(crate) limiter: Option<Limiter>,
+    /// Removes ANSI escape sequences for clients that do not support them
+    pub(crate) stripper: Option<ansi::Stripper>,
+    /// Holds back the output of a command that may be shown in a pager
+    buffer: Option<Vec<u8>>,
+}
+
+impl ShellOutput {
+    pub(crate) fn new(inner: Box<dyn Write>) -> Self {
+        ShellOutput {
+            inner,
+            transcript: None,
+            limiter: None,
+            stripper: None,
+            buffer: None,
+        }
+    }
+
+    /// Replaces the writer that receives the output and returns the previous
+    /// one
+    pub(crate) fn redirect(&mut self, inner: Box<dyn Write>) -> Box<dyn Write> {
+        std::mem::replace(&mut self.inner, inner)
+    }
+
+    /// Holds back everything that is written until [`ShellOutput::release`]
+    /// or [`ShellOutput::take_buffer`] is called
+    pub(crate) fn start_buffering(&mut self) {
+        self.buffer = Some(Vec::new());
+    }
+
+    /// Returns the output that is held back, if the output is buffered
+    pub(crate) fn buffered(&self) -> Option<&[u8]> {
+        self.buffer.as_deref()
+    }
+
+    /// Returns the held back output and stops buffering
+    pub(crate) fn take_buffer(&mut self) -> Option<Vec<u8>> {
+        self.buffer.take()
+    }
+
+    /// Writes the held back output and stops buffering, e.g. before the
+    /// shell asks the user for input
+    pub(crate) fn release(&mut self) -> io::Result<()> {
+        match self.buffer.take() {
+            Some(buffer) => self.inner.write_all(&buffer),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the text to the buffer or the inner writer
+    fn emit(&mut self, text: &[u8]) -> io::Result<()> {
+        self.record(text);
+        match &mut self.buffer {
+            Some(buffer) => buffer.extend_from_slice(text),
+            None => self.inner.write_all(text)?,
+        }
+        Ok(())
+    }
+
+    fn write_through(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.record(&buf[..written]);
+        Ok(written)
+    }
+
+    fn record(&mut self, output: &[u8]) {
+        if let Some(transcript) = &mut self.transcript {
+            let _ = transcript.record(EventKind::Output, &String::from_utf8_lossy(output));
+        }
+    }
+
+    /// Records a line the user typed in the transcript
+    pub(crate) fn record_input(&mut self, line: &str) {
+        if let Some(transcript) = self.transcript.as_mut().filter(|_| !line.is_empty()) {
+            // A failing transcript must not break the session
+            let _ = transcript.record(EventKind::Input, line);
+        }
+    }
+}
+
+impl Write for ShellOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.limiter.is_none() && self.stripper.is_none() && self.buffer.is_none() {
+            return self.write_through(buf);
+        }
+
+        let stripped = self.stripper.as_mut().map(|s| s.filter(buf));
+        let text = stripped.as_deref().unwrap_or(buf);
+        let (admitted, notice) = match &mut self.limiter {
+            Some(limiter) => limiter.admit(text),
+            None => (text.len(), None),
+        };
+        self.emit(&text[..admitted])?;
+        if let Some(notice) = notice {
+            self.emit(notice.as_bytes())?;
+        }
+        // Dropped output counts as written
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Collects everything that is written into a shared buffer
+#[derive(Clone, Default)]
+pub(crate) struct Capture(pub(crate) Rc<RefCell<Vec<u8>>>);
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,35 @@
+//! Showing long output in the pager of the user
+use std::{
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+use crate::terminal;
+
+/// Returns `true` if the output does not fit on the screen
+pub(crate) fn is_long(output: &[u8]) -> bool {
+    let lines = output.iter().filter(|b| **b == b'\n').count();
+    // One line is left for the prompt
+    terminal::height().is_some_and(|height| lines >= height)
+}
+
+/// Shows the output in `$PAGER`, or `less -R` if it is not set, and waits
+/// until the user quits it
+///
+/// Returns an error if the pager cannot be started.
+pub(crate) fn show(output: &[u8]) -> io::Result<()> {
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| "less -R".to_string());
+    let mut words = pager.split_whitespace();
+    let program = words.next().ok_or(io::ErrorKind::NotFound)?;
+    let mut child = Command::new(program)
+        .args(words)
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    // The user may quit the pager before it has read everything
+    let _ = stdin.write_all(output);
+    drop(stdin);
+    child.wait()?;
+    Ok(())
+}
@@ -0,0 +1,208 @@
+//! Parsing of user input into the command and its arguments
+//!
+//! The parser is free of I/O so that it can be used on its own, e.g. for
+//! fuzzing or to validate input before it is handed to a shell.
+
+use std::fmt::Display;
+
+/// Errors that may occur while splitting a line into tokens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The line ends inside of quotes. Contains the opening quote
+    UnterminatedQuote(char),
+}
+
+impl std::error::Error for ParseError {}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnterminatedQuote(quote) => write!(f, "unterminated {} quote", quote),
+        }
+    }
+}
+
+/// Splits lines of user input into tokens
+///
+/// Whitespace at the start and the end of a line is ignored and consecutive
+/// separators are collapsed, so no empty tokens are produced unless they are
+/// quoted. Text in double or single quotes forms a single token even if it
+/// contains separators. A backslash escapes the next character outside of
+/// single quotes. A line that ends inside of quotes is rejected.
+///
+/// # Example
+/// ```rust
+/// use simpleshell::parser::{ParseError, Tokenizer};
+///
+/// let tokenizer = Tokenizer::default();
+/// assert_eq!(tokenizer.tokenize("  version  extra "), Ok(vec!["version".into(), "extra".into()]));
+/// assert_eq!(
+///     tokenizer.tokenize(r#"say "hello world" ''"#),
+///     Ok(vec!["say".into(), "hello world".into(), "".into()])
+/// );
+/// assert_eq!(tokenizer.tokenize(r"echo a\ b"), Ok(vec!["echo".into(), "a b".into()]));
+/// assert_eq!(tokenizer.tokenize("   "), Ok(vec![]));
+/// assert_eq!(tokenizer.tokenize(r#"say "hello"#), Err(ParseError::UnterminatedQuote('"')));
+///
+/// let tokenizer = Tokenizer::default()
+///     .with_separators(&[' ', '\t', ','])
+///     .collapse_separators(false);
+/// assert_eq!(
+///     tokenizer.tokenize("add a,,b"),
+///     Ok(vec!["add".into(), "a".into(), "".into(), "b".into()])
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tokenizer {
+    /// The characters that separate two tokens
+    pub separators: Vec<char>,
+    /// Whether consecutive separators are treated as a single one. Otherwise
+    /// they produce empty tokens
+    pub collapse_separators: bool,
+}
+
+impl Tokenizer {
+    /// Replaces the characters that separate two tokens
+    pub fn with_separators(mut self, separators: &[char]) -> Self {
+        self.separators = separators.to_vec();
+        self
+    }
+
+    /// Configures whether consecutive separators are treated as a single one
+    pub fn collapse_separators(mut self, collapse: bool) -> Self {
+        self.collapse_separators = collapse;
+        self
+    }
+
+    /// Splits the line into its tokens
+    ///
+    /// # Errors
+    /// Returns [`ParseError::UnterminatedQuote`] if a quote is not closed.
+    pub fn tokenize(&self, line: &str) -> Result<Vec<String>, ParseError> {
+        let mut tokens = Vec::new();
+        let mut current = String::new();
+        // Whether the current token has been started, which is also the case
+        // for an empty pair of quotes
+        let mut started = false;
+        let mut quote = None;
+
+        let mut chars = line.trim().chars();
+        while let Some(c) = chars.next() {
+            match quote {
+                Some(q) if c == q => quote = None,
+                Some('"') if c == '\\' => current.extend(chars.next()),
+                Some(_) => current.push(c),
+                None if c == '\\' => {
+                    current.extend(chars.next());
+                    started = true;
+                }
+                None if c == '"' || c == '\'' => {
+                    quote = Some(c);
+                    started = true;
+                }
+                None if self.separators.contains(&c) => {
+                    if started || !self.collapse_separators {
+                        tokens.push(std::mem::take(&mut current));
+                        started = false;
+                    }
+                }
+                None => {
+                    current.push(c);
+                    started = true;
+                }
+            }
+        }
+
+        if let Some(quote) = quote {
+            return Err(ParseError::UnterminatedQuote(quote));
+        }
+        if started || (!self.collapse_separators && !tokens.is_empty()) {
+            tokens.push(current);
+        }
+        Ok(tokens)
+    }
+}
+
+impl Default for Tokenizer {
+    /// Splits at spaces and collapses consecutive ones
+    fn default() -> Self {
+        Tokenizer {
+            separators: vec![' '],
+            collapse_separators: true,
+        }
+    }
+}
+
+/// Splits a line of user input into its tokens with the default [`Tokenizer`]
+///
+/// # Example
+/// ```rust
+/// use simpleshell::parser::tokenize;
+///
+/// assert_eq!(tokenize("ping example.com\n"), Ok(vec!["ping".into(), "example.com".into()]));
+/// ```
+///
+/// # Errors
+/// Returns [`ParseError::UnterminatedQuote`] if a quote is not closed.
+pub fn tokenize(line: &str) -> Result<Vec<String>, ParseError> {
+    Tokenizer::default().tokenize(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(line: &str) -> Vec<String> {
+        tokenize(line).unwrap()
+    }
+
+    #[test]
+    fn splits_at_separators() {
+        assert_eq!(tokens("a b  c"), ["a", "b", "c"]);
+        assert_eq!(tokens(" a\tb "), ["a\tb"]);
+        assert!(tokens("").is_empty());
+    }
+
+    #[test]
+    fn keeps_quoted_text_together() {
+        assert_eq!(tokens(r#"say "a  b" 'c d'"#), ["say", "a  b", "c d"]);
+        assert_eq!(tokens(r#"pre"fix"ed"#), ["prefixed"]);
+        assert_eq!(tokens(r#"'"' "'""#), ["\"", "'"]);
+        assert_eq!(tokens(r#""" ''"#), ["", ""]);
+    }
+
+    #[test]
+    fn handles_escapes() {
+        assert_eq!(tokens(r#"a\"b "c\"d" 'e\f'"#), ["a\"b", "c\"d", "e\\f"]);
+        assert_eq!(tokens(r"a\ b\\"), ["a b\\"]);
+    }
+
+    #[test]
+    fn rejects_unterminated_quotes() {
+        assert_eq!(
+            tokenize(r#"say "hello"#),
+            Err(ParseError::UnterminatedQuote('"'))
+        );
+        assert_eq!(
+            tokenize("say 'it"),
+            Err(ParseError::UnterminatedQuote('\''))
+        );
+        assert_eq!(
+            tokenize(r#"say "it\""#),
+            Err(ParseError::UnterminatedQuote('"'))
+        );
+        assert_eq!(
+            tokenize(r#"say "it's"#),
+            Err(ParseError::UnterminatedQuote('"'))
+        );
+        assert!(tokenize(r#"say "it's""#).is_ok());
+    }
+
+    #[test]
+    fn keeps_empty_tokens_without_collapsing() {
+        let tokenizer = Tokenizer::default().collapse_separators(false);
+        assert_eq!(tokenizer.tokenize("a  b").unwrap(), ["a", "", "b"]);
+        assert_eq!(tokenizer.tokenize("a ").unwrap(), ["a"]);
+        assert_eq!(tokenizer.tokenize("a '' ").unwrap(), ["a", ""]);
+    }
+}
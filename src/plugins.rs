@@ -0,0 +1,82 @@
+//! Commands that are loaded from shared libraries at runtime
+//!
+//! A plugin is a `cdylib` that depends on the same version of this crate and
+//! exports its commands with [`export_plugin!`](crate::export_plugin):
+//!
+//! ```rust,ignore
+//! use simpleshell::{export_plugin, Command};
+//!
+//! fn commands() -> Vec<Command> {
+//!     vec![Command::new("hello", "Greets the world", |_, _| Ok(()))]
+//! }
+//!
+//! export_plugin!(commands);
+//! ```
+//!
+//! Plugins have to be built with the same compiler, the same version and the
+//! same features of this crate as the host, because the commands cross the
+//! library boundary with the Rust ABI. The host loads them
+//! with [`Shell::with_plugins_dir`](crate::Shell::with_plugins_dir), which
+//! requires the `plugins` feature.
+#[cfg(feature = "plugins")]
+use std::{fs, io, path::Path};
+
+#[cfg(feature = "plugins")]
+use libloading::Library;
+
+use crate::Command;
+
+/// The name of the function every plugin exports
+pub const REGISTER_SYMBOL: &str = "simpleshell_register";
+
+/// The signature of the function every plugin exports
+pub type RegisterFn = fn() -> Vec<Command>;
+
+/// Exports the function that returns the commands of a plugin
+#[macro_export]
+macro_rules! export_plugin {
+    ($commands:path) => {
+        #[no_mangle]
+        pub fn simpleshell_register() -> ::std::vec::Vec<$crate::Command> {
+            let commands: $crate::plugins::RegisterFn = $commands;
+            commands()
+        }
+    };
+}
+
+/// Loads every shared library in the directory and returns the libraries
+/// together with the commands they export
+///
+/// # Safety
+/// Loading a library runs its initialization code and the exported function
+/// is trusted to have the signature [`RegisterFn`].
+#[cfg(feature = "plugins")]
+pub(crate) unsafe fn load_dir(dir: &Path) -> io::Result<(Vec<Library>, Vec<Command>)> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    paths.retain(|p| {
+        p.extension()
+            .is_some_and(|e| e == std::env::consts::DLL_EXTENSION)
+    });
+    paths.sort();
+
+    let mut libraries = Vec::new();
+    let mut commands = Vec::new();
+    for path in paths {
+        let error = |e: libloading::Error| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        };
+        // SAFETY: the caller trusts the plugins in the directory
+        let library = unsafe { Library::new(&path) }.map_err(error)?;
+        // SAFETY: plugins export the function with `export_plugin!`
+        let register =
+            unsafe { library.get::<RegisterFn>(REGISTER_SYMBOL.as_bytes()) }.map_err(error)?;
+        commands.extend(register());
+        libraries.push(library);
+    }
+    Ok((libraries, commands))
+}
@@ -0,0 +1,456 @@
+//! Pretty printing of JSON documents as JSON or YAML, see
+//! [`Context::print_json`](crate::Context::print_json)
+use std::io;
+
+use crate::transcript::{escape_json, parse_json_string};
+
+/// A parsed JSON value
+///
+/// Numbers keep their original text and objects the order of their keys.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+/// How values are printed
+pub(crate) struct Style<'a> {
+    pub(crate) colors: bool,
+    /// Arrays with more items only show this many
+    pub(crate) max_items: Option<usize>,
+    /// Shown after the remaining number of items of a collapsed array
+    pub(crate) collapsed: &'a str,
+}
+
+impl Style<'_> {
+    fn paint(&self, text: &str, color: u8) -> String {
+        if self.colors {
+            format!("\x1b[{}m{}\x1b[0m", color, text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        self.paint(key, 34)
+    }
+
+    fn string(&self, text: &str) -> String {
+        self.paint(text, 32)
+    }
+
+    fn number(&self, number: &str) -> String {
+        self.paint(number, 36)
+    }
+
+    fn keyword(&self, keyword: &str) -> String {
+        self.paint(keyword, 35)
+    }
+
+    fn more(&self, remaining: usize) -> String {
+        self.paint(&format!("... {} {}", remaining, self.collapsed), 90)
+    }
+
+    /// Returns the items that are shown and the number of hidden items
+    fn visible<'v>(&self, items: &'v [Value]) -> (&'v [Value], usize) {
+        match self.max_items {
+            Some(max) if items.len() > max => (&items[..max], items.len() - max),
+            _ => (items, 0),
+        }
+    }
+}
+
+/// Pretty prints the JSON document as indented JSON
+pub(crate) fn json(text: &str, style: &Style) -> io::Result<String> {
+    let value = parse(text)?;
+    let mut printed = String::new();
+    write_json(&value, 0, style, &mut printed);
+    printed.push('\n');
+    Ok(printed)
+}
+
+/// Prints the JSON document as YAML
+pub(crate) fn yaml(text: &str, style: &Style) -> io::Result<String> {
+    let value = parse(text)?;
+    let mut printed = String::new();
+    match yaml_lines(&value, style) {
+        Some(lines) => {
+            for line in lines {
+                printed.push_str(&line);
+                printed.push('\n');
+            }
+        }
+        None => {
+            printed.push_str(&yaml_scalar(&value, style));
+            printed.push('\n');
+        }
+    }
+    Ok(printed)
+}
+
+fn write_json(value: &Value, indent: usize, style: &Style, printed: &mut String) {
+    let padding = "  ".repeat(indent + 1);
+    match value {
+        Value::Array(items) if !items.is_empty() => {
+            let (items, hidden) = style.visible(items);
+            printed.push_str("[\n");
+            for (i, item) in items.iter().enumerate() {
+                printed.push_str(&padding);
+                write_json(item, indent + 1, style, printed);
+                if i + 1 < items.len() || hidden > 0 {
+                    printed.push(',');
+                }
+                printed.push('\n');
+            }
+            if hidden > 0 {
+                printed.push_str(&padding);
+                printed.push_str(&style.more(hidden));
+                printed.push('\n');
+            }
+            printed.push_str(&"  ".repeat(indent));
+            printed.push(']');
+        }
+        Value::Object(entries) if !entries.is_empty() => {
+            printed.push_str("{\n");
+            for (i, (key, value)) in entries.iter().enumerate() {
+                printed.push_str(&padding);
+                printed.push_str(&style.key(&format!("\"{}\"", escape_json(key))));
+                printed.push_str(": ");
+                write_json(value, indent + 1, style, printed);
+                if i + 1 < entries.len() {
+                    printed.push(',');
+                }
+                printed.push('\n');
+            }
+            printed.push_str(&"  ".repeat(indent));
+            printed.push('}');
+        }
+        Value::Array(_) => printed.push_str("[]"),
+        Value::Object(_) => printed.push_str("{}"),
+        Value::Null => printed.push_str(&style.keyword("null")),
+        Value::Bool(b) => printed.push_str(&style.keyword(&b.to_string())),
+        Value::Number(n) => printed.push_str(&style.number(n)),
+        Value::String(s) => printed.push_str(&style.string(&format!("\"{}\"", escape_json(s)))),
+    }
+}
+
+/// Returns the lines of a non-empty array or object, or `None` for values
+/// that are printed inline
+fn yaml_lines(value: &Value, style: &Style) -> Option<Vec<String>> {
+    let mut lines = Vec::new();
+    match value {
+        Value::Array(items) if !items.is_empty() => {
+            let (items, hidden) = style.visible(items);
+            for item in items {
+                match yaml_lines(item, style) {
+                    Some(nested) => {
+                        for (i, line) in nested.into_iter().enumerate() {
+                            let prefix = if i == 0 { "- " } else { "  " };
+                            lines.push(format!("{}{}", prefix, line));
+                        }
+                    }
+                    None => lines.push(format!("- {}", yaml_scalar(item, style))),
+                }
+            }
+            if hidden > 0 {
+                lines.push(format!("# {}", style.more(hidden)));
+            }
+        }
+        Value::Object(entries) if !entries.is_empty() => {
+            for (key, value) in entries {
+                let key = style.key(&yaml_string(key));
+                match yaml_lines(value, style) {
+                    Some(nested) => {
+                        lines.push(format!("{}:", key));
+                        lines.extend(nested.into_iter().map(|line| format!("  {}", line)));
+                    }
+                    None => lines.push(format!("{}: {}", key, yaml_scalar(value, style))),
+                }
+            }
+        }
+        _ => return None,
+    }
+    Some(lines)
+}
+
+fn yaml_scalar(value: &Value, style: &Style) -> String {
+    match value {
+        Value::Null => style.keyword("null"),
+        Value::Bool(b) => style.keyword(&b.to_string()),
+        Value::Number(n) => style.number(n),
+        Value::String(s) => style.string(&yaml_string(s)),
+        Value::Array(_) => "[]".to_string(),
+        Value::Object(_) => "{}".to_string(),
+    }
+}
+
+/// Quotes the text unless it can be written as a plain YAML scalar
+fn yaml_string(text: &str) -> String {
+    let reserved = matches!(
+        text.to_ascii_lowercase().as_str(),
+        "" | "~" | "null" | "true" | "false" | "yes" | "no" | "on" | "off"
+    );
+    let plain = !reserved
+        && text.trim() == text
+        && text.parse::<f64>().is_err()
+        && !text.starts_with(|c: char| "-?:,[]{}#&*!|>'\"%@`".contains(c))
+        && !text.contains(": ")
+        && !text.contains(" #")
+        && !text.ends_with(':')
+        && !text.chars().any(char::is_control);
+    if plain {
+        text.to_string()
+    } else {
+        format!("\"{}\"", escape_json(text))
+    }
+}
+
+/// How deeply arrays and objects may be nested
+const MAX_DEPTH: usize = 128;
+
+fn parse(text: &str) -> io::Result<Value> {
+    let mut parser = Parser {
+        text,
+        position: 0,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.position < text.len() {
+        return Err(parser.error());
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+    /// The number of arrays and objects around the current value
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.text[self.position..]
+    }
+
+    fn error(&self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid JSON at offset {}", self.position),
+        )
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consumes the text if the remaining input starts with it
+    fn eat(&mut self, text: &str) -> bool {
+        self.skip_whitespace();
+        let found = self.rest().starts_with(text);
+        if found {
+            self.position += text.len();
+        }
+        found
+    }
+
+    fn value(&mut self) -> io::Result<Value> {
+        self.depth += 1;
+        let value = if self.depth > MAX_DEPTH {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("JSON nested too deeply at offset {}", self.position),
+            ))
+        } else {
+            self.element()
+        };
+        self.depth -= 1;
+        value
+    }
+
+    fn element(&mut self) -> io::Result<Value> {
+        self.skip_whitespace();
+        if self.eat("null") {
+            Ok(Value::Null)
+        } else if self.eat("true") {
+            Ok(Value::Bool(true))
+        } else if self.eat("false") {
+            Ok(Value::Bool(false))
+        } else if self.eat("[") {
+            let mut items = Vec::new();
+            if !self.eat("]") {
+                loop {
+                    items.push(self.value()?);
+                    if self.eat("]") {
+                        break;
+                    }
+                    if !self.eat(",") {
+                        return Err(self.error());
+                    }
+                }
+            }
+            Ok(Value::Array(items))
+        } else if self.eat("{") {
+            let mut entries = Vec::new();
+            if !self.eat("}") {
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    if !self.eat(":") {
+                        return Err(self.error());
+                    }
+                    entries.push((key, self.value()?));
+                    if self.eat("}") {
+                        break;
+                    }
+                    if !self.eat(",") {
+                        return Err(self.error());
+                    }
+                }
+            }
+            Ok(Value::Object(entries))
+        } else if self.rest().starts_with('"') {
+            Ok(Value::String(self.string()?))
+        } else {
+            self.number()
+        }
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let (parsed, rest) = parse_json_string(self.rest()).ok_or_else(|| self.error())?;
+        self.position = self.text.len() - rest.len();
+        Ok(parsed)
+    }
+
+    fn number(&mut self) -> io::Result<Value> {
+        let length = self
+            .rest()
+            .find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+            .unwrap_or(self.rest().len());
+        let number = self.rest()[..length].to_string();
+        if number.parse::<f64>().is_err() {
+            return Err(self.error());
+        }
+        self.position += length;
+        Ok(Value::Number(number))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAIN: Style = Style {
+        colors: false,
+        max_items: None,
+        collapsed: "more",
+    };
+
+    fn error(text: &str) -> String {
+        json(text, &PLAIN).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn indents_json() {
+        let printed = json(r#"{"b": [1, 2.50], "a": {}, "c": [], "d": "x\ny"}"#, &PLAIN).unwrap();
+        assert_eq!(
+            printed,
+            "{\n  \"b\": [\n    1,\n    2.50\n  ],\n  \"a\": {},\n  \"c\": [],\n  \"d\": \"x\\ny\"\n}\n"
+        );
+        assert_eq!(json(" null ", &PLAIN).unwrap(), "null\n");
+    }
+
+    #[test]
+    fn prints_yaml() {
+        let printed = yaml(
+            r#"{"name": "api", "ports": [80, 443], "env": {"DEBUG": "true"}, "tags": []}"#,
+            &PLAIN,
+        )
+        .unwrap();
+        assert_eq!(
+            printed,
+            "name: api\nports:\n  - 80\n  - 443\nenv:\n  DEBUG: \"true\"\ntags: []\n"
+        );
+        assert_eq!(
+            yaml(r#"[{"a": 1, "b": 2}]"#, &PLAIN).unwrap(),
+            "- a: 1\n  b: 2\n"
+        );
+        assert_eq!(yaml(r#""- x""#, &PLAIN).unwrap(), "\"- x\"\n");
+    }
+
+    #[test]
+    fn collapses_long_arrays() {
+        let style = Style {
+            max_items: Some(2),
+            ..PLAIN
+        };
+        assert_eq!(
+            json("[1, 2, 3, 4]", &style).unwrap(),
+            "[\n  1,\n  2,\n  ... 2 more\n]\n"
+        );
+        assert_eq!(
+            yaml("[1, 2, 3]", &style).unwrap(),
+            "- 1\n- 2\n# ... 1 more\n"
+        );
+    }
+
+    #[test]
+    fn colors_values() {
+        let style = Style {
+            colors: true,
+            ..PLAIN
+        };
+        assert_eq!(
+            json(r#"{"a": true}"#, &style).unwrap(),
+            "{\n  \x1b[34m\"a\"\x1b[0m: \x1b[35mtrue\x1b[0m\n}\n"
+        );
+    }
+
+    #[test]
+    fn parses_escapes() {
+        assert_eq!(parse(r#""ä😀\"""#).unwrap(), Value::String("ä😀\"".into()));
+    }
+
+    #[test]
+    fn rejects_unterminated_strings() {
+        assert_eq!(error(r#""abc"#), "invalid JSON at offset 0");
+        assert_eq!(error(r#"{"key": "value}"#), "invalid JSON at offset 8");
+        assert_eq!(error(r#"{"key: 1}"#), "invalid JSON at offset 1");
+        assert_eq!(error(r#"["\"]"#), "invalid JSON at offset 1");
+        assert_eq!(error(r#""\u12"#), "invalid JSON at offset 0");
+    }
+
+    #[test]
+    fn rejects_invalid_documents() {
+        assert_eq!(error(""), "invalid JSON at offset 0");
+        assert_eq!(error("[1, 2"), "invalid JSON at offset 5");
+        assert_eq!(error("[1,]"), "invalid JSON at offset 3");
+        assert_eq!(error("{} {}"), "invalid JSON at offset 3");
+        assert_eq!(error("nul"), "invalid JSON at offset 0");
+    }
+
+    #[test]
+    fn accepts_nesting_up_to_the_limit() {
+        let nested = format!("{}{}", "[".repeat(MAX_DEPTH - 1), "]".repeat(MAX_DEPTH - 1));
+        assert!(json(&nested, &PLAIN).is_ok());
+        assert!(yaml(&nested, &PLAIN).is_ok());
+    }
+
+    #[test]
+    fn rejects_deep_nesting() {
+        assert_eq!(
+            error(&"[".repeat(100_000)),
+            format!("JSON nested too deeply at offset {}", MAX_DEPTH)
+        );
+        assert_eq!(
+            error(&r#"{"a":"#.repeat(100_000)),
+            format!("JSON nested too deeply at offset {}", MAX_DEPTH * 5)
+        );
+    }
+}
@@ -0,0 +1,81 @@
+//! Named sets of variables and commands that can be switched at runtime
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+/// A named configuration of the shell, e.g. `staging` or `production`
+///
+/// # Example
+/// ```rust
+/// # use simpleshell::{testing::TestShell, Builtin, Command, Profile, Shell};
+/// let commands = vec![
+///     Command::new("status", "Shows the status", |_, _| Ok(())),
+///     Command::new("deploy", "Deploys the application", |_, _| Ok(())),
+/// ];
+/// let shell = Shell::new(None, commands)
+///     .with_builtin(Builtin::Profile)
+///     .with_profile(Profile::new("readonly").with_commands(&["status"]))
+///     .with_profile(Profile::new("staging").with_var("host", "staging.example.com"));
+///
+/// let mut shell = TestShell::new(shell);
+/// shell.run("profile use staging").expect_ok();
+/// assert_eq!(shell.shell().var("host").as_deref(), Some("staging.example.com"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    /// The name that is used to switch to the profile
+    pub name: String,
+    /// Variables that are visible while the profile is active
+    pub variables: BTreeMap<String, String>,
+    /// The names of the commands that are available while the profile is
+    /// active. `None` makes all commands available
+    pub commands: Option<Vec<String>>,
+    /// A prompt that replaces the prefix of the shell while the profile is
+    /// active
+    pub prompt: Option<String>,
+}
+
+impl Profile {
+    /// Creates a profile that makes all commands available
+    pub fn new(name: &str) -> Self {
+        Profile {
+            name: name.to_owned(),
+            variables: BTreeMap::new(),
+            commands: None,
+            prompt: None,
+        }
+    }
+
+    /// Defines a variable that is visible while the profile is active
+    pub fn with_var(mut self, name: &str, value: &str) -> Self {
+        self.variables.insert(name.to_owned(), value.to_owned());
+        self
+    }
+
+    /// Restricts the available commands to the given names
+    pub fn with_commands(mut self, commands: &[&str]) -> Self {
+        self.commands = Some(commands.iter().map(|c| c.to_string()).collect());
+        self
+    }
+
+    /// Replaces the prefix of the shell while the profile is active
+    pub fn with_prompt(mut self, prompt: &str) -> Self {
+        self.prompt = Some(prompt.to_owned());
+        self
+    }
+
+    /// Returns `true` if the command is available while the profile is active
+    pub fn allows(&self, command: &str) -> bool {
+        self.commands
+            .as_ref()
+            .is_none_or(|commands| commands.iter().any(|c| c == command))
+    }
+}
+
+/// Reads the name of the profile that was active when the shell was last used
+pub(crate) fn load_active(path: &Path) -> io::Result<String> {
+    Ok(fs::read_to_string(path)?.trim().to_string())
+}
+
+/// Remembers the name of the active profile for the next start of the shell
+pub(crate) fn store_active(path: &Path, name: &str) -> io::Result<()> {
+    fs::write(path, format!("{}\n", name))
+}
@@ -0,0 +1,80 @@
+//! Integration of the command metrics with a [`prometheus`] registry
+use std::time::Duration;
+
+use prometheus::{proto::MetricFamily, CounterVec, IntCounterVec, Opts, Registry};
+
+use crate::Metrics;
+
+/// The prometheus counters that mirror the [`Metrics`] of a shell
+pub(crate) struct PrometheusMetrics {
+    invocations: IntCounterVec,
+    errors: IntCounterVec,
+    duration: CounterVec,
+}
+
+impl PrometheusMetrics {
+    /// Creates the counters and registers them with the given registry
+    pub(crate) fn register(registry: &Registry) -> prometheus::Result<Self> {
+        let metrics = PrometheusMetrics {
+            invocations: IntCounterVec::new(
+                Opts::new(
+                    "simpleshell_command_invocations_total",
+                    "Number of times a shell command has been invoked",
+                ),
+                &["command"],
+            )?,
+            errors: IntCounterVec::new(
+                Opts::new(
+                    "simpleshell_command_errors_total",
+                    "Number of shell command invocations that returned an error",
+                ),
+                &["command"],
+            )?,
+            duration: CounterVec::new(
+                Opts::new(
+                    "simpleshell_command_duration_seconds_total",
+                    "Time spent executing a shell command",
+                ),
+                &["command"],
+            )?,
+        };
+
+        registry.register(Box::new(metrics.invocations.clone()))?;
+        registry.register(Box::new(metrics.errors.clone()))?;
+        registry.register(Box::new(metrics.duration.clone()))?;
+        Ok(metrics)
+    }
+
+    pub(crate) fn record(&self, name: &str, duration: Duration, failed: bool) {
+        self.invocations.with_label_values(&[name]).inc();
+        self.duration
+            .with_label_values(&[name])
+            .inc_by(duration.as_secs_f64());
+        if failed {
+            self.errors.with_label_values(&[name]).inc();
+        }
+    }
+}
+
+impl Metrics {
+    /// Converts a snapshot of the metrics into prometheus metric families that
+    /// can be encoded with a [`prometheus::Encoder`]
+    pub fn gather(&self) -> Vec<MetricFamily> {
+        let registry = Registry::new();
+        let exported =
+            PrometheusMetrics::register(&registry).expect("metric definitions are valid");
+
+        for (name, m) in self.iter() {
+            exported
+                .invocations
+                .with_label_values(&[name])
+                .inc_by(m.invocations);
+            exported.errors.with_label_values(&[name]).inc_by(m.errors);
+            exported
+                .duration
+                .with_label_values(&[name])
+                .inc_by(m.total_duration.as_secs_f64());
+        }
+        registry.gather()
+    }
+}
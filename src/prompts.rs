@@ -0,0 +1,720 @@
+//! Interactive questions that commands ask the user, see
+//! [`Context::input`](crate::Context::input),
+//! [`Context::select`](crate::Context::select),
+//! [`Context::multi_select`](crate::Context::multi_select) and
+//! [`Context::form`](crate::Context::form)
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, IsTerminal, Write},
+};
+
+use crate::{terminal, Shell};
+
+/// Checks an answer and returns the reason why it is not accepted
+pub type Validator = dyn Fn(&str) -> Result<(), String>;
+
+/// A question that is answered with a line of text
+///
+/// Created with [`Context::input`](crate::Context::input).
+///
+/// # Example
+/// ```rust
+/// # use std::io::Write;
+/// # use simpleshell::{testing::TestShell, Command, Shell};
+/// let commands = vec![Command::new("adduser", "Adds a user", |_, ctx| {
+///     let port = ctx
+///         .input("Port: ")
+///         .with_default("22")
+///         .with_validator(|answer| match answer.parse::<u16>() {
+///             Ok(_) => Ok(()),
+///             Err(_) => Err(format!("Not a port: {}", answer)),
+///         })
+///         .read()?;
+///     writeln!(ctx.out(), "Using port {}", port.unwrap_or_default())?;
+///     Ok(())
+/// })];
+///
+/// let mut shell = TestShell::deterministic(Shell::new(None, commands));
+/// shell.feed("http").feed("");
+/// shell.run("adduser").expect_ok();
+/// assert_eq!(
+///     shell.output(),
+///     "Port: [22] Not a port: http\nPort: [22] Using port 22\n"
+/// );
+/// ```
+pub struct InputPrompt<'a> {
+    shell: &'a Shell,
+    prompt: String,
+    default: Option<String>,
+    validator: Option<Box<Validator>>,
+}
+
+impl<'a> InputPrompt<'a> {
+    pub(crate) fn new(shell: &'a Shell, prompt: &str) -> Self {
+        InputPrompt {
+            shell,
+            prompt: prompt.to_string(),
+            default: None,
+            validator: None,
+        }
+    }
+
+    /// Sets the answer that is used if the user enters an empty line
+    ///
+    /// The default is shown in brackets after the prompt.
+    pub fn with_default(mut self, default: &str) -> Self {
+        self.default = Some(default.to_string());
+        self
+    }
+
+    /// Asks again with the returned reason until the answer is accepted
+    pub fn with_validator(
+        mut self,
+        validator: impl Fn(&str) -> Result<(), String> + 'static,
+    ) -> Self {
+        self.validator = Some(Box::new(validator));
+        self
+    }
+
+    /// Asks the question until an accepted answer is given
+    ///
+    /// Returns `None` if the input has been closed.
+    pub fn read(self) -> io::Result<Option<String>> {
+        let prompt = match &self.default {
+            Some(default) => format!("{}[{}] ", self.prompt, default),
+            None => self.prompt.clone(),
+        };
+        ask(
+            self.shell,
+            &prompt,
+            self.default.as_deref(),
+            self.validator.as_deref(),
+            false,
+        )
+    }
+}
+
+/// Asks until the validator accepts the answer or the default is used for an
+/// empty answer
+fn ask(
+    shell: &Shell,
+    prompt: &str,
+    default: Option<&str>,
+    validator: Option<&Validator>,
+    secret: bool,
+) -> io::Result<Option<String>> {
+    loop {
+        let answer = if secret {
+            shell.read_secret(prompt)?
+        } else {
+            shell.read_line(prompt)?
+        };
+        let Some(mut answer) = answer else {
+            return Ok(None);
+        };
+        if answer.is_empty() {
+            if let Some(default) = default {
+                answer = default.to_string();
+            }
+        }
+        match validator.map(|v| v(&answer)) {
+            Some(Err(reason)) => writeln!(shell.output.borrow_mut(), "{}", reason)?,
+            _ => return Ok(Some(answer)),
+        }
+    }
+}
+
+/// A field of a [`FormPrompt`]
+pub struct Field {
+    name: String,
+    label: String,
+    default: Option<String>,
+    validator: Option<Box<Validator>>,
+    secret: bool,
+}
+
+impl Field {
+    /// Creates a field whose value is returned under `name` and that is
+    /// asked for with `label`
+    pub fn new(name: &str, label: &str) -> Self {
+        Field {
+            name: name.to_string(),
+            label: label.to_string(),
+            default: None,
+            validator: None,
+            secret: false,
+        }
+    }
+
+    /// Sets the value that is used if the user enters an empty line
+    pub fn with_default(mut self, default: &str) -> Self {
+        self.default = Some(default.to_string());
+        self
+    }
+
+    /// Asks again with the returned reason until the value is accepted
+    pub fn with_validator(
+        mut self,
+        validator: impl Fn(&str) -> Result<(), String> + 'static,
+    ) -> Self {
+        self.validator = Some(Box::new(validator));
+        self
+    }
+
+    /// Hides the value while it is typed, e.g. for passwords
+    ///
+    /// The echo of the terminal is turned off and the value is neither shown
+    /// as a default nor recorded in the transcript of the shell.
+    pub fn secret(mut self) -> Self {
+        self.secret = true;
+        self
+    }
+}
+
+/// Several questions that are asked one after another
+///
+/// Every field is asked for on its own line with its label, followed by its
+/// default in brackets. Created with [`Context::form`](crate::Context::form).
+///
+/// # Example
+/// ```rust
+/// # use std::io::Write;
+/// # use simpleshell::{testing::TestShell, Command, Field, Shell};
+/// let commands = vec![Command::new("connect", "Connects to a database", |_, ctx| {
+///     let values = ctx
+///         .form()
+///         .with_field(Field::new("host", "Host").with_default("localhost"))
+///         .with_field(Field::new("user", "User"))
+///         .with_field(Field::new("password", "Password").secret())
+///         .read()?
+///         .unwrap_or_default();
+///     writeln!(ctx.out(), "Connecting to {}@{}", values["user"], values["host"])?;
+///     Ok(())
+/// })];
+///
+/// let mut shell = TestShell::deterministic(Shell::new(None, commands));
+/// shell.feed("").feed("admin").feed("hunter2");
+/// shell.run("connect").expect_ok();
+/// assert_eq!(
+///     shell.output(),
+///     "Host [localhost]: User: Password: Connecting to admin@localhost\n"
+/// );
+/// ```
+pub struct FormPrompt<'a> {
+    shell: &'a Shell,
+    fields: Vec<Field>,
+}
+
+impl<'a> FormPrompt<'a> {
+    pub(crate) fn new(shell: &'a Shell) -> Self {
+        FormPrompt {
+            shell,
+            fields: Vec::new(),
+        }
+    }
+
+    /// Adds a field that is asked for after the fields added before
+    pub fn with_field(mut self, field: Field) -> Self {
+        self.fields.push(field);
+        self
+    }
+
+    /// Asks for all fields
+    ///
+    /// Returns the values by the names of the fields or `None` if the input
+    /// has been closed before all fields were answered.
+    pub fn read(self) -> io::Result<Option<HashMap<String, String>>> {
+        let mut values = HashMap::new();
+        for field in self.fields {
+            let prompt = match &field.default {
+                Some(default) if !field.secret => format!("{} [{}]: ", field.label, default),
+                _ => format!("{}: ", field.label),
+            };
+            let value = ask(
+                self.shell,
+                &prompt,
+                field.default.as_deref(),
+                field.validator.as_deref(),
+                field.secret,
+            )?;
+            let Some(value) = value else {
+                return Ok(None);
+            };
+            values.insert(field.name, value);
+        }
+        Ok(Some(values))
+    }
+}
+
+/// A question that is answered by picking one of several items
+///
+/// On a terminal, the user moves to an item with the arrow keys and picks it
+/// with `Enter`. Otherwise the items are listed with numbers and the user
+/// answers with the number or the text of an item. Created with
+/// [`Context::select`](crate::Context::select).
+///
+/// # Example
+/// ```rust
+/// # use std::io::Write;
+/// # use simpleshell::{testing::TestShell, Command, Shell};
+/// let commands = vec![Command::new("deploy", "Deploys the app", |_, ctx| {
+///     let targets = ["staging", "production"];
+///     if let Some(i) = ctx.select("Choose target", &targets).with_default(0).read()? {
+///         writeln!(ctx.out(), "Deploying to {}", targets[i])?;
+///     }
+///     Ok(())
+/// })];
+///
+/// let mut shell = TestShell::deterministic(Shell::new(None, commands));
+/// shell.feed("3").feed("2");
+/// shell.run("deploy").expect_ok();
+/// assert_eq!(
+///     shell.output(),
+///     "Choose target\n* 1) staging\n  2) production\n> Invalid selection\n> Deploying to production\n"
+/// );
+/// ```
+pub struct SelectPrompt<'a, S> {
+    shell: &'a Shell,
+    prompt: String,
+    items: &'a [S],
+    default: Option<usize>,
+}
+
+impl<'a, S: AsRef<str>> SelectPrompt<'a, S> {
+    pub(crate) fn new(shell: &'a Shell, prompt: &str, items: &'a [S]) -> Self {
+        SelectPrompt {
+            shell,
+            prompt: prompt.to_string(),
+            items,
+            default: None,
+        }
+    }
+
+    /// Sets the index of the item that is picked if the user enters an empty
+    /// line
+    ///
+    /// The default item is marked with a `*`.
+    pub fn with_default(mut self, index: usize) -> Self {
+        self.default = Some(index).filter(|&i| i < self.items.len());
+        self
+    }
+
+    /// Lists the items and asks until one of them is picked
+    ///
+    /// Returns the index of the picked item or `None` if the input has been
+    /// closed.
+    pub fn read(self) -> io::Result<Option<usize>> {
+        if reads_keys(self.shell) {
+            let _mode = terminal::InputMode::keystrokes();
+            return self.read_keys();
+        }
+        {
+            let mut output = self.shell.output.borrow_mut();
+            writeln!(output, "{}", self.prompt)?;
+            for (i, item) in self.items.iter().enumerate() {
+                let marker = if Some(i) == self.default { '*' } else { ' ' };
+                writeln!(output, "{} {}) {}", marker, i + 1, item.as_ref())?;
+            }
+        }
+        loop {
+            let Some(answer) = self.shell.read_line("> ")? else {
+                return Ok(None);
+            };
+            if let Some(index) = self.pick(answer.trim()) {
+                return Ok(Some(index));
+            }
+            writeln!(
+                self.shell.output.borrow_mut(),
+                "{}",
+                self.shell.messages.invalid_selection
+            )?;
+        }
+    }
+
+    /// Lets the user move to an item with the arrow keys and pick it with
+    /// `Enter`, starting at the default item
+    fn read_keys(self) -> io::Result<Option<usize>> {
+        let shell = self.shell;
+        writeln!(
+            shell.output.borrow_mut(),
+            "{} ({})",
+            self.prompt,
+            shell.messages.select_keys_hint
+        )?;
+        let count = self.items.len();
+        let mut current = self.default.unwrap_or(0);
+        let mut shown = 0;
+        loop {
+            let rows = self.items.iter().enumerate().map(|(i, item)| {
+                let marker = if i == current { '>' } else { ' ' };
+                format!("{} {}", marker, item.as_ref())
+            });
+            shown = redraw(shell, shown, rows)?;
+            match read_key(&mut **shell.input.borrow_mut())? {
+                None => return Ok(None),
+                Some(_) if count == 0 => {}
+                Some(Key::Up) => current = (current + count - 1) % count,
+                Some(Key::Down) => current = (current + 1) % count,
+                Some(Key::Enter) => {
+                    let picked = format!("> {}", self.items[current].as_ref());
+                    redraw(shell, shown, std::iter::once(picked))?;
+                    return Ok(Some(current));
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    fn pick(&self, answer: &str) -> Option<usize> {
+        if answer.is_empty() {
+            return self.default;
+        }
+        match answer.parse::<usize>() {
+            Ok(number) => Some(number)
+                .filter(|&n| (1..=self.items.len()).contains(&n))
+                .map(|n| n - 1),
+            Err(_) => self.items.iter().position(|item| item.as_ref() == answer),
+        }
+    }
+}
+
+/// A question that is answered by picking any number of several items
+///
+/// On a terminal, the user moves between the items with the arrow keys,
+/// toggles them with `Space` and confirms with `Enter`. Typed text only lists
+/// the items that contain it. Otherwise the items are listed with numbers
+/// and check boxes. Every answer toggles
+/// the items with the given numbers (separated by spaces or commas), an
+/// answer like `/web` only lists the items that contain `web` and a single
+/// `/` lists all items again. An empty line confirms the selection. Created
+/// with [`Context::multi_select`](crate::Context::multi_select).
+///
+/// # Example
+/// ```rust
+/// # use std::io::Write;
+/// # use simpleshell::{testing::TestShell, Command, Shell};
+/// let commands = vec![Command::new("restart", "Restarts hosts", |_, ctx| {
+///     let hosts = ["web-1", "web-2", "db-1"];
+///     let picked = ctx.multi_select("Restart", &hosts).read()?.unwrap_or_default();
+///     for i in picked {
+///         writeln!(ctx.out(), "Restarting {}", hosts[i])?;
+///     }
+///     Ok(())
+/// })];
+///
+/// let mut shell = TestShell::deterministic(Shell::new(None, commands));
+/// shell.feed("/web").feed("1 2").feed("");
+/// shell.run("restart").expect_ok();
+/// assert!(shell.output().contains("[x] 1) web-1\n[x] 2) web-2\n> "));
+/// assert!(shell.output().ends_with("Restarting web-1\nRestarting web-2\n"));
+/// ```
+pub struct MultiSelectPrompt<'a, S> {
+    shell: &'a Shell,
+    prompt: String,
+    items: &'a [S],
+    selected: Vec<bool>,
+}
+
+impl<'a, S: AsRef<str>> MultiSelectPrompt<'a, S> {
+    pub(crate) fn new(shell: &'a Shell, prompt: &str, items: &'a [S]) -> Self {
+        MultiSelectPrompt {
+            shell,
+            prompt: prompt.to_string(),
+            items,
+            selected: vec![false; items.len()],
+        }
+    }
+
+    /// Selects the items with the given indices before the user is asked
+    pub fn with_selected(mut self, indices: &[usize]) -> Self {
+        for &i in indices {
+            if let Some(selected) = self.selected.get_mut(i) {
+                *selected = true;
+            }
+        }
+        self
+    }
+
+    /// Lists the items and toggles them until the user confirms
+    ///
+    /// Returns the indices of the selected items in the order of the items or
+    /// `None` if the input has been closed.
+    pub fn read(mut self) -> io::Result<Option<Vec<usize>>> {
+        if reads_keys(self.shell) {
+            let _mode = terminal::InputMode::keystrokes();
+            return self.read_keys();
+        }
+        writeln!(
+            self.shell.output.borrow_mut(),
+            "{} ({})",
+            self.prompt,
+            self.shell.messages.multi_select_hint
+        )?;
+        let mut filter = String::new();
+        self.list(&filter)?;
+        loop {
+            let Some(answer) = self.shell.read_line("> ")? else {
+                return Ok(None);
+            };
+            let answer = answer.trim();
+            if answer.is_empty() {
+                let picked = (0..self.items.len()).filter(|&i| self.selected[i]);
+                return Ok(Some(picked.collect()));
+            }
+
+            if let Some(term) = answer.strip_prefix('/') {
+                filter = term.to_lowercase();
+            } else if !self.toggle(answer) {
+                writeln!(
+                    self.shell.output.borrow_mut(),
+                    "{}",
+                    self.shell.messages.invalid_selection
+                )?;
+                continue;
+            }
+            self.list(&filter)?;
+        }
+    }
+
+    /// Lets the user move between the items with the arrow keys, toggle them
+    /// with `Space` and filter them by typing until `Enter` confirms
+    fn read_keys(mut self) -> io::Result<Option<Vec<usize>>> {
+        let shell = self.shell;
+        writeln!(
+            shell.output.borrow_mut(),
+            "{} ({})",
+            self.prompt,
+            shell.messages.multi_select_keys_hint
+        )?;
+        let mut filter = String::new();
+        // The position of the current item among the listed ones
+        let mut current = 0;
+        let mut shown = 0;
+        loop {
+            let listed: Vec<usize> = (0..self.items.len())
+                .filter(|&i| {
+                    let item = self.items[i].as_ref().to_lowercase();
+                    item.contains(&filter.to_lowercase())
+                })
+                .collect();
+            current = current.min(listed.len().saturating_sub(1));
+            let filter_row = (!filter.is_empty()).then(|| format!("/{}", filter));
+            let rows = listed.iter().enumerate().map(|(position, &i)| {
+                let marker = if position == current { '>' } else { ' ' };
+                let check = if self.selected[i] { 'x' } else { ' ' };
+                format!("{} [{}] {}", marker, check, self.items[i].as_ref())
+            });
+            shown = redraw(shell, shown, filter_row.into_iter().chain(rows))?;
+
+            let count = listed.len();
+            match read_key(&mut **shell.input.borrow_mut())? {
+                None => return Ok(None),
+                Some(Key::Enter) => break,
+                Some(Key::Up) if count > 0 => current = (current + count - 1) % count,
+                Some(Key::Down) if count > 0 => current = (current + 1) % count,
+                Some(Key::Space) if count > 0 => {
+                    let i = listed[current];
+                    self.selected[i] = !self.selected[i];
+                }
+                Some(Key::Char(c)) => filter.push(c),
+                Some(Key::Backspace) => {
+                    filter.pop();
+                }
+                Some(_) => {}
+            }
+        }
+
+        let picked: Vec<usize> = (0..self.items.len())
+            .filter(|&i| self.selected[i])
+            .collect();
+        let names: Vec<&str> = picked.iter().map(|&i| self.items[i].as_ref()).collect();
+        redraw(
+            shell,
+            shown,
+            std::iter::once(format!("> {}", names.join(", "))),
+        )?;
+        Ok(Some(picked))
+    }
+
+    /// Prints the items that contain the filter with their check boxes
+    fn list(&self, filter: &str) -> io::Result<()> {
+        let mut output = self.shell.output.borrow_mut();
+        for (i, item) in self.items.iter().enumerate() {
+            let item = item.as_ref();
+            if !item.to_lowercase().contains(filter) {
+                continue;
+            }
+            let check = if self.selected[i] { 'x' } else { ' ' };
+            writeln!(output, "[{}] {}) {}", check, i + 1, item)?;
+        }
+        Ok(())
+    }
+
+    /// Toggles the items with the numbers in the answer
+    ///
+    /// Nothing is toggled if any of the numbers does not belong to an item.
+    fn toggle(&mut self, answer: &str) -> bool {
+        let numbers: Option<Vec<usize>> = answer
+            .split([' ', ','])
+            .filter(|n| !n.is_empty())
+            .map(|n| {
+                n.parse::<usize>()
+                    .ok()
+                    .filter(|&n| (1..=self.items.len()).contains(&n))
+            })
+            .collect();
+        let Some(numbers) = numbers else {
+            return false;
+        };
+        for n in numbers {
+            self.selected[n - 1] = !self.selected[n - 1];
+        }
+        true
+    }
+}
+
+/// Returns `true` if the prompts can read single keys, which needs a
+/// terminal the input can be switched to keystrokes on
+fn reads_keys(shell: &Shell) -> bool {
+    cfg!(unix) && shell.terminal.get() && io::stdin().is_terminal()
+}
+
+/// A key the prompts react to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    Enter,
+    Space,
+    Backspace,
+    Char(char),
+    /// A key without a meaning for the prompts, e.g. `F1`
+    Other,
+}
+
+/// Reads the next key, or `None` if the input has been closed or `Ctrl-D`
+/// pressed
+fn read_key(input: &mut dyn BufRead) -> io::Result<Option<Key>> {
+    let Some(byte) = read_byte(input)? else {
+        return Ok(None);
+    };
+    Ok(Some(match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b' ' => Key::Space,
+        0x7f | 0x08 => Key::Backspace,
+        0x04 => return Ok(None),
+        0x1b => {
+            let mut sequence = Vec::new();
+            // CSI and SS3 sequences end with a character in `@`..=`~`
+            while let Some(byte) = read_byte(input)? {
+                sequence.push(byte);
+                let introducer = sequence.len() == 1 && matches!(byte, b'[' | b'O');
+                if !introducer && (b'@'..=b'~').contains(&byte) {
+                    break;
+                }
+            }
+            match sequence.as_slice() {
+                [b'[' | b'O', b'A'] => Key::Up,
+                [b'[' | b'O', b'B'] => Key::Down,
+                _ => Key::Other,
+            }
+        }
+        byte if byte.is_ascii_graphic() => Key::Char(byte as char),
+        _ => Key::Other,
+    }))
+}
+
+fn read_byte(input: &mut dyn BufRead) -> io::Result<Option<u8>> {
+    let mut byte = 0;
+    match input.read(std::slice::from_mut(&mut byte))? {
+        0 => Ok(None),
+        _ => Ok(Some(byte)),
+    }
+}
+
+/// Replaces the `shown` rows above the cursor with the new rows and returns
+/// how many rows of the terminal they take up
+fn redraw(shell: &Shell, shown: usize, rows: impl Iterator<Item = String>) -> io::Result<usize> {
+    let width = terminal::width().unwrap_or(80).max(1);
+    let mut output = shell.output.borrow_mut();
+    output.release()?;
+    if shown > 0 {
+        write!(output, "\x1b[{}A", shown)?;
+    }
+    write!(output, "\r\x1b[J")?;
+    let mut taken = 0;
+    for row in rows {
+        writeln!(output, "{}", row)?;
+        taken += row.chars().count().max(1).div_ceil(width);
+    }
+    output.flush()?;
+    Ok(taken)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(input: &[u8]) -> Vec<Key> {
+        let mut input = input;
+        std::iter::from_fn(|| read_key(&mut input).unwrap()).collect()
+    }
+
+    #[test]
+    fn reads_keys() {
+        assert_eq!(
+            keys(b"\x1b[A\x1bOB \r\x7fa"),
+            [
+                Key::Up,
+                Key::Down,
+                Key::Space,
+                Key::Enter,
+                Key::Backspace,
+                Key::Char('a')
+            ]
+        );
+        assert_eq!(keys(b"\x1b[1;5A\x1b[15~\x01"), [Key::Other; 3]);
+        assert!(keys(b"\x04a").is_empty());
+    }
+
+    fn select(input: &'static [u8], default: Option<usize>) -> Option<usize> {
+        let shell = Shell::new(None, vec![]).with_io(input, Vec::new());
+        let mut prompt = SelectPrompt::new(&shell, "Target", &["staging", "production", "qa"]);
+        if let Some(default) = default {
+            prompt = prompt.with_default(default);
+        }
+        prompt.read_keys().unwrap()
+    }
+
+    #[test]
+    fn picks_with_the_arrow_keys() {
+        assert_eq!(select(b"\r", None), Some(0));
+        assert_eq!(select(b"\x1b[B\x1b[Bx\r", None), Some(2));
+        assert_eq!(select(b"\x1b[A\r", Some(1)), Some(0));
+        assert_eq!(select(b"\x1b[A\r", None), Some(2));
+        assert_eq!(select(b"\x1b[B", None), None);
+    }
+
+    fn multi_select(input: &'static [u8]) -> Option<Vec<usize>> {
+        let shell = Shell::new(None, vec![]).with_io(input, Vec::new());
+        let hosts = ["web-1", "web-2", "db-1"];
+        let prompt = MultiSelectPrompt::new(&shell, "Hosts", &hosts).with_selected(&[2]);
+        prompt.read_keys().unwrap()
+    }
+
+    #[test]
+    fn toggles_with_space() {
+        assert_eq!(multi_select(b"\r"), Some(vec![2]));
+        assert_eq!(multi_select(b" \x1b[B\x1b[B \r"), Some(vec![0]));
+        assert_eq!(multi_select(b"\x1b[A \x1b[A \r"), Some(vec![1]));
+        assert_eq!(multi_select(b" "), None);
+    }
+
+    #[test]
+    fn toggles_the_filtered_items() {
+        assert_eq!(multi_select(b"WEB\x1b[B \r"), Some(vec![1, 2]));
+        assert_eq!(multi_select(b"db\x7f\x7f-2 \r"), Some(vec![1, 2]));
+        // Nothing is listed to toggle
+        assert_eq!(multi_select(b"x \r"), Some(vec![2]));
+    }
+}
@@ -0,0 +1,110 @@
+//! Repeated attempts of failing commands, see [`RetryPolicy`]
+use std::{io::Write, thread, time::Duration};
+
+use crate::{schedule::parse_interval, CommandError, Context, Shell};
+
+/// How often a failing command is attempted and how long the shell waits
+/// between the attempts
+///
+/// Only failures that may be transient are retried, i.e.
+/// [`CommandError::Custom`] and [`CommandError::ExecutionError`], which
+/// failing I/O turns into. A notice is shown before every further attempt.
+///
+/// # Example
+/// ```rust
+/// # use std::{cell::Cell, time::Duration};
+/// # use simpleshell::{testing::TestShell, Command, CommandError, RetryPolicy, Shell};
+/// let attempts = Cell::new(0);
+/// let fetch = Command::new("fetch", "Fetches the feed", move |_, _| {
+///     attempts.set(attempts.get() + 1);
+///     match attempts.get() {
+///         1 | 2 => Err(CommandError::Custom("connection refused".to_string())),
+///         _ => Ok(()),
+///     }
+/// })
+/// .with_retry(RetryPolicy::new(3).with_backoff(Duration::from_millis(10)));
+///
+/// let mut shell = TestShell::deterministic(Shell::new(None, vec![fetch]));
+/// shell.run("fetch").expect_ok();
+/// assert_eq!(shell.output(), "Retrying (2/3)\nRetrying (3/3)\n");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Attempts a command up to `max_attempts` times without waiting in
+    /// between
+    pub fn new(max_attempts: u32) -> Self {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            backoff: Duration::ZERO,
+        }
+    }
+
+    /// Waits `delay` before the second attempt and twice as long as before
+    /// the previous attempt before every further one
+    pub fn with_backoff(mut self, delay: Duration) -> Self {
+        self.backoff = delay;
+        self
+    }
+
+    /// Calls `attempt` until it succeeds, fails permanently or the attempts
+    /// are used up and returns its last result
+    pub(crate) fn run(
+        &self,
+        shell: &Shell,
+        mut attempt: impl FnMut() -> Result<(), CommandError>,
+    ) -> Result<(), CommandError> {
+        let mut delay = self.backoff;
+        let mut attempts = 1;
+        loop {
+            match attempt() {
+                Err(e) if attempts < self.max_attempts && is_transient(&e) => {
+                    attempts += 1;
+                    writeln!(
+                        shell.output.borrow_mut(),
+                        "{} ({}/{})",
+                        shell.messages.retrying,
+                        attempts,
+                        self.max_attempts
+                    )?;
+                    thread::sleep(delay);
+                    delay *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+fn is_transient(error: &CommandError) -> bool {
+    matches!(
+        error,
+        CommandError::Custom(_) | CommandError::ExecutionError
+    )
+}
+
+/// Runs the `retry` builtin
+pub(crate) fn retry(arguments: &[String], ctx: &Context) -> Result<(), CommandError> {
+    let usage = || CommandError::Usage(ctx.messages().retry_usage.clone());
+    let [attempts, command @ ..] = arguments else {
+        return Err(usage());
+    };
+    let attempts: u32 = attempts.parse().map_err(|_| usage())?;
+    let mut policy = RetryPolicy::new(attempts);
+    let command = match command {
+        [flag, backoff, command @ ..] if flag == "--backoff" => {
+            let backoff = parse_interval(backoff).ok_or_else(usage)?;
+            policy = policy.with_backoff(backoff);
+            command
+        }
+        command => command,
+    };
+    if command.is_empty() {
+        return Err(usage());
+    }
+    policy.run(ctx.shell, || ctx.shell.dispatch(command.to_vec()).result)
+}
@@ -0,0 +1,194 @@
+//! Commands that run periodically or at a time of day, see
+//! [`Builtin::Schedule`](crate::Builtin::Schedule)
+use std::{
+    io::Write,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{CommandError, Context};
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// The jobs that have been scheduled in a shell
+#[derive(Default)]
+pub(crate) struct Scheduler {
+    jobs: Vec<Job>,
+    next_id: u64,
+}
+
+struct Job {
+    id: u64,
+    line: String,
+    /// `every 30s` or `at 14:00`, as shown by `schedule list`
+    when: String,
+    /// Jobs without an interval run once
+    interval: Option<Duration>,
+    due: Instant,
+}
+
+impl Scheduler {
+    /// Returns the time the next job is due at
+    pub(crate) fn next_due(&self) -> Option<Instant> {
+        self.jobs.iter().map(|job| job.due).min()
+    }
+
+    /// Returns the lines of the jobs that are due and schedules their next
+    /// runs
+    pub(crate) fn take_due(&mut self, now: Instant) -> Vec<String> {
+        let mut lines = Vec::new();
+        self.jobs.retain_mut(|job| {
+            if job.due > now {
+                return true;
+            }
+            lines.push(job.line.clone());
+            match job.interval {
+                Some(interval) => {
+                    // Runs that were missed while a command blocked the shell
+                    // are skipped instead of caught up on
+                    job.due = job.due.checked_add(interval).unwrap_or(now).max(now);
+                    true
+                }
+                None => false,
+            }
+        });
+        lines
+    }
+
+    fn add(&mut self, line: String, when: String, interval: Option<Duration>, due: Instant) -> u64 {
+        self.next_id += 1;
+        self.jobs.push(Job {
+            id: self.next_id,
+            line,
+            when,
+            interval,
+            due,
+        });
+        self.next_id
+    }
+}
+
+/// Runs the `schedule` builtin
+pub(crate) fn schedule(arguments: &[String], ctx: &Context) -> Result<(), CommandError> {
+    let shell = ctx.shell;
+    let messages = ctx.messages();
+    let usage = || CommandError::Usage(messages.schedule_usage.clone());
+    match arguments {
+        [list] if list == "list" => {
+            let scheduler = shell.scheduler.borrow();
+            let width = scheduler
+                .jobs
+                .iter()
+                .map(|j| j.when.len())
+                .max()
+                .unwrap_or(0);
+            for job in &scheduler.jobs {
+                writeln!(ctx.out(), "{}  {:width$}  {}", job.id, job.when, job.line)?;
+            }
+        }
+        [cancel, id] if cancel == "cancel" => {
+            let mut scheduler = shell.scheduler.borrow_mut();
+            let position = scheduler
+                .jobs
+                .iter()
+                .position(|job| job.id.to_string() == *id)
+                .ok_or_else(|| CommandError::Custom(format!("{}: {}", messages.unknown_job, id)))?;
+            scheduler.jobs.remove(position);
+        }
+        [kind, when, command @ ..] if !command.is_empty() => {
+            if !shell.knows_command(&command[0]) {
+                return Err(CommandError::NotFound);
+            }
+            let (interval, delay) = match kind.as_str() {
+                "every" => {
+                    let interval = parse_interval(when).ok_or_else(usage)?;
+                    (Some(interval), interval)
+                }
+                "at" => (None, until_time_of_day(when).ok_or_else(usage)?),
+                _ => return Err(usage()),
+            };
+            let due = Instant::now()
+                .checked_add(delay)
+                .ok_or(CommandError::ExecutionError)?;
+            let id = shell.scheduler.borrow_mut().add(
+                command.join(" "),
+                format!("{} {}", kind, when),
+                interval,
+                due,
+            );
+            writeln!(ctx.out(), "{} {}", messages.job_scheduled, id)?;
+        }
+        _ => return Err(usage()),
+    }
+    Ok(())
+}
+
+/// Parses an interval like `30s`, `5m`, `1h30m` or `500ms`
+///
+/// A number without a unit is a number of seconds. Intervals that do not fit
+/// into a [`Duration`] are rejected.
+pub(crate) fn parse_interval(text: &str) -> Option<Duration> {
+    if let Ok(seconds) = text.parse::<u64>() {
+        return (seconds > 0).then(|| Duration::from_secs(seconds));
+    }
+
+    let mut total = Duration::ZERO;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        let value: u64 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+        let unit = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let part = match &rest[..unit] {
+            "ms" => Duration::from_millis(value),
+            "s" => Duration::from_secs(value),
+            "m" => Duration::from_secs(value.checked_mul(60)?),
+            "h" => Duration::from_secs(value.checked_mul(60 * 60)?),
+            "d" => Duration::from_secs(value.checked_mul(DAY)?),
+            _ => return None,
+        };
+        total = total.checked_add(part)?;
+        rest = &rest[unit..];
+    }
+    (!total.is_zero()).then_some(total)
+}
+
+/// Returns the time until the next occurrence of a local time of day like
+/// `14:00` or `14:00:30`
+fn until_time_of_day(text: &str) -> Option<Duration> {
+    let mut parts = text.split(':').map(|p| p.parse::<u64>().ok());
+    let hours = parts.next()??;
+    let minutes = parts.next()??;
+    let seconds = parts.next().unwrap_or(Some(0))?;
+    if parts.next().is_some() || hours > 23 || minutes > 59 || seconds > 59 {
+        return None;
+    }
+
+    let target = hours * 3600 + minutes * 60 + seconds;
+    let now = local_seconds_of_day();
+    let delay = (target + DAY - now) % DAY;
+    Some(Duration::from_secs(if delay == 0 { DAY } else { delay }))
+}
+
+/// Returns the seconds since midnight in the local time zone
+///
+/// Falls back to UTC if the local time zone is unknown.
+fn local_seconds_of_day() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    #[cfg(unix)]
+    {
+        let time = now as libc::time_t;
+        // SAFETY: localtime_r only writes into the provided tm, which is only
+        // used if the call succeeded
+        let mut local: libc::tm = unsafe { std::mem::zeroed() };
+        if !unsafe { libc::localtime_r(&time, &mut local) }.is_null() {
+            return (local.tm_hour * 3600 + local.tm_min * 60 + local.tm_sec) as u64;
+        }
+    }
+    now % DAY
+}
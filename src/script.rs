@@ -0,0 +1,378 @@
+//! Scripts with minimal control flow that are executed by
+//! [`Shell::run_script`](crate::Shell::run_script)
+use std::{
+    cell::RefCell,
+    io::Write,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use crate::{expand, external, terminal, CommandError, Context, Shell};
+
+/// The maximum number of script files that may run nested in each other
+pub(crate) const MAX_DEPTH: usize = 32;
+
+/// The options of scripts that are changed with `set -e` and `set -u`
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Options {
+    /// Stop the script at the first failing command
+    pub(crate) errexit: bool,
+    /// Fail on references to undefined variables instead of leaving them
+    /// unchanged
+    pub(crate) nounset: bool,
+}
+
+/// The cleanup commands registered with `trap` by a running script
+#[derive(Default)]
+pub(crate) struct Traps {
+    commands: Vec<String>,
+    /// Lets the script stop and run the commands if it is interrupted
+    #[cfg(unix)]
+    interrupt: Option<terminal::InterruptGuard>,
+}
+
+/// A single statement of a script
+pub(crate) enum Statement {
+    /// A command line
+    Line(String),
+    /// `if <command>` - runs `then` if the command succeeds, otherwise the
+    /// statements after `else`
+    If {
+        condition: String,
+        then: Vec<Statement>,
+        otherwise: Vec<Statement>,
+    },
+    /// `for <variable> in <values>` - runs the body once for every value
+    For {
+        variable: String,
+        values: Vec<String>,
+        body: Vec<Statement>,
+    },
+    /// `repeat <count>` - runs the body `count` times
+    Repeat { count: usize, body: Vec<Statement> },
+    /// `parallel [<limit>]` - runs the native OS commands of the lines
+    /// concurrently, at most `limit` at a time
+    Parallel { limit: usize, lines: Vec<String> },
+}
+
+/// How a block of statements was terminated
+enum Terminator {
+    Eof,
+    /// `else` in the given line
+    Else(usize),
+    /// `end` in the given line
+    End(usize),
+}
+
+/// Parses the script into statements
+///
+/// Returns the number of the line with the syntax error if the script is
+/// invalid.
+pub(crate) fn parse(shell: &Shell, script: &str) -> Result<Vec<Statement>, usize> {
+    let mut lines = script
+        .lines()
+        .enumerate()
+        .map(|(n, line)| (n + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+    match block(shell, &mut lines)? {
+        (statements, Terminator::Eof) => Ok(statements),
+        (_, Terminator::Else(n) | Terminator::End(n)) => Err(n),
+    }
+}
+
+fn block<'a>(
+    shell: &Shell,
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+) -> Result<(Vec<Statement>, Terminator), usize> {
+    let mut statements = Vec::new();
+    while let Some((n, line)) = lines.next() {
+        let Ok(tokens) = shell.tokenizer.tokenize(line) else {
+            return Err(n);
+        };
+        let statement = match tokens.first().map(String::as_str) {
+            Some("end") if tokens.len() == 1 => return Ok((statements, Terminator::End(n))),
+            Some("else") if tokens.len() == 1 => return Ok((statements, Terminator::Else(n))),
+            Some("if") if tokens.len() > 1 => {
+                let condition = line["if".len()..].trim_start().to_string();
+                let (then, otherwise) = match block(shell, lines)? {
+                    (then, Terminator::Else(_)) => match block(shell, lines)? {
+                        (otherwise, Terminator::End(_)) => (then, otherwise),
+                        _ => return Err(n),
+                    },
+                    (then, Terminator::End(_)) => (then, Vec::new()),
+                    (_, Terminator::Eof) => return Err(n),
+                };
+                Statement::If {
+                    condition,
+                    then,
+                    otherwise,
+                }
+            }
+            Some("for") if tokens.len() >= 3 && tokens[2] == "in" => Statement::For {
+                variable: tokens[1].clone(),
+                values: tokens[3..].to_vec(),
+                body: end_block(shell, lines, n)?,
+            },
+            Some("repeat") if tokens.len() == 2 => Statement::Repeat {
+                count: tokens[1].parse().map_err(|_| n)?,
+                body: end_block(shell, lines, n)?,
+            },
+            Some("parallel") if tokens.len() <= 2 => {
+                let limit = match tokens.get(1) {
+                    Some(limit) => limit.parse::<NonZeroUsize>().map_err(|_| n)?,
+                    None => thread::available_parallelism().unwrap_or(NonZeroUsize::MIN),
+                };
+                // Only commands can run concurrently, not nested blocks
+                let lines = end_block(shell, lines, n)?
+                    .into_iter()
+                    .map(|statement| match statement {
+                        Statement::Line(line) => Ok(line),
+                        _ => Err(n),
+                    })
+                    .collect::<Result<_, _>>()?;
+                Statement::Parallel {
+                    limit: limit.get(),
+                    lines,
+                }
+            }
+            Some("if" | "for" | "repeat" | "parallel" | "else" | "end") => return Err(n),
+            _ => Statement::Line(line.to_string()),
+        };
+        statements.push(statement);
+    }
+    Ok((statements, Terminator::Eof))
+}
+
+/// Parses a block that has to be terminated by `end`
+fn end_block<'a>(
+    shell: &Shell,
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+    start: usize,
+) -> Result<Vec<Statement>, usize> {
+    match block(shell, lines)? {
+        (body, Terminator::End(_)) => Ok(body),
+        _ => Err(start),
+    }
+}
+
+/// Runs the statements of a script
+///
+/// Options that the script changes with `set` only apply until it ends.
+/// The commands registered with `trap` run in reverse order when it ends,
+/// including when it fails, exits or is interrupted.
+pub(crate) fn run_script(shell: &Shell, statements: &[Statement]) -> Result<(), CommandError> {
+    let options = shell.script_options.get();
+    shell.traps.borrow_mut().push(Traps::default());
+    let mut result = run(shell, statements);
+
+    let traps = shell.traps.borrow_mut().pop().unwrap_or_default();
+    for command in traps.commands.iter().rev() {
+        if let Err(e) = shell.process_line(command).result {
+            shell.report_error(&e);
+        }
+    }
+    drop(traps);
+    if terminal::interrupted() {
+        // Scripts that run this one stop as well
+        if shell.traps.borrow().is_empty() {
+            terminal::clear_interrupt();
+        }
+        result = Err(CommandError::Custom(shell.messages.interrupted.clone()));
+    }
+    shell.script_options.set(options);
+    result
+}
+
+/// Runs the `trap` builtin
+pub(crate) fn trap(arguments: &[String], ctx: &Context) -> Result<(), CommandError> {
+    let usage = || CommandError::Usage(ctx.messages().trap_usage.clone());
+    let [command @ .., condition] = arguments else {
+        return Err(usage());
+    };
+    if command.is_empty() || !condition.eq_ignore_ascii_case("exit") {
+        return Err(usage());
+    }
+
+    let mut traps = ctx.shell.traps.borrow_mut();
+    let traps = traps
+        .last_mut()
+        .ok_or_else(|| CommandError::Custom(ctx.messages().trap_outside_script.clone()))?;
+    match command {
+        [reset] if reset == "-" => traps.commands.clear(),
+        _ => {
+            traps.commands.push(command.join(" "));
+            #[cfg(unix)]
+            traps
+                .interrupt
+                .get_or_insert_with(terminal::InterruptGuard::new);
+        }
+    }
+    Ok(())
+}
+
+/// Runs the statements one after another
+///
+/// Failing commands are reported and only stop the script in strict mode
+/// (`set -e`), references to undefined variables only in `set -u` mode.
+/// Returns the result of the last command that was executed.
+fn run(shell: &Shell, statements: &[Statement]) -> Result<(), CommandError> {
+    let mut result = Ok(());
+    for statement in statements {
+        if shell.exit_requested() || terminal::interrupted() {
+            break;
+        }
+        result = match statement {
+            Statement::Line(line) => {
+                let result = shell.process_line(&expand_line(shell, line)?).result;
+                if let Err(e) = &result {
+                    shell.report_error(e);
+                }
+                result
+            }
+            Statement::If {
+                condition,
+                then,
+                otherwise,
+            } => match shell.process_line(&expand_line(shell, condition)?).result {
+                Ok(()) => run(shell, then),
+                Err(_) => run(shell, otherwise),
+            },
+            Statement::For {
+                variable,
+                values,
+                body,
+            } => {
+                let mut result = Ok(());
+                for value in values {
+                    shell.set_var(variable, &expand_line(shell, value)?);
+                    result = run(shell, body);
+                    if aborts(shell, &result) {
+                        break;
+                    }
+                }
+                result
+            }
+            Statement::Repeat { count, body } => {
+                let mut result = Ok(());
+                for _ in 0..*count {
+                    result = run(shell, body);
+                    if aborts(shell, &result) {
+                        break;
+                    }
+                }
+                result
+            }
+            Statement::Parallel { limit, lines } => run_parallel(shell, *limit, lines),
+        };
+        if aborts(shell, &result) {
+            break;
+        }
+    }
+    result
+}
+
+/// Runs the lines of a `parallel` block
+///
+/// The native OS commands run on up to `limit` threads while the other
+/// commands run on the shell thread, which their handlers are bound to. The
+/// output of the native commands is collected and shown in the order of the
+/// block once all of them have exited. Every failed command is reported.
+fn run_parallel(shell: &Shell, limit: usize, lines: &[String]) -> Result<(), CommandError> {
+    let mut native = Vec::new();
+    let mut others = Vec::new();
+    for line in lines {
+        let line = expand_line(shell, line)?;
+        match shell.external_command(&line) {
+            Some(command) => native.push(command),
+            None => others.push(line),
+        }
+    }
+
+    let working_dir = shell.working_dir();
+    let next = AtomicUsize::new(0);
+    let outputs = Mutex::new(Vec::new());
+    let mut failed = 0;
+    thread::scope(|scope| {
+        for _ in 0..limit.min(native.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some((program, arguments)) = native.get(index) else {
+                    break;
+                };
+                let output = external::output(program, arguments, &working_dir);
+                if let Ok(mut outputs) = outputs.lock() {
+                    outputs.push((index, output));
+                }
+            });
+        }
+        for line in &others {
+            if let Err(e) = shell.process_line(line).result {
+                shell.report_error(&e);
+                failed += 1;
+            }
+        }
+    });
+
+    let mut outputs = outputs.into_inner().unwrap_or_default();
+    outputs.sort_by_key(|(index, _)| *index);
+    let ctx = Context::new(shell);
+    for (index, output) in outputs {
+        let program = &native[index].0;
+        let error = match output {
+            Ok((output, status)) => {
+                ctx.out().write_all(&output)?;
+                (!status.success())
+                    .then(|| format!("{}: {} {}", program, shell.messages.process_failed, status))
+            }
+            Err(e) => Some(format!("{}: {}", program, e)),
+        };
+        if let Some(error) = error {
+            shell.report_error(&CommandError::Custom(error));
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(CommandError::Custom(format!(
+            "{}: {}/{}",
+            shell.messages.parallel_failed,
+            failed,
+            lines.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Returns `true` if the result stops the script in strict mode
+fn aborts(shell: &Shell, result: &Result<(), CommandError>) -> bool {
+    result.is_err() && shell.script_options.get().errexit
+}
+
+/// Replaces the references to variables in the line
+///
+/// In `set -u` mode a reference to an undefined variable is reported and
+/// returned as an error.
+fn expand_line(shell: &Shell, line: &str) -> Result<String, CommandError> {
+    let unbound = RefCell::new(None);
+    let expanded = expand::variables(line, |name| {
+        let value = shell.var(name);
+        if value.is_none() && !name.is_empty() {
+            unbound.borrow_mut().get_or_insert_with(|| name.to_string());
+        }
+        value
+    });
+    match unbound.into_inner() {
+        Some(name) if shell.script_options.get().nounset => {
+            let error =
+                CommandError::Custom(format!("{}: {}", shell.messages.unbound_variable, name));
+            shell.report_error(&error);
+            Err(error)
+        }
+        _ => Ok(expanded),
+    }
+}
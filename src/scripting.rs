@@ -0,0 +1,31 @@
+//! Engines for commands that users define at runtime with the
+//! [`Builtin::Define`](crate::Builtin::Define) command
+use crate::CommandFn;
+
+#[cfg(feature = "lua")]
+mod lua;
+
+#[cfg(feature = "rhai")]
+mod rhai;
+
+#[cfg(feature = "rhai")]
+pub use self::rhai::RhaiEngine;
+#[cfg(feature = "lua")]
+pub use lua::LuaEngine;
+
+/// Compiles the bodies of user-defined commands
+///
+/// The bodies have access to a curated API instead of the whole shell:
+/// * `args` - the arguments of the invocation
+/// * `print(value)` - writes the value to the output of the shell
+/// * `get_var(name)` - returns the value of a variable or nothing
+/// * `set_var(name, value)` - sets a variable
+/// * `invoke(line)` - processes the line like user input and returns whether
+///   it succeeded
+///
+/// Raising an error fails the command with the error message.
+pub trait ScriptEngine {
+    /// Compiles the body of the command `name` or returns the reason why it
+    /// is invalid
+    fn compile(&self, name: &str, body: &str) -> Result<Box<CommandFn>, String>;
+}
@@ -0,0 +1,116 @@
+use std::{io::Write, rc::Rc};
+
+use mlua::{Function, Lua, MultiValue, Variadic};
+
+use crate::{CommandError, CommandFn, Context};
+
+use super::ScriptEngine;
+
+/// A [`ScriptEngine`] for commands written in Lua
+///
+/// # Example
+/// ```rust
+/// # use simpleshell::{scripting::LuaEngine, testing::TestShell, Builtin, Shell};
+/// let shell = Shell::new(None, vec![])
+///     .with_builtin(Builtin::Define)
+///     .with_script_engine(LuaEngine::new());
+///
+/// let mut shell = TestShell::deterministic(shell);
+/// shell
+///     .run(r#"define greet 'print("Hello " .. (args[1] or get_var("user")))'"#)
+///     .expect_ok();
+/// shell.shell().set_var("user", "admin");
+/// shell.run("greet").expect_ok();
+/// assert_eq!(shell.output(), "Hello admin\n");
+/// ```
+pub struct LuaEngine {
+    lua: Rc<Lua>,
+}
+
+impl LuaEngine {
+    /// Creates an engine with the Lua standard library
+    pub fn new() -> Self {
+        LuaEngine {
+            lua: Rc::new(Lua::new()),
+        }
+    }
+}
+
+impl Default for LuaEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScriptEngine for LuaEngine {
+    fn compile(&self, name: &str, body: &str) -> Result<Box<CommandFn>, String> {
+        let chunk = format!("={}", name);
+        self.lua
+            .load(body)
+            .set_name(&chunk)
+            .into_function()
+            .map_err(|e| message(&e))?;
+
+        let lua = self.lua.clone();
+        let body = body.to_string();
+        Ok(Box::new(move |arguments, ctx| {
+            run(&lua, &chunk, &body, arguments, ctx).map_err(|e| CommandError::Custom(message(&e)))
+        }))
+    }
+}
+
+/// Runs the body in a fresh environment that contains the API for the
+/// invocation
+fn run(
+    lua: &Lua,
+    chunk: &str,
+    body: &str,
+    arguments: &[String],
+    ctx: &Context,
+) -> mlua::Result<()> {
+    lua.scope(|scope| {
+        let env = lua.create_table()?;
+        let globals = lua.create_table()?;
+        globals.set("__index", lua.globals())?;
+        env.set_metatable(Some(globals))?;
+
+        env.set("args", arguments.to_vec())?;
+        env.set(
+            "print",
+            scope.create_function(|lua, values: MultiValue| {
+                let tostring: Function = lua.globals().get("tostring")?;
+                let mut line = Vec::new();
+                for value in values {
+                    line.push(tostring.call::<String>(value)?);
+                }
+                writeln!(ctx.out(), "{}", line.join("\t")).map_err(mlua::Error::external)
+            })?,
+        )?;
+        env.set(
+            "get_var",
+            scope.create_function(|_, name: String| Ok(ctx.var(&name)))?,
+        )?;
+        env.set(
+            "set_var",
+            scope.create_function(|_, (name, value): (String, String)| {
+                ctx.set_var(&name, &value);
+                Ok(())
+            })?,
+        )?;
+        env.set(
+            "invoke",
+            scope.create_function(|_, line: Variadic<String>| {
+                let outcome = ctx.shell.process_line(&line.join(" "));
+                Ok(outcome.is_ok())
+            })?,
+        )?;
+
+        lua.load(body).set_name(chunk).set_environment(env).exec()
+    })
+}
+
+/// Returns the error message without the stack traceback
+fn message(error: &mlua::Error) -> String {
+    let error = error.to_string();
+    error.lines().next().unwrap_or_default().to_string()
+}
@@ -0,0 +1,104 @@
+use std::{cell::Cell, io::Write, ptr, rc::Rc};
+
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Scope};
+
+use crate::{CommandError, CommandFn, Context, Shell};
+
+use super::ScriptEngine;
+
+thread_local! {
+    /// The shell that executes the running command
+    static SHELL: Cell<*const Shell> = const { Cell::new(ptr::null()) };
+}
+
+/// A [`ScriptEngine`] for commands written in [Rhai](https://rhai.rs)
+///
+/// # Example
+/// ```rust
+/// # use simpleshell::{scripting::RhaiEngine, testing::TestShell, Builtin, Shell};
+/// let shell = Shell::new(None, vec![])
+///     .with_builtin(Builtin::Define)
+///     .with_script_engine(RhaiEngine::new());
+///
+/// let mut shell = TestShell::deterministic(shell);
+/// shell
+///     .run(r#"define greet 'print(`Hello ${if args.is_empty() { get_var("user") } else { args[0] }}`)'"#)
+///     .expect_ok();
+/// shell.shell().set_var("user", "admin");
+/// shell.run("greet").expect_ok();
+/// assert_eq!(shell.output(), "Hello admin\n");
+/// ```
+pub struct RhaiEngine {
+    engine: Rc<Engine>,
+}
+
+impl RhaiEngine {
+    /// Creates an engine with the standard packages of Rhai
+    pub fn new() -> Self {
+        let mut engine = Engine::new();
+        engine.on_print(|text| {
+            with_shell(|shell| {
+                let _ = writeln!(Context::new(shell).out(), "{}", text);
+            })
+        });
+        engine.register_fn("get_var", |name: &str| {
+            with_shell(|shell| shell.var(name).map_or(Dynamic::UNIT, Dynamic::from))
+        });
+        engine.register_fn("set_var", |name: &str, value: &str| {
+            with_shell(|shell| shell.set_var(name, value))
+        });
+        engine.register_fn("invoke", |line: &str| {
+            with_shell(|shell| shell.process_line(line).is_ok())
+        });
+
+        RhaiEngine {
+            engine: Rc::new(engine),
+        }
+    }
+}
+
+impl Default for RhaiEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScriptEngine for RhaiEngine {
+    fn compile(&self, name: &str, body: &str) -> Result<Box<CommandFn>, String> {
+        let ast = self
+            .engine
+            .compile(body)
+            .map_err(|e| format!("{}: {}", name, e))?;
+
+        let engine = self.engine.clone();
+        let name = name.to_string();
+        Ok(Box::new(move |arguments, ctx| {
+            let args: Array = arguments.iter().cloned().map(Dynamic::from).collect();
+            let mut scope = Scope::new();
+            scope.push_constant("args", args);
+
+            let previous = SHELL.with(|s| s.replace(ctx.shell));
+            let result = engine.run_ast_with_scope(&mut scope, &ast);
+            SHELL.with(|s| s.set(previous));
+
+            result.map_err(|e| CommandError::Custom(format!("{}: {}", name, message(*e))))
+        }))
+    }
+}
+
+/// Calls `f` with the shell that executes the running command
+fn with_shell<R>(f: impl FnOnce(&Shell) -> R) -> R {
+    let shell = SHELL.with(Cell::get);
+    assert!(!shell.is_null(), "called outside of a command");
+    // SAFETY: The pointer is only set while the shell executes a command and
+    // reset before the borrow ends
+    f(unsafe { &*shell })
+}
+
+/// Returns the message of errors that are thrown by the script
+fn message(error: EvalAltResult) -> String {
+    match error {
+        EvalAltResult::ErrorRuntime(value, _) => value.to_string(),
+        error => error.to_string(),
+    }
+}
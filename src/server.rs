@@ -0,0 +1,546 @@
+//! A server that multiplexes the sessions of a shell, see [`ShellServer`]
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::{self, BufRead, BufReader, Write},
+    net::Shutdown,
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex, MutexGuard,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{output::Limiter, CommandError, Context, OutputLimit, Shell};
+
+/// Serves sessions of a shell to several clients of a Unix domain socket at
+/// once
+///
+/// Every client that connects gets a new session. A client detaches from its
+/// session by closing the connection or with `sessions detach`, and any
+/// client can attach to a detached session again with `sessions attach
+/// <id>`, see [`Builtin::Sessions`](crate::Builtin::Sessions). The output of
+/// a session is retained up to the scrollback limit and replayed when a
+/// client attaches. Detached sessions are removed after a timeout and, beyond
+/// a limit, starting with the ones that were detached first.
+///
+/// Every client is written to by a thread of its own, so a client that stops
+/// reading does not hold up the other sessions. A client that falls more than
+/// 1 MiB of output behind is detached.
+///
+/// The lines of all sessions are executed one after another on the thread
+/// that called [`ShellServer::serve_unix`], so commands keep running when
+/// their client detaches. The sessions share the state of the shell such as
+/// its variables, and commands cannot read additional input from the client.
+///
+/// # Example
+/// ```rust,no_run
+/// # use simpleshell::{Builtin, Shell, ShellServer};
+/// let shell = Shell::new(Some("admin> "), vec![]).with_builtin(Builtin::Sessions);
+/// ShellServer::new()
+///     .with_scrollback_limit(1 << 20)
+///     .serve_unix(&shell, "/run/app.sock")?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Clone)]
+pub struct ShellServer {
+    state: Arc<Mutex<State>>,
+}
+
+pub(crate) struct State {
+    sessions: BTreeMap<u64, Session>,
+    output_limit: Option<OutputLimit>,
+    ansi: bool,
+    next_session: u64,
+    next_connection: u64,
+    scrollback_limit: usize,
+    detached_limit: usize,
+    detached_timeout: Duration,
+    prompt: String,
+}
+
+struct Session {
+    scrollback: VecDeque<u8>,
+    client: Option<Client>,
+    /// When the client of the session detached
+    detached: Option<Instant>,
+    /// Whether a line of the session is being executed
+    running: bool,
+}
+
+/// The output a client may fall behind by before it is detached
+const MAX_PENDING: usize = 1 << 20;
+
+struct Client {
+    connection: u64,
+    stream: UnixStream,
+    /// The output for the writer thread of the client
+    queue: mpsc::Sender<Vec<u8>>,
+    /// The number of queued bytes that have not been written yet
+    pending: Arc<AtomicUsize>,
+}
+
+/// A line a client has entered in a session
+struct Job {
+    session: u64,
+    connection: u64,
+    line: String,
+}
+
+/// The session a line is executed in
+pub(crate) struct Current {
+    state: Arc<Mutex<State>>,
+    session: u64,
+    connection: u64,
+}
+
+impl ShellServer {
+    /// Creates a server with a scrollback limit of 64 KiB per session that
+    /// keeps up to 16 detached sessions for an hour
+    pub fn new() -> Self {
+        ShellServer {
+            state: Arc::new(Mutex::new(State {
+                sessions: BTreeMap::new(),
+                output_limit: None,
+                ansi: true,
+                next_session: 1,
+                next_connection: 1,
+                scrollback_limit: 64 * 1024,
+                detached_limit: 16,
+                detached_timeout: Duration::from_secs(60 * 60),
+                prompt: String::new(),
+            })),
+        }
+    }
+
+    /// Sets the number of bytes of output that are retained per session
+    pub fn with_scrollback_limit(self, bytes: usize) -> Self {
+        lock(&self.state).scrollback_limit = bytes;
+        self
+    }
+
+    /// Sets the number of detached sessions that are kept for clients to
+    /// attach to
+    pub fn with_detached_session_limit(self, sessions: usize) -> Self {
+        lock(&self.state).detached_limit = sessions;
+        self
+    }
+
+    /// Sets how long a detached session is kept for clients to attach to
+    pub fn with_detached_session_timeout(self, timeout: Duration) -> Self {
+        lock(&self.state).detached_timeout = timeout;
+        self
+    }
+
+    /// Limits how much output every command may send to a session
+    ///
+    /// The limit applies in addition to the limit of the shell set with
+    /// [`Shell::with_output_limit`].
+    pub fn with_output_limit(self, limit: OutputLimit) -> Self {
+        lock(&self.state).output_limit = Some(limit);
+        self
+    }
+
+    /// Declares whether the clients support ANSI escape sequences, which are
+    /// removed from the output of the sessions otherwise
+    ///
+    /// The notices of [`ShellServer::broadcast`] clear the prompt with an
+    /// escape sequence, which dumb clients show verbatim.
+    pub fn with_ansi(self, supported: bool) -> Self {
+        lock(&self.state).ansi = supported;
+        self
+    }
+
+    /// Shows a message above the prompt of every session a client is attached
+    /// to, e.g. to announce a maintenance
+    ///
+    /// The server may be cloned to broadcast from other threads while it
+    /// serves the sessions.
+    pub fn broadcast(&self, message: &str) {
+        lock(&self.state).broadcast(message);
+    }
+
+    /// Listens on a Unix domain socket and serves the sessions with the shell
+    ///
+    /// A stale socket file at `path` is replaced. The socket is accessible
+    /// according to the umask of the process, so it should be placed in a
+    /// directory that only the intended users can access.
+    ///
+    /// Returns only if accepting a connection fails and all clients have
+    /// disconnected.
+    pub fn serve_unix(&self, shell: &Shell, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        if std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        lock(&self.state).prompt = shell.current_prompt();
+
+        let (jobs, queue) = mpsc::channel();
+        let state = self.state.clone();
+        let acceptor = thread::spawn(move || -> io::Result<()> {
+            for stream in listener.incoming() {
+                let stream = stream?;
+                let (state, jobs) = (state.clone(), jobs.clone());
+                // A failing client must not stop the server
+                thread::spawn(move || connect(&state, &jobs, stream));
+            }
+            Ok(())
+        });
+
+        // The queue is closed once no client is connected and the acceptor
+        // has stopped
+        for job in queue {
+            self.execute(shell, job);
+        }
+        acceptor
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("the acceptor panicked")))
+    }
+
+    /// Executes the line in its session and shows the next prompt
+    fn execute(&self, shell: &Shell, job: Job) {
+        lock(&self.state).set_running(job.session, true);
+        shell.history.borrow_mut().add(&job.line);
+        shell.session.replace(Some(Current {
+            state: self.state.clone(),
+            session: job.session,
+            connection: job.connection,
+        }));
+        let (limit, state_ansi) = {
+            let state = lock(&self.state);
+            (state.output_limit, state.ansi)
+        };
+        let output = SessionOutput {
+            state: self.state.clone(),
+            session: job.session,
+            limiter: limit.map(|limit| Limiter::new(limit, &shell.messages)),
+        };
+        shell.redirected(Box::new(io::empty()), Box::new(output), state_ansi, || {
+            // Empty lines are not worth reporting
+            match shell.process_line(&job.line).result {
+                Ok(()) | Err(CommandError::Empty) => {}
+                Err(e) => shell.report_error(&e),
+            }
+        });
+        shell.session.replace(None);
+
+        let prompt = shell.current_prompt();
+        let mut state = lock(&self.state);
+        state.prompt = prompt.clone();
+        state.set_running(job.session, false);
+        state.write(job.session, prompt.as_bytes());
+        state.reap();
+    }
+}
+
+impl Default for ShellServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl State {
+    /// Writes to the scrollback of the session and to its client
+    fn write(&mut self, session: u64, data: &[u8]) {
+        let limit = self.scrollback_limit;
+        let Some(session) = self.sessions.get_mut(&session) else {
+            return;
+        };
+        session.scrollback.extend(data);
+        let excess = session.scrollback.len().saturating_sub(limit);
+        session.scrollback.drain(..excess);
+
+        if session.client.as_ref().is_some_and(|c| !c.send(data)) {
+            if let Some(client) = session.detach() {
+                let _ = client.stream.shutdown(Shutdown::Both);
+            }
+        }
+    }
+
+    /// Removes the detached sessions that timed out and the ones that were
+    /// detached first beyond the limit
+    ///
+    /// Sessions with a running command are kept until it finishes.
+    fn reap(&mut self) {
+        let now = Instant::now();
+        let timeout = self.detached_timeout;
+        self.sessions.retain(|_, s| {
+            s.running || s.detached.is_none_or(|at| now.duration_since(at) < timeout)
+        });
+
+        let mut detached: Vec<(Instant, u64)> = self
+            .sessions
+            .iter()
+            .filter(|(_, s)| !s.running)
+            .filter_map(|(id, s)| s.detached.map(|at| (at, *id)))
+            .collect();
+        detached.sort_unstable();
+        let excess = detached.len().saturating_sub(self.detached_limit);
+        for (_, id) in &detached[..excess] {
+            self.sessions.remove(id);
+        }
+    }
+
+    fn set_running(&mut self, session: u64, running: bool) {
+        if let Some(session) = self.sessions.get_mut(&session) {
+            session.running = running;
+        }
+    }
+
+    /// Writes the message to every session a client is attached to
+    ///
+    /// The prompt of idle sessions is cleared and shown again below the
+    /// message.
+    fn broadcast(&mut self, message: &str) {
+        let sessions: Vec<(u64, bool)> = self
+            .sessions
+            .iter()
+            .filter(|(_, s)| s.client.is_some())
+            .map(|(id, s)| (*id, s.running))
+            .collect();
+        for (session, running) in sessions {
+            let text = if running {
+                format!("{}\n", message)
+            } else {
+                format!("\r\x1b[2K{}\n{}", message, self.prompt)
+            };
+            self.write(session, text.as_bytes());
+        }
+    }
+
+    /// Returns the session the connection is attached to
+    fn session_of(&self, connection: u64) -> Option<u64> {
+        self.sessions
+            .iter()
+            .find(|(_, s)| {
+                s.client
+                    .as_ref()
+                    .is_some_and(|c| c.connection == connection)
+            })
+            .map(|(id, _)| *id)
+    }
+}
+
+impl Session {
+    /// Detaches the client from the session and returns it
+    fn detach(&mut self) -> Option<Client> {
+        let client = self.client.take();
+        if client.is_some() {
+            self.detached = Some(Instant::now());
+        }
+        client
+    }
+}
+
+impl Client {
+    /// Starts the thread that writes the output to the client
+    fn new(connection: u64, stream: UnixStream) -> io::Result<Self> {
+        let (queue, output) = mpsc::channel();
+        let pending = Arc::new(AtomicUsize::new(0));
+        let (writer, written) = (stream.try_clone()?, pending.clone());
+        thread::spawn(move || write_queued(writer, &output, &written));
+        Ok(Client {
+            connection,
+            stream,
+            queue,
+            pending,
+        })
+    }
+
+    /// Queues the data for the client and returns `false` if the client is
+    /// gone or fell too far behind
+    fn send(&self, data: &[u8]) -> bool {
+        let pending = self.pending.fetch_add(data.len(), Ordering::Relaxed) + data.len();
+        pending <= MAX_PENDING && self.queue.send(data.to_vec()).is_ok()
+    }
+}
+
+/// Writes the queued output to the client until the client is dropped or
+/// writing fails
+fn write_queued(mut stream: UnixStream, output: &mpsc::Receiver<Vec<u8>>, pending: &AtomicUsize) {
+    for data in output {
+        if stream.write_all(&data).is_err() {
+            // Ends the session of the client in `connect` as well
+            let _ = stream.shutdown(Shutdown::Both);
+            return;
+        }
+        pending.fetch_sub(data.len(), Ordering::Relaxed);
+    }
+}
+
+/// Serves a client until it closes the connection or detaches
+fn connect(state: &Mutex<State>, jobs: &mpsc::Sender<Job>, stream: UnixStream) -> io::Result<()> {
+    let reader = BufReader::new(stream.try_clone()?);
+    let connection = {
+        let mut state = lock(state);
+        state.reap();
+        let (connection, session) = (state.next_connection, state.next_session);
+        state.next_connection += 1;
+        state.next_session += 1;
+        state.sessions.insert(
+            session,
+            Session {
+                scrollback: VecDeque::new(),
+                client: Some(Client::new(connection, stream)?),
+                detached: None,
+                running: false,
+            },
+        );
+        let prompt = state.prompt.clone();
+        state.write(session, prompt.as_bytes());
+        connection
+    };
+
+    for line in reader.lines() {
+        let Some(session) = lock(state).session_of(connection) else {
+            break;
+        };
+        let line = line?;
+        let job = Job {
+            session,
+            connection,
+            line: line.trim_end_matches('\r').to_string(),
+        };
+        if jobs.send(job).is_err() {
+            break;
+        }
+    }
+
+    let mut state = lock(state);
+    if let Some(session) = state.session_of(connection) {
+        state
+            .sessions
+            .get_mut(&session)
+            .expect("the session exists")
+            .detach();
+    }
+    state.reap();
+    Ok(())
+}
+
+/// Writes the output of a command to its session
+struct SessionOutput {
+    state: Arc<Mutex<State>>,
+    session: u64,
+    limiter: Option<Limiter>,
+}
+
+impl Write for SessionOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = lock(&self.state);
+        match &mut self.limiter {
+            Some(limiter) => {
+                let (admitted, notice) = limiter.admit(buf);
+                state.write(self.session, &buf[..admitted]);
+                if let Some(notice) = notice {
+                    state.write(self.session, notice.as_bytes());
+                }
+            }
+            None => state.write(self.session, buf),
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Locks the state, which stays consistent even if a thread panicked while
+/// holding the lock
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Implements the [`Builtin::Sessions`](crate::Builtin::Sessions) command
+pub(crate) fn sessions(arguments: &[String], ctx: &Context) -> Result<(), CommandError> {
+    let messages = ctx.messages();
+    let current = ctx.shell.session.borrow();
+    let current = current
+        .as_ref()
+        .ok_or_else(|| CommandError::Custom(messages.no_session_server.clone()))?;
+    let unknown = |id: &str| CommandError::Custom(format!("{}: {}", messages.unknown_session, id));
+    let parse = |id: &str| id.parse::<u64>().map_err(|_| unknown(id));
+
+    match arguments.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] | ["list"] => {
+            let lines: Vec<String> = lock(&current.state)
+                .sessions
+                .iter()
+                .map(|(id, session)| {
+                    let marker = if *id == current.session { "*" } else { " " };
+                    let status = match session.client {
+                        Some(_) => &messages.session_attached,
+                        None => &messages.session_detached,
+                    };
+                    format!("{} {}  {}", marker, id, status)
+                })
+                .collect();
+            for line in lines {
+                writeln!(ctx.out(), "{}", line)?;
+            }
+        }
+        ["attach", id] => {
+            let target = parse(id)?;
+            let mut state = lock(&current.state);
+            let session = state.sessions.get(&target).ok_or_else(|| unknown(id))?;
+            if session.client.is_some() {
+                return Err(CommandError::Custom(format!(
+                    "{}: {}",
+                    messages.session_in_use, id
+                )));
+            }
+            let Some(client) = state
+                .session_of(current.connection)
+                .and_then(|s| state.sessions.get_mut(&s))
+                .and_then(Session::detach)
+            else {
+                return Ok(());
+            };
+
+            let session = state.sessions.get_mut(&target).expect("the session exists");
+            let scrollback: Vec<u8> = session.scrollback.iter().copied().collect();
+            // The client may already be gone
+            client.send(&scrollback);
+            session.client = Some(client);
+            session.detached = None;
+        }
+        ["detach"] => {
+            let mut state = lock(&current.state);
+            let client = state
+                .sessions
+                .get_mut(&current.session)
+                .and_then(Session::detach);
+            if let Some(client) = client {
+                let _ = client.stream.shutdown(Shutdown::Both);
+            }
+        }
+        ["broadcast", ..] if arguments.len() > 1 => {
+            lock(&current.state).broadcast(&arguments[1..].join(" "));
+        }
+        ["close", id] => {
+            let target = parse(id)?;
+            let mut state = lock(&current.state);
+            match state.sessions.get(&target) {
+                None => return Err(unknown(id)),
+                Some(session) if session.client.is_some() => {
+                    return Err(CommandError::Custom(format!(
+                        "{}: {}",
+                        messages.session_in_use, id
+                    )))
+                }
+                Some(_) => {
+                    state.sessions.remove(&target);
+                }
+            }
+        }
+        _ => return Err(CommandError::Usage(ctx.messages().sessions_usage.clone())),
+    }
+    Ok(())
+}
@@ -0,0 +1,135 @@
+//! Commands that are defined as data in a TOML specification and bound to
+//! registered handler functions
+//!
+//! # Example
+//! ```rust
+//! # use std::io::Write;
+//! # use simpleshell::{spec::{self, Registry}, testing::TestShell, Shell};
+//! let registry = Registry::new().register("greet", |args, ctx| {
+//!     writeln!(ctx.out(), "Hello {}", args[0])?;
+//!     Ok(())
+//! });
+//! let commands = spec::commands(
+//!     r#"
+//!     [[command]]
+//!     name = "hello"
+//!     description = "Greets someone"
+//!     handler = "greet"
+//!     args = [{ name = "name", description = "who to greet" }]
+//!     examples = [{ invocation = "hello world", explanation = "Greets the world" }]
+//!     "#,
+//!     &registry,
+//! )?;
+//!
+//! let mut shell = TestShell::deterministic(Shell::new(None, commands));
+//! shell.run("hello world").expect_ok();
+//! assert_eq!(shell.output(), "Hello world\n");
+//! # Ok::<(), std::io::Error>(())
+//! ```
+use std::{collections::HashMap, fs, io, path::Path, rc::Rc, time::Duration};
+
+use serde::Deserialize;
+
+use crate::{ArgInfo, Command, CommandError, Context, Example};
+
+/// The handler functions commands in a specification can refer to by key
+#[derive(Default)]
+pub struct Registry {
+    handlers: HashMap<String, Rc<crate::CommandFn>>,
+}
+
+impl Registry {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the handler under the given key
+    pub fn register(
+        mut self,
+        key: &str,
+        handler: impl Fn(&[String], &Context) -> Result<(), CommandError> + 'static,
+    ) -> Self {
+        self.handlers.insert(key.to_owned(), Rc::new(handler));
+        self
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Spec {
+    #[serde(default)]
+    command: Vec<CommandSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CommandSpec {
+    name: String,
+    #[serde(default)]
+    description: String,
+    handler: String,
+    #[serde(default)]
+    args: Vec<ArgInfo>,
+    #[serde(default)]
+    examples: Vec<Example>,
+    #[serde(default)]
+    destructive: bool,
+    #[serde(default)]
+    non_reentrant: bool,
+    #[serde(default)]
+    expand_globs: bool,
+    cooldown_secs: Option<f64>,
+    version: Option<String>,
+}
+
+/// Creates the commands of the TOML specification
+///
+/// Every `[[command]]` table has a `name`, a `description`, the key of its
+/// `handler` in the registry and optionally its required `args`,
+/// `examples`, `cooldown_secs`, `version` and the flags `destructive`, `non_reentrant`
+/// and `expand_globs`. Invalid specifications and unknown handler keys are
+/// reported as [`io::ErrorKind::InvalidData`].
+pub fn commands(spec: &str, registry: &Registry) -> io::Result<Vec<Command>> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let spec: Spec = toml::from_str(spec).map_err(|e| invalid(e.to_string()))?;
+
+    spec.command
+        .into_iter()
+        .map(|spec| {
+            let handler = registry
+                .handlers
+                .get(&spec.handler)
+                .cloned()
+                .ok_or_else(|| {
+                    invalid(format!(
+                        "unknown handler '{}' of command '{}'",
+                        spec.handler, spec.name
+                    ))
+                })?;
+            let mut cmd = Command::new(&spec.name, &spec.description, move |args, ctx| {
+                handler(args, ctx)
+            });
+            for arg in &spec.args {
+                cmd = cmd.with_required_arg(&arg.name, &arg.description);
+            }
+            cmd.examples = spec.examples;
+            cmd.destructive = spec.destructive;
+            cmd.reentrant = !spec.non_reentrant;
+            cmd.expand_globs = spec.expand_globs;
+            cmd.version = spec.version;
+            if let Some(secs) = spec.cooldown_secs {
+                let cooldown =
+                    Duration::try_from_secs_f64(secs).map_err(|e| invalid(e.to_string()))?;
+                cmd = cmd.with_cooldown(cooldown);
+            }
+            Ok(cmd)
+        })
+        .collect()
+}
+
+/// Reads the specification from a file and creates its commands with
+/// [`commands`]
+pub fn commands_from_file(path: impl AsRef<Path>, registry: &Registry) -> io::Result<Vec<Command>> {
+    commands(&fs::read_to_string(path)?, registry)
+}
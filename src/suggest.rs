@@ -0,0 +1,30 @@
+//! Suggestions for misspelled values, see
+//! [`Command::with_choice_arg`](crate::Command::with_choice_arg)
+
+/// Returns the candidate that is closest to the value if it is close enough
+/// to be a likely misspelling
+pub(crate) fn closest<'a>(value: &str, candidates: &'a [String]) -> Option<&'a str> {
+    let max_distance = (value.chars().count() / 3).max(1);
+    candidates
+        .iter()
+        .map(|candidate| (distance(value, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.as_str())
+}
+
+/// Returns the number of characters that have to be inserted, removed or
+/// replaced to turn one text into the other
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(ca != *cb);
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
@@ -0,0 +1,177 @@
+//! Queries of the terminal the shell is running in
+
+/// Returns the width of the terminal in characters
+///
+/// Falls back to the `COLUMNS` environment variable if the size cannot be
+/// queried from the terminal.
+pub(crate) fn width() -> Option<usize> {
+    size()
+        .map(|(columns, _)| columns)
+        .or_else(|| std::env::var("COLUMNS").ok()?.parse().ok())
+}
+
+/// Returns the height of the terminal in lines
+///
+/// Falls back to the `LINES` environment variable if the size cannot be
+/// queried from the terminal.
+pub(crate) fn height() -> Option<usize> {
+    size()
+        .map(|(_, rows)| rows)
+        .or_else(|| std::env::var("LINES").ok()?.parse().ok())
+}
+
+/// Queries the number of columns and rows of the terminal
+fn size() -> Option<(usize, usize)> {
+    #[cfg(unix)]
+    {
+        let mut size = libc::winsize {
+            ws_row: 0,
+            ws_col: 0,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        // SAFETY: TIOCGWINSZ only writes into the provided winsize
+        let ret = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+        if ret == 0 && size.ws_col > 0 && size.ws_row > 0 {
+            return Some((size.ws_col as usize, size.ws_row as usize));
+        }
+    }
+    None
+}
+
+/// Changes how the terminal the input is read from handles typed keys until
+/// it is dropped
+///
+/// Nothing happens if the input is no terminal.
+pub(crate) struct InputMode {
+    #[cfg(unix)]
+    original: Option<libc::termios>,
+}
+
+impl InputMode {
+    /// Turns off the echo of the typed keys, e.g. for passwords
+    pub(crate) fn hidden() -> Self {
+        #[cfg(unix)]
+        return Self::change(|mode| mode.c_lflag &= !libc::ECHO);
+        #[cfg(not(unix))]
+        InputMode {}
+    }
+
+    /// Makes every key available to the shell as soon as it is pressed,
+    /// without echoing it
+    pub(crate) fn keystrokes() -> Self {
+        #[cfg(unix)]
+        return Self::change(|mode| {
+            mode.c_lflag &= !(libc::ECHO | libc::ICANON);
+            mode.c_cc[libc::VMIN] = 1;
+            mode.c_cc[libc::VTIME] = 0;
+        });
+        #[cfg(not(unix))]
+        InputMode {}
+    }
+
+    #[cfg(unix)]
+    fn change(f: impl FnOnce(&mut libc::termios)) -> Self {
+        // SAFETY: tcgetattr only writes into the provided termios, which is
+        // only used if the call succeeded
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return InputMode { original: None };
+        }
+        let mut changed = original;
+        f(&mut changed);
+        // SAFETY: the settings were read from the same terminal
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &changed) };
+        InputMode {
+            original: Some(original),
+        }
+    }
+}
+
+impl Drop for InputMode {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(original) = &self.original {
+            // SAFETY: restores the settings read in `InputMode::change`
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original) };
+        }
+    }
+}
+
+/// Waits up to `timeout` for input and returns `true` if there is some
+#[cfg(unix)]
+pub(crate) fn wait_for_input(timeout: std::time::Duration) -> bool {
+    let mut input = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let timeout = timeout.as_millis().min(i32::MAX as u128) as i32;
+    // SAFETY: poll only writes into the provided pollfd
+    let ready = unsafe { libc::poll(&mut input, 1, timeout) };
+    // Errors are left to the following read
+    ready != 0
+}
+
+/// Reads and discards the keys that have been pressed
+#[cfg(unix)]
+pub(crate) fn discard_keys() {
+    let mut keys = [0u8; 64];
+    // SAFETY: read writes at most the length of the buffer into it
+    unsafe { libc::read(libc::STDIN_FILENO, keys.as_mut_ptr().cast(), keys.len()) };
+}
+
+/// Set by the handler that [`InterruptGuard`] installs for `SIGINT`
+#[cfg(unix)]
+static INTERRUPTED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Catches `SIGINT` (e.g. Ctrl-C) instead of letting it terminate the
+/// process until it is dropped, see [`interrupted`]
+#[cfg(unix)]
+pub(crate) struct InterruptGuard {
+    previous: libc::sigaction,
+}
+
+#[cfg(unix)]
+impl InterruptGuard {
+    pub(crate) fn new() -> Self {
+        extern "C" fn handle(_: libc::c_int) {
+            INTERRUPTED.store(true, std::sync::atomic::Ordering::SeqCst);
+        }
+
+        // SAFETY: the handler only stores into an atomic and sigaction only
+        // writes the previous action into the provided struct
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            let mut previous: libc::sigaction = std::mem::zeroed();
+            libc::sigaction(libc::SIGINT, &action, &mut previous);
+            InterruptGuard { previous }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        // SAFETY: restores the action read in `InterruptGuard::new`
+        unsafe { libc::sigaction(libc::SIGINT, &self.previous, std::ptr::null_mut()) };
+    }
+}
+
+/// Returns `true` if `SIGINT` was caught since the last call of
+/// [`clear_interrupt`]
+pub(crate) fn interrupted() -> bool {
+    #[cfg(unix)]
+    return INTERRUPTED.load(std::sync::atomic::Ordering::SeqCst);
+    #[cfg(not(unix))]
+    false
+}
+
+/// Forgets that `SIGINT` was caught
+pub(crate) fn clear_interrupt() {
+    #[cfg(unix)]
+    INTERRUPTED.store(false, std::sync::atomic::Ordering::SeqCst);
+}
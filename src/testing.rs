@@ -0,0 +1,238 @@
+//! Utilities for testing command sets without a terminal
+//!
+//! # Example
+//! ```rust
+//! # use std::io::Write;
+//! # use simpleshell::{Command, CommandError, Shell};
+//! use simpleshell::testing::TestShell;
+//!
+//! let commands = vec![Command::new(
+//!     "version",
+//!     "Returns the version of the software",
+//!     |_, ctx| {
+//!         writeln!(ctx.out(), "v0.1.0")?;
+//!         Ok(())
+//!     },
+//! )];
+//!
+//! let mut shell = TestShell::deterministic(Shell::new(None, commands));
+//! shell.run("version").expect_ok();
+//! assert_eq!(shell.output(), "v0.1.0\n");
+//! shell.run("unknown").expect_error(CommandError::NotFound);
+//! ```
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    io::{self, BufReader, Read, Write},
+    rc::Rc,
+};
+
+use crate::{CommandError, ProcessOutcome, Shell};
+
+#[cfg(all(unix, feature = "pty-test"))]
+pub mod pty;
+
+/// A [`Shell`] that is fed with scripted input lines and captures everything
+/// it writes
+pub struct TestShell {
+    shell: Shell,
+    input: Rc<RefCell<VecDeque<u8>>>,
+    output: Rc<RefCell<Vec<u8>>>,
+    last_output: String,
+    last_outcome: Option<ProcessOutcome>,
+}
+
+impl TestShell {
+    /// Wraps the given shell and redirects its input and output
+    pub fn new(shell: Shell) -> Self {
+        let input = Rc::new(RefCell::new(VecDeque::new()));
+        let output = Rc::new(RefCell::new(Vec::new()));
+        TestShell {
+            shell: shell.with_io(
+                BufReader::new(SharedInput(input.clone())),
+                SharedOutput(output.clone()),
+            ),
+            input,
+            output,
+            last_output: String::new(),
+            last_outcome: None,
+        }
+    }
+
+    /// Wraps the given shell and enables its deterministic output mode
+    ///
+    /// Prompts, colors and timing information are left out and line endings
+    /// are normalized to `\n`, which keeps snapshot tests stable.
+    pub fn deterministic(shell: Shell) -> Self {
+        Self::new(shell.with_deterministic_output(true))
+    }
+
+    /// Returns the wrapped shell
+    pub fn shell(&self) -> &Shell {
+        &self.shell
+    }
+
+    /// Queues a line of input without processing it
+    ///
+    /// Queued lines are read by the next commands that ask the user for
+    /// additional input while they are executed (e.g. confirmations).
+    pub fn feed(&mut self, line: &str) -> &mut Self {
+        let mut input = self.input.borrow_mut();
+        input.extend(line.as_bytes());
+        input.push_back(b'\n');
+        drop(input);
+        self
+    }
+
+    /// Feeds the line to the shell and processes it
+    ///
+    /// The line is processed before any input queued with
+    /// [`TestShell::feed`].
+    pub fn run(&mut self, line: &str) -> &mut Self {
+        let mut input = self.input.borrow_mut();
+        input.push_front(b'\n');
+        line.bytes().rev().for_each(|b| input.push_front(b));
+        drop(input);
+        self.output.borrow_mut().clear();
+        // The input and output of a test shell are in memory and cannot fail
+        self.last_outcome = self.shell.process().ok();
+        self.last_output = String::from_utf8_lossy(&self.output.borrow()).into_owned();
+        if self.shell.deterministic_output {
+            self.last_output = self.last_output.replace("\r\n", "\n");
+        }
+        self
+    }
+
+    /// Runs every line of the script one after another
+    pub fn run_script(&mut self, script: &str) -> Vec<Result<(), CommandError>> {
+        script
+            .lines()
+            .map(|line| {
+                self.run(line);
+                self.result().cloned().expect("a line has been processed")
+            })
+            .collect()
+    }
+
+    /// Returns everything the shell has written while processing the last line
+    pub fn output(&self) -> &str {
+        &self.last_output
+    }
+
+    /// Returns the outcome of the last processed line
+    pub fn outcome(&self) -> Option<&ProcessOutcome> {
+        self.last_outcome.as_ref()
+    }
+
+    /// Returns the result of the last processed line
+    pub fn result(&self) -> Option<&Result<(), CommandError>> {
+        self.last_outcome.as_ref().map(|o| &o.result)
+    }
+
+    /// Asserts that the output of the last line contains `needle`
+    #[track_caller]
+    pub fn expect_output_contains(&self, needle: &str) -> &Self {
+        assert!(
+            self.last_output.contains(needle),
+            "expected output to contain {:?}, but got {:?}",
+            needle,
+            self.last_output
+        );
+        self
+    }
+
+    /// Asserts that the last line was processed successfully
+    #[track_caller]
+    pub fn expect_ok(&self) -> &Self {
+        assert_eq!(
+            self.result(),
+            Some(&Ok(())),
+            "expected the command to succeed"
+        );
+        self
+    }
+
+    /// Asserts that processing the last line failed with `error`
+    #[track_caller]
+    pub fn expect_error(&self, error: CommandError) -> &Self {
+        assert_eq!(
+            self.result(),
+            Some(&Err(error)),
+            "expected the command to fail"
+        );
+        self
+    }
+}
+
+struct SharedInput(Rc<RefCell<VecDeque<u8>>>);
+
+impl Read for SharedInput {
+    /// Reads at most one line, so that the shell never buffers input that is
+    /// queued for later commands
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut input = self.0.borrow_mut();
+        let mut read = 0;
+        while read < buf.len() {
+            match input.pop_front() {
+                Some(byte) => {
+                    buf[read] = byte;
+                    read += 1;
+                    if byte == b'\n' {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+        Ok(read)
+    }
+}
+
+struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The lines and the echo that the line editor of
+/// [`Shell::serve_websocket`] produced for recorded keystrokes, see
+/// [`replay_keystrokes`]
+#[cfg(feature = "websocket")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeystrokeReplay {
+    /// The completed lines
+    pub lines: Vec<String>,
+    /// Everything the line editor echoed to the terminal
+    pub echo: String,
+}
+
+/// Replays keystrokes recorded with [`Shell::record_keystrokes`] against the
+/// line editor of [`Shell::serve_websocket`]
+///
+/// # Example
+/// ```rust
+/// # use simpleshell::testing::replay_keystrokes;
+/// let path = std::env::temp_dir().join(format!("keys-{}.cast", std::process::id()));
+/// std::fs::write(
+///     &path,
+///     "{\"version\": 2, \"width\": 80, \"height\": 24}\n\
+///      [0.1, \"i\", \"ls 日本\"]\n\
+///      [0.2, \"i\", \"\\u007f\\rpwd\\r\"]\n",
+/// )?;
+///
+/// let replay = replay_keystrokes(&path)?;
+/// assert_eq!(replay.lines, ["ls 日", "pwd"]);
+/// # std::fs::remove_file(&path)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[cfg(feature = "websocket")]
+pub fn replay_keystrokes(path: impl AsRef<std::path::Path>) -> io::Result<KeystrokeReplay> {
+    let (lines, echo) = crate::websocket::replay(path.as_ref())?;
+    Ok(KeystrokeReplay { lines, echo })
+}
@@ -0,0 +1,231 @@
+//! End-to-end testing of shell binaries under a pseudo-terminal
+//!
+//! # Example
+//! ```rust,no_run
+//! use std::{process::Command, time::Duration};
+//! use simpleshell::testing::pty::{Key, PtySession};
+//!
+//! let mut session = PtySession::spawn(Command::new("target/debug/myapp")).unwrap();
+//! session.expect("cmdshell> ", Duration::from_secs(5)).unwrap();
+//! session.send("version").unwrap();
+//! session.send_key(Key::Enter).unwrap();
+//! session.expect("v0.1.0", Duration::from_secs(5)).unwrap();
+//! ```
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::process::CommandExt,
+    },
+    process::{Child, Command, Stdio},
+    ptr,
+    time::{Duration, Instant},
+};
+
+/// A key that can be sent to the pseudo-terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Enter,
+    Tab,
+    Backspace,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    /// The given character pressed together with `Ctrl`, e.g. `Ctrl('c')`
+    ///
+    /// Only ASCII letters and ``@[\]^_`` have a control code, sending any
+    /// other character panics.
+    Ctrl(char),
+}
+
+impl Key {
+    /// The bytes a terminal sends for this key
+    fn sequence(&self) -> Vec<u8> {
+        match self {
+            Self::Enter => b"\r".to_vec(),
+            Self::Tab => b"\t".to_vec(),
+            Self::Backspace => b"\x7f".to_vec(),
+            Self::Escape => b"\x1b".to_vec(),
+            Self::Up => b"\x1b[A".to_vec(),
+            Self::Down => b"\x1b[B".to_vec(),
+            Self::Right => b"\x1b[C".to_vec(),
+            Self::Left => b"\x1b[D".to_vec(),
+            Self::Home => b"\x1b[H".to_vec(),
+            Self::End => b"\x1b[F".to_vec(),
+            Self::Ctrl(c) => {
+                assert!(
+                    c.is_ascii_alphabetic() || "@[\\]^_".contains(*c),
+                    "Ctrl({:?}) has no control code",
+                    c
+                );
+                vec![(c.to_ascii_lowercase() as u8) & 0x1f]
+            }
+        }
+    }
+}
+
+/// A process that runs under a pseudo-terminal and is driven by keystrokes
+pub struct PtySession {
+    child: Child,
+    master: File,
+    output: Vec<u8>,
+    consumed: usize,
+}
+
+impl PtySession {
+    /// Spawns the command with a pseudo-terminal of 80x24 characters as its
+    /// controlling terminal and standard streams
+    pub fn spawn(mut command: Command) -> io::Result<Self> {
+        let (mut master, mut slave) = (0, 0);
+        let mut size = libc::winsize {
+            ws_row: 24,
+            ws_col: 80,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        // SAFETY: all pointers are either null or point to valid locals
+        let ret = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::addr_of_mut!(size),
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: openpty succeeded, so both descriptors are open and owned by us
+        let (master, slave) =
+            unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+
+        command
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave));
+        // SAFETY: only async-signal-safe functions are called after the fork
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let child = command.spawn()?;
+
+        Ok(PtySession {
+            child,
+            master: File::from(master),
+            output: Vec::new(),
+            consumed: 0,
+        })
+    }
+
+    /// Types the given text
+    pub fn send(&mut self, text: &str) -> io::Result<()> {
+        self.master.write_all(text.as_bytes())?;
+        self.master.flush()
+    }
+
+    /// Types the given line and presses enter
+    pub fn send_line(&mut self, line: &str) -> io::Result<()> {
+        self.send(line)?;
+        self.send_key(Key::Enter)
+    }
+
+    /// Presses the given key
+    pub fn send_key(&mut self, key: Key) -> io::Result<()> {
+        self.master.write_all(&key.sequence())?;
+        self.master.flush()
+    }
+
+    /// Waits until the terminal output contains `needle`
+    ///
+    /// Returns everything that was printed since the previous successful
+    /// `expect` up to and including `needle`. Fails with
+    /// [`io::ErrorKind::TimedOut`] if `needle` did not show up in time.
+    pub fn expect(&mut self, needle: &str, timeout: Duration) -> io::Result<String> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let pending = &self.output[self.consumed..];
+            if let Some(pos) = find(pending, needle.as_bytes()) {
+                let end = pos + needle.len();
+                let matched = String::from_utf8_lossy(&pending[..end]).into_owned();
+                self.consumed += end;
+                return Ok(matched);
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() || !self.read_available(remaining)? {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!(
+                        "{:?} did not appear in the output {:?}",
+                        needle,
+                        String::from_utf8_lossy(&self.output[self.consumed..])
+                    ),
+                ));
+            }
+        }
+    }
+
+    /// Returns everything the process has printed so far
+    pub fn output(&self) -> String {
+        String::from_utf8_lossy(&self.output).into_owned()
+    }
+
+    /// Waits up to `timeout` for output and appends it to the buffer
+    ///
+    /// Returns `false` if the process closed the terminal.
+    fn read_available(&mut self, timeout: Duration) -> io::Result<bool> {
+        let mut fd = libc::pollfd {
+            fd: self.master.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let millis = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        // SAFETY: `fd` is a valid pollfd for the duration of the call
+        if unsafe { libc::poll(&mut fd, 1, millis) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        if fd.revents == 0 {
+            return Ok(true);
+        }
+
+        let mut buf = [0; 4096];
+        match self.master.read(&mut buf) {
+            Ok(0) => Ok(false),
+            Ok(n) => {
+                self.output.extend_from_slice(&buf[..n]);
+                Ok(true)
+            }
+            // Linux reports EIO once the other side of the terminal is closed
+            Err(e) if e.raw_os_error() == Some(libc::EIO) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Returns the position of the first occurrence of `needle` in `haystack`
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(0);
+    }
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+impl Drop for PtySession {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,270 @@
+//! Recording of everything that is shown on the screen of a shell session
+//!
+//! Transcripts are written in the [asciicast v2] format, so they can be played
+//! back with `asciinema play` as well as with
+//! [`Shell::replay`](crate::Shell::replay).
+//!
+//! [asciicast v2]: https://docs.asciinema.org/manual/asciicast/v2/
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{ansi, terminal};
+
+/// The kind of a recorded event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EventKind {
+    Output,
+    Input,
+}
+
+/// A transcript file that is being recorded
+pub(crate) struct Transcript {
+    file: BufWriter<File>,
+    started: Instant,
+    strip_ansi: bool,
+}
+
+impl Transcript {
+    /// Creates the transcript file and writes its header
+    pub(crate) fn create(path: &Path, strip_ansi: bool) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        writeln!(
+            file,
+            r#"{{"version": 2, "width": {}, "height": 24, "timestamp": {}}}"#,
+            terminal::width().unwrap_or(80),
+            timestamp
+        )?;
+        file.flush()?;
+
+        Ok(Transcript {
+            file,
+            started: Instant::now(),
+            strip_ansi,
+        })
+    }
+
+    /// Appends an event with the time elapsed since the start of the recording
+    pub(crate) fn record(&mut self, kind: EventKind, data: &str) -> io::Result<()> {
+        let data = if self.strip_ansi {
+            ansi::strip(data)
+        } else {
+            data.to_string()
+        };
+        let code = match kind {
+            EventKind::Output => "o",
+            EventKind::Input => "i",
+        };
+        writeln!(
+            self.file,
+            "[{:.6}, \"{}\", \"{}\"]",
+            self.started.elapsed().as_secs_f64(),
+            code,
+            escape_json(&data)
+        )?;
+        self.file.flush()
+    }
+}
+
+/// A recorded event with the seconds since the start of the recording
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Event {
+    pub(crate) time: f64,
+    pub(crate) kind: Option<EventKind>,
+    pub(crate) data: String,
+}
+
+/// Parses an event line of an asciicast v2 file
+///
+/// Events of unknown kinds are returned with `kind` set to `None`.
+pub(crate) fn parse_event(line: &str) -> Option<Event> {
+    let inner = line.trim().strip_prefix('[')?.strip_suffix(']')?;
+    let (time, rest) = inner.split_once(',')?;
+    let (code, rest) = parse_json_string(rest.trim_start())?;
+    let (data, rest) = parse_json_string(rest.trim_start().strip_prefix(',')?.trim_start())?;
+    if !rest.trim().is_empty() {
+        return None;
+    }
+
+    let kind = match code.as_str() {
+        "o" => Some(EventKind::Output),
+        "i" => Some(EventKind::Input),
+        _ => None,
+    };
+    Some(Event {
+        time: time.trim().parse().ok()?,
+        kind,
+        data,
+    })
+}
+
+/// Parses a JSON string at the start of `text` and returns it together with
+/// the remaining text
+pub(crate) fn parse_json_string(text: &str) -> Option<(String, &str)> {
+    let mut chars = text.strip_prefix('"')?.char_indices();
+    let mut parsed = String::new();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((parsed, &text[i + 2..])),
+            '\\' => match chars.next()?.1 {
+                'n' => parsed.push('\n'),
+                'r' => parsed.push('\r'),
+                't' => parsed.push('\t'),
+                'b' => parsed.push('\x08'),
+                'f' => parsed.push('\x0c'),
+                'u' => {
+                    let mut code = parse_hex(&mut chars)?;
+                    // Characters outside the BMP are encoded as surrogate pairs
+                    if (0xd800..0xdc00).contains(&code) {
+                        if chars.next()?.1 != '\\' || chars.next()?.1 != 'u' {
+                            return None;
+                        }
+                        let low = parse_hex(&mut chars)?;
+                        if !(0xdc00..0xe000).contains(&low) {
+                            return None;
+                        }
+                        code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                    }
+                    parsed.push(char::from_u32(code)?);
+                }
+                c => parsed.push(c),
+            },
+            c => parsed.push(c),
+        }
+    }
+    None
+}
+
+fn parse_hex(chars: &mut std::str::CharIndices) -> Option<u32> {
+    let digits: String = chars.take(4).map(|(_, c)| c).collect();
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(&digits, 16).ok()
+}
+
+/// Escapes the text for use inside a JSON string
+pub(crate) fn escape_json(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_json_strings() {
+        assert_eq!(escape_json("a\"b\\c"), r#"a\"b\\c"#);
+        assert_eq!(escape_json("\r\n\t\x1b[0m"), r"\r\n\t\u001b[0m");
+        assert_eq!(escape_json("ä😀"), "ä😀");
+    }
+
+    #[test]
+    fn parses_json_strings() {
+        assert_eq!(
+            parse_json_string(r#""a\"b" rest"#),
+            Some(("a\"b".to_string(), " rest"))
+        );
+        assert_eq!(
+            parse_json_string(r#""ä😀\/\b\f""#),
+            Some(("ä😀/\x08\x0c".to_string(), ""))
+        );
+        assert_eq!(parse_json_string(r#""ä" x"#), Some(("ä".to_string(), " x")));
+    }
+
+    #[test]
+    fn rejects_invalid_json_strings() {
+        assert_eq!(parse_json_string("abc"), None);
+        assert_eq!(parse_json_string(r#""abc"#), None);
+        assert_eq!(parse_json_string(r#""abc\"#), None);
+        assert_eq!(parse_json_string(r#""\u12""#), None);
+        assert_eq!(parse_json_string(r#""\u+123""#), None);
+        assert_eq!(parse_json_string(r#""\ud83d""#), None);
+        assert_eq!(parse_json_string(r#""\ud83dA""#), None);
+        assert_eq!(parse_json_string(r#""\ude00""#), None);
+    }
+
+    #[test]
+    fn escaped_strings_parse_back() {
+        let text = "line 1\r\n\"quoted\" \\ \x07 ä😀";
+        let quoted = format!("\"{}\"", escape_json(text));
+        assert_eq!(parse_json_string(&quoted), Some((text.to_string(), "")));
+    }
+
+    #[test]
+    fn parses_events() {
+        assert_eq!(
+            parse_event(r#"[0.5, "o", "hi\r\n"]"#),
+            Some(Event {
+                time: 0.5,
+                kind: Some(EventKind::Output),
+                data: "hi\r\n".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_event(r#"[1.25,"i","x"]"#).and_then(|e| e.kind),
+            Some(EventKind::Input)
+        );
+        assert_eq!(
+            parse_event(r#"[2, "r", "80x24"]"#).map(|e| e.kind),
+            Some(None)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_events() {
+        assert_eq!(parse_event(r#"{"version": 2}"#), None);
+        assert_eq!(parse_event(r#"[x, "o", "a"]"#), None);
+        assert_eq!(parse_event(r#"[1, "o"]"#), None);
+        assert_eq!(parse_event(r#"[1, "o", "a", "b"]"#), None);
+        assert_eq!(parse_event(r#"[1, "o", "a]"#), None);
+    }
+
+    #[test]
+    fn records_events() {
+        let path = std::env::temp_dir().join(format!(
+            "simpleshell-transcript-{}.cast",
+            std::process::id()
+        ));
+        let mut transcript = Transcript::create(&path, true).unwrap();
+        transcript
+            .record(EventKind::Output, "\x1b[1mcmdshell>\x1b[0m ")
+            .unwrap();
+        transcript.record(EventKind::Input, "say \"hi\"\n").unwrap();
+        drop(transcript);
+
+        let recorded = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut lines = recorded.lines();
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with(r#"{"version": 2, "width": "#));
+        let events: Vec<Event> = lines.map(|l| parse_event(l).unwrap()).collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].kind, Some(EventKind::Output));
+        assert_eq!(events[0].data, "cmdshell> ");
+        assert_eq!(events[1].kind, Some(EventKind::Input));
+        assert_eq!(events[1].data, "say \"hi\"\n");
+        assert!(events[0].time <= events[1].time);
+    }
+}
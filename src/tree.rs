@@ -0,0 +1,102 @@
+//! Rendering of hierarchies, see [`Context::print_tree`](crate::Context::print_tree)
+
+/// A node of a tree that is printed with [`Context::print_tree`](crate::Context::print_tree)
+///
+/// # Example
+/// ```rust
+/// # use simpleshell::{testing::TestShell, Command, Shell, TreeNode};
+/// let commands = vec![Command::new("config", "Shows the configuration", |_, ctx| {
+///     let root = TreeNode::new("config")
+///         .with_child(TreeNode::new("server").with_child(TreeNode::new("port = 80")))
+///         .with_child(TreeNode::new("log = info"));
+///     ctx.print_tree(&root)?;
+///     Ok(())
+/// })];
+///
+/// let mut shell = TestShell::deterministic(Shell::new(None, commands));
+/// shell.run("config").expect_ok();
+/// assert_eq!(
+///     shell.output(),
+///     "config\n├── server\n│   └── port = 80\n└── log = info\n"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeNode {
+    /// The text that is shown for the node
+    pub label: String,
+    /// The nodes below this node
+    pub children: Vec<TreeNode>,
+}
+
+impl TreeNode {
+    /// Creates a node without children
+    pub fn new(label: &str) -> Self {
+        TreeNode {
+            label: label.to_string(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Adds a node below this node
+    pub fn with_child(mut self, child: TreeNode) -> Self {
+        self.children.push(child);
+        self
+    }
+}
+
+/// The characters the branches of a tree are drawn with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TreeStyle {
+    /// Box drawing characters, e.g. `├──`
+    #[default]
+    Unicode,
+    /// Plain ASCII characters for terminals without unicode support, e.g.
+    /// `|--`
+    Ascii,
+}
+
+impl TreeStyle {
+    /// Returns the prefixes of a child, a last child and the lines below
+    /// them
+    fn branches(self) -> [&'static str; 4] {
+        match self {
+            Self::Unicode => ["├── ", "└── ", "│   ", "    "],
+            Self::Ascii => ["|-- ", "`-- ", "|   ", "    "],
+        }
+    }
+}
+
+/// Renders the node and all nodes below it into lines
+pub(crate) fn render(root: &TreeNode, style: TreeStyle) -> Vec<String> {
+    let mut lines: Vec<String> = root.label.lines().map(str::to_string).collect();
+    if lines.is_empty() {
+        lines.push(String::new());
+    }
+    render_children(root, "", style, &mut lines);
+    lines
+}
+
+fn render_children(node: &TreeNode, prefix: &str, style: TreeStyle, lines: &mut Vec<String>) {
+    let [branch, last_branch, below, below_last] = style.branches();
+    let count = node.children.len();
+    for (i, child) in node.children.iter().enumerate() {
+        let (branch, below) = if i + 1 == count {
+            (last_branch, below_last)
+        } else {
+            (branch, below)
+        };
+        let indent = format!("{}{}", prefix, below);
+        let mut label = child.label.lines();
+        lines.push(format!(
+            "{}{}{}",
+            prefix,
+            branch,
+            label.next().unwrap_or_default()
+        ));
+        // Further lines of the label are aligned with its first line
+        for line in label {
+            lines.push(format!("{}{}", indent, line).trim_end().to_string());
+        }
+        render_children(child, &indent, style, lines);
+    }
+}
@@ -0,0 +1,93 @@
+//! Guided walkthroughs that teach users the commands of the shell
+use std::fmt::Debug;
+
+use crate::ProcessOutcome;
+
+/// Decides whether the user completed a step of a [`Tutorial`]
+pub type StepValidator = dyn Fn(&ProcessOutcome) -> bool;
+
+/// A sequence of steps the `tutorial` builtin guides the user through
+///
+/// # Example
+/// ```rust
+/// # use simpleshell::{testing::TestShell, Builtin, Command, Shell, Tutorial};
+/// let commands = vec![
+///     Command::new("status", "Shows the status", |_, _| Ok(())),
+///     Command::new("deploy", "Deploys a service", |_, _| Ok(())),
+/// ];
+/// let tutorial = Tutorial::new()
+///     .step("Check the status of the system with `status`", "status")
+///     .step_with("Deploy the api with `deploy api`", "deploy", |outcome| {
+///         outcome.is_ok() && outcome.arguments == ["api"]
+///     });
+/// let shell = Shell::new(None, commands)
+///     .with_builtin(Builtin::Tutorial)
+///     .with_tutorial(tutorial);
+///
+/// let mut shell = TestShell::deterministic(shell);
+/// shell.feed("status").feed("deploy web").feed("deploy api");
+/// shell.run("tutorial").expect_ok().expect_output_contains("Step 2/2");
+/// assert_eq!(shell.shell().tutorial_progress(), Some((2, 2)));
+/// ```
+#[derive(Default)]
+pub struct Tutorial {
+    pub(crate) steps: Vec<Step>,
+}
+
+/// A single step of a [`Tutorial`]
+pub(crate) struct Step {
+    pub(crate) instruction: String,
+    pub(crate) validate: Box<StepValidator>,
+}
+
+impl Tutorial {
+    /// Creates a tutorial without steps
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a step that is completed by running `command` successfully
+    ///
+    /// # Arguments
+    /// * `instruction` - Explains the user what to do
+    /// * `command` - The name of the command the user has to run
+    pub fn step(self, instruction: &str, command: &str) -> Self {
+        self.step_with(instruction, command, ProcessOutcome::is_ok)
+    }
+
+    /// Adds a step that is completed by running `command` with an outcome
+    /// that is accepted by `validate`
+    pub fn step_with(
+        mut self,
+        instruction: &str,
+        command: &str,
+        validate: impl Fn(&ProcessOutcome) -> bool + 'static,
+    ) -> Self {
+        let command = command.to_owned();
+        self.steps.push(Step {
+            instruction: instruction.to_owned(),
+            validate: Box::new(move |outcome| {
+                outcome.command.as_ref() == Some(&command) && validate(outcome)
+            }),
+        });
+        self
+    }
+
+    /// Returns the number of steps
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Returns `true` if the tutorial has no steps
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+impl Debug for Tutorial {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tutorial")
+            .field("steps", &self.steps.len())
+            .finish()
+    }
+}
@@ -0,0 +1,12 @@
+//! Undo actions that destructive commands can record
+
+use crate::{CommandError, Context};
+
+/// The signature of an action that reverts the effect of a command
+pub type UndoFn = dyn FnOnce(&Context) -> Result<(), CommandError>;
+
+/// An action recorded with [`Context::push_undo`]
+pub(crate) struct UndoEntry {
+    pub(crate) description: String,
+    pub(crate) action: Box<UndoFn>,
+}
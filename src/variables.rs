@@ -0,0 +1,62 @@
+//! Persistence of variables across restarts of the shell
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use crate::transcript::{escape_json, parse_json_string};
+
+/// Reads the variables from a file that contains a JSON object with string
+/// values
+///
+/// A missing file contains no variables.
+pub(crate) fn load(path: &Path) -> io::Result<BTreeMap<String, String>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e),
+    };
+    parse(&content).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is no JSON object of strings", path.display()),
+        )
+    })
+}
+
+fn parse(content: &str) -> Option<BTreeMap<String, String>> {
+    let mut variables = BTreeMap::new();
+    let mut rest = content.trim().strip_prefix('{')?.trim_start();
+    if let Some(end) = rest.strip_prefix('}') {
+        return end.trim().is_empty().then_some(variables);
+    }
+    loop {
+        let (name, after) = parse_json_string(rest)?;
+        let (value, after) = parse_json_string(after.trim_start().strip_prefix(':')?.trim_start())?;
+        variables.insert(name, value);
+        let after = after.trim_start();
+        if let Some(end) = after.strip_prefix('}') {
+            return end.trim().is_empty().then_some(variables);
+        }
+        rest = after.strip_prefix(',')?.trim_start();
+    }
+}
+
+/// Writes the variables to the file as a JSON object
+pub(crate) fn store(path: &Path, variables: &BTreeMap<String, String>) -> io::Result<()> {
+    let entries = variables
+        .iter()
+        .map(|(name, value)| format!("  \"{}\": \"{}\"", escape_json(name), escape_json(value)))
+        .collect::<Vec<_>>();
+    if entries.is_empty() {
+        return fs::write(path, "{}\n");
+    }
+    fs::write(path, format!("{{\n{}\n}}\n", entries.join(",\n")))
+}
+
+/// Splits a `NAME=value` token into the name and the value
+///
+/// Returns `None` if the part before the `=` is not a valid variable name.
+pub(crate) fn assignment(token: &str) -> Option<(&str, &str)> {
+    let (name, value) = token.split_once('=')?;
+    let valid = name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+    valid.then_some((name, value))
+}
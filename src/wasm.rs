@@ -0,0 +1,169 @@
+//! Commands that are provided by sandboxed WebAssembly modules
+//!
+//! A plugin module has no access to the host besides the functions it
+//! imports from `env`:
+//! * `write(ptr: i32, len: i32)` - writes the bytes to the output of the shell
+//! * `error(ptr: i32, len: i32)` - sets the error message of the running
+//!   command
+//!
+//! and has to export:
+//! * `memory` - its linear memory
+//! * `alloc(len: i32) -> i32` - allocates `len` bytes for data the host
+//!   passes to the module
+//! * `manifest() -> i64` - returns the location of its manifest as
+//!   `ptr << 32 | len`. The manifest is UTF-8 text with one command per line
+//!   in the form `<name>\t<description>`
+//! * `execute(name_ptr, name_len, args_ptr, args_len) -> i32` - executes the
+//!   command with the arguments, which are separated by NUL bytes. All
+//!   parameters are `i32`. Returns `0` if the command succeeded
+//!
+//! Every invocation may use a limited amount of fuel, so plugins cannot hang
+//! the shell.
+use std::{cell::RefCell, io, path::Path, rc::Rc};
+
+use wasmtime::{Caller, Config, Engine, Linker, Memory, Module, Store, TypedFunc};
+
+use crate::{Command, CommandError};
+
+/// The amount of fuel a single invocation of a plugin command may consume
+const FUEL: u64 = 1_000_000_000;
+
+#[derive(Default)]
+struct HostState {
+    output: Vec<u8>,
+    error: Option<String>,
+}
+
+struct Plugin {
+    store: Store<HostState>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    execute: TypedFunc<(i32, i32, i32, i32), i32>,
+}
+
+impl Plugin {
+    /// Copies the data into the memory of the module
+    fn pass(&mut self, data: &[u8]) -> wasmtime::Result<(i32, i32)> {
+        let len = i32::try_from(data.len())?;
+        let ptr = self.alloc.call(&mut self.store, len)?;
+        self.memory
+            .write(&mut self.store, usize::try_from(ptr)?, data)?;
+        Ok((ptr, len))
+    }
+
+    /// Executes the command and returns its output
+    fn run(&mut self, name: &str, arguments: &[String]) -> (Vec<u8>, Result<(), CommandError>) {
+        *self.store.data_mut() = HostState::default();
+        let status = self.store.set_fuel(FUEL).and_then(|_| {
+            let (name_ptr, name_len) = self.pass(name.as_bytes())?;
+            let (args_ptr, args_len) = self.pass(arguments.join("\0").as_bytes())?;
+            let args = (name_ptr, name_len, args_ptr, args_len);
+            self.execute.call(&mut self.store, args)
+        });
+
+        let state = std::mem::take(self.store.data_mut());
+        let result = match status {
+            Ok(0) => Ok(()),
+            Ok(_) => Err(state
+                .error
+                .map(CommandError::Custom)
+                .unwrap_or(CommandError::ExecutionError)),
+            Err(e) => Err(CommandError::Custom(e.to_string())),
+        };
+        (state.output, result)
+    }
+}
+
+/// Reads `len` bytes at `ptr` from the memory of the calling module
+fn read(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32) -> wasmtime::Result<Vec<u8>> {
+    let memory = caller
+        .get_export("memory")
+        .and_then(|e| e.into_memory())
+        .ok_or_else(|| wasmtime::Error::msg("the plugin exports no memory"))?;
+    let start = usize::try_from(ptr)?;
+    let end = start + usize::try_from(len)?;
+    memory
+        .data(&caller)
+        .get(start..end)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| wasmtime::Error::msg("out of bounds memory access"))
+}
+
+/// Instantiates the module and returns the commands of its manifest
+pub(crate) fn load(path: &Path) -> io::Result<Vec<Command>> {
+    instantiate(path).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+    })
+}
+
+fn instantiate(path: &Path) -> wasmtime::Result<Vec<Command>> {
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    let engine = Engine::new(&config)?;
+    let module = Module::from_file(&engine, path)?;
+
+    let mut linker = Linker::new(&engine);
+    linker.func_wrap(
+        "env",
+        "write",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+            let data = read(&mut caller, ptr, len)?;
+            caller.data_mut().output.extend(data);
+            wasmtime::Result::<()>::Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        "env",
+        "error",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+            let data = read(&mut caller, ptr, len)?;
+            caller.data_mut().error = Some(String::from_utf8_lossy(&data).into_owned());
+            wasmtime::Result::<()>::Ok(())
+        },
+    )?;
+
+    let mut store = Store::new(&engine, HostState::default());
+    store.set_fuel(FUEL)?;
+    let instance = linker.instantiate(&mut store, &module)?;
+    let memory = instance
+        .get_memory(&mut store, "memory")
+        .ok_or_else(|| wasmtime::Error::msg("the plugin exports no memory"))?;
+    let alloc = instance.get_typed_func(&mut store, "alloc")?;
+    let execute = instance.get_typed_func(&mut store, "execute")?;
+
+    let location = instance
+        .get_typed_func::<(), i64>(&mut store, "manifest")?
+        .call(&mut store, ())?;
+    let (ptr, len) = (location >> 32, location & 0xffff_ffff);
+    let start = usize::try_from(ptr)?;
+    let manifest = memory
+        .data(&store)
+        .get(start..start + usize::try_from(len)?)
+        .ok_or_else(|| wasmtime::Error::msg("the manifest is out of bounds"))?;
+    let manifest = String::from_utf8(manifest.to_vec())?;
+
+    let plugin = Rc::new(RefCell::new(Plugin {
+        store,
+        memory,
+        alloc,
+        execute,
+    }));
+    let commands = manifest
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (name, description) = line.split_once('\t').unwrap_or((line, ""));
+            let plugin = plugin.clone();
+            let command = name.to_string();
+            Command::new(name, description, move |arguments, ctx| {
+                let (output, result) = plugin.borrow_mut().run(&command, arguments);
+                io::Write::write_all(&mut ctx.out(), &output)?;
+                result
+            })
+        })
+        .collect();
+    Ok(commands)
+}
@@ -0,0 +1,132 @@
+//! Repeated execution of a command, see [`Builtin::Watch`](crate::Builtin::Watch)
+use std::io::Write;
+#[cfg(unix)]
+use std::time::Duration;
+
+#[cfg(unix)]
+use crate::{ansi, terminal};
+use crate::{schedule::parse_interval, CommandError, Context, Shell};
+
+/// Runs the `watch` builtin
+pub(crate) fn watch(arguments: &[String], ctx: &Context) -> Result<(), CommandError> {
+    let (diff, arguments) = match arguments {
+        [flag, rest @ ..] if flag == "--diff" => (true, rest),
+        _ => (false, arguments),
+    };
+    let usage = || CommandError::Usage(ctx.messages().watch_usage.clone());
+    let [interval, command @ ..] = arguments else {
+        return Err(usage());
+    };
+    #[cfg_attr(not(unix), allow(unused_variables))]
+    let interval_value = parse_interval(interval).ok_or_else(usage)?;
+    if command.is_empty() {
+        return Err(usage());
+    }
+    let shell = ctx.shell;
+    if !shell.knows_command(&command[0]) {
+        return Err(CommandError::NotFound);
+    }
+
+    let header = format!(
+        "{} {}: {}",
+        ctx.messages().watch_header,
+        interval,
+        command.join(" ")
+    );
+    // The changes are shown relative to the previous `watch` of the command,
+    // even if it was stopped in between
+    let key = diff.then(|| format!("watch {}", command.join(" ")));
+    #[cfg(unix)]
+    if shell.terminal.get() {
+        return watch_terminal(ctx, &header, interval_value, command, key.as_deref());
+    }
+    // Without a terminal a pressed key cannot be noticed, so the command only
+    // runs once
+    writeln!(ctx.out(), "{}", header)?;
+    let Some(key) = key else {
+        return shell.dispatch(command.to_vec()).result;
+    };
+    shell.output.borrow_mut().release()?;
+    let (run, result) = run_buffered(shell, command);
+    write!(ctx.out(), "{}", ctx.changes_since_previous(&key, &run))?;
+    result
+}
+
+/// Runs the command and returns its output instead of printing it
+fn run_buffered(shell: &Shell, command: &[String]) -> (String, Result<(), CommandError>) {
+    shell.output.borrow_mut().start_buffering();
+    let result = shell.dispatch(command.to_vec()).result;
+    let run = shell.output.borrow_mut().take_buffer().unwrap_or_default();
+    (String::from_utf8_lossy(&run).into_owned(), result)
+}
+
+/// Runs the command until a key is pressed and draws its output over the
+/// output of the previous run
+///
+/// With accessible output the runs are appended instead, leaving out the
+/// ones whose output did not change. With a key, the lines that changed
+/// since the previous run are marked.
+#[cfg(unix)]
+fn watch_terminal(
+    ctx: &Context,
+    header: &str,
+    interval: Duration,
+    command: &[String],
+    diff_key: Option<&str>,
+) -> Result<(), CommandError> {
+    let shell = ctx.shell;
+    let _mode = terminal::InputMode::keystrokes();
+    let width = terminal::width().unwrap_or(80).max(1);
+    shell.output.borrow_mut().release()?;
+
+    let mut shown = 0;
+    let mut previous = None;
+    loop {
+        let (mut run, result) = run_buffered(shell, command);
+        if let Err(e) = result {
+            run.push_str(&shell.render_error(&e));
+        }
+        if !run.is_empty() && !run.ends_with('\n') {
+            run.push('\n');
+        }
+        let unchanged = previous.as_ref() == Some(&run);
+        previous = Some(run.clone());
+        if let Some(key) = diff_key {
+            run = ctx.changes_since_previous(key, &run);
+        }
+
+        let mut output = shell.output.borrow_mut();
+        if shell.accessible_output {
+            if !unchanged {
+                writeln!(output, "{}\n", header)?;
+                write!(output, "{}", run)?;
+                output.flush()?;
+            }
+        } else {
+            if shown > 0 {
+                // Move to the header of the previous run and clear everything
+                // below it
+                write!(output, "\x1b[{}A\r\x1b[J", shown)?;
+            }
+            writeln!(output, "{}\n", header)?;
+            write!(output, "{}", run)?;
+            output.flush()?;
+            shown = rows(header, width) + 1 + rows(&run, width);
+        }
+        drop(output);
+
+        if terminal::wait_for_input(interval) {
+            terminal::discard_keys();
+            return Ok(());
+        }
+    }
+}
+
+/// Returns the number of rows the text takes up on a terminal of the width
+#[cfg(unix)]
+fn rows(text: &str, width: usize) -> usize {
+    ansi::strip(text)
+        .lines()
+        .map(|line| line.chars().count().max(1).div_ceil(width))
+        .sum()
+}
@@ -0,0 +1,325 @@
+//! Adapters that connect a shell to a WebSocket, see
+//! [`Shell::serve_websocket`](crate::Shell::serve_websocket)
+use std::{
+    cell::{Cell, RefCell},
+    io::{self, BufRead, Read, Write},
+    path::Path,
+    rc::Rc,
+};
+
+use tungstenite::{Message, WebSocket};
+use unicode_width::UnicodeWidthChar;
+
+use crate::{
+    completion::{CompleterSlot, SessionCompleter},
+    editor::{Edit, LineEditor},
+    transcript::{self, EventKind, Transcript},
+};
+
+type Socket<S> = Rc<RefCell<WebSocket<S>>>;
+
+/// Assembles the keystrokes a terminal emulator sends into lines with a
+/// [`LineEditor`]
+pub(crate) struct Input<S: Read + Write> {
+    socket: Socket<S>,
+    editor: LineEditor,
+    /// Whether the editor has started the line that is being edited
+    editing: bool,
+    /// The column the output left the cursor of the terminal in
+    column: Rc<Cell<usize>>,
+    /// An incomplete UTF-8 character at the end of a binary message
+    partial: Vec<u8>,
+    recorder: Option<Rc<RefCell<Recorder>>>,
+    ready: Vec<u8>,
+    position: usize,
+}
+
+impl<S: Read + Write> Input<S> {
+    /// Applies the next keys and returns `false` if the socket was closed
+    ///
+    /// A new line starts with the keys the client sent after the previous
+    /// one, so that several lines in one message are all run.
+    fn receive(&mut self) -> io::Result<bool> {
+        let mut echo = String::new();
+        let edit = if self.editor.is_completing() {
+            // The keys that arrive meanwhile stay in the socket
+            self.editor.wait_for_completion(&mut echo);
+            self.editor.feed("", &mut echo)
+        } else if self.editing {
+            let message = match self.socket.borrow_mut().read() {
+                Ok(message) => message,
+                Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => {
+                    return Ok(false)
+                }
+                Err(e) => return Err(io::Error::other(e)),
+            };
+            let keys = match message {
+                Message::Text(text) => text.to_string(),
+                Message::Binary(data) => {
+                    self.partial.extend_from_slice(&data);
+                    take_utf8(&mut self.partial)
+                }
+                Message::Close(_) => return Ok(false),
+                _ => return Ok(true),
+            };
+            let edit = self.editor.feed(&keys, &mut echo);
+            if let Some(recorder) = &self.recorder {
+                // A failing recording must not break the session
+                let _ = recorder.borrow_mut().record(&keys, edit.is_some());
+            }
+            edit
+        } else {
+            // The prompt has been written, so the line starts behind it
+            self.editor.start(self.column.get(), &mut echo);
+            self.editing = true;
+            self.editor.feed("", &mut echo)
+        };
+        send(&self.socket, echo)?;
+
+        match edit {
+            None => Ok(true),
+            Some(Edit::Line(line)) => {
+                self.editing = false;
+                self.column.set(0);
+                self.ready = (line + "\n").into_bytes();
+                self.position = 0;
+                Ok(true)
+            }
+            Some(Edit::Closed) => Ok(false),
+        }
+    }
+}
+
+/// Records the keys that clients send to a file, see
+/// [`Shell::record_keystrokes`](crate::Shell::record_keystrokes)
+pub(crate) struct Recorder {
+    transcript: Transcript,
+    secrets: Vec<String>,
+    /// The keys of the line that is being edited
+    pending: Vec<String>,
+}
+
+impl Recorder {
+    pub(crate) fn create(path: &Path, secrets: &[&str]) -> io::Result<Self> {
+        Ok(Recorder {
+            transcript: Transcript::create(path, false)?,
+            secrets: secrets
+                .iter()
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string())
+                .collect(),
+            pending: Vec::new(),
+        })
+    }
+
+    /// Records the keys of a message
+    ///
+    /// The keys of a line are held back until it is complete, so that secrets
+    /// typed over several messages are found.
+    pub(crate) fn record(&mut self, keys: &str, line_complete: bool) -> io::Result<()> {
+        self.pending.push(keys.to_string());
+        if line_complete {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the held back keys with every secret replaced by `*`
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        let typed = self.pending.concat();
+        let mut masked = vec![false; typed.len()];
+        for secret in &self.secrets {
+            for (start, _) in typed.match_indices(secret.as_str()) {
+                masked[start..start + secret.len()].fill(true);
+            }
+        }
+
+        let mut offset = 0;
+        for keys in std::mem::take(&mut self.pending) {
+            let anonymized: String = keys
+                .char_indices()
+                .map(|(i, c)| if masked[offset + i] { '*' } else { c })
+                .collect();
+            offset += keys.len();
+            self.transcript.record(EventKind::Input, &anonymized)?;
+        }
+        Ok(())
+    }
+}
+
+/// Replays the keys recorded with
+/// [`Shell::record_keystrokes`](crate::Shell::record_keystrokes) against the
+/// line editor, see [`testing::replay_keystrokes`](crate::testing::replay_keystrokes)
+pub(crate) fn replay(path: &Path) -> io::Result<(Vec<String>, String)> {
+    let recording = std::fs::read_to_string(path)?;
+    let mut editor = LineEditor::new();
+    let (mut lines, mut echo) = (Vec::new(), String::new());
+    // The first line is the header of the recording
+    for event in recording
+        .lines()
+        .skip(1)
+        .filter_map(transcript::parse_event)
+    {
+        if event.kind != Some(EventKind::Input) {
+            continue;
+        }
+        let mut edit = editor.feed(&event.data, &mut echo);
+        while let Some(completed) = edit {
+            match completed {
+                Edit::Line(line) => lines.push(line),
+                Edit::Closed => return Ok((lines, echo)),
+            }
+            // The keys after a line belong to the next one
+            edit = editor.feed("", &mut echo);
+        }
+    }
+    Ok((lines, echo))
+}
+
+/// Removes the complete UTF-8 characters from the start of the bytes and
+/// returns them, leaving an incomplete character at the end for the next
+/// message
+fn take_utf8(bytes: &mut Vec<u8>) -> String {
+    let complete = match std::str::from_utf8(bytes) {
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        _ => bytes.len(),
+    };
+    let text = String::from_utf8_lossy(&bytes[..complete]).into_owned();
+    bytes.drain(..complete);
+    text
+}
+
+impl<S: Read + Write> Drop for Input<S> {
+    /// Records the keys of a line the client did not complete
+    fn drop(&mut self) {
+        if let Some(recorder) = &self.recorder {
+            let _ = recorder.borrow_mut().flush();
+        }
+    }
+}
+
+impl<S: Read + Write> Read for Input<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let read = available.len().min(buf.len());
+        buf[..read].copy_from_slice(&available[..read]);
+        self.consume(read);
+        Ok(read)
+    }
+}
+
+impl<S: Read + Write> BufRead for Input<S> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.position == self.ready.len() {
+            if !self.receive()? {
+                return Ok(&[]);
+            }
+        }
+        Ok(&self.ready[self.position..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position += amount;
+    }
+}
+
+/// Sends everything that is written as text messages with the line endings a
+/// terminal emulator expects
+pub(crate) struct Output<S: Read + Write> {
+    socket: Socket<S>,
+    /// The column the text written so far left the cursor in
+    column: Rc<Cell<usize>>,
+    /// The escape sequence the text written so far ended in: `\x1b` after
+    /// the escape character, `[` in a CSI and `]` in an OSC sequence
+    escape: Option<char>,
+}
+
+impl<S: Read + Write> Output<S> {
+    /// Moves the column along the text, ignoring escape sequences like colors
+    fn advance(&mut self, text: &str) {
+        let mut column = self.column.get();
+        for c in text.chars() {
+            if let Some(escape) = self.escape {
+                self.escape = match (escape, c) {
+                    ('\x1b', '[' | ']') => Some(c),
+                    // CSI sequences end with a character in `@`..=`~`
+                    ('[', c) if ('@'..='~').contains(&c) => None,
+                    // OSC sequences end with BEL or ST, which is `\x1b\\`
+                    (']', '\x07') | ('\x1b', _) => None,
+                    (']', '\x1b') => Some('\x1b'),
+                    _ => Some(escape),
+                };
+                continue;
+            }
+            match c {
+                '\r' | '\n' => column = 0,
+                '\x1b' => self.escape = Some(c),
+                '\x08' => column = column.saturating_sub(1),
+                c => column += c.width().unwrap_or(0),
+            }
+        }
+        self.column.set(column);
+    }
+}
+
+impl<S: Read + Write> Write for Output<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf).replace("\r\n", "\n");
+        self.advance(&text);
+        send(&self.socket, text.replace('\n', "\r\n"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.socket.borrow_mut().flush().map_err(io::Error::other)
+    }
+}
+
+impl<S: Read + Write> Drop for Output<S> {
+    /// Closes the socket once the session is over
+    fn drop(&mut self) {
+        let mut socket = self.socket.borrow_mut();
+        if socket.close(None).is_ok() {
+            let _ = socket.flush();
+        }
+    }
+}
+
+fn send<S: Read + Write>(socket: &Socket<S>, text: String) -> io::Result<()> {
+    if text.is_empty() {
+        return Ok(());
+    }
+    socket
+        .borrow_mut()
+        .send(Message::text(text))
+        .map_err(io::Error::other)
+}
+
+/// Splits the socket into the input and the output of a session
+///
+/// The keys the client sends are recorded with the recorder, if there is one.
+/// `Tab` completes the word before the cursor with the completer in the slot.
+pub(crate) fn split<S: Read + Write>(
+    socket: WebSocket<S>,
+    recorder: Option<Rc<RefCell<Recorder>>>,
+    completions: CompleterSlot,
+) -> (Input<S>, Output<S>) {
+    let socket = Rc::new(RefCell::new(socket));
+    let column = Rc::new(Cell::new(0));
+    let input = Input {
+        socket: socket.clone(),
+        editor: LineEditor::new().with_completer(SessionCompleter(completions)),
+        editing: false,
+        column: column.clone(),
+        partial: Vec::new(),
+        recorder,
+        ready: Vec::new(),
+        position: 0,
+    };
+    let output = Output {
+        socket,
+        column,
+        escape: None,
+    };
+    (input, output)
+}